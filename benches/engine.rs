@@ -0,0 +1,144 @@
+// Throughput of reading, applying and the whole run on generated datasets, run with
+// `cargo bench`. Regressions show up as changes against the previous run's baseline.
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io;
+use tempfile::NamedTempFile;
+use transactions::generate::{self, DatasetShape};
+use transactions::{
+    parse_transactions, process_transactions, write_client_funds_to, EngineConfig, IdHasher,
+    Transaction, TransactionEngine,
+};
+
+const SIZES: [usize; 2] = [10_000, 100_000];
+
+fn shape(transactions: usize) -> DatasetShape {
+    DatasetShape {
+        transactions,
+        ..Default::default()
+    }
+}
+
+fn dataset_file(shape: &DatasetShape) -> NamedTempFile {
+    let file = NamedTempFile::new().unwrap();
+    generate::write_csv(shape, File::create(file.path()).unwrap()).unwrap();
+    file
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for size in SIZES {
+        let file = dataset_file(&shape(size));
+        let path = file.path().to_string_lossy().into_owned();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &path, |b, path| {
+            b.iter(|| parse_transactions(path.clone()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn apply(c: &mut Criterion) {
+    let mut group = c.benchmark_group("apply");
+    for size in SIZES {
+        let transactions = generate::transactions(&shape(size));
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(size),
+            &transactions,
+            |b, transactions| {
+                // The copy handed to each iteration isn't part of the measurement
+                b.iter_batched(
+                    || transactions.clone(),
+                    |transactions| process_transactions(transactions, Default::default()).unwrap(),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+// The same transactions pushed one by one and in batches of 10,000
+fn batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch");
+    let size = 100_000;
+    let transactions = generate::transactions(&shape(size));
+    group.throughput(Throughput::Elements(size as u64));
+    group.bench_with_input("push", &transactions, |b, transactions| {
+        b.iter_batched(
+            || transactions.clone(),
+            |transactions| {
+                let mut engine = TransactionEngine::default();
+                for tx in transactions {
+                    engine.push(tx);
+                }
+                engine.finish()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_with_input("push_batch", &transactions, |b, transactions| {
+        b.iter(|| {
+            let mut engine = TransactionEngine::default();
+            for batch in transactions.chunks(10_000) {
+                engine.push_batch(batch);
+            }
+            engine.finish()
+        })
+    });
+    group.finish();
+}
+
+// The same transactions applied by engines hashing ids with FxHash, the default, and SipHash
+fn hasher(c: &mut Criterion) {
+    fn run<S: BuildHasher + Default + Clone>(hasher: S, transactions: Vec<Transaction>) -> usize {
+        let mut engine = TransactionEngine::with_hasher(EngineConfig::default(), hasher);
+        for tx in transactions {
+            engine.push(tx);
+        }
+        engine.finish().len()
+    }
+
+    let mut group = c.benchmark_group("hasher");
+    let size = 100_000;
+    let transactions = generate::transactions(&shape(size));
+    group.throughput(Throughput::Elements(size as u64));
+    group.bench_with_input("fx", &transactions, |b, transactions| {
+        b.iter_batched(
+            || transactions.clone(),
+            |transactions| run(IdHasher, transactions),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_with_input("sip", &transactions, |b, transactions| {
+        b.iter_batched(
+            || transactions.clone(),
+            |transactions| run(RandomState::new(), transactions),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn end_to_end(c: &mut Criterion) {
+    let mut group = c.benchmark_group("end_to_end");
+    for size in SIZES {
+        let file = dataset_file(&shape(size));
+        let path = file.path().to_string_lossy().into_owned();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &path, |b, path| {
+            b.iter(|| {
+                let transactions = parse_transactions(path.clone()).unwrap();
+                let clients = process_transactions(transactions, Default::default()).unwrap();
+                write_client_funds_to(clients, io::sink()).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, apply, batch, hasher, end_to_end);
+criterion_main!(benches);
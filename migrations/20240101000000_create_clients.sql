@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS clients (
+    client_id INTEGER PRIMARY KEY,
+    available REAL NOT NULL,
+    held REAL NOT NULL,
+    locked BOOLEAN NOT NULL,
+    past_tx BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS transactions (
+    client_id INTEGER NOT NULL REFERENCES clients (client_id),
+    tx_id BIGINT NOT NULL,
+    tx_type TEXT NOT NULL,
+    amount REAL,
+    timestamp TIMESTAMPTZ,
+    disputed BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (client_id, tx_id)
+);
@@ -0,0 +1,8 @@
+-- Balances and amounts are kept as integer 1/10000 units, the amount's input scale alongside it
+ALTER TABLE clients
+    ALTER COLUMN available TYPE BIGINT USING round(available * 10000)::BIGINT,
+    ALTER COLUMN held TYPE BIGINT USING round(held * 10000)::BIGINT;
+
+ALTER TABLE transactions
+    ALTER COLUMN amount TYPE BIGINT USING round(amount * 10000)::BIGINT,
+    ADD COLUMN amount_scale SMALLINT NOT NULL DEFAULT 4;
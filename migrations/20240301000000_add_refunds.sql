@@ -0,0 +1,2 @@
+-- Amount refunded so far against a deposit, in 1/10000 units
+ALTER TABLE transactions ADD COLUMN refunded BIGINT NOT NULL DEFAULT 0;
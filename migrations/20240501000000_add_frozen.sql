@@ -0,0 +1,2 @@
+-- Set by operators, separate from the lock a chargeback puts on an account
+ALTER TABLE clients ADD COLUMN frozen BOOLEAN NOT NULL DEFAULT FALSE;
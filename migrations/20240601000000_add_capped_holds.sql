@@ -0,0 +1,3 @@
+-- Amount held for a dispute capped at the funds available when it came in, in 1/10000 units.
+-- Null when the dispute holds the whole amount or the transaction isn't disputed
+ALTER TABLE transactions ADD COLUMN capped_hold BIGINT;
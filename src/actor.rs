@@ -0,0 +1,126 @@
+use crate::client::Clients;
+use crate::engine::{EngineConfig, TransactionEngine};
+use crate::transaction::Transaction;
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+
+// Number of transactions queued per worker before `push` blocks the caller
+const CHANNEL_CAPACITY: usize = 1024;
+
+// Splits clients into shards, each owned by a dedicated worker thread running its own
+// TransactionEngine. Transactions are routed to the owning shard over a channel, so
+// no lock is shared between workers and throughput scales with the number of cores.
+// `push` takes `&self`, letting several ingestion threads feed the same engine.
+#[derive(Debug)]
+pub struct ActorEngine {
+    senders: Vec<SyncSender<Transaction>>,
+    workers: Vec<JoinHandle<Clients>>,
+}
+
+impl ActorEngine {
+    pub fn new(config: EngineConfig, shards: usize) -> Self {
+        ActorEngine::with_clients(config, shards, Clients::default())
+    }
+
+    // Hands each worker the existing clients of its shard before it starts receiving
+    pub fn with_clients(config: EngineConfig, shards: usize, clients: Clients) -> Self {
+        let shards = shards.max(1);
+        let mut shard_clients: Vec<Clients> = (0..shards).map(|_| Clients::default()).collect();
+        for (client_id, client) in clients {
+            shard_clients[usize::from(client_id.value()) % shards].insert(client_id, client);
+        }
+
+        let (senders, workers) = shard_clients
+            .into_iter()
+            .map(|clients| {
+                let (sender, receiver) = mpsc::sync_channel::<Transaction>(CHANNEL_CAPACITY);
+                let config = config.clone();
+                let worker = thread::spawn(move || {
+                    let mut engine = TransactionEngine::with_clients(config, clients);
+                    for tx in receiver {
+                        engine.push(tx);
+                    }
+                    engine.finish()
+                });
+
+                (sender, worker)
+            })
+            .unzip();
+
+        ActorEngine { senders, workers }
+    }
+
+    pub fn shards(&self) -> usize {
+        self.senders.len()
+    }
+
+    pub fn push(&self, tx: Transaction) -> Result<(), String> {
+        let shard = tx.raw_client_id() as usize % self.senders.len();
+
+        self.senders[shard]
+            .send(tx)
+            .map_err(|_| format!("Worker for shard {} has stopped", shard))
+    }
+
+    // Closes every channel, waits for the workers to drain them and merges their clients
+    pub fn finish(self) -> Result<Clients, String> {
+        drop(self.senders);
+
+        let mut clients = Clients::default();
+        for (shard, worker) in self.workers.into_iter().enumerate() {
+            let shard_clients = worker
+                .join()
+                .map_err(|_| format!("Worker for shard {} panicked", shard))?;
+            clients.extend(shard_clients);
+        }
+
+        Ok(clients)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TransactionType;
+
+    #[test]
+    fn can_process_clients_across_shards() {
+        let engine = ActorEngine::new(EngineConfig::default(), 3);
+        for client_id in 1..=6 {
+            let tx_id = u32::from(client_id);
+            engine
+                .push(Transaction::raw(
+                    TransactionType::Deposit,
+                    tx_id,
+                    client_id,
+                    Some(2.0),
+                ))
+                .unwrap();
+            engine
+                .push(Transaction::raw(
+                    TransactionType::Withdrawal,
+                    tx_id + 10,
+                    client_id,
+                    Some(0.5),
+                ))
+                .unwrap();
+        }
+
+        let clients = engine.finish().unwrap();
+        assert_eq!(clients.len(), 6);
+        for (client_id, client) in &clients {
+            assert_eq!(
+                client.get_record(*client_id),
+                vec![
+                    client_id.to_string().as_str(),
+                    "1.5000",
+                    "0.0000",
+                    "1.5000",
+                    "false",
+                    "false",
+                    "0.0000"
+                ]
+            );
+        }
+    }
+}
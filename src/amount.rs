@@ -0,0 +1,191 @@
+// A transaction amount together with the number of decimals it was written with,
+// so balances can be formatted with the same precision the source system used.
+// It (de)serializes as its decimal string, e.g. "2.0001", in every format.
+use crate::money::Money;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Amount {
+    value: Money,
+    scale: u8,
+}
+
+impl Amount {
+    pub fn new(value: Money, scale: u8) -> Self {
+        Amount { value, scale }
+    }
+
+    pub fn value(&self) -> Money {
+        self.value
+    }
+
+    // Number of decimals the amount was written with
+    pub fn scale(&self) -> u8 {
+        self.scale
+    }
+}
+
+// Amounts created in code take the scale of their shortest representation, 1.5 has a scale of 1
+impl From<f32> for Amount {
+    fn from(value: f32) -> Self {
+        let scale = match value.to_string().split_once('.') {
+            Some((_, decimals)) => decimals.len(),
+            None => 0,
+        };
+
+        Amount {
+            value: Money::from(value),
+            scale: u8::try_from(scale).unwrap_or(u8::MAX),
+        }
+    }
+}
+
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let value = text.parse::<Money>()?;
+        let scale = match text.split_once('.') {
+            Some((_, decimals)) => decimals.chars().take_while(char::is_ascii_digit).count(),
+            None => 0,
+        };
+
+        Ok(Amount {
+            value,
+            scale: u8::try_from(scale).unwrap_or(u8::MAX),
+        })
+    }
+}
+
+// Reads amounts the way real-world exports write them, e.g. "$1,234.50" or "1 234,50 €".
+// Currency symbols and codes before or after the number are dropped, as are grouping
+// separators before the decimal separator, which is turned into a '.'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmountParser {
+    pub decimal_separator: char,
+}
+
+impl Default for AmountParser {
+    fn default() -> Self {
+        AmountParser {
+            decimal_separator: '.',
+        }
+    }
+}
+
+impl AmountParser {
+    const GROUP_SEPARATORS: [char; 6] = [',', '.', '\'', ' ', '\u{a0}', '\u{202f}'];
+
+    // Plain decimal text the strict parser accepts
+    pub fn normalize(&self, text: &str) -> Result<String, String> {
+        let invalid = || format!("Invalid amount: {}", text);
+        let first = text
+            .find(|c: char| c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let last = text
+            .rfind(|c: char| c.is_ascii_digit())
+            .ok_or_else(invalid)?
+            + 1;
+        let (prefix, suffix) = (&text[..first], &text[last..]);
+
+        let mut normalized = String::new();
+        if prefix.contains('-') || suffix.contains('-') {
+            normalized.push('-');
+        }
+        // ".5" and ",5" have nothing before their decimal separator
+        let mut decimals = prefix.ends_with(self.decimal_separator);
+        if decimals {
+            normalized.push_str("0.");
+        }
+
+        for c in text[first..last].chars() {
+            if c.is_ascii_digit() {
+                normalized.push(c);
+            } else if c == self.decimal_separator && !decimals {
+                normalized.push('.');
+                decimals = true;
+            } else if decimals || !Self::GROUP_SEPARATORS.contains(&c) {
+                return Err(invalid());
+            }
+        }
+
+        Ok(normalized)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.*}", self.scale as usize, self.value)
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(AmountVisitor)
+    }
+}
+
+struct AmountVisitor;
+
+impl Visitor<'_> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a decimal amount")
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<Amount, E> {
+        text.parse().map_err(E::custom)
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Amount, E> {
+        self.visit_str(&value.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Amount, E> {
+        self.visit_str(&value.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Amount, E> {
+        self.visit_str(&value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_scale_it_was_written_with() {
+        for text in ["1.5", "2.0001", "3", "2.0"] {
+            assert_eq!(text.parse::<Amount>().unwrap().to_string(), text);
+        }
+        assert_eq!(Amount::from(1.25).scale(), 2);
+        assert!("amount".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn normalizes_formatted_amounts() {
+        let parser = AmountParser::default();
+        assert_eq!(parser.normalize("$1,234.50").unwrap(), "1234.50");
+        assert_eq!(parser.normalize("-USD 7").unwrap(), "-7");
+        assert!(parser.normalize("1.234.5").is_err());
+        assert!(parser.normalize("1,2x3").is_err());
+
+        let parser = AmountParser {
+            decimal_separator: ',',
+        };
+        assert_eq!(parser.normalize("1 234,50").unwrap(), "1234.50");
+        assert_eq!(parser.normalize("1.234,5 €").unwrap(), "1234.5");
+        assert_eq!(parser.normalize(",25").unwrap(), "0.25");
+    }
+}
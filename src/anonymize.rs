@@ -0,0 +1,189 @@
+// Rewrites an input so it can be shared for debugging without exposing customer data. Client
+// ids are shuffled and tx ids renumbered keeping their order, so disputes still reference the
+// right rows and nothing is accepted or rejected differently. Memos are blanked.
+use crate::csv_reader;
+use crate::generate::XorShift;
+use crate::money::Money;
+use csv::{StringRecord, WriterBuilder};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anonymization {
+    // The same seed always gives the same output for the same input
+    pub seed: u64,
+    // Scales every amount by a random factor up to this fraction away from 1, e.g. 0.05. Can
+    // change which withdrawals have sufficient funds
+    pub perturb_amounts: Option<f64>,
+}
+
+impl Default for Anonymization {
+    fn default() -> Self {
+        Anonymization {
+            seed: 1,
+            perturb_amounts: None,
+        }
+    }
+}
+
+pub fn anonymize<W: io::Write>(
+    input: &str,
+    options: Anonymization,
+    writer: W,
+) -> Result<(), String> {
+    let (clients, txs) = read_ids(input)?;
+    let mut random = XorShift(options.seed.max(1));
+
+    // Clients get the ids 1 to n in a seeded random order, wrapping to 0 if every id is used,
+    // tx ids their rank
+    let mut client_ids: Vec<u16> = (0..clients.len())
+        .map(|rank| (rank as u16).wrapping_add(1))
+        .collect();
+    for index in (1..client_ids.len()).rev() {
+        let other = (random.next() % (index as u64 + 1)) as usize;
+        client_ids.swap(index, other);
+    }
+    let clients: HashMap<u16, u16> = clients.into_iter().zip(client_ids).collect();
+    let txs: HashMap<u32, u32> = txs.into_iter().zip(1..).collect();
+
+    let mut rdr = csv_reader(input)?;
+    let headers = rdr
+        .headers()
+        .map_err(|error| format!("Error reading csv headers: {}", error))?
+        .clone();
+    let [client_column, tx_column] = id_columns(&headers)?;
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let (amount_column, memo_column) = (column("amount"), column("memo"));
+
+    let write_error = |error: csv::Error| format!("Error writing to std out: {}", error);
+    let mut wtr = WriterBuilder::new().flexible(true).from_writer(writer);
+    wtr.write_record(&headers).map_err(write_error)?;
+
+    for result in rdr.records() {
+        let record = result.map_err(|error| format!("Error parsing csv line: {}", error))?;
+        let mut fields: Vec<String> = record.iter().map(str::to_string).collect();
+
+        if let Some(field) = fields.get_mut(client_column) {
+            *field = clients[&parse_id(field)?].to_string();
+        }
+        if let Some(field) = fields.get_mut(tx_column) {
+            *field = txs[&parse_id(field)?].to_string();
+        }
+        if let Some(field) = memo_column.and_then(|column| fields.get_mut(column)) {
+            field.clear();
+        }
+        if let (Some(fraction), Some(field)) = (
+            options.perturb_amounts,
+            amount_column.and_then(|column| fields.get_mut(column)),
+        ) {
+            if !field.is_empty() {
+                let amount: Money = field.parse()?;
+                let factor = 1.0 + fraction * (2.0 * random.fraction() - 1.0);
+                *field =
+                    Money::from_minor_units((amount.minor_units() as f64 * factor).round() as i64)
+                        .to_string();
+            }
+        }
+
+        wtr.write_record(&fields).map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing to std out: {}", error))
+}
+
+// Every client and tx id in the input, in order
+fn read_ids(input: &str) -> Result<(BTreeSet<u16>, BTreeSet<u32>), String> {
+    let mut rdr = csv_reader(input)?;
+    let headers = rdr
+        .headers()
+        .map_err(|error| format!("Error reading csv headers: {}", error))?
+        .clone();
+    let [client_column, tx_column] = id_columns(&headers)?;
+
+    let (mut clients, mut txs) = (BTreeSet::new(), BTreeSet::new());
+    for result in rdr.records() {
+        let record = result.map_err(|error| format!("Error parsing csv line: {}", error))?;
+        if let Some(client) = record.get(client_column) {
+            clients.insert(parse_id(client)?);
+        }
+        if let Some(tx) = record.get(tx_column) {
+            txs.insert(parse_id(tx)?);
+        }
+    }
+
+    Ok((clients, txs))
+}
+
+fn id_columns(headers: &StringRecord) -> Result<[usize; 2], String> {
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or(format!("Input has no {} column", name))
+    };
+    Ok([column("client")?, column("tx")?])
+}
+
+fn parse_id<T: std::str::FromStr>(field: &str) -> Result<T, String> {
+    field.parse().map_err(|_| format!("Invalid id {:?}", field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_transactions, process_transactions};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn keeps_references_and_outcomes() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("txs.csv");
+        fs::write(
+            &input,
+            "type,client,tx,amount,memo\n\
+             deposit,700,50,5.0,salary\n\
+             deposit,12,90,1.0,\n\
+             withdrawal,700,40,1.0,in the past\n\
+             dispute,700,50,,card stolen\n\
+             chargeback,700,50,,\n",
+        )
+        .unwrap();
+        let input = input.to_string_lossy().into_owned();
+
+        let mut output = Vec::new();
+        anonymize(&input, Anonymization::default(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("type,client,tx,amount,memo\ndeposit,"));
+        assert!(output.lines().nth(1).unwrap().ends_with(",2,5.0,"));
+        assert!(!output.contains("700") && !output.contains("salary"));
+
+        // Same balances and locks, only under other ids
+        let anonymized = dir.path().join("anonymized.csv");
+        fs::write(&anonymized, &output).unwrap();
+        let accounts = |path: &Path| {
+            let transactions = parse_transactions(path.to_string_lossy().into_owned()).unwrap();
+            let clients = process_transactions(transactions, Default::default()).unwrap();
+            let mut accounts: Vec<_> = clients
+                .values()
+                .map(|client| (client.funds().available(), client.is_locked()))
+                .collect();
+            accounts.sort();
+            accounts
+        };
+        assert_eq!(accounts(Path::new(&input)), accounts(&anonymized));
+
+        let perturbed = |seed| {
+            let mut output = Vec::new();
+            let options = Anonymization {
+                seed,
+                perturb_amounts: Some(0.1),
+            };
+            anonymize(&input, options, &mut output).unwrap();
+            output
+        };
+        assert_eq!(perturbed(3), perturbed(3));
+        assert_ne!(perturbed(3), perturbed(4));
+    }
+}
@@ -0,0 +1,128 @@
+// Every change to a client's funds is recorded as an audit entry so a final balance can be
+// explained line by line: which tx moved how much between available and held.
+#[cfg(feature = "csv")]
+use crate::client::Clients;
+use crate::ids::ClientId;
+use crate::money::Money;
+use crate::transaction::TransactionType;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "csv")]
+use std::io;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub tx_id: u32,
+    pub operation: TransactionType,
+    pub available_change: Money,
+    pub held_change: Money,
+    // Balances once the change was applied
+    pub available: Money,
+    pub held: Money,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub memo: Option<String>,
+    // Joint owner who made the change, None when it was the account's own client
+    #[serde(default)]
+    pub owner: Option<ClientId>,
+}
+
+pub type AuditTrail = Vec<AuditEntry>;
+
+// Writes the audit trail of every client as csv, ordered by client and then by the order
+// the changes were applied in
+#[cfg(feature = "csv")]
+pub fn write_report<W: io::Write>(clients: &Clients, writer: W) -> Result<(), String> {
+    let write_error = |error: csv::Error| format!("Error writing audit trail: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    wtr.write_record([
+        "client",
+        "tx",
+        "operation",
+        "available_change",
+        "held_change",
+        "available",
+        "held",
+        "total",
+        "timestamp",
+        "memo",
+        "owner",
+    ])
+    .map_err(write_error)?;
+
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+
+    for client_id in client_ids {
+        for entry in clients[client_id].audit_trail() {
+            wtr.write_record([
+                client_id.to_string(),
+                entry.tx_id.to_string(),
+                entry.operation.to_string(),
+                entry.available_change.to_string(),
+                entry.held_change.to_string(),
+                entry.available.to_string(),
+                entry.held.to_string(),
+                entry.available.saturating_add(entry.held).to_string(),
+                entry
+                    .timestamp
+                    .map(|timestamp| timestamp.to_rfc3339())
+                    .unwrap_or_default(),
+                entry.memo.clone().unwrap_or_default(),
+                entry
+                    .owner
+                    .map(|owner| owner.to_string())
+                    .unwrap_or_default(),
+            ])
+            .map_err(write_error)?;
+        }
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing audit trail: {}", error))
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::ids::{ClientId, TxId};
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn explains_the_final_balance() {
+        let mut client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+        );
+        client
+            .handle_transaction(Transaction::raw(
+                TransactionType::Withdrawal,
+                2,
+                1,
+                Some(0.5),
+            ))
+            .unwrap();
+        client
+            .handle_transaction(
+                Transaction::raw(TransactionType::Dispute, 1, 1, None)
+                    .with_memo("card reported stolen"),
+            )
+            .unwrap();
+
+        let mut output = Vec::new();
+        write_report(
+            &Clients::from_iter([(ClientId::new(1), client)]),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,tx,operation,available_change,held_change,available,held,total,timestamp,memo,owner\n\
+             1,1,deposit,2.0000,0.0000,2.0000,0.0000,2.0000,,,\n\
+             1,2,withdrawal,-0.5000,0.0000,1.5000,0.0000,1.5000,,,\n\
+             1,1,dispute,-2.0000,2.0000,-0.5000,2.0000,1.5000,,card reported stolen,\n"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+// A balance consistency check of an `--audit-trail` report against saved engine state, e.g. to
+// confirm after an incident that a snapshot's balances are what its log says. Each entry's
+// change has to lead from the client's previous balances to the ones it records, and the last
+// balances of every client have to match the snapshot's. The log doesn't hold the transactions
+// themselves, so nothing else of the engine's state, e.g. open disputes, locks or stored
+// transactions, is checked.
+use crate::client::{Clients, Funds};
+use crate::csv_reader;
+use crate::ids::ClientId;
+use crate::money::Money;
+use std::collections::BTreeMap;
+use std::io;
+
+pub type Balances = BTreeMap<ClientId, Funds>;
+
+pub fn read_balances(path: &str) -> Result<Balances, String> {
+    let mut rdr = csv_reader(path)?;
+    let headers = rdr
+        .headers()
+        .map_err(|error| format!("Error reading {} headers: {}", path, error))?
+        .clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or(format!("{} has no {} column", path, name))
+    };
+    let columns = [
+        column("client")?,
+        column("available_change")?,
+        column("held_change")?,
+        column("available")?,
+        column("held")?,
+    ];
+
+    let mut balances = Balances::new();
+    for (index, result) in rdr.records().enumerate() {
+        // Line 1 is the header
+        let line = index + 2;
+        let record = result.map_err(|error| format!("Error parsing {}: {}", path, error))?;
+        let [client, available_change, held_change, available, held] =
+            columns.map(|column| record.get(column).unwrap_or_default());
+        let client = client
+            .parse::<ClientId>()
+            .map_err(|_| format!("Line {}: invalid client {:?}", line, client))?;
+        let money = |text: &str| {
+            text.parse::<Money>()
+                .map_err(|error| format!("Line {}: {}", line, error))
+        };
+
+        let previous = balances.get(&client).copied().unwrap_or_default();
+        let funds = Funds::from_balances(money(available)?, money(held)?);
+        if previous.available().checked_add(money(available_change)?)? != funds.available()
+            || previous.held().checked_add(money(held_change)?)? != funds.held()
+        {
+            return Err(format!(
+                "Line {}: client {} balances don't follow from the previous entry",
+                line, client
+            ));
+        }
+        balances.insert(client, funds);
+    }
+
+    Ok(balances)
+}
+
+// Every client whose balances in the log differ from `clients`, described. A client only on
+// one side counts as having no funds on the other
+pub fn mismatches(balances: &Balances, clients: &Clients) -> Vec<String> {
+    let mut client_ids: Vec<ClientId> = balances.keys().chain(clients.keys()).copied().collect();
+    client_ids.sort();
+    client_ids.dedup();
+
+    client_ids
+        .into_iter()
+        .filter_map(|client_id| {
+            let logged = balances.get(&client_id).copied().unwrap_or_default();
+            let saved = clients
+                .get(&client_id)
+                .map(|client| *client.funds())
+                .unwrap_or_default();
+            (logged != saved).then(|| {
+                format!(
+                    "client {}: log has available {} held {}, snapshot has available {} held {}",
+                    client_id,
+                    logged.available(),
+                    logged.held(),
+                    saved.available(),
+                    saved.held()
+                )
+            })
+        })
+        .collect()
+}
+
+pub fn write_balances<W: io::Write>(balances: &Balances, writer: W) -> Result<(), String> {
+    let write_error = |error: csv::Error| format!("Error writing to std out: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    wtr.write_record(["client", "available", "held", "total"])
+        .map_err(write_error)?;
+    for (client_id, funds) in balances {
+        wtr.write_record([
+            client_id.to_string(),
+            funds.available().to_string(),
+            funds.held().to_string(),
+            funds.available().saturating_add(funds.held()).to_string(),
+        ])
+        .map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing to std out: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit;
+    use crate::engine::{EngineConfig, TransactionEngine};
+    use crate::ids::ClientId;
+    use crate::transaction::{Transaction, TransactionType};
+    use std::fs;
+
+    #[test]
+    fn checks_the_audit_trail_balances_of_a_snapshot() {
+        let mut engine = TransactionEngine::new(EngineConfig::default());
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            Transaction::raw(TransactionType::Deposit, 2, 2, Some(3.0)),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(0.5)),
+            Transaction::raw(TransactionType::Dispute, 2, 2, None),
+        ] {
+            engine.push(tx);
+        }
+        let clients = engine.finish();
+
+        let path = std::env::temp_dir().join("checks_the_audit_trail_balances_of_a_snapshot.csv");
+        audit::write_report(&clients, fs::File::create(&path).unwrap()).unwrap();
+        let balances = read_balances(path.to_str().unwrap()).unwrap();
+        assert!(mismatches(&balances, &clients).is_empty());
+
+        let mut tampered = clients.clone();
+        tampered.remove(&ClientId::new(1));
+        assert_eq!(
+            mismatches(&balances, &tampered),
+            vec!["client 1: log has available 1.5000 held 0.0000, snapshot has available 0.0000 held 0.0000".to_string()]
+        );
+
+        fs::write(
+            &path,
+            "client,tx,operation,available_change,held_change,available,held\n\
+             1,1,deposit,2,0,2,0\n\
+             1,2,withdrawal,-1,0,1.5,0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_balances(path.to_str().unwrap()),
+            Err("Line 3: client 1 balances don't follow from the previous entry".to_string())
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+// One place to set up an engine: every policy of the engine config, the clients to start from,
+// where applied transactions are kept and who hears about chargebacks, e.g.
+//
+//     let engine = TransactionEngine::builder()
+//         .cross_client(CrossClientPolicy::Route)
+//         .credit_limit(ClientId::new(7), "500".parse()?)
+//         .sink(Box::new(LogSink))
+//         .build()?;
+use crate::client::{Clients, IdHasher};
+use crate::codes::ErrorFormat;
+use crate::engine::{
+    CrossClientPolicy, DisputeOverdraftPolicy, EngineConfig, OrderingPolicy, ResolvePolicy,
+    RetentionPolicy, TierLimits, TransactionEngine, UnknownClientPolicy,
+};
+use crate::history::TransactionHistory;
+use crate::ids::ClientId;
+use crate::money::Money;
+use crate::notify::NotificationSink;
+use crate::transaction::TransactionType;
+use std::hash::BuildHasher;
+
+// `S` hashes the client and tx ids of the engine built, see `IdHasher`
+pub struct EngineBuilder<S = IdHasher> {
+    config: EngineConfig,
+    clients: Clients<S>,
+    expected_clients: usize,
+    history: Option<Box<dyn TransactionHistory>>,
+    sinks: Vec<Box<dyn NotificationSink>>,
+    rejections: bool,
+    load_tracking: bool,
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        EngineBuilder::with_hasher(IdHasher)
+    }
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        EngineBuilder::new()
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> EngineBuilder<S> {
+    // Same as `new` for an engine hashing ids with `hasher`, see `TransactionEngine::with_hasher`
+    pub fn with_hasher(hasher: S) -> Self {
+        EngineBuilder {
+            config: EngineConfig::default(),
+            clients: Clients::with_hasher(hasher),
+            expected_clients: 0,
+            history: None,
+            sinks: Vec::new(),
+            rejections: false,
+            load_tracking: false,
+        }
+    }
+
+    // Replaces every policy at once, e.g. with a config read from a file
+    pub fn config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn reorder_window(mut self, transactions: usize) -> Self {
+        self.config.reorder_window = transactions;
+        self
+    }
+
+    pub fn disputable_types(mut self, types: Vec<TransactionType>) -> Self {
+        self.config.disputable_types = types;
+        self
+    }
+
+    pub fn locked_allows(mut self, types: Vec<TransactionType>) -> Self {
+        self.config.locked_allows = types;
+        self
+    }
+
+    pub fn frozen_allows(mut self, types: Vec<TransactionType>) -> Self {
+        self.config.frozen_allows = types;
+        self
+    }
+
+    pub fn cross_client(mut self, policy: CrossClientPolicy) -> Self {
+        self.config.cross_client = policy;
+        self
+    }
+
+    pub fn ordering(mut self, policy: OrderingPolicy) -> Self {
+        self.config.ordering = policy;
+        self
+    }
+
+    pub fn dispute_overdraft(mut self, policy: DisputeOverdraftPolicy) -> Self {
+        self.config.dispute_overdraft = policy;
+        self
+    }
+
+    pub fn resolve(mut self, policy: ResolvePolicy) -> Self {
+        self.config.resolve = policy;
+        self
+    }
+
+    pub fn retention(mut self, policy: RetentionPolicy) -> Self {
+        self.config.retention = policy;
+        self
+    }
+
+    // How this engine reports rejections on std err
+    pub fn error_format(mut self, format: ErrorFormat) -> Self {
+        self.config.error_format = format;
+        self
+    }
+
+    pub fn unknown_clients(mut self, policy: UnknownClientPolicy) -> Self {
+        self.config.unknown_clients = policy;
+        self
+    }
+
+    // Lets `client`'s available funds go as far as `limit` below zero
+    pub fn credit_limit(mut self, client: ClientId, limit: Money) -> Self {
+        self.config.credit_limits.insert(client, limit);
+        self
+    }
+
+    // Applies `limits` to `client`'s withdrawals, as `--tiers` does for the clients of a tier
+    pub fn tier_limits(mut self, client: ClientId, limits: TierLimits) -> Self {
+        self.config.tiers.insert(client, limits);
+        self
+    }
+
+    // Makes `member` a joint owner of `account`'s funds
+    pub fn joint_account(mut self, member: ClientId, account: ClientId) -> Self {
+        self.config.joint_accounts.insert(member, account);
+        self
+    }
+
+    pub fn release_scheduled(mut self, release: bool) -> Self {
+        self.config.release_scheduled = release;
+        self
+    }
+
+    pub fn auto_unlock(mut self, unlock: bool) -> Self {
+        self.config.auto_unlock = unlock;
+        self
+    }
+
+    // Previously computed clients to start from, e.g. loaded from a store
+    pub fn clients(mut self, clients: Clients<S>) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    // Sizes the client map up front, see `TransactionEngine::with_expected_clients`
+    pub fn with_expected_clients(mut self, clients: usize) -> Self {
+        self.expected_clients = clients;
+        self
+    }
+
+    // Keeps applied transactions in `history` instead of memory
+    pub fn history(mut self, history: Box<dyn TransactionHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    // Adds a sink for chargebacks and newly locked accounts, on top of any added before
+    pub fn sink(mut self, sink: Box<dyn NotificationSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    // Keeps the rejected transactions for `TransactionEngine::finish_with_rejections`
+    pub fn keep_rejections(mut self) -> Self {
+        self.rejections = true;
+        self
+    }
+
+    // Times each client's transactions for `TransactionEngine::loads`
+    pub fn track_load(mut self) -> Self {
+        self.load_tracking = true;
+        self
+    }
+
+    // Fails when the policies set can't work together, see `EngineConfig::validate`
+    pub fn build(self) -> Result<TransactionEngine<S>, String> {
+        self.config.validate()?;
+        let mut engine = TransactionEngine::with_clients(self.config, self.clients)
+            .with_expected_clients(self.expected_clients);
+        for sink in self.sinks {
+            engine = engine.with_sink(sink);
+        }
+        if let Some(history) = self.history {
+            engine = engine.with_history(history);
+        }
+        if self.rejections {
+            engine = engine.with_rejections();
+        }
+        if self.load_tracking {
+            engine = engine.with_load_tracking();
+        }
+
+        Ok(engine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::ClientId;
+    use crate::transaction::Transaction;
+    use std::collections::hash_map::RandomState;
+
+    #[test]
+    fn builds_with_every_policy() {
+        let mut engine = TransactionEngine::builder()
+            .credit_limit(ClientId::new(1), "3".parse().unwrap())
+            .tier_limits(
+                ClientId::new(1),
+                TierLimits {
+                    tier: "basic".to_string(),
+                    withdrawal_limit: Some("4".parse().unwrap()),
+                    withdrawal_fee: Money::ZERO,
+                },
+            )
+            .ordering(OrderingPolicy::Ignore)
+            .unknown_clients(UnknownClientPolicy::Reject)
+            .keep_rejections()
+            .build()
+            .unwrap();
+
+        engine.push(Transaction::raw(TransactionType::Deposit, 5, 1, Some(1.0)));
+        engine.push(Transaction::raw(TransactionType::Dispute, 5, 2, None));
+        engine.push(Transaction::raw(
+            TransactionType::Withdrawal,
+            4,
+            1,
+            Some(3.0),
+        ));
+        engine.push(Transaction::raw(
+            TransactionType::Withdrawal,
+            6,
+            1,
+            Some(5.0),
+        ));
+
+        let (clients, rejections) = engine.finish_with_rejections();
+        assert_eq!(
+            clients[&ClientId::new(1)].funds().available(),
+            "-2".parse().unwrap()
+        );
+        assert!(!clients.contains_key(&ClientId::new(2)));
+        assert_eq!(rejections.len(), 2);
+        assert_eq!(rejections[1].tx.raw_tx_id(), 6);
+        assert!(rejections[1].error.message().contains("tier basic"));
+    }
+
+    #[test]
+    fn rejects_retention_without_ordering() {
+        let builder = || {
+            TransactionEngine::builder()
+                .retention(RetentionPolicy::KeepLast(10))
+                .ordering(OrderingPolicy::Ignore)
+        };
+        assert!(builder().build().is_err());
+        assert!(builder().ordering(OrderingPolicy::Enforce).build().is_ok());
+    }
+
+    #[test]
+    fn builds_with_another_hasher() {
+        let mut engine = EngineBuilder::with_hasher(RandomState::new())
+            .with_expected_clients(8)
+            .build()
+            .unwrap();
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(1.0)));
+
+        let clients: Clients<RandomState> = engine.finish();
+        assert_eq!(
+            clients[&ClientId::new(1)].funds().available(),
+            "1".parse().unwrap()
+        );
+    }
+}
@@ -0,0 +1,166 @@
+// Periodic checkpoints for long runs: every N transactions the engine state is saved together
+// with the byte offset reached in the input, so a killed run can resume from there instead
+// of reprocessing the whole file.
+use crate::client::Clients;
+use crate::engine::TransactionEngine;
+use crate::snapshot::{self, add_header, Migration, Snapshot};
+use crate::transaction::Transaction;
+use crate::{csv_reader, interrupt};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    byte: u64,
+    line: u64,
+    record: u64,
+    processed: u64,
+    engine: Vec<u8>,
+}
+
+impl Snapshot for Checkpoint {
+    const MIGRATIONS: &'static [Migration] = &[add_header];
+}
+
+impl Checkpoint {
+    fn position(&self) -> csv::Position {
+        let mut position = csv::Position::new();
+        position
+            .set_byte(self.byte)
+            .set_line(self.line)
+            .set_record(self.record);
+        position
+    }
+
+    pub fn processed(&self) -> u64 {
+        self.processed
+    }
+}
+
+pub fn load(path: &str) -> Result<Option<Checkpoint>, String> {
+    if !Path::new(path).exists() {
+        return Ok(None);
+    }
+
+    let bytes =
+        fs::read(path).map_err(|error| format!("Error reading checkpoint {}: {}", path, error))?;
+    snapshot::decode_versioned(&snapshot::open(bytes)?).map(Some)
+}
+
+// Written to a sibling file first and renamed over the old checkpoint, so a kill while
+// saving never leaves a truncated checkpoint behind
+pub fn save(path: &str, checkpoint: &Checkpoint) -> Result<(), String> {
+    let partial = format!("{}.partial", path);
+
+    fs::write(
+        &partial,
+        snapshot::seal(snapshot::encode_versioned(checkpoint)?)?,
+    )
+    .and_then(|_| fs::rename(&partial, path))
+    .map_err(|error| format!("Error writing checkpoint {}: {}", path, error))
+}
+
+pub fn remove(path: &str) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Error removing checkpoint {}: {}", path, error))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Streams `input` through `engine`, checkpointing to `path` every `every` transactions.
+// When `path` already holds a checkpoint its state replaces the engine's and reading
+// continues from the saved offset. The checkpoint is left in place, remove it once the
+// results have been written. Returns the clients and the number of rows processed,
+// counting those covered by the checkpoint. On Ctrl-C a final checkpoint is saved.
+pub fn process(
+    input: &str,
+    mut engine: TransactionEngine,
+    path: &str,
+    every: u64,
+) -> Result<(Clients, u64), String> {
+    let mut rdr = csv_reader(input)?;
+    // Headers have to be read before seeking, they are needed to deserialize what follows
+    rdr.headers()
+        .map_err(|error| format!("Error reading csv headers: {}", error))?;
+
+    let mut processed = 0;
+    if let Some(checkpoint) = load(path)? {
+        engine = engine.restore(&checkpoint.engine)?;
+        rdr.seek(checkpoint.position())
+            .map_err(|error| format!("Error seeking to checkpoint: {}", error))?;
+        processed = checkpoint.processed;
+        eprintln!("Resuming from checkpoint after {} transactions", processed);
+    }
+
+    let mut records = rdr.deserialize::<Transaction>();
+    while let Some(result) = records.next() {
+        let tx = result.map_err(|error| format!("Error parsing csv line: {}", error))?;
+        if tx.tenant().is_some() {
+            return Err("The tenant column isn't supported with --checkpoint".to_string());
+        }
+        engine.push(tx);
+        processed += 1;
+
+        let interrupted = interrupt::requested();
+        if interrupted || (every > 0 && processed % every == 0) {
+            let position = records.reader().position();
+            save(
+                path,
+                &Checkpoint {
+                    byte: position.byte(),
+                    line: position.line(),
+                    record: position.record(),
+                    processed,
+                    engine: engine.to_bytes()?,
+                },
+            )?;
+        }
+
+        if interrupted {
+            break;
+        }
+    }
+
+    Ok((engine.finish(), processed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::ids::ClientId;
+
+    #[test]
+    fn can_resume_from_checkpoint_without_reapplying() {
+        let dir = tempfile::tempdir().unwrap();
+        let checkpoint = dir.path().join("run.checkpoint");
+        let checkpoint = checkpoint.to_str().unwrap();
+
+        let header_and_first_rows = "type,client,tx,amount\ndeposit,1,1,2.0\ndeposit,1,2,1.0\n";
+        let partial_input = dir.path().join("partial.csv");
+        fs::write(&partial_input, header_and_first_rows).unwrap();
+        let full_input = dir.path().join("full.csv");
+        fs::write(
+            &full_input,
+            format!("{}withdrawal,1,3,0.5\n", header_and_first_rows),
+        )
+        .unwrap();
+
+        // The "killed" run only got through the first two rows
+        let engine = TransactionEngine::new(EngineConfig::default());
+        process(partial_input.to_str().unwrap(), engine, checkpoint, 2).unwrap();
+        assert_eq!(load(checkpoint).unwrap().unwrap().processed(), 2);
+
+        let engine = TransactionEngine::new(EngineConfig::default());
+        let (clients, processed) =
+            process(full_input.to_str().unwrap(), engine, checkpoint, 2).unwrap();
+        assert_eq!(processed, 3);
+        assert_eq!(
+            clients[&ClientId::new(1)].get_record(ClientId::new(1)),
+            vec!["1", "2.5000", "0.0000", "2.5000", "false", "false", "0.0000"]
+        );
+    }
+}
@@ -1,100 +1,890 @@
+use crate::audit::{AuditEntry, AuditTrail};
+use crate::codes::{CodedError, RejectionCode};
+use crate::conservation::{Row, Tally};
+use crate::engine::{
+    DisputeOverdraftPolicy, EngineConfig, OrderingPolicy, ResolvePolicy, RetentionPolicy,
+    TierLimits,
+};
+use crate::ids::{ClientId, TxId};
+use crate::money::Money;
+use crate::sorted;
 use crate::transaction::{Transaction, TransactionType};
-use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::BuildHasher;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Funds {
-    available: f32,
-    held: f32,
+    available: Money,
+    held: Money,
 }
 
 impl Funds {
     pub fn new(tx: &Transaction) -> Self {
         match tx.tx_type() {
+            // Deposits without an amount never open an account, see `Client::open`
             TransactionType::Deposit => Funds {
-                available: tx.amount().unwrap(),
-                held: 0f32,
+                available: tx.raw_amount().unwrap_or_default(),
+                held: Money::ZERO,
             },
             _ => Funds {
-                available: 0f32,
-                held: 0f32,
+                available: Money::ZERO,
+                held: Money::ZERO,
             },
         }
     }
 
-    fn calculate_total(&self) -> f32 {
-        self.available + self.held
+    pub fn from_balances(available: Money, held: Money) -> Self {
+        Funds { available, held }
+    }
+
+    pub fn available(&self) -> Money {
+        self.available
+    }
+
+    pub fn held(&self) -> Money {
+        self.held
+    }
+
+    // How far available went below zero on a credit line of `credit_limit`. Disputes can take
+    // available further below zero, that part isn't credit
+    pub fn credit_used(&self, credit_limit: Money) -> Money {
+        Money::from_minor_units(self.available.minor_units().saturating_neg().max(0))
+            .min(credit_limit)
+    }
+
+    // `update` never lets the total overflow
+    fn calculate_total(&self) -> Money {
+        self.available.saturating_add(self.held)
+    }
+
+    // Sets both balances at once, leaving them untouched if the total would overflow or held
+    // would go below zero. Only available may go negative, e.g. through a dispute or credit
+    fn update(&mut self, available: Money, held: Money) -> Result<(), CodedError> {
+        ensure_held(held)?;
+        available.checked_add(held)?;
+        self.available = available;
+        self.held = held;
+
+        Ok(())
+    }
+}
+
+// How balances are formatted in the output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    Fixed(usize),
+    // As many decimals as the most precise amount the client's transactions were written with
+    Preserve,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision::Fixed(4)
+    }
+}
+
+// Precision and separators of the balances in the output, e.g. `1.234,50` for German readers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberFormat {
+    pub precision: Precision,
+    pub decimal_separator: char,
+    // Separates every three integer digits when set
+    pub thousands_separator: Option<char>,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Precision::default().into()
+    }
+}
+
+impl From<Precision> for NumberFormat {
+    fn from(precision: Precision) -> Self {
+        NumberFormat {
+            precision,
+            decimal_separator: '.',
+            thousands_separator: None,
+        }
+    }
+}
+
+impl NumberFormat {
+    // `input_scale` is the decimals used by `Precision::Preserve`
+    pub fn format(&self, amount: Money, input_scale: u8) -> String {
+        let decimals = match self.precision {
+            Precision::Fixed(decimals) => decimals,
+            Precision::Preserve => input_scale as usize,
+        };
+        let formatted = format!("{:.*}", decimals, amount);
+        let (integer, fraction) = match formatted.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (formatted.as_str(), None),
+        };
+        let (sign, digits) = match integer.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", integer),
+        };
+
+        let mut output = sign.to_string();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index) % 3 == 0 {
+                if let Some(separator) = self.thousands_separator {
+                    output.push(separator);
+                }
+            }
+            output.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            output.push(self.decimal_separator);
+            output.push_str(fraction);
+        }
+        output
     }
 }
 
-pub type Transactions = HashMap<u32, Transaction>;
-pub type DisputedTransactions = HashSet<u32>;
+// Default hasher of the maps keyed by client or tx id, FxHash is much quicker than SipHash on
+// small integer keys. The engine and its clients take the hasher as a type parameter, so a
+// program reading ids from a source that could craft colliding ones can pick `RandomState`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IdHasher;
+
+impl BuildHasher for IdHasher {
+    type Hasher = rustc_hash::FxHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        rustc_hash::FxHasher::default()
+    }
+}
 
-#[derive(Debug)]
-pub struct Client {
+pub type Transactions<S = IdHasher> = HashMap<u32, Transaction, S>;
+pub type DisputedTransactions<S = IdHasher> = HashSet<u32, S>;
+// Amount refunded so far per deposit
+pub type Refunds<S = IdHasher> = HashMap<u32, Money, S>;
+// Funds of each named sub-account, the client's own funds are the rollup of all of them
+pub type SubAccounts = BTreeMap<String, Funds>;
+// Batch of each batched deposit and withdrawal
+pub type Batches<S = IdHasher> = HashMap<u32, u32, S>;
+// Amount held for disputes capped at the funds available when they came in
+pub type Holds<S = IdHasher> = HashMap<u32, Money, S>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "", deserialize = "S: BuildHasher + Default"))]
+pub struct Client<S = IdHasher> {
     funds: Funds,
-    transactions: Transactions,
-    disputed_transactions: DisputedTransactions,
+    #[serde(serialize_with = "sorted::map")]
+    transactions: Transactions<S>,
+    #[serde(serialize_with = "sorted::set")]
+    disputed_transactions: DisputedTransactions<S>,
     past_tx: u32,
     locked: bool,
+    #[serde(default)]
+    input_scale: u8,
+    #[serde(default, serialize_with = "sorted::map")]
+    refunds: Refunds<S>,
+    #[serde(default)]
+    accounts: SubAccounts,
+    #[serde(default)]
+    audit_trail: AuditTrail,
+    #[serde(default)]
+    frozen: bool,
+    // Set from the engine config whenever a transaction is applied
+    #[serde(default)]
+    credit_limit: Money,
+    // Period end of the last interest accrual
+    #[serde(default)]
+    interest_accrued_at: Option<DateTime<Utc>>,
+    #[serde(default, serialize_with = "sorted::map")]
+    unsettled: Batches<S>,
+    #[serde(default, serialize_with = "sorted::map")]
+    settled: Batches<S>,
+    #[serde(default, serialize_with = "sorted::map")]
+    capped_holds: Holds<S>,
+    #[serde(default)]
+    stats: TransactionStats,
+    #[serde(default)]
+    lifecycle: Lifecycle,
+    #[serde(skip)]
+    retained: Retained,
+    #[serde(default)]
+    tally: Tally,
+}
+
+// What `Client::retain` needs to only look at the transactions it may drop next
+#[derive(Debug, Default, Clone)]
+struct Retained {
+    // Stored tx ids, lowest first. Built from the transactions on first use, e.g. for clients
+    // from a snapshot
+    queue: Option<BTreeSet<u32>>,
+    // Highest tx id dropped for its age or position, the audit trail forgets changes up to it
+    dropped_up_to: u32,
+    // Length of the audit trail after it was last compacted
+    compacted: usize,
+}
+
+// Entries the audit trail grows by before it is first compacted
+const COMPACTION_SLACK: usize = 64;
+
+pub type Clients<S = IdHasher> = HashMap<ClientId, Client<S>, S>;
+
+// Transactions applied to a client, by type
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TransactionStats {
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub disputes: u64,
+    pub resolves: u64,
+    pub chargebacks: u64,
+}
+
+impl TransactionStats {
+    fn count(&mut self, tx_type: &TransactionType) {
+        match tx_type {
+            TransactionType::Deposit => self.deposits += 1,
+            TransactionType::Withdrawal => self.withdrawals += 1,
+            TransactionType::Dispute => self.disputes += 1,
+            TransactionType::Resolve => self.resolves += 1,
+            TransactionType::Chargeback => self.chargebacks += 1,
+            _ => {}
+        }
+    }
+
+    pub fn record(&self) -> Vec<String> {
+        [
+            self.deposits,
+            self.withdrawals,
+            self.disputes,
+            self.resolves,
+            self.chargebacks,
+        ]
+        .map(|count| count.to_string())
+        .to_vec()
+    }
+}
+
+// An applied transaction and its timestamp, if it had one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Activity {
+    pub tx_id: u32,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+// When a client was first seen, last had a transaction applied and had its account locked.
+// Clients from a store or an older snapshot start without any
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Lifecycle {
+    pub first_seen: Option<Activity>,
+    pub last_activity: Option<Activity>,
+    // Cleared again when the account is unlocked
+    pub locked_at: Option<Activity>,
+}
+
+impl Lifecycle {
+    // The tx id and RFC 3339 timestamp of each, empty when unknown
+    pub fn record(&self) -> Vec<String> {
+        [self.first_seen, self.last_activity, self.locked_at]
+            .iter()
+            .flat_map(|activity| {
+                [
+                    activity.map(|activity| activity.tx_id.to_string()),
+                    activity
+                        .and_then(|activity| activity.timestamp)
+                        .map(|timestamp| timestamp.to_rfc3339()),
+                ]
+            })
+            .map(Option::unwrap_or_default)
+            .collect()
+    }
 }
 
-pub type Clients = HashMap<u16, Client>;
+// A client as encoded before it had stats, read when upgrading older snapshots
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ClientV1(
+    Funds,
+    Transactions,
+    DisputedTransactions,
+    u32,
+    bool,
+    u8,
+    Refunds,
+    SubAccounts,
+    AuditTrail,
+    bool,
+    Money,
+    Option<DateTime<Utc>>,
+    Batches,
+    Batches,
+    Holds,
+);
+
+// Bincode writes a struct as its fields in order, so a client with stats is the old client
+// followed by them. Clients upgraded this way count from zero
+pub(crate) fn add_stats(clients: Vec<(u16, ClientV1)>) -> Vec<(u16, (ClientV1, TransactionStats))> {
+    clients
+        .into_iter()
+        .map(|(client_id, client)| (client_id, (client, TransactionStats::default())))
+        .collect()
+}
+
+// A client as encoded before it had a lifecycle
+pub(crate) type ClientV2 = (ClientV1, TransactionStats);
+
+pub(crate) fn add_lifecycle(clients: Vec<(u16, ClientV2)>) -> Vec<(u16, (ClientV2, Lifecycle))> {
+    clients
+        .into_iter()
+        .map(|(client_id, client)| (client_id, (client, Lifecycle::default())))
+        .collect()
+}
+
+// A client as encoded before it had a tally. Skipped fields aren't encoded, so the tally
+// directly follows the lifecycle
+pub(crate) type ClientV3 = (ClientV2, Lifecycle);
+
+// Clients upgraded this way start from an empty tally
+pub(crate) fn add_tally(clients: Vec<(u16, ClientV3)>) -> Vec<(u16, (ClientV3, Tally))> {
+    clients
+        .into_iter()
+        .map(|(client_id, client)| (client_id, (client, Tally::default())))
+        .collect()
+}
+
+impl<S: BuildHasher + Default> Client<S> {
+    // Opens an account with its first transaction, rejecting a deposit or withdrawal without
+    // an amount. A new account has nothing to withdraw, so only a batched withdrawal, debited
+    // once its batch settles, can open one
+    pub fn open(tx_id: TxId, tx: Transaction) -> Result<Self, CodedError> {
+        if let TransactionType::Deposit | TransactionType::Withdrawal = tx.tx_type() {
+            let amount = amount_of(tx_id.value(), &tx)?;
+            if tx.tx_type() == &TransactionType::Withdrawal && tx.batch().is_none() {
+                return Err(CodedError::new(
+                    RejectionCode::InsufficientFunds,
+                    format!("Insufficient funds to withdraw {}", amount),
+                ));
+            }
+        }
+        Ok(Client::new(tx_id, tx))
+    }
+
+    pub fn new(tx_id: TxId, tx: Transaction) -> Self {
+        let tx_id = tx_id.value();
+        // Batched transactions wait for their settlement
+        let unsettled = match tx.batch() {
+            Some(batch) => Batches::from_iter([(tx_id, batch)]),
+            None => Batches::default(),
+        };
+        let funds = match tx.batch() {
+            Some(_) => Funds::default(),
+            None => Funds::new(&tx),
+        };
+        let audit_trail = match tx.tx_type() {
+            TransactionType::Deposit if unsettled.is_empty() => vec![AuditEntry {
+                tx_id,
+                operation: TransactionType::Deposit,
+                available_change: funds.available,
+                held_change: Money::ZERO,
+                available: funds.available,
+                held: funds.held,
+                timestamp: tx.timestamp(),
+                memo: tx.memo().map(str::to_string),
+                owner: None,
+            }],
+            _ => AuditTrail::new(),
+        };
+        let accounts = match tx.account() {
+            Some(account) => SubAccounts::from([(account.to_string(), funds)]),
+            None => SubAccounts::new(),
+        };
+        let mut stats = TransactionStats::default();
+        stats.count(tx.tx_type());
+        let mut tally = Tally::default();
+        if let TransactionType::Deposit | TransactionType::Withdrawal = tx.tx_type() {
+            tally.record(&Row::of(&tx), None, None, &EngineConfig::default());
+        }
+        let seen = Activity {
+            tx_id,
+            timestamp: tx.timestamp(),
+        };
 
-impl Client {
-    pub fn new(tx_id: u32, tx: Transaction) -> Self {
         Client {
-            funds: Funds::new(&tx),
-            transactions: Transactions::from([(tx_id, tx)]),
-            disputed_transactions: DisputedTransactions::new(),
+            funds,
+            input_scale: tx.amount_scale(),
+            transactions: Transactions::from_iter([(tx_id, tx)]),
+            disputed_transactions: DisputedTransactions::default(),
             past_tx: tx_id,
             locked: false,
+            refunds: Refunds::default(),
+            accounts,
+            audit_trail,
+            frozen: false,
+            credit_limit: Money::ZERO,
+            interest_accrued_at: None,
+            unsettled,
+            settled: Batches::default(),
+            capped_holds: Holds::default(),
+            stats,
+            lifecycle: Lifecycle {
+                first_seen: Some(seen),
+                last_activity: Some(seen),
+                locked_at: None,
+            },
+            retained: Retained::default(),
+            tally,
+        }
+    }
+
+    // Rebuilds a client from state kept outside the engine, e.g. in a store
+    pub fn from_parts(
+        funds: Funds,
+        transactions: Transactions<S>,
+        disputed_transactions: DisputedTransactions<S>,
+        past_tx: u32,
+        locked: bool,
+    ) -> Self {
+        Client {
+            funds,
+            input_scale: transactions
+                .values()
+                .map(Transaction::amount_scale)
+                .max()
+                .unwrap_or(0),
+            transactions,
+            disputed_transactions,
+            past_tx,
+            locked,
+            refunds: Refunds::default(),
+            accounts: SubAccounts::new(),
+            audit_trail: AuditTrail::new(),
+            frozen: false,
+            credit_limit: Money::ZERO,
+            interest_accrued_at: None,
+            unsettled: Batches::default(),
+            settled: Batches::default(),
+            capped_holds: Holds::default(),
+            stats: TransactionStats::default(),
+            lifecycle: Lifecycle::default(),
+            retained: Retained::default(),
+            tally: Tally::default(),
+        }
+    }
+
+    pub fn with_frozen(mut self, frozen: bool) -> Self {
+        self.frozen = frozen;
+        self
+    }
+
+    pub fn with_refunds(mut self, refunds: Refunds<S>) -> Self {
+        self.refunds = refunds;
+        self
+    }
+
+    pub fn with_capped_holds(mut self, capped_holds: Holds<S>) -> Self {
+        self.capped_holds = capped_holds;
+        self
+    }
+
+    pub fn transactions(&self) -> &Transactions<S> {
+        &self.transactions
+    }
+
+    pub fn disputed_transactions(&self) -> &DisputedTransactions<S> {
+        &self.disputed_transactions
+    }
+
+    pub fn refunds(&self) -> &Refunds<S> {
+        &self.refunds
+    }
+
+    // Open disputes holding less than their amount, see `DisputeOverdraftPolicy::Cap`
+    pub fn capped_holds(&self) -> &Holds<S> {
+        &self.capped_holds
+    }
+
+    pub fn stats(&self) -> &TransactionStats {
+        &self.stats
+    }
+
+    pub fn lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    // What the rows applied to the client add up to, see `conservation`
+    pub fn tally(&self) -> &Tally {
+        &self.tally
+    }
+
+    // Only rows with an account column create sub-accounts, rows without one only change the rollup
+    pub fn accounts(&self) -> &SubAccounts {
+        &self.accounts
+    }
+
+    // Batched deposits and withdrawals still waiting for their batch to settle, with their batch
+    pub fn unsettled_transactions(&self) -> &Batches<S> {
+        &self.unsettled
+    }
+
+    // Batched deposits and withdrawals whose batch has settled, with their batch
+    pub fn settled_transactions(&self) -> &Batches<S> {
+        &self.settled
+    }
+
+    pub fn past_tx(&self) -> u32 {
+        self.past_tx
+    }
+
+    // Moves a transaction back in from an external history so it can be disputed
+    pub(crate) fn restore_transaction(&mut self, tx_id: u32, tx: Transaction) {
+        self.transactions.insert(tx_id, tx);
+        if let Some(queue) = &mut self.retained.queue {
+            queue.insert(tx_id);
+        }
+    }
+
+    // Drops a transaction from memory once it is safely kept in an external history.
+    // Disputed and unsettled transactions stay, their resolution or settlement still needs them.
+    pub(crate) fn evict_transaction(&mut self, tx_id: u32) -> Option<Transaction> {
+        if self.disputed_transactions.contains(&tx_id) || self.unsettled.contains_key(&tx_id) {
+            return None;
+        }
+        if let Some(queue) = &mut self.retained.queue {
+            queue.remove(&tx_id);
         }
+        self.transactions.remove(&tx_id)
+    }
+
+    // Drops the transactions the retention policy no longer keeps once `tx_id` was applied,
+    // `now` being its timestamp, and returns their ids. Each time the audit trail doubled it
+    // forgets the changes made under them. Nothing is dropped without the order check, a
+    // dropped id could then be applied again
+    pub(crate) fn retain(
+        &mut self,
+        config: &EngineConfig,
+        tx_id: u32,
+        now: Option<DateTime<Utc>>,
+    ) -> Vec<u32> {
+        if config.retention == RetentionPolicy::KeepAll || config.ordering == OrderingPolicy::Ignore
+        {
+            return Vec::new();
+        }
+
+        let (transactions, disputed, unsettled) = (
+            &self.transactions,
+            &self.disputed_transactions,
+            &self.unsettled,
+        );
+        let queue = self
+            .retained
+            .queue
+            .get_or_insert_with(|| transactions.keys().copied().collect());
+        if transactions.contains_key(&tx_id) {
+            queue.insert(tx_id);
+        }
+        let keep = |tx_id: &u32| disputed.contains(tx_id) || unsettled.contains_key(tx_id);
+
+        // Ids are dropped lowest first, those at the front are dropped for their age
+        let mut aged = Vec::new();
+        let mut dropped = Vec::new();
+        match config.retention {
+            RetentionPolicy::KeepAll => {}
+            RetentionPolicy::KeepLast(count) => {
+                let excess = queue
+                    .len()
+                    .saturating_sub(count + disputed.len() + unsettled.len());
+                aged.extend(queue.iter().filter(|tx_id| !keep(tx_id)).take(excess));
+            }
+            RetentionPolicy::DepositsWithin(seconds) => {
+                let cutoff = now.map(|now| now - chrono::Duration::seconds(seconds));
+                let is_deposit = |tx: &Transaction| tx.tx_type() == &TransactionType::Deposit;
+                // Anything but a deposit goes as soon as it's applied
+                if transactions.get(&tx_id).is_some_and(|tx| !is_deposit(tx)) && !keep(&tx_id) {
+                    dropped.push(tx_id);
+                }
+                let mut forgotten = Vec::new();
+                for queued in queue.iter() {
+                    if keep(queued) || dropped.contains(queued) {
+                        continue;
+                    }
+                    match transactions
+                        .get(queued)
+                        .map(|tx| (is_deposit(tx), tx.timestamp()))
+                    {
+                        Some((false, _)) => aged.push(*queued),
+                        Some((true, Some(at))) if cutoff.is_some_and(|cutoff| at < cutoff) => {
+                            aged.push(*queued)
+                        }
+                        Some((true, Some(_))) => break,
+                        // Deposits without a timestamp never age, they only leave the queue
+                        Some((true, None)) | None => forgotten.push(*queued),
+                    }
+                }
+                for queued in forgotten {
+                    queue.remove(&queued);
+                }
+            }
+        }
+
+        self.retained.dropped_up_to = aged
+            .iter()
+            .copied()
+            .fold(self.retained.dropped_up_to, u32::max);
+        dropped.extend(aged);
+        for tx_id in &dropped {
+            self.transactions.remove(tx_id);
+            self.refunds.remove(tx_id);
+            self.settled.remove(tx_id);
+            self.tally.forget(*tx_id);
+            if let Some(queue) = &mut self.retained.queue {
+                queue.remove(tx_id);
+            }
+        }
+
+        if self.audit_trail.len() > 2 * self.retained.compacted + COMPACTION_SLACK {
+            let (up_to, transactions) = (self.retained.dropped_up_to, &self.transactions);
+            self.audit_trail
+                .retain(|entry| entry.tx_id > up_to || transactions.contains_key(&entry.tx_id));
+            self.retained.compacted = self.audit_trail.len();
+        }
+
+        dropped
     }
 
     pub fn funds(&self) -> &Funds {
         &self.funds
     }
 
+    // Locked by a chargeback
     pub fn is_locked(&self) -> bool {
         self.locked
     }
 
-    pub fn handle_transaction(&mut self, tx: Transaction) -> Result<(), String> {
-        if self.is_locked() {
-            return Err(format!("Account locked, ignoring {}", tx.tx_id()));
+    // How far below zero available may go, from the engine config the last transaction was
+    // applied with
+    pub fn credit_limit(&self) -> Money {
+        self.credit_limit
+    }
+
+    // Frozen by an operator
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    // Marks the audit entries from `from` on as made by the joint owner `owner`
+    pub(crate) fn attribute_to(&mut self, from: usize, owner: ClientId) {
+        for entry in self.audit_trail.iter_mut().skip(from) {
+            entry.owner = Some(owner);
+        }
+    }
+
+    // Every change made to the funds, oldest first. Clients rebuilt from a store start with an empty trail
+    pub fn audit_trail(&self) -> &[AuditEntry] {
+        &self.audit_trail
+    }
+
+    // Balances right after the latest change made under `tx_id`, disputes, resolves and
+    // chargebacks share the id of the transaction they reference
+    pub fn balance_after(&self, tx_id: TxId) -> Option<Funds> {
+        self.audit_trail
+            .iter()
+            .rfind(|entry| entry.tx_id == tx_id.value())
+            .map(|entry| Funds::from_balances(entry.available, entry.held))
+    }
+
+    // Balances as they stood at `at`, replayed from the audit trail. Changes without a
+    // timestamp count as made at the time of the change before them. None without an audit
+    // trail to replay
+    pub fn balance_at(&self, at: DateTime<Utc>) -> Option<Funds> {
+        let first = self.audit_trail.first()?;
+        let last = self
+            .audit_trail
+            .iter()
+            .take_while(|entry| entry.timestamp.is_none_or(|timestamp| timestamp <= at))
+            .last();
+
+        match last {
+            Some(entry) => Some(Funds::from_balances(entry.available, entry.held)),
+            // Before the first change, which may not be the first ever for clients from a store
+            None => Some(Funds::from_balances(
+                first.available.checked_sub(first.available_change).ok()?,
+                first.held.checked_sub(first.held_change).ok()?,
+            )),
+        }
+    }
+
+    pub fn handle_transaction(&mut self, tx: Transaction) -> Result<(), CodedError> {
+        self.handle_transaction_with_config(tx, &EngineConfig::default())
+    }
+
+    // The transaction's timestamp and memo are copied onto the audit entry it produces,
+    // the memo is also appended to any error
+    pub fn handle_transaction_with_config(
+        &mut self,
+        tx: Transaction,
+        config: &EngineConfig,
+    ) -> Result<(), CodedError> {
+        let memo = tx.memo().map(str::to_string);
+        let timestamp = tx.timestamp();
+        let tx_type = tx.tx_type().clone();
+        let activity = Activity {
+            tx_id: tx.raw_tx_id(),
+            timestamp,
+        };
+        let entries = self.audit_trail.len();
+        let was_locked = self.locked;
+        let row = Row::of(&tx);
+
+        match self.apply_transaction(tx, config) {
+            Ok(()) => {
+                let tx_id = activity.tx_id;
+                self.tally.record(
+                    &row,
+                    self.transactions.get(&tx_id),
+                    self.capped_holds.get(&tx_id).copied(),
+                    config,
+                );
+                self.stats.count(&tx_type);
+                self.lifecycle.last_activity = Some(activity);
+                match (was_locked, self.locked) {
+                    (false, true) => self.lifecycle.locked_at = Some(activity),
+                    (true, false) => self.lifecycle.locked_at = None,
+                    _ => {}
+                }
+                if let Some(entry) = self.audit_trail.get_mut(entries) {
+                    entry.timestamp = timestamp;
+                    entry.memo = memo;
+                }
+                Ok(())
+            }
+            Err(error) => match memo {
+                Some(memo) => Err(error.with_suffix(&format!(" (memo: {})", memo))),
+                None => Err(error),
+            },
+        }
+    }
+
+    fn apply_transaction(
+        &mut self,
+        tx: Transaction,
+        config: &EngineConfig,
+    ) -> Result<(), CodedError> {
+        let unlocking = config.auto_unlock && tx.tx_type() == &TransactionType::Resolve;
+        if self.is_locked() && !unlocking && !config.locked_allows.contains(tx.tx_type()) {
+            return Err(CodedError::new(
+                RejectionCode::AccountLocked,
+                format!("Account locked, ignoring {}", tx.raw_tx_id()),
+            ));
+        }
+        if self.is_frozen() && !config.frozen_allows.contains(tx.tx_type()) {
+            return Err(CodedError::new(
+                RejectionCode::AccountFrozen,
+                format!("Account frozen, ignoring {}", tx.raw_tx_id()),
+            ));
+        }
+
+        self.credit_limit = config
+            .credit_limits
+            .get(&tx.client_id())
+            .copied()
+            .unwrap_or_default();
+
+        if let TransactionType::Deposit
+        | TransactionType::Withdrawal
+        | TransactionType::Settlement = tx.tx_type()
+        {
+            self.ensure_future_tx(tx.raw_tx_id(), config.ordering)?;
         }
 
         match tx.tx_type() {
-            TransactionType::Deposit => self.deposit_amount(tx.tx_id(), tx),
-            TransactionType::Withdrawal => self.withdraw_amount(tx.tx_id(), tx),
-            TransactionType::Dispute => self.dispute_transaction(tx.tx_id()),
-            TransactionType::Resolve => self.resolve_transaction(tx.tx_id()),
-            TransactionType::Chargeback => self.chargeback_transaction(tx.tx_id()),
+            TransactionType::Deposit => self.deposit_amount(tx.raw_tx_id(), tx),
+            TransactionType::Withdrawal => {
+                let tier = config.tiers.get(&tx.client_id());
+                self.withdraw_amount(tx.raw_tx_id(), tx, tier)
+            }
+            TransactionType::Dispute => self.dispute_transaction(tx.raw_tx_id(), config),
+            TransactionType::Resolve => {
+                self.resolve_transaction(tx.raw_tx_id(), config.resolve)?;
+                if config.auto_unlock && self.disputed_transactions.is_empty() {
+                    self.locked = false;
+                }
+                Ok(())
+            }
+            TransactionType::Chargeback => self.chargeback_transaction(tx.raw_tx_id()),
+            TransactionType::Refund => self.refund_transaction(tx.raw_tx_id(), &tx),
+            TransactionType::Settlement => self.settle_batch(tx.raw_tx_id(), &tx),
+            TransactionType::Interest => Err(CodedError::new(
+                RejectionCode::InterestNotAccepted,
+                format!(
+                    "Tx {} is interest, which is only accrued by the engine",
+                    tx.raw_tx_id()
+                ),
+            )),
         }
     }
 
-    pub fn get_record(&self, client_id: u16) -> Vec<String> {
+    pub fn get_record(&self, client_id: ClientId) -> Vec<String> {
+        self.get_record_with_format(client_id, NumberFormat::default())
+    }
+
+    pub fn get_record_with_format(&self, client_id: ClientId, format: NumberFormat) -> Vec<String> {
+        let mut record = vec![client_id.to_string()];
+        record.extend(self.funds_record(&self.funds, format));
+        record
+    }
+
+    // One record per sub-account with the account name after the client id, followed by the
+    // client-level rollup with an empty account
+    pub fn get_account_records(
+        &self,
+        client_id: ClientId,
+        format: NumberFormat,
+    ) -> Vec<Vec<String>> {
+        self.accounts
+            .iter()
+            .map(|(account, funds)| (account.as_str(), funds))
+            .chain([("", &self.funds)])
+            .map(|(account, funds)| {
+                let mut record = vec![client_id.to_string(), account.to_string()];
+                record.extend(self.funds_record(funds, format));
+                record
+            })
+            .collect()
+    }
+
+    fn funds_record(&self, funds: &Funds, format: NumberFormat) -> Vec<String> {
         vec![
-            client_id.to_string(),
-            format!("{:.4}", self.funds.available),
-            format!("{:.4}", self.funds.held),
-            format!("{:.4}", self.funds.calculate_total()),
+            format.format(funds.available, self.input_scale),
+            format.format(funds.held, self.input_scale),
+            format.format(funds.calculate_total(), self.input_scale),
             self.locked.to_string(),
+            self.frozen.to_string(),
+            format.format(funds.credit_used(self.credit_limit), self.input_scale),
         ]
     }
 
     fn add_tx(&mut self, tx_id: u32, tx: Transaction) {
+        self.input_scale = self.input_scale.max(tx.amount_scale());
         self.transactions.insert(tx_id, tx);
-        self.past_tx = tx_id;
+        self.past_tx = self.past_tx.max(tx_id);
     }
 
     // Transaction IDs (tx) are globally unique, though are also not guaranteed to be ordered.
-    // Ensure txs arrive in chronological order per client
-    fn ensure_future_tx(&self, tx_id: u32) -> Result<(), String> {
-        if self.past_tx < tx_id {
-            Ok(())
-        } else {
-            Err(format!("Tx {} is in the past!", tx_id))
+    // Ensure txs arrive in chronological order per client, unless `ordering` says otherwise
+    fn ensure_future_tx(&self, tx_id: u32, ordering: OrderingPolicy) -> Result<(), CodedError> {
+        match ordering {
+            OrderingPolicy::Enforce if self.past_tx >= tx_id => Err(CodedError::new(
+                RejectionCode::OutOfOrder,
+                format!("Tx {} is in the past!", tx_id),
+            )),
+            OrderingPolicy::Ignore if self.transactions.contains_key(&tx_id) => {
+                Err(CodedError::new(
+                    RejectionCode::DuplicateTransaction,
+                    format!("Tx {} was already applied", tx_id),
+                ))
+            }
+            _ => Ok(()),
         }
     }
 
@@ -102,132 +892,932 @@ impl Client {
         self.disputed_transactions.contains(&tx_id) == should_be_disputed
     }
 
-    fn get_tx(&self, tx_id: u32) -> Result<&Transaction, String> {
+    fn get_tx(&self, tx_id: u32) -> Result<&Transaction, CodedError> {
         match self.transactions.get(&tx_id) {
             Some(tx) => Ok(tx),
-            None => Err(format!("Tx {} does not exist for client", tx_id)),
+            None => Err(CodedError::new(
+                RejectionCode::UnknownTransaction,
+                format!("Tx {} does not exist for client", tx_id),
+            )),
         }
     }
 
-    fn tx_is_not_disputed(&self, tx_id: u32) -> Result<(), String> {
+    // Disputes and refunds need the transaction to have moved funds
+    fn tx_is_settled(&self, tx_id: u32) -> Result<(), CodedError> {
+        match self.unsettled.get(&tx_id) {
+            Some(batch) => Err(CodedError::new(
+                RejectionCode::Unsettled,
+                format!("Tx {} is waiting for batch {} to settle", tx_id, batch),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn tx_is_not_disputed(&self, tx_id: u32) -> Result<(), CodedError> {
         if self.should_tx_be_disputed(tx_id, false) {
             Ok(())
         } else {
-            Err(format!(
-                "Tx {} should not have been disputed already",
-                tx_id
+            Err(CodedError::new(
+                RejectionCode::AlreadyDisputed,
+                format!("Tx {} should not have been disputed already", tx_id),
             ))
         }
     }
 
-    fn tx_is_disputed(&self, tx_id: u32) -> Result<(), String> {
+    fn tx_is_disputed(&self, tx_id: u32) -> Result<(), CodedError> {
         if self.should_tx_be_disputed(tx_id, true) {
             Ok(())
         } else {
-            Err(format!("Tx {} should have been disputed already", tx_id))
+            Err(CodedError::new(
+                RejectionCode::NotDisputed,
+                format!("Tx {} should have been disputed already", tx_id),
+            ))
         }
     }
 
-    fn can_withdraw(&self, withdrawal_amount: f32) -> bool {
-        self.funds.available >= withdrawal_amount
+    // Withdrawals from a sub-account are limited to what that account has available, the
+    // client's own funds may go as far below zero as its credit limit
+    fn can_withdraw(&self, withdrawal_amount: Money, account: Option<&str>) -> bool {
+        match account {
+            Some(account) => self
+                .accounts
+                .get(account)
+                .is_some_and(|funds| funds.available >= withdrawal_amount),
+            None => self.funds.available.saturating_add(self.credit_limit) >= withdrawal_amount,
+        }
+    }
+
+    // Available funds of `account`, or of the whole client without one
+    fn available_in(&self, account: Option<&str>) -> Money {
+        match account {
+            Some(account) => self
+                .accounts
+                .get(account)
+                .map(|funds| funds.available)
+                .unwrap_or_default(),
+            None => self.funds.available,
+        }
     }
 
-    fn deposit_amount(&mut self, tx_id: u32, tx: Transaction) -> Result<(), String> {
-        self.ensure_future_tx(tx_id)?;
+    // Sub-account of the transaction `tx_id`, disputes and refunds move funds in that account
+    fn account_of(&self, tx_id: u32) -> Option<String> {
+        self.transactions
+            .get(&tx_id)
+            .and_then(|tx| tx.account().map(str::to_string))
+    }
+
+    // Credits `rate` times a positive available balance for the period ending `at`, recorded in
+    // the audit trail under the last tx id. A period is only accrued once, returns the interest
+    pub(crate) fn accrue_interest(
+        &mut self,
+        rate: f64,
+        at: DateTime<Utc>,
+    ) -> Result<Money, CodedError> {
+        if self
+            .interest_accrued_at
+            .is_some_and(|accrued| accrued >= at)
+            || self.funds.available <= Money::ZERO
+        {
+            return Ok(Money::ZERO);
+        }
+
+        let interest = Money::from_minor_units(
+            (self.funds.available.minor_units() as f64 * rate).round() as i64,
+        );
+        let available = self.funds.available.checked_add(interest)?;
+        self.update_funds(
+            self.past_tx,
+            TransactionType::Interest,
+            None,
+            available,
+            self.funds.held,
+        )?;
+        if let Some(entry) = self.audit_trail.last_mut() {
+            entry.timestamp = Some(at);
+        }
+        self.interest_accrued_at = Some(at);
+        self.tally.credit_interest(interest);
 
-        self.funds.available += tx.amount().unwrap();
+        Ok(interest)
+    }
+
+    // Records a batched deposit or withdrawal without moving funds yet
+    fn add_unsettled(&mut self, tx_id: u32, batch: u32, tx: Transaction) -> Result<(), CodedError> {
         self.add_tx(tx_id, tx);
+        self.unsettled.insert(tx_id, batch);
+
+        Ok(())
+    }
+
+    // Moves the deposits less the withdrawals of `settlement`'s batch in one audit entry
+    fn settle_batch(&mut self, tx_id: u32, settlement: &Transaction) -> Result<(), CodedError> {
+        let batch = settlement.batch().ok_or_else(|| {
+            CodedError::new(
+                RejectionCode::InvalidSettlement,
+                format!("Settlement {} has no batch", tx_id),
+            )
+        })?;
+        let batched: Vec<u32> = self
+            .unsettled
+            .iter()
+            .filter(|(_, tx_batch)| **tx_batch == batch)
+            .map(|(batched_tx, _)| *batched_tx)
+            .collect();
+        if batched.is_empty() {
+            return Err(CodedError::new(
+                RejectionCode::InvalidSettlement,
+                format!("No unsettled transactions in batch {}", batch),
+            ));
+        }
+
+        let mut net = Money::ZERO;
+        for batched_tx in &batched {
+            let tx = self.get_tx(*batched_tx)?;
+            let amount = tx.raw_amount().unwrap_or_default();
+            net = match tx.tx_type() {
+                TransactionType::Withdrawal => net.checked_sub(amount)?,
+                _ => net.checked_add(amount)?,
+            };
+        }
+        if net < Money::ZERO
+            && !self.can_withdraw(Money::ZERO.checked_sub(net)?, settlement.account())
+        {
+            return Err(CodedError::new(
+                RejectionCode::InsufficientFunds,
+                format!("Insufficient funds to settle batch {}", batch),
+            ));
+        }
+
+        let available = self.funds.available.checked_add(net)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Settlement,
+            settlement.account(),
+            available,
+            self.funds.held,
+        )?;
+        self.past_tx = self.past_tx.max(tx_id);
+        for batched_tx in batched {
+            self.unsettled.remove(&batched_tx);
+            self.settled.insert(batched_tx, batch);
+        }
 
         Ok(())
     }
 
-    fn withdraw_amount(&mut self, tx_id: u32, tx: Transaction) -> Result<(), String> {
-        self.ensure_future_tx(tx_id)?;
+    fn deposit_amount(&mut self, tx_id: u32, tx: Transaction) -> Result<(), CodedError> {
+        if let Some(batch) = tx.batch() {
+            return self.add_unsettled(tx_id, batch, tx);
+        }
+
+        let available = self.funds.available.checked_add(amount_of(tx_id, &tx)?)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Deposit,
+            tx.account(),
+            available,
+            self.funds.held,
+        )?;
+        self.add_tx(tx_id, tx);
+
+        Ok(())
+    }
 
-        let withdrawal_amount = tx.amount().unwrap();
+    // The tier's fee is only charged on withdrawals applied straight away, batched ones are
+    // settled as a whole
+    fn withdraw_amount(
+        &mut self,
+        tx_id: u32,
+        tx: Transaction,
+        tier: Option<&TierLimits>,
+    ) -> Result<(), CodedError> {
+        let withdrawal_amount = amount_of(tx_id, &tx)?;
+        if let Some((tier, limit)) = tier.and_then(|tier| Some((tier, tier.withdrawal_limit?))) {
+            if withdrawal_amount > limit {
+                return Err(CodedError::new(
+                    RejectionCode::WithdrawalLimitExceeded,
+                    format!(
+                        "Withdrawal of {} is over the {} limit of tier {}",
+                        withdrawal_amount, limit, tier.tier
+                    ),
+                ));
+            }
+        }
+        if let Some(batch) = tx.batch() {
+            return self.add_unsettled(tx_id, batch, tx);
+        }
 
-        if self.can_withdraw(withdrawal_amount) {
-            self.funds.available -= withdrawal_amount;
+        let fee = tier.map(|tier| tier.withdrawal_fee).unwrap_or_default();
+        let charged = withdrawal_amount.checked_add(fee)?;
+        if self.can_withdraw(charged, tx.account()) {
+            let available = self.funds.available.checked_sub(charged)?;
+            self.update_funds(
+                tx_id,
+                TransactionType::Withdrawal,
+                tx.account(),
+                available,
+                self.funds.held,
+            )?;
             self.add_tx(tx_id, tx);
 
             Ok(())
         } else {
-            Err(format!(
-                "Insufficient funds to withdraw {}",
-                withdrawal_amount
+            Err(CodedError::new(
+                RejectionCode::InsufficientFunds,
+                format!("Insufficient funds to withdraw {}", withdrawal_amount),
             ))
         }
     }
 
-    fn resolve_amount(&mut self, resolve_amount: f32) {
-        self.funds.held -= resolve_amount;
-        self.funds.available += resolve_amount;
+    // Applies new balances, makes the same change to the sub-account if there is one and records
+    // the change in the audit trail
+    fn update_funds(
+        &mut self,
+        tx_id: u32,
+        operation: TransactionType,
+        account: Option<&str>,
+        available: Money,
+        held: Money,
+    ) -> Result<(), CodedError> {
+        let available_change = available.checked_sub(self.funds.available)?;
+        let held_change = held.checked_sub(self.funds.held)?;
+
+        let account_funds = match account {
+            Some(account) => {
+                let funds = self.accounts.get(account).copied().unwrap_or_default();
+                let account_available = funds.available.checked_add(available_change)?;
+                let account_held = funds.held.checked_add(held_change)?;
+                ensure_held(account_held)?;
+                account_available.checked_add(account_held)?;
+                Some((
+                    account,
+                    Funds::from_balances(account_available, account_held),
+                ))
+            }
+            None => None,
+        };
+
+        self.funds.update(available, held)?;
+        if let Some((account, funds)) = account_funds {
+            self.accounts.insert(account.to_string(), funds);
+        }
+
+        self.audit_trail.push(AuditEntry {
+            tx_id,
+            operation,
+            available_change,
+            held_change,
+            available,
+            held,
+            timestamp: None,
+            memo: None,
+            owner: None,
+        });
+
+        Ok(())
+    }
+
+    fn resolve_amount(&mut self, tx_id: u32, resolve_amount: Money) -> Result<(), CodedError> {
+        let account = self.account_of(tx_id);
+        let available = self.funds.available.checked_add(resolve_amount)?;
+        let held = self.funds.held.checked_sub(resolve_amount)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Resolve,
+            account.as_deref(),
+            available,
+            held,
+        )
+    }
+
+    // The held funds go back to the payer and count as refunded, so what is left of the deposit
+    // is all a later refund or dispute can touch
+    fn refund_held_amount(&mut self, tx_id: u32, refund_amount: Money) -> Result<(), CodedError> {
+        let account = self.account_of(tx_id);
+        let held = self.funds.held.checked_sub(refund_amount)?;
+        let refunded = self.refunds.get(&tx_id).copied().unwrap_or_default();
+        let refunded = refunded.checked_add(refund_amount)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Refund,
+            account.as_deref(),
+            self.funds.available,
+            held,
+        )?;
+        self.refunds.insert(tx_id, refunded);
+
+        Ok(())
+    }
+
+    fn withhold_amount(&mut self, tx_id: u32, disputed_amount: Money) -> Result<(), CodedError> {
+        let account = self.account_of(tx_id);
+        let available = self.funds.available.checked_sub(disputed_amount)?;
+        let held = self.funds.held.checked_add(disputed_amount)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Dispute,
+            account.as_deref(),
+            available,
+            held,
+        )
+    }
+
+    fn chargeback_amount(
+        &mut self,
+        tx_id: u32,
+        chargeback_amount: Money,
+    ) -> Result<(), CodedError> {
+        let account = self.account_of(tx_id);
+        let held = self.funds.held.checked_sub(chargeback_amount)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Chargeback,
+            account.as_deref(),
+            self.funds.available,
+            held,
+        )
+    }
+
+    // A disputed withdrawal already left available, so its amount is only held on top of it
+    // rather than debited a second time
+    fn hold_withdrawal_amount(
+        &mut self,
+        tx_id: u32,
+        disputed_amount: Money,
+    ) -> Result<(), CodedError> {
+        let account = self.account_of(tx_id);
+        let held = self.funds.held.checked_add(disputed_amount)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Dispute,
+            account.as_deref(),
+            self.funds.available,
+            held,
+        )
+    }
+
+    // The withdrawal stands, the hold is dropped
+    fn release_withdrawal_amount(
+        &mut self,
+        tx_id: u32,
+        resolve_amount: Money,
+    ) -> Result<(), CodedError> {
+        let account = self.account_of(tx_id);
+        let held = self.funds.held.checked_sub(resolve_amount)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Resolve,
+            account.as_deref(),
+            self.funds.available,
+            held,
+        )
+    }
+
+    // The withdrawal is reversed, the withdrawn amount returns to available
+    fn reverse_withdrawal_amount(
+        &mut self,
+        tx_id: u32,
+        chargeback_amount: Money,
+    ) -> Result<(), CodedError> {
+        let account = self.account_of(tx_id);
+        let available = self.funds.available.checked_add(chargeback_amount)?;
+        let held = self.funds.held.checked_sub(chargeback_amount)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Chargeback,
+            account.as_deref(),
+            available,
+            held,
+        )
+    }
+
+    // A partially refunded deposit can only be disputed for what is left of it
+    fn unrefunded_amount(&self, tx: &Transaction) -> Result<Money, CodedError> {
+        let refunded = self
+            .refunds
+            .get(&tx.raw_tx_id())
+            .copied()
+            .unwrap_or_default();
+        amount_of(tx.raw_tx_id(), tx)?.checked_sub(refunded)
+    }
+
+    // What an open dispute of `tx` holds, less than its amount when it was capped
+    fn held_amount(&self, tx: &Transaction) -> Result<Money, CodedError> {
+        match self.capped_holds.get(&tx.raw_tx_id()) {
+            Some(held) => Ok(*held),
+            None => self.unrefunded_amount(tx),
+        }
     }
 
-    fn withhold_amount(&mut self, disputed_amount: f32) {
-        self.funds.available -= disputed_amount;
-        self.funds.held += disputed_amount;
+    // Part of a disputed deposit's `amount` to hold given the funds still available for it
+    fn deposit_hold(
+        &self,
+        tx_id: u32,
+        amount: Money,
+        policy: DisputeOverdraftPolicy,
+    ) -> Result<Money, CodedError> {
+        let available = self
+            .available_in(self.account_of(tx_id).as_deref())
+            .max(Money::ZERO);
+        if amount <= available {
+            return Ok(amount);
+        }
+
+        match policy {
+            DisputeOverdraftPolicy::Allow => Ok(amount),
+            DisputeOverdraftPolicy::Cap => Ok(available),
+            DisputeOverdraftPolicy::Reject => Err(CodedError::new(
+                RejectionCode::DisputeExceedsAvailable,
+                format!(
+                    "Dispute of tx {} for {} exceeds the {} available",
+                    tx_id, amount, available
+                ),
+            )),
+        }
     }
 
-    fn chargeback_amount(&mut self, chargeback_amount: f32) {
-        self.funds.held -= chargeback_amount;
+    fn refund_transaction(&mut self, tx_id: u32, refund: &Transaction) -> Result<(), CodedError> {
+        let refund_amount = refund.raw_amount().ok_or_else(|| {
+            CodedError::new(
+                RejectionCode::MissingAmount,
+                format!("Refund of tx {} has no amount", tx_id),
+            )
+        })?;
+        self.tx_is_settled(tx_id)?;
+        let tx = self.get_tx(tx_id)?;
+
+        if *tx.tx_type() != TransactionType::Deposit {
+            return Err(CodedError::new(
+                RejectionCode::NotRefundable,
+                format!(
+                    "Tx {} is a {}, only deposits can be refunded",
+                    tx_id,
+                    tx.tx_type()
+                ),
+            ));
+        }
+        if self.disputed_transactions.contains(&tx_id) {
+            return Err(CodedError::new(
+                RejectionCode::NotRefundable,
+                format!("Tx {} is disputed and cannot be refunded", tx_id),
+            ));
+        }
+
+        let refundable = self.unrefunded_amount(tx)?;
+        if refund_amount > refundable {
+            return Err(CodedError::new(
+                RejectionCode::RefundExceedsDeposit,
+                format!(
+                    "Refund of {} exceeds the {} left to refund on tx {}",
+                    refund_amount, refundable, tx_id
+                ),
+            ));
+        }
+        let account = tx.account().map(str::to_string);
+        if !self.can_withdraw(refund_amount, account.as_deref()) {
+            return Err(CodedError::new(
+                RejectionCode::InsufficientFunds,
+                format!("Insufficient funds to refund {}", refund_amount),
+            ));
+        }
+
+        let available = self.funds.available.checked_sub(refund_amount)?;
+        let refunded = self.refunds.get(&tx_id).copied().unwrap_or_default();
+        let refunded = refunded.checked_add(refund_amount)?;
+        self.update_funds(
+            tx_id,
+            TransactionType::Refund,
+            account.as_deref(),
+            available,
+            self.funds.held,
+        )?;
+        self.refunds.insert(tx_id, refunded);
+
+        Ok(())
     }
 
-    fn dispute_transaction(&mut self, tx_id: u32) -> Result<(), String> {
+    fn dispute_transaction(&mut self, tx_id: u32, config: &EngineConfig) -> Result<(), CodedError> {
         self.tx_is_not_disputed(tx_id)?;
+        self.tx_is_settled(tx_id)?;
         let tx = self.get_tx(tx_id)?;
 
-        self.withhold_amount(tx.amount().unwrap());
+        if !config.disputable_types.contains(tx.tx_type()) {
+            return Err(CodedError::new(
+                RejectionCode::NotDisputable,
+                format!(
+                    "Tx {} is a {}, which is not allowed to be disputed",
+                    tx_id,
+                    tx.tx_type()
+                ),
+            ));
+        }
+
+        let amount = self.unrefunded_amount(tx)?;
+        match tx.tx_type() {
+            TransactionType::Withdrawal => self.hold_withdrawal_amount(tx_id, amount)?,
+            _ => {
+                let held = self.deposit_hold(tx_id, amount, config.dispute_overdraft)?;
+                self.withhold_amount(tx_id, held)?;
+                if held < amount {
+                    self.capped_holds.insert(tx_id, held);
+                }
+            }
+        }
         self.disputed_transactions.insert(tx_id);
 
         Ok(())
     }
 
-    fn resolve_transaction(&mut self, tx_id: u32) -> Result<(), String> {
+    fn resolve_transaction(&mut self, tx_id: u32, policy: ResolvePolicy) -> Result<(), CodedError> {
         self.tx_is_disputed(tx_id)?;
         let tx = self.get_tx(tx_id)?;
 
-        self.resolve_amount(tx.amount().unwrap());
+        let amount = self.held_amount(tx)?;
+        match (tx.tx_type(), policy) {
+            (TransactionType::Withdrawal, _) => self.release_withdrawal_amount(tx_id, amount)?,
+            (_, ResolvePolicy::Release) => self.resolve_amount(tx_id, amount)?,
+            (_, ResolvePolicy::RefundToSource) => self.refund_held_amount(tx_id, amount)?,
+        }
         self.disputed_transactions.remove(&tx_id);
+        self.capped_holds.remove(&tx_id);
 
         Ok(())
     }
 
-    fn chargeback_transaction(&mut self, tx_id: u32) -> Result<(), String> {
+    fn chargeback_transaction(&mut self, tx_id: u32) -> Result<(), CodedError> {
         self.tx_is_disputed(tx_id)?;
         let tx = self.get_tx(tx_id)?;
 
-        self.chargeback_amount(tx.amount().unwrap());
+        let amount = self.held_amount(tx)?;
+        match tx.tx_type() {
+            TransactionType::Withdrawal => self.reverse_withdrawal_amount(tx_id, amount)?,
+            _ => self.chargeback_amount(tx_id, amount)?,
+        }
         self.locked = true;
         self.disputed_transactions.remove(&tx_id);
+        self.capped_holds.remove(&tx_id);
 
         Ok(())
     }
 }
 
+fn amount_of(tx_id: u32, tx: &Transaction) -> Result<Money, CodedError> {
+    tx.raw_amount().ok_or_else(|| {
+        CodedError::new(
+            RejectionCode::MissingAmount,
+            format!("Tx {} is a {} without an amount", tx_id, tx.tx_type()),
+        )
+    })
+}
+
+// Holds only ever release what was held before, a negative one means state went wrong, e.g. a
+// client rebuilt from a store holding less than its open disputes
+fn ensure_held(held: Money) -> Result<(), CodedError> {
+    if held < Money::ZERO {
+        return Err(CodedError::new(
+            RejectionCode::HeldBelowZero,
+            format!("Held funds would go below zero to {}", held),
+        ));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::amount::Amount;
+    use chrono::TimeZone;
+
+    fn money(text: &str) -> Money {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn never_holds_below_zero() {
+        let deposit = Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0));
+        // The store lost the hold of the open dispute
+        let mut client: Client = Client::from_parts(
+            Funds::from_balances(money("2"), Money::ZERO),
+            Transactions::from_iter([(1, deposit)]),
+            DisputedTransactions::from_iter([1]),
+            1,
+            false,
+        );
+
+        assert!(client
+            .handle_transaction(Transaction::raw(TransactionType::Chargeback, 1, 1, None))
+            .is_err());
+        assert!(client
+            .handle_transaction(Transaction::raw(TransactionType::Resolve, 1, 1, None))
+            .is_err());
+        assert_eq!(client.funds().held(), Money::ZERO);
+        assert_eq!(client.funds().available(), money("2"));
+        assert!(!client.is_locked());
+    }
+
+    #[test]
+    fn can_ignore_tx_order() {
+        let config = EngineConfig {
+            ordering: OrderingPolicy::Ignore,
+            ..Default::default()
+        };
+        let mut client: Client = Client::new(
+            TxId::new(5),
+            Transaction::raw(TransactionType::Deposit, 5, 1, Some(2.0)),
+        );
+
+        client
+            .handle_transaction_with_config(
+                Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(1.0)),
+                &config,
+            )
+            .unwrap();
+        assert!(client
+            .handle_transaction_with_config(
+                Transaction::raw(TransactionType::Deposit, 5, 1, Some(1.0)),
+                &config,
+            )
+            .is_err());
+        assert!(client
+            .handle_transaction(Transaction::raw(TransactionType::Deposit, 4, 1, Some(1.0)))
+            .is_err());
+
+        assert_eq!(client.funds().available(), money("1"));
+        assert_eq!(client.past_tx(), 5);
+    }
+
+    #[test]
+    fn counts_applied_transactions() {
+        let mut client: Client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+        );
+        for tx in [
+            Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(9.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Chargeback, 1, 1, None),
+        ] {
+            let _ = client.handle_transaction(tx);
+        }
+
+        assert_eq!(
+            client.stats(),
+            &TransactionStats {
+                deposits: 1,
+                withdrawals: 1,
+                disputes: 1,
+                resolves: 0,
+                chargebacks: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn records_lifecycle() {
+        let at = |hour: u32| Utc.with_ymd_and_hms(2024, 1, 1, hour, 0, 0).unwrap();
+        let mut client: Client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)).with_timestamp(at(9)),
+        );
+        for tx in [
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Chargeback, 1, 1, None).with_timestamp(at(11)),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)).with_timestamp(at(12)),
+        ] {
+            let _ = client.handle_transaction(tx);
+        }
+
+        assert_eq!(
+            client.lifecycle().record(),
+            [
+                "1",
+                "2024-01-01T09:00:00+00:00",
+                "1",
+                "2024-01-01T11:00:00+00:00",
+                "1",
+                "2024-01-01T11:00:00+00:00",
+            ]
+        );
+        assert_eq!(Lifecycle::default().record(), [""; 6]);
+    }
+
+    #[test]
+    fn dispute_overdraft_policies() {
+        let withdrawn = || {
+            let mut client = Client::new(
+                TxId::new(1),
+                Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+            );
+            client
+                .handle_transaction(Transaction::raw(
+                    TransactionType::Withdrawal,
+                    2,
+                    1,
+                    Some(3.0),
+                ))
+                .unwrap();
+            client
+        };
+        let dispute = |client: &mut Client, policy| {
+            let config = EngineConfig {
+                dispute_overdraft: policy,
+                ..Default::default()
+            };
+            client.handle_transaction_with_config(
+                Transaction::raw(TransactionType::Dispute, 1, 1, None),
+                &config,
+            )
+        };
+
+        let mut allowed = withdrawn();
+        dispute(&mut allowed, DisputeOverdraftPolicy::Allow).unwrap();
+        assert_eq!(allowed.funds().available(), money("-3"));
+        assert_eq!(allowed.funds().held(), money("5"));
+
+        let mut capped = withdrawn();
+        dispute(&mut capped, DisputeOverdraftPolicy::Cap).unwrap();
+        assert_eq!(capped.funds().available(), Money::ZERO);
+        assert_eq!(capped.funds().held(), money("2"));
+        assert_eq!(capped.capped_holds().get(&1), Some(&money("2")));
+        // Only what was held is charged back
+        capped
+            .handle_transaction(Transaction::raw(TransactionType::Chargeback, 1, 1, None))
+            .unwrap();
+        assert_eq!(capped.funds().held(), Money::ZERO);
+        assert_eq!(capped.funds().available(), Money::ZERO);
+        assert!(capped.capped_holds().is_empty());
+
+        let mut rejected = withdrawn();
+        assert_eq!(
+            dispute(&mut rejected, DisputeOverdraftPolicy::Reject),
+            Err(CodedError::new(
+                RejectionCode::DisputeExceedsAvailable,
+                "Dispute of tx 1 for 5.0000 exceeds the 2.0000 available"
+            ))
+        );
+        assert_eq!(rejected.funds().available(), money("2"));
+        assert!(rejected.disputed_transactions().is_empty());
+    }
+
+    #[test]
+    fn retention_drops_transactions_no_longer_disputable() {
+        let apply = |client: &mut Client, tx: Transaction, retention| {
+            let config = EngineConfig {
+                retention,
+                ..Default::default()
+            };
+            let (tx_id, timestamp) = (tx.raw_tx_id(), tx.timestamp());
+            client.handle_transaction_with_config(tx, &config)?;
+            client.retain(&config, tx_id, timestamp);
+            Ok::<(), String>(())
+        };
+        let at = |day| Utc.with_ymd_and_hms(2024, 3, day, 0, 0, 0).unwrap();
+
+        let last = RetentionPolicy::KeepLast(2);
+        let mut client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+        );
+        apply(
+            &mut client,
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            last,
+        )
+        .unwrap();
+        for tx_id in 2..5 {
+            let deposit = Transaction::raw(TransactionType::Deposit, tx_id, 1, Some(1.0));
+            apply(&mut client, deposit, last).unwrap();
+        }
+        // The open dispute keeps its deposit on top of the last two
+        let mut kept: Vec<u32> = client.transactions().keys().copied().collect();
+        kept.sort();
+        assert_eq!(kept, vec![1, 3, 4]);
+        assert_eq!(
+            apply(
+                &mut client,
+                Transaction::raw(TransactionType::Dispute, 2, 1, None),
+                last
+            ),
+            Err("Tx 2 does not exist for client".to_string())
+        );
+
+        let within = RetentionPolicy::DepositsWithin(2 * 86_400);
+        let mut client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)).with_timestamp(at(1)),
+        );
+        for (tx_id, tx_type, day) in [
+            (2, TransactionType::Withdrawal, 2),
+            (3, TransactionType::Deposit, 3),
+            (4, TransactionType::Deposit, 4),
+        ] {
+            let tx = Transaction::raw(tx_type, tx_id, 1, Some(1.0)).with_timestamp(at(day));
+            apply(&mut client, tx, within).unwrap();
+        }
+        let mut kept: Vec<u32> = client.transactions().keys().copied().collect();
+        kept.sort();
+        assert_eq!(kept, vec![3, 4]);
+        assert_eq!(client.funds().available(), money("6"));
+    }
+
+    #[test]
+    fn retention_bounds_what_a_client_keeps() {
+        let config = EngineConfig {
+            retention: RetentionPolicy::KeepLast(2),
+            ..Default::default()
+        };
+        let mut client: Client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(1.0)),
+        );
+        for batch in 1..1_000 {
+            for tx in [
+                Transaction::raw(TransactionType::Deposit, batch * 2, 1, Some(1.0))
+                    .with_batch(batch),
+                Transaction::raw(TransactionType::Settlement, batch * 2 + 1, 1, None)
+                    .with_batch(batch),
+            ] {
+                let tx_id = tx.raw_tx_id();
+                client.handle_transaction_with_config(tx, &config).unwrap();
+                client.retain(&config, tx_id, None);
+            }
+        }
+
+        assert_eq!(client.funds().available(), money("1000"));
+        let mut kept: Vec<u32> = client.transactions().keys().copied().collect();
+        kept.sort();
+        assert_eq!(kept, vec![1996, 1998]);
+        assert_eq!(client.settled_transactions().len(), 2);
+        assert!(client.audit_trail().len() <= 2 * 4 + COMPACTION_SLACK);
+        assert_eq!(client.audit_trail().last().unwrap().tx_id, 1999);
+    }
+
+    #[test]
+    fn replays_balances_at_a_point_in_time() {
+        let at = |timestamp: &str| timestamp.parse::<DateTime<Utc>>().unwrap();
+        let mut client: Client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0))
+                .with_timestamp(at("2024-01-01T00:00:00Z")),
+        );
+        client
+            .handle_transaction(
+                Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(1.0))
+                    .with_timestamp(at("2024-01-02T00:00:00Z")),
+            )
+            .unwrap();
+        client
+            .handle_transaction(
+                Transaction::raw(TransactionType::Dispute, 1, 1, None)
+                    .with_timestamp(at("2024-01-03T00:00:00Z")),
+            )
+            .unwrap();
+
+        let balance = client.balance_at(at("2024-01-02T12:00:00Z")).unwrap();
+        assert_eq!(
+            (balance.available(), balance.held()),
+            (money("4"), money("0"))
+        );
+        let balance = client.balance_at(at("2023-12-31T00:00:00Z")).unwrap();
+        assert_eq!(
+            (balance.available(), balance.held()),
+            (money("0"), money("0"))
+        );
+
+        let balance = client.balance_after(TxId::new(2)).unwrap();
+        assert_eq!(
+            (balance.available(), balance.held()),
+            (money("4"), money("0"))
+        );
+        let balance = client.balance_after(TxId::new(1)).unwrap();
+        assert_eq!(
+            (balance.available(), balance.held()),
+            (money("-1"), money("5"))
+        );
+        assert!(client.balance_after(TxId::new(3)).is_none());
+    }
 
     #[test]
     fn can_calculate_total_funds() {
-        let tx_1 = Transaction::new(TransactionType::Deposit, 1, 1, Some(1.5));
+        let tx_1 = Transaction::raw(TransactionType::Deposit, 1, 1, Some(1.5));
         let funds = Funds::new(&tx_1);
         assert_eq!(
             funds,
             Funds {
-                available: 1.5,
-                held: 0.0
+                available: money("1.5"),
+                held: money("0.0")
             }
         );
 
-        let tx_2 = Transaction::new(TransactionType::Chargeback, 1, 1, None);
+        let tx_2 = Transaction::raw(TransactionType::Chargeback, 1, 1, None);
         let funds = Funds::new(&tx_2);
         assert_eq!(
             funds,
             Funds {
-                available: 0.0,
-                held: 0.0
+                available: money("0.0"),
+                held: money("0.0")
             }
         );
     }
@@ -235,17 +1825,17 @@ mod tests {
     #[test]
     fn can_handle_deposit() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let next_deposit = Transaction::new(TransactionType::Deposit, 2, client_id, Some(1.5));
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let next_deposit = Transaction::raw(TransactionType::Deposit, 2, client_id, Some(1.5));
 
-        let mut client = Client::new(1, initial_deposit);
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
         client.handle_transaction(next_deposit).unwrap();
 
         assert_eq!(
             *client.funds(),
             Funds {
-                available: 3.0,
-                held: 0.0
+                available: money("3.0"),
+                held: money("0.0")
             }
         )
     }
@@ -253,17 +1843,17 @@ mod tests {
     #[test]
     fn can_handle_withdrawal() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let withdrawal = Transaction::new(TransactionType::Withdrawal, 2, client_id, Some(1.5));
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 2, client_id, Some(1.5));
 
-        let mut client = Client::new(1, initial_deposit);
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
         client.handle_transaction(withdrawal).unwrap();
 
         assert_eq!(
             *client.funds(),
             Funds {
-                available: 0.0,
-                held: 0.0
+                available: money("0.0"),
+                held: money("0.0")
             }
         )
     }
@@ -271,17 +1861,17 @@ mod tests {
     #[test]
     fn can_handle_dispute() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let dispute = Transaction::new(TransactionType::Dispute, 1, client_id, None);
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let dispute = Transaction::raw(TransactionType::Dispute, 1, client_id, None);
 
-        let mut client = Client::new(1, initial_deposit);
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
         client.handle_transaction(dispute).unwrap();
 
         assert_eq!(
             *client.funds(),
             Funds {
-                available: 0.0,
-                held: 1.5
+                available: money("0.0"),
+                held: money("1.5")
             }
         )
     }
@@ -289,19 +1879,19 @@ mod tests {
     #[test]
     fn can_handle_resolution() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let dispute = Transaction::new(TransactionType::Dispute, 1, client_id, None);
-        let resolution = Transaction::new(TransactionType::Resolve, 1, client_id, None);
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let dispute = Transaction::raw(TransactionType::Dispute, 1, client_id, None);
+        let resolution = Transaction::raw(TransactionType::Resolve, 1, client_id, None);
 
-        let mut client = Client::new(1, initial_deposit);
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
         client.handle_transaction(dispute).unwrap();
         client.handle_transaction(resolution).unwrap();
 
         assert_eq!(
             *client.funds(),
             Funds {
-                available: 1.5,
-                held: 0.0
+                available: money("1.5"),
+                held: money("0.0")
             }
         )
     }
@@ -309,91 +1899,343 @@ mod tests {
     #[test]
     fn can_handle_chargeback() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let dispute = Transaction::new(TransactionType::Dispute, 1, client_id, None);
-        let chargeback = Transaction::new(TransactionType::Chargeback, 1, client_id, None);
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let dispute = Transaction::raw(TransactionType::Dispute, 1, client_id, None);
+        let chargeback = Transaction::raw(TransactionType::Chargeback, 1, client_id, None);
+
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        client.handle_transaction(dispute).unwrap();
+        client.handle_transaction(chargeback).unwrap();
+
+        assert_eq!(
+            *client.funds(),
+            Funds {
+                available: money("0.0"),
+                held: money("0.0")
+            }
+        );
+
+        assert!(client.is_locked())
+    }
+
+    #[test]
+    fn can_handle_withdrawal_dispute() {
+        let client_id = 1;
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(2.0));
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 2, client_id, Some(1.5));
+        let dispute = Transaction::raw(TransactionType::Dispute, 2, client_id, None);
+
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        client.handle_transaction(withdrawal).unwrap();
+        client.handle_transaction(dispute).unwrap();
+
+        assert_eq!(
+            *client.funds(),
+            Funds {
+                available: money("0.5"),
+                held: money("1.5")
+            }
+        );
+
+        let resolution = Transaction::raw(TransactionType::Resolve, 2, client_id, None);
+        client.handle_transaction(resolution).unwrap();
+
+        assert_eq!(
+            *client.funds(),
+            Funds {
+                available: money("0.5"),
+                held: money("0.0")
+            }
+        )
+    }
+
+    #[test]
+    fn can_handle_withdrawal_chargeback() {
+        let client_id = 1;
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(2.0));
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 2, client_id, Some(1.5));
+        let dispute = Transaction::raw(TransactionType::Dispute, 2, client_id, None);
+        let chargeback = Transaction::raw(TransactionType::Chargeback, 2, client_id, None);
 
-        let mut client = Client::new(1, initial_deposit);
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        client.handle_transaction(withdrawal).unwrap();
         client.handle_transaction(dispute).unwrap();
         client.handle_transaction(chargeback).unwrap();
 
         assert_eq!(
             *client.funds(),
             Funds {
-                available: 0.0,
-                held: 0.0
+                available: money("2.0"),
+                held: money("0.0")
             }
         );
 
         assert!(client.is_locked())
     }
 
+    #[test]
+    fn unlocks_once_the_last_open_dispute_is_resolved() {
+        let config = EngineConfig {
+            auto_unlock: true,
+            ..Default::default()
+        };
+        let mut client: Client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(3.0)),
+        );
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Deposit, 3, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Dispute, 2, 1, None),
+            Transaction::raw(TransactionType::Dispute, 3, 1, None),
+            Transaction::raw(TransactionType::Chargeback, 1, 1, None),
+            Transaction::raw(TransactionType::Resolve, 2, 1, None),
+        ] {
+            client.handle_transaction_with_config(tx, &config).unwrap();
+        }
+        assert!(client.is_locked());
+
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 4, 1, Some(1.0));
+        assert!(client
+            .handle_transaction_with_config(withdrawal, &config)
+            .is_err());
+
+        let resolve = Transaction::raw(TransactionType::Resolve, 3, 1, None);
+        client
+            .handle_transaction_with_config(resolve, &config)
+            .unwrap();
+        assert!(!client.is_locked());
+    }
+
+    #[test]
+    fn settles_the_net_of_a_batch_at_once() {
+        let mut client: Client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(1.0)),
+        );
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(5.0)).with_batch(7),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(2.0)).with_batch(7),
+        ] {
+            client.handle_transaction(tx).unwrap();
+        }
+        assert_eq!(client.funds().available().to_string(), "1.0000");
+        assert!(client
+            .handle_transaction(Transaction::raw(TransactionType::Dispute, 2, 1, None))
+            .is_err());
+
+        let settlement = Transaction::raw(TransactionType::Settlement, 4, 1, None).with_batch(7);
+        client.handle_transaction(settlement).unwrap();
+        assert_eq!(client.funds().available().to_string(), "4.0000");
+        assert_eq!(client.audit_trail().len(), 2);
+        assert_eq!(
+            client.settled_transactions(),
+            &Batches::from_iter([(2, 7), (3, 7)])
+        );
+        assert!(client.unsettled_transactions().is_empty());
+
+        // Nothing left to settle in the batch
+        let settlement = Transaction::raw(TransactionType::Settlement, 5, 1, None).with_batch(7);
+        assert!(client.handle_transaction(settlement).is_err());
+    }
+
+    #[test]
+    fn fails_dispute_of_type_that_is_not_disputable() {
+        let client_id = 1;
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(2.0));
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 2, client_id, Some(1.5));
+        let dispute = Transaction::raw(TransactionType::Dispute, 2, client_id, None);
+        let config = EngineConfig {
+            disputable_types: vec![TransactionType::Deposit],
+            ..Default::default()
+        };
+
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        client.handle_transaction(withdrawal).unwrap();
+        assert!(client
+            .handle_transaction_with_config(dispute, &config)
+            .is_err());
+        assert!(client.disputed_transactions().is_empty())
+    }
+
+    #[test]
+    fn can_handle_partial_refunds() {
+        let client_id = 1;
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(2.0));
+        let refund = Transaction::raw(TransactionType::Refund, 1, client_id, Some(1.5));
+        let excess_refund = Transaction::raw(TransactionType::Refund, 1, client_id, Some(1.0));
+        let dispute = Transaction::raw(TransactionType::Dispute, 1, client_id, None);
+
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        client.handle_transaction(refund).unwrap();
+        assert!(client.handle_transaction(excess_refund).is_err());
+        client.handle_transaction(dispute).unwrap();
+
+        assert_eq!(client.refunds()[&1], money("1.5"));
+        assert_eq!(
+            *client.funds(),
+            Funds {
+                available: money("0.0"),
+                held: money("0.5")
+            }
+        )
+    }
+
+    #[test]
+    fn fails_refund_of_withdrawal() {
+        let client_id = 1;
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(2.0));
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 2, client_id, Some(1.0));
+        let refund = Transaction::raw(TransactionType::Refund, 2, client_id, Some(1.0));
+
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        client.handle_transaction(withdrawal).unwrap();
+        assert!(client.handle_transaction(refund).is_err())
+    }
+
+    #[test]
+    fn can_handle_sub_accounts() {
+        let client_id = 1;
+        let checking = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(2.0))
+            .with_account("checking");
+        let savings = Transaction::raw(TransactionType::Deposit, 2, client_id, Some(1.0))
+            .with_account("savings");
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 3, client_id, Some(1.5))
+            .with_account("savings");
+        let dispute = Transaction::raw(TransactionType::Dispute, 2, client_id, None);
+
+        let mut client: Client = Client::new(TxId::new(1), checking);
+        client.handle_transaction(savings).unwrap();
+        assert!(client.handle_transaction(withdrawal).is_err());
+        client.handle_transaction(dispute).unwrap();
+
+        assert_eq!(
+            client.get_account_records(ClientId::new(client_id), Precision::Fixed(1).into()),
+            vec![
+                vec!["1", "checking", "2.0", "0.0", "2.0", "false", "false", "0.0"],
+                vec!["1", "savings", "0.0", "1.0", "1.0", "false", "false", "0.0"],
+                vec!["1", "", "2.0", "1.0", "3.0", "false", "false", "0.0"],
+            ]
+        )
+    }
+
     #[test]
     fn can_get_record() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let client = Client::new(1, initial_deposit);
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let client: Client = Client::new(TxId::new(1), initial_deposit);
 
         assert_eq!(
-            client.get_record(client_id),
-            vec!["1", "1.5000", "0.0000", "1.5000", "false"]
+            client.get_record(ClientId::new(client_id)),
+            vec!["1", "1.5000", "0.0000", "1.5000", "false", "false", "0.0000"]
         )
     }
 
+    #[test]
+    fn can_get_record_with_input_precision() {
+        let client_id = 1;
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let next_deposit = Transaction::raw(TransactionType::Deposit, 2, client_id, Some(2.0));
+
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        client.handle_transaction(next_deposit).unwrap();
+
+        assert_eq!(
+            client.get_record_with_format(ClientId::new(client_id), Precision::Preserve.into()),
+            vec!["1", "3.5", "0.0", "3.5", "false", "false", "0.0"]
+        )
+    }
+
+    #[test]
+    fn formats_with_locale_separators() {
+        let format = NumberFormat {
+            precision: Precision::Fixed(2),
+            decimal_separator: ',',
+            thousands_separator: Some('.'),
+        };
+
+        assert_eq!(
+            format.format("1234567.505".parse().unwrap(), 0),
+            "1.234.567,51"
+        );
+        assert_eq!(format.format("-999.5".parse().unwrap(), 0), "-999,50");
+        assert_eq!(
+            NumberFormat::from(Precision::Fixed(0)).format("1234.5".parse().unwrap(), 0),
+            "1235"
+        );
+    }
+
     #[test]
     fn fails_dispute_when_tx_does_not_exist() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let dispute = Transaction::new(TransactionType::Dispute, 2, client_id, None);
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let dispute = Transaction::raw(TransactionType::Dispute, 2, client_id, None);
 
-        let mut client = Client::new(1, initial_deposit);
-        if let Err(_error) = client.handle_transaction(dispute) {
-            assert!(true)
-        } else {
-            assert!(false)
-        }
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        assert!(client.handle_transaction(dispute).is_err())
     }
 
     #[test]
     fn fails_resolve_when_tx_does_not_exist() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let resolve = Transaction::new(TransactionType::Resolve, 2, client_id, None);
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let resolve = Transaction::raw(TransactionType::Resolve, 2, client_id, None);
 
-        let mut client = Client::new(1, initial_deposit);
-        if let Err(_error) = client.handle_transaction(resolve) {
-            assert!(true)
-        } else {
-            assert!(false)
-        }
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        assert!(client.handle_transaction(resolve).is_err())
     }
 
     #[test]
     fn fails_chargeback_when_tx_does_not_exist() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let chargeback = Transaction::new(TransactionType::Chargeback, 2, client_id, None);
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let chargeback = Transaction::raw(TransactionType::Chargeback, 2, client_id, None);
 
-        let mut client = Client::new(1, initial_deposit);
-        if let Err(_error) = client.handle_transaction(chargeback) {
-            assert!(true)
-        } else {
-            assert!(false)
-        }
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        assert!(client.handle_transaction(chargeback).is_err())
     }
 
     #[test]
     fn fails_withdrawal_on_insufficient_funds() {
         let client_id = 1;
-        let initial_deposit = Transaction::new(TransactionType::Deposit, 1, client_id, Some(1.5));
-        let withdrawal = Transaction::new(TransactionType::Withdrawal, 2, client_id, Some(2.0));
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 2, client_id, Some(2.0));
 
-        let mut client = Client::new(1, initial_deposit);
-        if let Err(_error) = client.handle_transaction(withdrawal) {
-            assert!(true)
-        } else {
-            assert!(false)
-        }
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        assert!(client.handle_transaction(withdrawal).is_err())
+    }
+
+    #[test]
+    fn fails_deposit_on_overflow() {
+        let client_id = 1;
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.0))
+            .with_amount(Amount::new(Money::from_minor_units(i64::MAX), 4));
+        let next_deposit = Transaction::raw(TransactionType::Deposit, 2, client_id, Some(1.0));
+
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        assert!(client.handle_transaction(next_deposit).is_err());
+        assert_eq!(
+            client.funds().available(),
+            Money::from_minor_units(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn includes_memo_in_errors() {
+        let client_id = 1;
+        let initial_deposit = Transaction::raw(TransactionType::Deposit, 1, client_id, Some(1.5));
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 2, client_id, Some(2.0))
+            .with_memo("atm 42");
+
+        let mut client: Client = Client::new(TxId::new(1), initial_deposit);
+        assert_eq!(
+            client.handle_transaction(withdrawal),
+            Err(CodedError::new(
+                RejectionCode::InsufficientFunds,
+                "Insufficient funds to withdraw 2.0000 (memo: atm 42)"
+            ))
+        )
     }
 
     #[test]
@@ -401,14 +2243,10 @@ mod tests {
         let client_id = 1;
         let tx_id = 1;
         let initial_deposit =
-            Transaction::new(TransactionType::Deposit, tx_id, client_id, Some(1.5));
-        let next_deposit = Transaction::new(TransactionType::Deposit, tx_id, client_id, Some(1.5));
+            Transaction::raw(TransactionType::Deposit, tx_id, client_id, Some(1.5));
+        let next_deposit = Transaction::raw(TransactionType::Deposit, tx_id, client_id, Some(1.5));
 
-        let mut client = Client::new(tx_id, initial_deposit);
-        if let Err(_error) = client.handle_transaction(next_deposit) {
-            assert!(true)
-        } else {
-            assert!(false)
-        }
+        let mut client: Client = Client::new(TxId::new(tx_id), initial_deposit);
+        assert!(client.handle_transaction(next_deposit).is_err())
     }
 }
@@ -0,0 +1,324 @@
+// Stable machine readable codes for why a transaction was rejected or the input couldn't be
+// read, so automation doesn't depend on the wording of messages. Messages may be reworded
+// between versions, a code and its name never change once released and new ones are only
+// added. E1xxx are rejected transactions, E2xxx input that couldn't be read.
+use crate::transaction::Transaction;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RejectionCode {
+    InsufficientFunds,
+    AccountLocked,
+    AccountFrozen,
+    UnknownTransaction,
+    NotDisputed,
+    AlreadyDisputed,
+    NotDisputable,
+    DisputeExceedsAvailable,
+    DuplicateTransaction,
+    OutOfOrder,
+    MissingAmount,
+    NotRefundable,
+    RefundExceedsDeposit,
+    Unsettled,
+    InvalidSettlement,
+    InterestNotAccepted,
+    Overflow,
+    HeldBelowZero,
+    WrongClient,
+    UnknownClient,
+    Quarantined,
+    WithdrawalLimitExceeded,
+    NotDue,
+    MalformedRow,
+    InvalidAmount,
+    UnknownTransactionType,
+    MissingHeaders,
+    Other,
+}
+
+impl RejectionCode {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectionCode::InsufficientFunds => "E1001",
+            RejectionCode::AccountLocked => "E1002",
+            RejectionCode::AccountFrozen => "E1003",
+            RejectionCode::UnknownTransaction => "E1004",
+            RejectionCode::NotDisputed => "E1005",
+            RejectionCode::AlreadyDisputed => "E1006",
+            RejectionCode::NotDisputable => "E1007",
+            RejectionCode::DisputeExceedsAvailable => "E1008",
+            RejectionCode::DuplicateTransaction => "E1009",
+            RejectionCode::OutOfOrder => "E1010",
+            RejectionCode::MissingAmount => "E1011",
+            RejectionCode::NotRefundable => "E1012",
+            RejectionCode::RefundExceedsDeposit => "E1013",
+            RejectionCode::Unsettled => "E1014",
+            RejectionCode::InvalidSettlement => "E1015",
+            RejectionCode::InterestNotAccepted => "E1016",
+            RejectionCode::Overflow => "E1017",
+            RejectionCode::HeldBelowZero => "E1018",
+            RejectionCode::WrongClient => "E1019",
+            RejectionCode::UnknownClient => "E1020",
+            RejectionCode::Quarantined => "E1021",
+            RejectionCode::WithdrawalLimitExceeded => "E1022",
+            RejectionCode::NotDue => "E1023",
+            RejectionCode::Other => "E1999",
+            RejectionCode::MalformedRow => "E2001",
+            RejectionCode::InvalidAmount => "E2002",
+            RejectionCode::UnknownTransactionType => "E2003",
+            RejectionCode::MissingHeaders => "E2004",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RejectionCode::InsufficientFunds => "InsufficientFunds",
+            RejectionCode::AccountLocked => "AccountLocked",
+            RejectionCode::AccountFrozen => "AccountFrozen",
+            RejectionCode::UnknownTransaction => "UnknownTransaction",
+            RejectionCode::NotDisputed => "NotDisputed",
+            RejectionCode::AlreadyDisputed => "AlreadyDisputed",
+            RejectionCode::NotDisputable => "NotDisputable",
+            RejectionCode::DisputeExceedsAvailable => "DisputeExceedsAvailable",
+            RejectionCode::DuplicateTransaction => "DuplicateTransaction",
+            RejectionCode::OutOfOrder => "OutOfOrder",
+            RejectionCode::MissingAmount => "MissingAmount",
+            RejectionCode::NotRefundable => "NotRefundable",
+            RejectionCode::RefundExceedsDeposit => "RefundExceedsDeposit",
+            RejectionCode::Unsettled => "Unsettled",
+            RejectionCode::InvalidSettlement => "InvalidSettlement",
+            RejectionCode::InterestNotAccepted => "InterestNotAccepted",
+            RejectionCode::Overflow => "Overflow",
+            RejectionCode::HeldBelowZero => "HeldBelowZero",
+            RejectionCode::WrongClient => "WrongClient",
+            RejectionCode::UnknownClient => "UnknownClient",
+            RejectionCode::Quarantined => "Quarantined",
+            RejectionCode::WithdrawalLimitExceeded => "WithdrawalLimitExceeded",
+            RejectionCode::NotDue => "NotDue",
+            RejectionCode::Other => "Other",
+            RejectionCode::MalformedRow => "MalformedRow",
+            RejectionCode::InvalidAmount => "InvalidAmount",
+            RejectionCode::UnknownTransactionType => "UnknownTransactionType",
+            RejectionCode::MissingHeaders => "MissingHeaders",
+        }
+    }
+}
+
+impl fmt::Display for RejectionCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.name())
+    }
+}
+
+// An error and the code it was raised with, set where it is raised so rewording the message
+// never changes it. Errors that are neither a rejected transaction nor an unreadable input,
+// e.g. a file that can't be opened, are `Other`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodedError {
+    code: RejectionCode,
+    message: String,
+}
+
+impl CodedError {
+    pub fn new(code: RejectionCode, message: impl Into<String>) -> Self {
+        CodedError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn code(&self) -> RejectionCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // Adds context to the message, e.g. the memo of the transaction, keeping the code
+    pub(crate) fn with_suffix(mut self, suffix: &str) -> Self {
+        self.message.push_str(suffix);
+        self
+    }
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<String> for CodedError {
+    fn from(message: String) -> Self {
+        CodedError::new(RejectionCode::Other, message)
+    }
+}
+
+impl From<&str> for CodedError {
+    fn from(message: &str) -> Self {
+        CodedError::new(RejectionCode::Other, message)
+    }
+}
+
+impl From<CodedError> for String {
+    fn from(error: CodedError) -> Self {
+        error.message
+    }
+}
+
+// How errors are written to std err
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ErrorFormat {
+    // The message alone, as it always was
+    #[default]
+    Text,
+    // A JSON object per line with the code, its name and the message
+    Json,
+}
+
+// Reports a transaction the engine refused
+pub(crate) fn report_rejection(format: ErrorFormat, tx: (u16, u32), error: &CodedError) {
+    match format {
+        ErrorFormat::Json => eprintln!(
+            "{{{},\"client\":{},\"tx\":{}}}",
+            json_fields(error),
+            tx.0,
+            tx.1
+        ),
+        ErrorFormat::Text => eprintln!("error handling tx: {}", error),
+    }
+}
+
+// Reports the error a run stopped on, e.g. a row that couldn't be parsed
+pub fn report_error(format: ErrorFormat, error: &CodedError) {
+    match format {
+        ErrorFormat::Json => eprintln!("{{{}}}", json_fields(error)),
+        ErrorFormat::Text => eprintln!("{}", error),
+    }
+}
+
+// Reports something worth knowing that didn't stop the run, e.g. rows that were skipped
+pub fn report_warning(format: ErrorFormat, warning: &str) {
+    match format {
+        ErrorFormat::Json => eprintln!("{{\"warning\":\"{}\"}}", escape(warning)),
+        ErrorFormat::Text => eprintln!("{}", warning),
+    }
+}
+
+// The client and tx id a rejection is reported with
+pub(crate) fn ids(tx: &Transaction) -> (u16, u32) {
+    (tx.raw_client_id(), tx.raw_tx_id())
+}
+
+fn json_fields(error: &CodedError) -> String {
+    format!(
+        "\"code\":\"{}\",\"name\":\"{}\",\"error\":\"{}\"",
+        error.code.code(),
+        error.code.name(),
+        escape(&error.message)
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{CrossClientPolicy, EngineConfig, TransactionEngine};
+    #[cfg(feature = "csv")]
+    use crate::input::{CsvSource, ReadOptions};
+    use crate::transaction::TransactionType;
+
+    #[test]
+    fn codes_engine_rejections_where_they_are_raised() {
+        let mut engine = TransactionEngine::new(EngineConfig {
+            cross_client: CrossClientPolicy::Reject,
+            ..Default::default()
+        })
+        .with_rejections();
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Dispute, 9, 1, None),
+            Transaction::raw(TransactionType::Resolve, 1, 1, None),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Deposit, 3, 1, None),
+            Transaction::raw(TransactionType::Dispute, 1, 2, None),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Chargeback, 1, 1, None),
+            Transaction::raw(TransactionType::Deposit, 4, 1, Some(1.0)),
+            // The memo is only added to the message, the account is still locked
+            Transaction::raw(TransactionType::Deposit, 5, 1, Some(1.0))
+                .with_memo("Insufficient funds"),
+        ] {
+            engine.push(tx);
+        }
+
+        let (_, rejections) = engine.finish_with_rejections();
+        let codes: Vec<&str> = rejections
+            .iter()
+            .map(|rejection| rejection.code().code())
+            .collect();
+        assert_eq!(
+            codes,
+            ["E1001", "E1004", "E1005", "E1010", "E1011", "E1019", "E1006", "E1002", "E1002"]
+        );
+        assert_eq!(
+            rejections[8].error.message(),
+            "Account locked, ignoring 5 (memo: Insufficient funds)"
+        );
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn codes_rows_that_cant_be_read() {
+        let code = |csv: &str| {
+            let mut source = CsvSource::from_reader(csv.as_bytes(), ReadOptions::default())?;
+            match source.next_row() {
+                Some(Err(error)) => Err(error),
+                _ => Ok(()),
+            }
+        };
+
+        let codes: Vec<RejectionCode> = [
+            "type,client,tx,amount\nbogus,1,1,1.0\n",
+            "type,client,tx,amount\ndeposit,1,1,abc\n",
+            "type,client,tx,amount\ndeposit,x,1,1.0\n",
+        ]
+        .into_iter()
+        .map(|csv| code(csv).unwrap_err().code())
+        .collect();
+        assert_eq!(
+            codes,
+            [
+                RejectionCode::UnknownTransactionType,
+                RejectionCode::InvalidAmount,
+                RejectionCode::MalformedRow
+            ]
+        );
+        assert_eq!(code("type,client,tx,amount\ndeposit,1,1,1.0\n"), Ok(()));
+    }
+
+    #[test]
+    fn escapes_messages_in_json() {
+        assert_eq!(
+            json_fields(&CodedError::from("Tx 1 \"memo\"")),
+            "\"code\":\"E1999\",\"name\":\"Other\",\"error\":\"Tx 1 \\\"memo\\\"\""
+        );
+    }
+}
@@ -0,0 +1,223 @@
+// Transactional processing of an input split into groups by `commit` rows, e.g.
+//
+//     type,client,tx,amount
+//     deposit,1,1,5.0
+//     withdrawal,1,2,2.0
+//     commit,,,
+//
+// A group is applied as a whole or not at all: a row that doesn't parse or is rejected rolls
+// the engine back to the last commit and the rest of the group is skipped. Rows after the last
+// commit are rolled back once the input ends. At every commit the state can be saved, so a
+// crash never leaves a half applied group behind.
+//
+// Rows are applied as they are read, so a group holds exactly its rows when it's committed:
+// nothing waits in a reorder buffer, and a row whose value date isn't due by its own timestamp
+// is rejected rather than held back into a later group.
+use crate::client::Clients;
+use crate::engine::TransactionEngine;
+use crate::transaction::Transaction;
+use crate::{csv_reader, interrupt, snapshot};
+use std::fs;
+
+// Type of the rows marking a group boundary
+pub const COMMIT: &str = "commit";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CommitSummary {
+    pub commits: u64,
+    // Rows of the groups that were committed, commit rows not included
+    pub applied: u64,
+    // Rows undone or skipped because their group failed or was never committed
+    pub rolled_back: u64,
+}
+
+// Applies `input` group by group, writing the engine to `snapshot` at every commit when given
+pub fn process(
+    input: &str,
+    mut engine: TransactionEngine,
+    snapshot: Option<&str>,
+) -> Result<(Clients, CommitSummary), String> {
+    let mut rdr = csv_reader(input)?;
+    let headers = rdr
+        .headers()
+        .map_err(|error| format!("Error reading csv headers: {}", error))?
+        .clone();
+    let type_column = headers
+        .iter()
+        .position(|header| header == "type")
+        .ok_or("The input has no type column")?;
+
+    engine.commit()?;
+    let mut summary = CommitSummary::default();
+    // Rows of the current group, and whether one of them failed
+    let mut pending = 0;
+    let mut failed = false;
+
+    for record in rdr.records() {
+        if interrupt::requested() {
+            break;
+        }
+        let record = record.map_err(|error| format!("Error parsing csv line: {}", error));
+
+        if let Ok(record) = &record {
+            if record.get(type_column) == Some(COMMIT) {
+                match failed {
+                    true => summary.rolled_back += pending,
+                    false => summary.applied += pending,
+                }
+                engine.commit()?;
+                if let Some(path) = snapshot {
+                    save(path, &engine)?;
+                }
+                summary.commits += 1;
+                (pending, failed) = (0, false);
+                continue;
+            }
+        }
+
+        pending += 1;
+        if failed {
+            continue;
+        }
+        let applied = record
+            .and_then(|record| parse(&record, &headers))
+            .and_then(|tx| {
+                let line = format!("tx {}", tx.tx_id());
+                engine
+                    .submit(tx)
+                    .map_err(|error| format!("{}: {}", line, error))
+            });
+        if let Err(error) = applied {
+            eprintln!("Rolling back to commit {}, {}", summary.commits, error);
+            engine = engine.rollback()?;
+            failed = true;
+        }
+    }
+
+    if pending > 0 {
+        eprintln!(
+            "Rolling back {} rows after the last commit, the input ended before their commit",
+            pending
+        );
+        engine = engine.rollback()?;
+        summary.rolled_back += pending;
+    }
+
+    Ok((engine.finish(), summary))
+}
+
+fn parse(record: &csv::StringRecord, headers: &csv::StringRecord) -> Result<Transaction, String> {
+    record
+        .deserialize(Some(headers))
+        .map_err(|error| format!("Error parsing csv line: {}", error))
+}
+
+// Written to a sibling file first and renamed over the old snapshot, like checkpoints
+fn save(path: &str, engine: &TransactionEngine) -> Result<(), String> {
+    let partial = format!("{}.partial", path);
+
+    fs::write(&partial, snapshot::seal(engine.to_bytes()?)?)
+        .and_then(|_| fs::rename(&partial, path))
+        .map_err(|error| format!("Error writing snapshot {}: {}", path, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::ids::ClientId;
+    use crate::money::Money;
+
+    #[test]
+    fn applies_groups_as_a_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.csv");
+        let saved = dir.path().join("engine.snapshot");
+        fs::write(
+            &input,
+            "type,client,tx,amount\n\
+             deposit,1,1,5.0\n\
+             commit,,,\n\
+             withdrawal,1,2,2.0\n\
+             withdrawal,1,3,9.0\n\
+             deposit,1,4,1.0\n\
+             commit,,,\n\
+             deposit,2,5,1.0\n\
+             bogus,2,6,\n\
+             commit,,,\n\
+             deposit,1,7,3.0\n\
+             commit,,,\n\
+             deposit,1,8,1.0\n",
+        )
+        .unwrap();
+
+        let (clients, summary) = process(
+            input.to_str().unwrap(),
+            TransactionEngine::new(EngineConfig::default()),
+            Some(saved.to_str().unwrap()),
+        )
+        .unwrap();
+        assert_eq!(
+            summary,
+            CommitSummary {
+                commits: 4,
+                applied: 2,
+                rolled_back: 6,
+            }
+        );
+        assert_eq!(
+            clients[&ClientId::new(1)].funds().available(),
+            Money::from(8.0)
+        );
+        assert!(!clients.contains_key(&ClientId::new(2)));
+
+        // The snapshot is the state at the last commit
+        let bytes = snapshot::open(fs::read(&saved).unwrap()).unwrap();
+        let restored = TransactionEngine::from_bytes(&bytes).unwrap().finish();
+        assert_eq!(
+            restored[&ClientId::new(1)].funds(),
+            clients[&ClientId::new(1)].funds()
+        );
+    }
+
+    #[test]
+    fn commits_every_row_of_a_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.csv");
+        fs::write(
+            &input,
+            "type,client,tx,amount,timestamp,value_date\n\
+             deposit,1,1,5.0,,\n\
+             deposit,1,2,1.0,,\n\
+             commit,,,,,\n\
+             deposit,1,3,1.0,2024-01-01T00:00:00Z,2024-01-02T00:00:00Z\n\
+             commit,,,,,\n\
+             deposit,1,4,2.0,2024-01-02T00:00:00Z,2024-01-02T00:00:00Z\n\
+             commit,,,,,\n",
+        )
+        .unwrap();
+
+        // A reorder window would hold the last rows of a group past its commit
+        let (clients, summary) = process(
+            input.to_str().unwrap(),
+            TransactionEngine::new(EngineConfig {
+                reorder_window: 4,
+                ..Default::default()
+            }),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            summary,
+            CommitSummary {
+                commits: 3,
+                applied: 3,
+                rolled_back: 1,
+            }
+        );
+        assert_eq!(
+            clients[&ClientId::new(1)].funds().available(),
+            Money::from(8.0)
+        );
+    }
+}
@@ -0,0 +1,109 @@
+// Optional zstd compression of saved engine state and of the accounts output. Built with the
+// zstd feature every snapshot and checkpoint is written compressed, before any encryption since
+// encrypted bytes don't compress. Files are recognised by the zstd frame magic when loading, so
+// uncompressed ones keep loading either way.
+use std::io::{self, Write};
+
+const MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+#[cfg(feature = "zstd")]
+pub fn compress(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    zstd::encode_all(bytes.as_slice(), 0)
+        .map_err(|error| format!("Error compressing state: {}", error))
+}
+
+// Without the zstd feature state is saved as it is
+#[cfg(not(feature = "zstd"))]
+pub fn compress(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    Ok(bytes)
+}
+
+pub fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !is_compressed(&bytes) {
+        return Ok(bytes);
+    }
+
+    #[cfg(feature = "zstd")]
+    return zstd::decode_all(bytes.as_slice())
+        .map_err(|error| format!("Error decompressing state: {}", error));
+    #[cfg(not(feature = "zstd"))]
+    Err("State is compressed, rebuild with --features zstd to load it".to_string())
+}
+
+// Writes through to `inner`, compressed into one zstd stream when asked to
+pub enum Writer<W: Write> {
+    Plain(W),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W, compress: bool) -> Result<Self, String> {
+        if !compress {
+            return Ok(Writer::Plain(inner));
+        }
+
+        #[cfg(feature = "zstd")]
+        return zstd::Encoder::new(inner, 0)
+            .map(Writer::Zstd)
+            .map_err(|error| format!("Error compressing output: {}", error));
+        #[cfg(not(feature = "zstd"))]
+        Err(
+            "Compressing the output needs the zstd feature, rebuild with --features zstd"
+                .to_string(),
+        )
+    }
+
+    // Ends the zstd stream, the compressed output is truncated without this
+    pub fn finish(self) -> Result<W, String> {
+        match self {
+            Writer::Plain(inner) => Ok(inner),
+            #[cfg(feature = "zstd")]
+            Writer::Zstd(encoder) => encoder
+                .finish()
+                .map_err(|error| format!("Error compressing output: {}", error)),
+        }
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::Plain(inner) => inner.write(buf),
+            #[cfg(feature = "zstd")]
+            Writer::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Plain(inner) => inner.flush(),
+            #[cfg(feature = "zstd")]
+            Writer::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "zstd"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compresses_state_and_output() {
+        let state = b"client 1 has 5.0000 ".repeat(100);
+        let compressed = compress(state.clone()).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < state.len());
+        assert_eq!(decompress(compressed).unwrap(), state);
+        assert_eq!(decompress(state.clone()).unwrap(), state);
+
+        let mut writer = Writer::new(Vec::new(), true).unwrap();
+        writer.write_all(&state).unwrap();
+        let output = writer.finish().unwrap();
+        assert_eq!(zstd::decode_all(output.as_slice()).unwrap(), state);
+    }
+}
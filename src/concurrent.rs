@@ -0,0 +1,123 @@
+use crate::client::{Client, Clients};
+use crate::codes;
+use crate::engine::EngineConfig;
+use crate::ids::ClientId;
+use crate::reorder::ReorderBuffer;
+use crate::transaction::Transaction;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+
+// A `Send + Sync` engine for multi-threaded ingestion (HTTP handlers, Kafka partitions, ...).
+// Clients live in a sharded concurrent map, so threads pushing transactions for different
+// clients rarely contend, while mutations of a single client are still serialized.
+#[derive(Debug, Default)]
+pub struct ConcurrentEngine {
+    config: EngineConfig,
+    clients: DashMap<ClientId, Client>,
+    reorder_buffers: DashMap<ClientId, ReorderBuffer>,
+}
+
+impl ConcurrentEngine {
+    pub fn new(config: EngineConfig) -> Self {
+        ConcurrentEngine {
+            config,
+            clients: DashMap::new(),
+            reorder_buffers: DashMap::new(),
+        }
+    }
+
+    pub fn push(&self, tx: Transaction) {
+        if self.config.reorder_window == 0 {
+            return self.apply(tx);
+        }
+
+        // The buffer entry stays locked while the released tx is applied, so two threads
+        // can't apply transactions of the same client out of the order they left the buffer
+        let mut buffer = self
+            .reorder_buffers
+            .entry(tx.client_id())
+            .or_insert_with(|| ReorderBuffer::new(self.config.reorder_window));
+
+        if let Some(tx) = buffer.push(tx) {
+            self.apply(tx);
+        }
+    }
+
+    // Applies anything still waiting in the reorder buffers and hands back the final client state
+    pub fn finish(self) -> Clients {
+        let ConcurrentEngine {
+            config,
+            clients,
+            reorder_buffers,
+        } = self;
+
+        for (_, buffer) in reorder_buffers {
+            for tx in buffer.drain() {
+                apply_tx(&config, &clients, tx);
+            }
+        }
+
+        clients.into_iter().collect()
+    }
+
+    fn apply(&self, tx: Transaction) {
+        apply_tx(&self.config, &self.clients, tx)
+    }
+}
+
+fn apply_tx(config: &EngineConfig, clients: &DashMap<ClientId, Client>, tx: Transaction) {
+    let ids = codes::ids(&tx);
+    let timestamp = tx.timestamp();
+    match clients.entry(tx.client_id()) {
+        Entry::Occupied(mut entry) => {
+            let client = entry.get_mut();
+            match client.handle_transaction_with_config(tx, config) {
+                Ok(()) => {
+                    client.retain(config, ids.1, timestamp);
+                }
+                Err(error) => codes::report_rejection(config.error_format, ids, &error),
+            }
+        }
+        Entry::Vacant(entry) => match Client::open(tx.tx_id(), tx) {
+            Ok(client) => {
+                entry.insert(client);
+            }
+            Err(error) => codes::report_rejection(config.error_format, ids, &error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TransactionType;
+    use std::thread;
+
+    #[test]
+    fn can_push_from_multiple_threads() {
+        let engine = ConcurrentEngine::new(EngineConfig::default());
+
+        thread::scope(|scope| {
+            for client_id in 1..=4u16 {
+                let engine = &engine;
+                scope.spawn(move || {
+                    for tx_id in 0..10u32 {
+                        let tx_id = u32::from(client_id) * 100 + tx_id;
+                        engine.push(Transaction::raw(
+                            TransactionType::Deposit,
+                            tx_id,
+                            client_id,
+                            Some(1.0),
+                        ));
+                    }
+                });
+            }
+        });
+
+        let clients = engine.finish();
+        assert_eq!(clients.len(), 4);
+        for (client_id, client) in &clients {
+            assert_eq!(client.get_record(*client_id)[1], "10.0000");
+        }
+    }
+}
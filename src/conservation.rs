@@ -0,0 +1,362 @@
+// Checks that funds are neither created nor lost inside the engine. Every client keeps a tally
+// of the rows applied to it, counted from the rows' own amounts apart from the engine's balance
+// arithmetic: deposits, withdrawals and their fees, chargebacks, refunds, settled batches,
+// credited interest and the holds of disputed withdrawals. Each client's total has to equal
+// what its tally adds up to.
+use crate::client::Clients;
+use crate::engine::{EngineConfig, ResolvePolicy};
+use crate::ids::ClientId;
+use crate::money::Money;
+use crate::sorted;
+use crate::transaction::{Transaction, TransactionType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+// System wide totals, every amount is what the operations added up to across all clients
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Totals {
+    pub deposits: Money,
+    // Taken out, so positive
+    pub withdrawals: Money,
+    pub chargebacks: Money,
+    // Net of refunds, settlements, interest and holds of disputed withdrawals
+    pub other: Money,
+    // Sum of every client's total
+    pub clients: Money,
+}
+
+impl Totals {
+    // What the clients should hold between them
+    pub fn expected(&self) -> Money {
+        Money::from_minor_units(
+            self.deposits
+                .minor_units()
+                .saturating_sub(self.withdrawals.minor_units())
+                .saturating_sub(self.chargebacks.minor_units())
+                .saturating_add(self.other.minor_units()),
+        )
+    }
+
+    fn add(&mut self, other: &Totals) {
+        self.deposits = self.deposits.saturating_add(other.deposits);
+        self.withdrawals = self.withdrawals.saturating_add(other.withdrawals);
+        self.chargebacks = self.chargebacks.saturating_add(other.chargebacks);
+        self.other = self.other.saturating_add(other.other);
+    }
+}
+
+// What a row applied to a client moves, taken before the engine consumes the row
+#[derive(Debug, Clone)]
+pub(crate) struct Row {
+    tx_type: TransactionType,
+    tx_id: u32,
+    client_id: ClientId,
+    amount: Money,
+    batch: Option<u32>,
+}
+
+impl Row {
+    pub(crate) fn of(tx: &Transaction) -> Self {
+        Row {
+            tx_type: tx.tx_type().clone(),
+            tx_id: tx.raw_tx_id(),
+            client_id: tx.client_id(),
+            amount: tx.raw_amount().unwrap_or_default(),
+            batch: tx.batch(),
+        }
+    }
+}
+
+// What the rows applied to one client add up to. Clients rebuilt from a store or a snapshot
+// written before tallies were kept start from an empty one
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Tally {
+    totals: Totals,
+    // Refunded so far per deposit, by refunds and resolves refunded to the source
+    #[serde(serialize_with = "sorted::map")]
+    refunded: HashMap<u32, Money>,
+    // What each open dispute holds
+    #[serde(serialize_with = "sorted::map")]
+    holds: HashMap<u32, Money>,
+    // Net of each batch still waiting for its settlement
+    #[serde(serialize_with = "sorted::map")]
+    batches: HashMap<u32, Money>,
+}
+
+impl Tally {
+    pub fn totals(&self) -> &Totals {
+        &self.totals
+    }
+
+    // Adds an applied `row`. Disputes, resolves, chargebacks and refunds take their amount from
+    // `referenced`, the row they reference, and disputes hold `capped` instead when the engine
+    // capped them, the one figure taken from its bookkeeping
+    pub(crate) fn record(
+        &mut self,
+        row: &Row,
+        referenced: Option<&Transaction>,
+        capped: Option<Money>,
+        config: &EngineConfig,
+    ) {
+        let totals = &mut self.totals;
+        let withdrawal = referenced.is_some_and(|tx| tx.tx_type() == &TransactionType::Withdrawal);
+        match (&row.tx_type, row.batch) {
+            (TransactionType::Deposit, Some(batch)) => {
+                let net = self.batches.entry(batch).or_default();
+                *net = net.saturating_add(row.amount);
+            }
+            (TransactionType::Withdrawal, Some(batch)) => {
+                let net = self.batches.entry(batch).or_default();
+                *net = net.saturating_sub(row.amount);
+            }
+            (TransactionType::Deposit, None) => {
+                totals.deposits = totals.deposits.saturating_add(row.amount)
+            }
+            (TransactionType::Withdrawal, None) => {
+                let fee = config
+                    .tiers
+                    .get(&row.client_id)
+                    .map(|tier| tier.withdrawal_fee)
+                    .unwrap_or_default();
+                totals.withdrawals = totals
+                    .withdrawals
+                    .saturating_add(row.amount.saturating_add(fee));
+            }
+            (TransactionType::Settlement, batch) => {
+                let net = batch
+                    .and_then(|batch| self.batches.remove(&batch))
+                    .unwrap_or_default();
+                totals.other = totals.other.saturating_add(net);
+            }
+            (TransactionType::Refund, _) => {
+                totals.other = totals.other.saturating_sub(row.amount);
+                self.refund(row.tx_id, row.amount);
+            }
+            (TransactionType::Dispute, _) => {
+                let amount = referenced
+                    .and_then(Transaction::raw_amount)
+                    .unwrap_or_default()
+                    .saturating_sub(self.refunded.get(&row.tx_id).copied().unwrap_or_default());
+                let held = capped.unwrap_or(amount);
+                self.holds.insert(row.tx_id, held);
+                // A disputed withdrawal already left available, so its hold is added on top
+                if withdrawal {
+                    totals.other = totals.other.saturating_add(held);
+                }
+            }
+            (TransactionType::Resolve, _) => {
+                let held = self.holds.remove(&row.tx_id).unwrap_or_default();
+                match config.resolve {
+                    _ if withdrawal => totals.other = totals.other.saturating_sub(held),
+                    ResolvePolicy::Release => {}
+                    ResolvePolicy::RefundToSource => {
+                        totals.other = totals.other.saturating_sub(held);
+                        self.refund(row.tx_id, held);
+                    }
+                }
+            }
+            // Charging a withdrawal back keeps the hold its dispute added, now as available
+            (TransactionType::Chargeback, _) => {
+                let held = self.holds.remove(&row.tx_id).unwrap_or_default();
+                if !withdrawal {
+                    totals.chargebacks = totals.chargebacks.saturating_add(held);
+                }
+            }
+            (TransactionType::Interest, _) => {}
+        }
+    }
+
+    // Interest the engine credited, it has no row of its own
+    pub(crate) fn credit_interest(&mut self, interest: Money) {
+        self.totals.other = self.totals.other.saturating_add(interest);
+    }
+
+    // Forgets a transaction retention dropped
+    pub(crate) fn forget(&mut self, tx_id: u32) {
+        self.refunded.remove(&tx_id);
+    }
+
+    fn refund(&mut self, tx_id: u32, amount: Money) {
+        let refunded = self.refunded.entry(tx_id).or_default();
+        *refunded = refunded.saturating_add(amount);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Leak {
+    // The client's total differs from what the rows applied to it add up to
+    Balance {
+        client_id: ClientId,
+        expected: Money,
+        actual: Money,
+    },
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Leak::Balance {
+                client_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Client {} holds {} but its deposits, withdrawals and chargebacks add up to {}",
+                client_id, actual, expected
+            ),
+        }
+    }
+}
+
+// Totals across `clients` and every leak found, ordered by client id. Clients rebuilt with
+// funds from before their tally, e.g. loaded from a store, show up as leaks of their starting
+// balance
+pub fn check(clients: &Clients) -> (Totals, Vec<Leak>) {
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+
+    let mut totals = Totals::default();
+    let mut leaks = Vec::new();
+    for client_id in client_ids {
+        let client = &clients[client_id];
+        let tally = client.tally().totals();
+        totals.add(tally);
+        let expected = tally.expected();
+        let funds = client.funds();
+        let actual = funds.available().saturating_add(funds.held());
+        totals.clients = totals.clients.saturating_add(actual);
+        if actual != expected {
+            leaks.push(Leak::Balance {
+                client_id: *client_id,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    (totals, leaks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{Client, DisputedTransactions, Funds, Transactions};
+    use crate::engine::{DisputeOverdraftPolicy, TierLimits, TransactionEngine};
+    use crate::ids::{ClientId, TxId};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn finds_funds_the_rows_dont_add_up_to() {
+        let mut engine = TransactionEngine::default();
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Deposit, 2, 2, Some(3.0)),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(1.5)),
+            Transaction::raw(TransactionType::Dispute, 2, 2, None),
+            Transaction::raw(TransactionType::Chargeback, 2, 2, None),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Dispute, 3, 1, None),
+        ] {
+            engine.push(tx);
+        }
+        let mut clients = engine.finish();
+
+        let (totals, leaks) = check(&clients);
+        assert_eq!(leaks, vec![]);
+        assert_eq!(totals.deposits, Money::from(8.0));
+        assert_eq!(totals.withdrawals, Money::from(1.5));
+        assert_eq!(totals.chargebacks, Money::from(3.0));
+        assert_eq!(totals.other, Money::from(1.5));
+        assert_eq!(totals.expected(), Money::from(5.0));
+        assert_eq!(totals.clients, totals.expected());
+
+        // Funds that never went through a recorded deposit
+        clients.insert(
+            ClientId::new(3),
+            Client::from_parts(
+                Funds::from_balances(Money::from(2.0), Money::ZERO),
+                Transactions::default(),
+                DisputedTransactions::default(),
+                0,
+                false,
+            ),
+        );
+        let (totals, leaks) = check(&clients);
+        assert_eq!(totals.clients, Money::from(7.0));
+        assert_eq!(
+            leaks.iter().map(Leak::to_string).collect::<Vec<_>>(),
+            vec!["Client 3 holds 2.0000 but its deposits, withdrawals and chargebacks add up to 0.0000"]
+        );
+    }
+
+    #[test]
+    fn counts_each_row_as_its_policy_applies_it() {
+        let mut engine = TransactionEngine::new(EngineConfig {
+            dispute_overdraft: DisputeOverdraftPolicy::Cap,
+            resolve: ResolvePolicy::RefundToSource,
+            tiers: HashMap::from([(
+                ClientId::new(1),
+                TierLimits {
+                    tier: "basic".to_string(),
+                    withdrawal_limit: None,
+                    withdrawal_fee: Money::from(0.5),
+                },
+            )]),
+            ..Default::default()
+        });
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(3.0)),
+            // Capped at the 1.5 left
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Resolve, 1, 1, None),
+            Transaction::raw(TransactionType::Deposit, 3, 2, Some(4.0)),
+            Transaction::raw(TransactionType::Refund, 3, 2, Some(1.0)),
+            Transaction::raw(TransactionType::Dispute, 3, 2, None),
+            Transaction::raw(TransactionType::Chargeback, 3, 2, None),
+            Transaction::raw(TransactionType::Deposit, 4, 3, Some(2.0)).with_batch(7),
+            Transaction::raw(TransactionType::Withdrawal, 5, 3, Some(0.5)).with_batch(7),
+            Transaction::raw(TransactionType::Settlement, 6, 3, None).with_batch(7),
+            Transaction::raw(TransactionType::Withdrawal, 7, 3, Some(1.0)),
+            Transaction::raw(TransactionType::Dispute, 7, 3, None),
+            Transaction::raw(TransactionType::Chargeback, 7, 3, None),
+        ] {
+            engine.submit(tx).unwrap();
+        }
+        let at = Utc.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).unwrap();
+        assert_eq!(engine.accrue_interest(0.1, at), Ok(1));
+
+        let clients = engine.finish();
+        let (totals, leaks) = check(&clients);
+        assert_eq!(leaks, vec![]);
+        assert_eq!(totals.deposits, Money::from(9.0));
+        assert_eq!(totals.withdrawals, Money::from(4.5));
+        assert_eq!(totals.chargebacks, Money::from(3.0));
+        assert_eq!(totals.clients, totals.expected());
+        assert_eq!(
+            clients[&ClientId::new(3)].funds().available(),
+            Money::from(1.65)
+        );
+    }
+
+    // A withdrawal stored without being debited leaves the audit trail adding up, but not
+    // the rows
+    #[test]
+    fn finds_rows_the_engine_never_applied_to_the_funds() {
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 1, 1, Some(2.0));
+        let clients =
+            Clients::from_iter([(ClientId::new(1), Client::new(TxId::new(1), withdrawal))]);
+
+        let (totals, leaks) = check(&clients);
+        assert_eq!(totals.withdrawals, Money::from(2.0));
+        assert_eq!(
+            leaks,
+            vec![Leak::Balance {
+                client_id: ClientId::new(1),
+                expected: Money::from(-2.0),
+                actual: Money::ZERO,
+            }]
+        );
+    }
+}
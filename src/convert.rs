@@ -0,0 +1,296 @@
+// Converts transaction files and accounts between csv, JSON lines and Parquet, picking each
+// format from the file extension. Known columns keep their type across formats: ids are
+// integers, balances decimals and the locked and frozen flags booleans, the rest is text.
+use crate::csv_reader;
+use crate::money::Money;
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::path::Path;
+
+#[cfg(feature = "parquet")]
+mod parquet;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    JsonLines,
+    Parquet,
+}
+
+impl Format {
+    pub fn of(path: &str) -> Result<Format, String> {
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("csv") => Ok(Format::Csv),
+            Some("jsonl" | "ndjson") => Ok(Format::JsonLines),
+            Some("parquet") => Ok(Format::Parquet),
+            _ => Err(format!(
+                "Can't tell the format of {}, use a .csv, .jsonl or .parquet extension",
+                path
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ColumnType {
+    ClientId,
+    TxId,
+    Money,
+    Flag,
+    Text,
+}
+
+impl ColumnType {
+    pub(crate) fn of(column: &str) -> ColumnType {
+        match column {
+            "client" => ColumnType::ClientId,
+            "tx" | "batch" => ColumnType::TxId,
+            "amount" | "available" | "held" | "total" | "credit_used" => ColumnType::Money,
+            "locked" | "frozen" => ColumnType::Flag,
+            _ => ColumnType::Text,
+        }
+    }
+}
+
+// Rows of text fields under their headers, an empty field is a missing value
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+pub fn convert(input: &str, output: &str) -> Result<(), String> {
+    let table = match Format::of(input)? {
+        Format::Csv => read_csv(input)?,
+        Format::JsonLines => read_json_lines(input)?,
+        Format::Parquet => read_parquet(input)?,
+    };
+
+    let output_format = Format::of(output)?;
+    let file = File::create(output).map_err(|error| format!("{}: {}", output, error))?;
+    match output_format {
+        Format::Csv => write_csv(&table, file),
+        Format::JsonLines => write_json_lines(&table, BufWriter::new(file)),
+        Format::Parquet => write_parquet(&table, file),
+    }
+}
+
+pub fn read_csv(path: &str) -> Result<Table, String> {
+    let mut rdr = csv_reader(path)?;
+    let headers: Vec<String> = rdr
+        .headers()
+        .map_err(|error| format!("Error reading {} headers: {}", path, error))?
+        .iter()
+        .map(str::to_string)
+        .collect();
+
+    let mut rows = Vec::new();
+    for result in rdr.records() {
+        let record = result.map_err(|error| format!("Error parsing {}: {}", path, error))?;
+        // Short rows, e.g. disputes without an amount, are padded
+        let row = (0..headers.len())
+            .map(|column| record.get(column).unwrap_or_default().to_string())
+            .collect();
+        rows.push(row);
+    }
+
+    Ok(Table { headers, rows })
+}
+
+pub fn write_csv<W: io::Write>(table: &Table, writer: W) -> Result<(), String> {
+    let write_error = |error: csv::Error| format!("Error writing csv: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    wtr.write_record(&table.headers).map_err(write_error)?;
+    for row in &table.rows {
+        wtr.write_record(row).map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing csv: {}", error))
+}
+
+// Headers are every key in the order they first appear
+pub fn read_json_lines(path: &str) -> Result<Table, String> {
+    let file = File::open(path).map_err(|error| format!("{}: {}", path, error))?;
+
+    let mut objects = Vec::new();
+    let mut headers: Vec<String> = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|error| format!("{}: {}", path, error))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object: JsonRow = serde_json::from_str(&line)
+            .map_err(|error| format!("{} line {}: {}", path, index + 1, error))?;
+        for (key, _) in &object.0 {
+            if !headers.contains(key) {
+                headers.push(key.clone());
+            }
+        }
+        objects.push(object);
+    }
+
+    let rows = objects
+        .into_iter()
+        .map(|object| {
+            headers
+                .iter()
+                .map(
+                    |header| match object.0.iter().find(|(key, _)| key == header) {
+                        Some((_, Value::String(text))) => text.clone(),
+                        Some((_, Value::Null)) | None => String::new(),
+                        Some((_, value)) => value.to_string(),
+                    },
+                )
+                .collect()
+        })
+        .collect();
+
+    Ok(Table { headers, rows })
+}
+
+// Missing values are left out of their object, amounts are decimal text so none are rounded
+pub fn write_json_lines<W: io::Write>(table: &Table, mut writer: W) -> Result<(), String> {
+    let write_error = |error: io::Error| format!("Error writing JSON lines: {}", error);
+
+    for row in &table.rows {
+        let mut object = Vec::new();
+        for (header, field) in table.headers.iter().zip(row) {
+            if field.is_empty() {
+                continue;
+            }
+            let value = match ColumnType::of(header) {
+                ColumnType::ClientId | ColumnType::TxId => field
+                    .parse::<u64>()
+                    .map(Value::from)
+                    .map_err(|_| format!("Invalid {} {:?}", header, field))?,
+                ColumnType::Flag => field
+                    .parse::<bool>()
+                    .map(Value::from)
+                    .map_err(|_| format!("Invalid {} {:?}", header, field))?,
+                ColumnType::Money => Value::from(field.parse::<Money>()?.to_string()),
+                ColumnType::Text => Value::from(field.as_str()),
+            };
+            object.push((header.clone(), value));
+        }
+
+        serde_json::to_writer(&mut writer, &JsonRow(object))
+            .map_err(|error| format!("Error writing JSON lines: {}", error))?;
+        writeln!(writer).map_err(write_error)?;
+    }
+
+    writer.flush().map_err(write_error)
+}
+
+// A JSON object keeping its keys in order, `serde_json::Map` sorts them
+struct JsonRow(Vec<(String, Value)>);
+
+impl Serialize for JsonRow {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonRow {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RowVisitor;
+
+        impl<'de> Visitor<'de> for RowVisitor {
+            type Value = JsonRow;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonRow, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(JsonRow(entries))
+            }
+        }
+
+        deserializer.deserialize_map(RowVisitor)
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet(path: &str) -> Result<Table, String> {
+    parquet::read(File::open(path).map_err(|error| format!("{}: {}", path, error))?)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn read_parquet(_path: &str) -> Result<Table, String> {
+    Err("Reading Parquet needs the parquet feature, rebuild with --features parquet".to_string())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(table: &Table, file: File) -> Result<(), String> {
+    parquet::write(table, file)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_table: &Table, _file: File) -> Result<(), String> {
+    Err("Writing Parquet needs the parquet feature, rebuild with --features parquet".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn round_trips_through_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv = dir.path().join("accounts.csv");
+        fs::write(
+            &csv,
+            "client,available,held,total,locked,frozen,credit_used\n\
+             1,1.5000,0.0000,1.5000,false,false,0.0000\n",
+        )
+        .unwrap();
+        let jsonl = dir.path().join("accounts.jsonl");
+        let (csv, jsonl) = (csv.to_str().unwrap(), jsonl.to_str().unwrap());
+
+        convert(csv, jsonl).unwrap();
+        assert_eq!(
+            fs::read_to_string(jsonl).unwrap(),
+            "{\"client\":1,\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\",\
+             \"locked\":false,\"frozen\":false,\"credit_used\":\"0.0000\"}\n"
+        );
+        assert_eq!(read_json_lines(jsonl).unwrap(), read_csv(csv).unwrap());
+    }
+
+    #[test]
+    fn leaves_missing_values_out() {
+        let table = Table {
+            headers: vec![
+                "type".to_string(),
+                "client".to_string(),
+                "amount".to_string(),
+            ],
+            rows: vec![vec!["dispute".to_string(), "2".to_string(), String::new()]],
+        };
+        let mut output = Vec::new();
+        write_json_lines(&table, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"type\":\"dispute\",\"client\":2}\n"
+        );
+        assert!(Format::of("accounts.txt").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+// Every column is optional so missing values, e.g. a dispute's amount, round trip as nulls.
+// Balances are stored as DECIMAL(18, 4) over their 1/10000 units like the balance history
+use super::{ColumnType, Table};
+use crate::money::Money;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, Int32Type, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::record::Field;
+use parquet::schema::parser::parse_message_type;
+use std::fs::File;
+use std::sync::Arc;
+
+pub fn read(file: File) -> Result<Table, String> {
+    let read_error = |error: ParquetError| format!("Error reading parquet: {}", error);
+    let reader = SerializedFileReader::new(file).map_err(read_error)?;
+    let headers: Vec<String> = reader
+        .metadata()
+        .file_metadata()
+        .schema_descr()
+        .columns()
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect();
+
+    let mut rows = Vec::new();
+    for row in reader.get_row_iter(None).map_err(read_error)? {
+        let row = row.map_err(read_error)?;
+        rows.push(
+            row.get_column_iter()
+                .map(|(_, field)| match field {
+                    Field::Null => String::new(),
+                    Field::Str(text) => text.clone(),
+                    field => field.to_string(),
+                })
+                .collect(),
+        );
+    }
+
+    Ok(Table { headers, rows })
+}
+
+pub fn write(table: &Table, file: File) -> Result<(), String> {
+    let columns = table
+        .headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| Column::read(table, index, ColumnType::of(header)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    write_columns(table, &columns, file)
+        .map_err(|error| format!("Error writing parquet: {}", error))
+}
+
+// One column's values without its missing ones, and which rows have a value
+enum Values {
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Bool(Vec<bool>),
+    Text(Vec<ByteArray>),
+}
+
+struct Column {
+    values: Values,
+    levels: Vec<i16>,
+}
+
+impl Column {
+    fn read(table: &Table, index: usize, column_type: ColumnType) -> Result<Column, String> {
+        let header = &table.headers[index];
+        let fields: Vec<&str> = table
+            .rows
+            .iter()
+            .map(|row| row.get(index).map_or("", String::as_str))
+            .collect();
+        let levels = fields
+            .iter()
+            .map(|field| i16::from(!field.is_empty()))
+            .collect();
+        let present = fields.into_iter().filter(|field| !field.is_empty());
+        let invalid = |field: &str| format!("Invalid {} {:?}", header, field);
+
+        let values = match column_type {
+            ColumnType::ClientId => Values::Int32(
+                present
+                    .map(|field| {
+                        field
+                            .parse::<u16>()
+                            .map(i32::from)
+                            .map_err(|_| invalid(field))
+                    })
+                    .collect::<Result<_, String>>()?,
+            ),
+            // Unsigned 32 bit values are stored in INT32 columns with the same bits
+            ColumnType::TxId => Values::Int32(
+                present
+                    .map(|field| {
+                        field
+                            .parse::<u32>()
+                            .map(|id| id as i32)
+                            .map_err(|_| invalid(field))
+                    })
+                    .collect::<Result<_, String>>()?,
+            ),
+            ColumnType::Money => Values::Int64(
+                present
+                    .map(|field| field.parse::<Money>().map(|amount| amount.minor_units()))
+                    .collect::<Result<_, String>>()?,
+            ),
+            ColumnType::Flag => Values::Bool(
+                present
+                    .map(|field| field.parse::<bool>().map_err(|_| invalid(field)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            ColumnType::Text => Values::Text(present.map(ByteArray::from).collect()),
+        };
+
+        Ok(Column { values, levels })
+    }
+}
+
+fn schema(table: &Table) -> String {
+    let fields: String = table
+        .headers
+        .iter()
+        .map(|header| match ColumnType::of(header) {
+            ColumnType::ClientId => format!("OPTIONAL INT32 {} (INTEGER(16, false));", header),
+            ColumnType::TxId => format!("OPTIONAL INT32 {} (INTEGER(32, false));", header),
+            ColumnType::Money => format!("OPTIONAL INT64 {} (DECIMAL(18, 4));", header),
+            ColumnType::Flag => format!("OPTIONAL BOOLEAN {};", header),
+            ColumnType::Text => format!("OPTIONAL BYTE_ARRAY {} (STRING);", header),
+        })
+        .collect();
+    format!("message table {{ {} }}", fields)
+}
+
+fn write_columns(table: &Table, columns: &[Column], file: File) -> Result<(), ParquetError> {
+    let schema = Arc::new(parse_message_type(&schema(table))?);
+    let mut writer =
+        SerializedFileWriter::new(file, schema, Arc::new(WriterProperties::builder().build()))?;
+    let mut row_group = writer.next_row_group()?;
+
+    let mut columns = columns.iter();
+    while let Some(mut writer) = row_group.next_column()? {
+        let Some(column) = columns.next() else {
+            break;
+        };
+        let levels = Some(column.levels.as_slice());
+        match &column.values {
+            Values::Int32(values) => writer
+                .typed::<Int32Type>()
+                .write_batch(values, levels, None)?,
+            Values::Int64(values) => writer
+                .typed::<Int64Type>()
+                .write_batch(values, levels, None)?,
+            Values::Bool(values) => writer
+                .typed::<BoolType>()
+                .write_batch(values, levels, None)?,
+            Values::Text(values) => writer
+                .typed::<ByteArrayType>()
+                .write_batch(values, levels, None)?,
+        };
+        writer.close()?;
+    }
+
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_transactions() {
+        let table = Table {
+            headers: ["type", "client", "tx", "amount", "memo"]
+                .map(str::to_string)
+                .to_vec(),
+            rows: vec![
+                ["deposit", "1", "4294967295", "1.5000", "rent"]
+                    .map(str::to_string)
+                    .to_vec(),
+                ["dispute", "1", "4294967295", "", ""]
+                    .map(str::to_string)
+                    .to_vec(),
+            ],
+        };
+        let file = tempfile::tempfile().unwrap();
+        write(&table, file.try_clone().unwrap()).unwrap();
+
+        assert_eq!(read(file).unwrap(), table);
+    }
+}
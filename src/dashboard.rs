@@ -0,0 +1,106 @@
+// Live view of a run for operators watching it: per-client balances, the latest rejected
+// transactions and the throughput, redrawn while the input is processed. The terminal UI
+// needs the tui feature, the figures it shows are kept here.
+use crate::client::Clients;
+use crate::engine::TransactionEngine;
+use crate::transaction::Transaction;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tui")]
+mod tui;
+
+// Number of rejected transactions shown
+const RECENT_ERRORS: usize = 10;
+
+#[derive(Debug)]
+pub struct Progress {
+    pub processed: u64,
+    pub rejected: u64,
+    pub recent_errors: VecDeque<String>,
+    pub started: Instant,
+    pub finished: Option<Duration>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress {
+            processed: 0,
+            rejected: 0,
+            recent_errors: VecDeque::new(),
+            started: Instant::now(),
+            finished: None,
+        }
+    }
+}
+
+impl Progress {
+    pub fn record(&mut self, result: Result<(), String>) {
+        self.processed += 1;
+        if let Err(error) = result {
+            self.rejected += 1;
+            if self.recent_errors.len() == RECENT_ERRORS {
+                self.recent_errors.pop_front();
+            }
+            self.recent_errors.push_back(error);
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.finished.unwrap_or_else(|| self.started.elapsed())
+    }
+
+    // Transactions per second since the run started
+    pub fn throughput(&self) -> f64 {
+        let seconds = self.elapsed().as_secs_f64();
+        if seconds > 0.0 {
+            self.processed as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+// Processes `transactions` while drawing the dashboard, which stays up once the input is done
+// until q is pressed. Pressing q earlier stops reading the input
+pub fn process(
+    engine: TransactionEngine,
+    transactions: impl IntoIterator<Item = Transaction>,
+) -> Result<Clients, String> {
+    run(engine, transactions)
+}
+
+#[cfg(feature = "tui")]
+fn run(
+    engine: TransactionEngine,
+    transactions: impl IntoIterator<Item = Transaction>,
+) -> Result<Clients, String> {
+    tui::run(engine, transactions)
+}
+
+#[cfg(not(feature = "tui"))]
+fn run(
+    _engine: TransactionEngine,
+    _transactions: impl IntoIterator<Item = Transaction>,
+) -> Result<Clients, String> {
+    Err("The dashboard needs the tui feature, rebuild with --features tui".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_errors() {
+        let mut progress = Progress::default();
+        for tx in 0..15 {
+            progress.record(Err(format!("tx {}", tx)));
+        }
+        progress.record(Ok(()));
+
+        assert_eq!(progress.processed, 16);
+        assert_eq!(progress.rejected, 15);
+        assert_eq!(progress.recent_errors.len(), RECENT_ERRORS);
+        assert_eq!(progress.recent_errors.front().unwrap(), "tx 5");
+    }
+}
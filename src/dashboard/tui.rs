@@ -0,0 +1,138 @@
+// Draws the dashboard with ratatui on the alternate screen, at most every REDRAW_EVERY so
+// drawing doesn't slow processing down
+use super::{Progress, RECENT_ERRORS};
+use crate::client::Clients;
+use crate::engine::TransactionEngine;
+use crate::ids::ClientId;
+use crate::transaction::Transaction;
+use ratatui::crossterm::event::{self, Event, KeyCode};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::widgets::{Block, List, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::time::{Duration, Instant};
+
+const REDRAW_EVERY: Duration = Duration::from_millis(100);
+
+pub fn run(
+    mut engine: TransactionEngine,
+    transactions: impl IntoIterator<Item = Transaction>,
+) -> Result<Clients, String> {
+    let mut terminal = ratatui::init();
+    let result = show(&mut terminal, &mut engine, transactions);
+    ratatui::restore();
+
+    result.map(|()| engine.finish())
+}
+
+fn show(
+    terminal: &mut DefaultTerminal,
+    engine: &mut TransactionEngine,
+    transactions: impl IntoIterator<Item = Transaction>,
+) -> Result<(), String> {
+    let mut progress = Progress::default();
+    let mut drawn: Option<Instant> = None;
+
+    for tx in transactions {
+        progress.record(engine.submit(tx).map_err(String::from));
+
+        if drawn.is_none_or(|drawn| drawn.elapsed() >= REDRAW_EVERY) {
+            draw(terminal, engine.clients(), &progress)?;
+            if quit_pressed(Duration::ZERO)? {
+                return Ok(());
+            }
+            drawn = Some(Instant::now());
+        }
+    }
+
+    progress.finished = Some(progress.started.elapsed());
+    loop {
+        draw(terminal, engine.clients(), &progress)?;
+        if quit_pressed(REDRAW_EVERY)? {
+            return Ok(());
+        }
+    }
+}
+
+fn quit_pressed(timeout: Duration) -> Result<bool, String> {
+    let read_error = |error: std::io::Error| format!("Error reading key: {}", error);
+
+    if !event::poll(timeout).map_err(read_error)? {
+        return Ok(false);
+    }
+    match event::read().map_err(read_error)? {
+        Event::Key(key) => Ok(matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)),
+        _ => Ok(false),
+    }
+}
+
+fn draw(
+    terminal: &mut DefaultTerminal,
+    clients: &Clients,
+    progress: &Progress,
+) -> Result<(), String> {
+    terminal
+        .draw(|frame| render(frame, clients, progress))
+        .map(|_| ())
+        .map_err(|error| format!("Error drawing dashboard: {}", error))
+}
+
+fn render(frame: &mut Frame, clients: &Clients, progress: &Progress) {
+    let [summary, balances, errors] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(4),
+        Constraint::Length(RECENT_ERRORS as u16 + 2),
+    ])
+    .areas(frame.area());
+
+    let status = match progress.finished {
+        Some(_) => "done, press q to quit",
+        None => "processing, press q to stop",
+    };
+    let summary_text = format!(
+        "{} processed   {} rejected   {:.0} tx/s   {:.1}s   {}",
+        progress.processed,
+        progress.rejected,
+        progress.throughput(),
+        progress.elapsed().as_secs_f64(),
+        status
+    );
+    frame.render_widget(
+        Paragraph::new(summary_text).block(Block::bordered().title("funds")),
+        summary,
+    );
+
+    // Only as many clients as fit, inside the borders and below the header
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+    let rows = client_ids
+        .into_iter()
+        .take(balances.height.saturating_sub(3) as usize)
+        .map(|client_id| Row::new(clients[client_id].get_record(*client_id)));
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8),
+            Constraint::Length(20),
+            Constraint::Length(20),
+            Constraint::Length(20),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(20),
+        ],
+    )
+    .header(Row::new([
+        "client",
+        "available",
+        "held",
+        "total",
+        "locked",
+        "frozen",
+        "credit_used",
+    ]))
+    .block(Block::bordered().title(format!("balances ({} clients)", clients.len())));
+    frame.render_widget(table, balances);
+
+    let recent = List::new(progress.recent_errors.iter().map(String::as_str))
+        .block(Block::bordered().title("recent errors"));
+    frame.render_widget(recent, errors);
+}
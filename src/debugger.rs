@@ -0,0 +1,260 @@
+// Steps through an input file one transaction at a time, e.g. to follow a dispute edge case.
+// Execution stops before a transaction matching a breakpoint, so the state it meets can be
+// looked at before `step` applies it.
+use crate::engine::TransactionEngine;
+use crate::ids::{ClientId, TxId};
+use crate::interrupt;
+use crate::transaction::Transaction;
+use crate::{inspect, repl};
+use std::io::{BufRead, Write};
+
+const HELP: &str = "\
+step [N]            apply the next transaction, or the next N
+continue            apply transactions until a breakpoint or the end of the input
+next                show the next transaction without applying it
+break client|tx ID  stop before transactions of that client or with that tx id
+breakpoints         list the breakpoints
+delete              remove every breakpoint
+client ID           show the state of one client
+dump                show the state of every client and what is held back
+help
+quit";
+
+#[derive(Debug, PartialEq)]
+enum Breakpoint {
+    Client(ClientId),
+    Tx(TxId),
+}
+
+impl Breakpoint {
+    fn matches(&self, tx: &Transaction) -> bool {
+        match self {
+            Breakpoint::Client(client_id) => tx.client_id() == *client_id,
+            Breakpoint::Tx(tx_id) => tx.tx_id() == *tx_id,
+        }
+    }
+}
+
+struct Session {
+    engine: TransactionEngine,
+    transactions: Vec<Transaction>,
+    // Index of the next transaction to apply
+    position: usize,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Session {
+    fn next(&self) -> Result<String, String> {
+        self.transactions
+            .get(self.position)
+            .map(|tx| describe(self.position, tx))
+            .ok_or("End of input".to_string())
+    }
+
+    // Applies up to `count` transactions, stopping early before a breakpoint once at least
+    // one was applied
+    fn step(&mut self, count: usize, until_breakpoint: bool) -> Result<String, String> {
+        if self.position == self.transactions.len() {
+            return Err("End of input".to_string());
+        }
+
+        let mut lines = Vec::new();
+        for applied in 0..count {
+            let Some(tx) = self.transactions.get(self.position) else {
+                lines.push("end of input".to_string());
+                break;
+            };
+            if until_breakpoint && applied > 0 {
+                if let Some(breakpoint) = self.breakpoints.iter().find(|b| b.matches(tx)) {
+                    lines.push(format!(
+                        "breakpoint {} before {}",
+                        name(breakpoint),
+                        describe(self.position, tx)
+                    ));
+                    break;
+                }
+            }
+
+            let outcome = match self.engine.submit(tx.clone()) {
+                Ok(()) => "ok".to_string(),
+                Err(error) => format!("error: {}", error),
+            };
+            lines.push(format!("{} {}", describe(self.position, tx), outcome));
+            self.position += 1;
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    fn client(&self, client_id: ClientId) -> Result<String, String> {
+        let mut state = Vec::new();
+        inspect::write_state(self.engine.clients(), Some(client_id), &mut state)
+            .map_err(|_| format!("No client {}", client_id))?;
+        Ok(String::from_utf8_lossy(&state).trim_end().to_string())
+    }
+
+    fn dump(&self) -> Result<String, String> {
+        let mut state = Vec::new();
+        inspect::write_engine(&self.engine, &mut state)?;
+        Ok(String::from_utf8_lossy(&state).trim_end().to_string())
+    }
+}
+
+// Runs commands read from `input` until it ends, `quit` is entered or Ctrl-C is pressed
+pub fn run<R: BufRead, W: Write>(
+    engine: TransactionEngine,
+    transactions: Vec<Transaction>,
+    input: R,
+    mut output: W,
+) -> Result<(), String> {
+    let write_error = |error: std::io::Error| format!("Error writing to std out: {}", error);
+    let mut session = Session {
+        engine,
+        transactions,
+        position: 0,
+        breakpoints: Vec::new(),
+    };
+
+    write!(
+        output,
+        "{} transactions loaded\n> ",
+        session.transactions.len()
+    )
+    .map_err(write_error)?;
+    output.flush().map_err(write_error)?;
+
+    for line in input.lines() {
+        let line = line.map_err(|error| format!("Error reading input: {}", error))?;
+        if interrupt::requested() {
+            return Ok(());
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let reply = match words.as_slice() {
+            [] => Ok(String::new()),
+            ["quit" | "exit"] => return Ok(()),
+            ["help"] => Ok(HELP.to_string()),
+            ["step" | "s"] => session.step(1, false),
+            ["step" | "s", count] => {
+                repl::parse::<usize>(count, "count").and_then(|count| session.step(count, false))
+            }
+            ["continue" | "c"] => session.step(usize::MAX, true),
+            ["next" | "n"] => session.next(),
+            ["break", "client", client] => repl::parse(client, "client").map(|client_id| {
+                session.breakpoints.push(Breakpoint::Client(client_id));
+                format!("breakpoint client {}", client_id)
+            }),
+            ["break", "tx", tx] => repl::parse(tx, "tx").map(|tx_id| {
+                session.breakpoints.push(Breakpoint::Tx(tx_id));
+                format!("breakpoint tx {}", tx_id)
+            }),
+            ["breakpoints"] => Ok(session
+                .breakpoints
+                .iter()
+                .map(name)
+                .collect::<Vec<_>>()
+                .join("\n")),
+            ["delete"] => {
+                session.breakpoints.clear();
+                Ok("breakpoints removed".to_string())
+            }
+            ["client", client] => {
+                repl::parse(client, "client").and_then(|client_id| session.client(client_id))
+            }
+            ["dump"] => session.dump(),
+            [command, ..] => Err(format!("Unknown command {}, see help", command)),
+        };
+
+        match reply {
+            Ok(reply) if reply.is_empty() => {}
+            Ok(reply) => writeln!(output, "{}", reply).map_err(write_error)?,
+            Err(error) => writeln!(output, "error: {}", error).map_err(write_error)?,
+        }
+        write!(output, "> ").map_err(write_error)?;
+        output.flush().map_err(write_error)?;
+    }
+
+    Ok(())
+}
+
+// Rows are numbered from 1, not counting the header
+fn describe(position: usize, tx: &Transaction) -> String {
+    let amount = tx
+        .raw_amount()
+        .map(|amount| format!(" {:.*}", usize::from(tx.amount_scale()), amount))
+        .unwrap_or_default();
+    format!(
+        "row {}: {} client {} tx {}{}",
+        position + 1,
+        tx.tx_type(),
+        tx.raw_client_id(),
+        tx.raw_tx_id(),
+        amount
+    )
+}
+
+fn name(breakpoint: &Breakpoint) -> String {
+    match breakpoint {
+        Breakpoint::Client(client_id) => format!("client {}", client_id),
+        Breakpoint::Tx(tx_id) => format!("tx {}", tx_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TransactionType;
+
+    #[test]
+    fn steps_to_breakpoints() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Deposit, 2, 2, Some(1.0)),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(9.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Resolve, 1, 1, None),
+        ];
+        let input = "step\n\
+                     break tx 1\n\
+                     continue\n\
+                     next\n\
+                     step\n\
+                     client 1\n\
+                     bogus\n\
+                     continue\n\
+                     step\n";
+        let mut output = Vec::new();
+        run(
+            TransactionEngine::default(),
+            transactions,
+            input.as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "5 transactions loaded\n\
+             > row 1: deposit client 1 tx 1 5 ok\n\
+             > breakpoint tx 1\n\
+             > row 2: deposit client 2 tx 2 1 ok\n\
+             row 3: withdrawal client 1 tx 3 9 error: Insufficient funds to withdraw 9.0000\n\
+             breakpoint tx 1 before row 4: dispute client 1 tx 1\n\
+             > row 4: dispute client 1 tx 1\n\
+             > row 4: dispute client 1 tx 1 ok\n\
+             > client 1\n  \
+             available     0.0000\n  \
+             held          5.0000\n  \
+             total         5.0000\n  \
+             locked        false\n  \
+             frozen        false\n  \
+             open disputes 1\n  \
+             stored txs    1\n\
+             > error: Unknown command bogus, see help\n\
+             > row 5: resolve client 1 tx 1 ok\n\
+             end of input\n\
+             > error: End of input\n\
+             > "
+        );
+    }
+}
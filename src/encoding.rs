@@ -0,0 +1,139 @@
+// Bank exports don't always arrive as UTF-8: some are UTF-16 with a BOM, others use a legacy
+// codepage. The encoding is sniffed from the start of the file and anything other than plain
+// UTF-8 is transcoded before it reaches the csv reader.
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+const SAMPLE_SIZE: usize = 8 * 1024;
+
+#[derive(Debug)]
+pub enum InputReader {
+    Utf8(File),
+    Decoded(DecodeReaderBytes<File, Vec<u8>>),
+}
+
+impl Read for InputReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            InputReader::Utf8(file) => file.read(buf),
+            InputReader::Decoded(reader) => reader.read(buf),
+        }
+    }
+}
+
+// Offsets only line up with the file for UTF-8 input, so seeking (used by checkpoints)
+// isn't available once the input is transcoded
+impl Seek for InputReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            InputReader::Utf8(file) => file.seek(pos),
+            InputReader::Decoded(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "can't seek in transcoded input, convert it to UTF-8 first",
+            )),
+        }
+    }
+}
+
+pub fn open(path: &str) -> io::Result<InputReader> {
+    let mut file = File::open(path)?;
+
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    (&mut file)
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    match detect(&sample) {
+        encoding if encoding == UTF_8 => Ok(InputReader::Utf8(file)),
+        encoding => Ok(InputReader::Decoded(
+            DecodeReaderBytesBuilder::new()
+                .encoding(Some(encoding))
+                .build(file),
+        )),
+    }
+}
+
+pub fn detect(sample: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+
+    if let Some(encoding) = detect_utf16(sample) {
+        return encoding;
+    }
+
+    match std::str::from_utf8(sample) {
+        Ok(_) => UTF_8,
+        // The sample may end half way through a multi-byte character
+        Err(error) if error.error_len().is_none() => UTF_8,
+        Err(_) => WINDOWS_1252,
+    }
+}
+
+// Csv is mostly ASCII, so UTF-16 without a BOM shows up as every other byte being zero
+fn detect_utf16(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs == 0 {
+        return None;
+    }
+
+    let zero_even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let zero_odd = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|b| **b == 0)
+        .count();
+
+    if zero_odd * 10 >= pairs * 9 && zero_even == 0 {
+        Some(UTF_16LE)
+    } else if zero_even * 10 >= pairs * 9 && zero_odd == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str, bom: bool) -> Vec<u8> {
+        let mut bytes = if bom { vec![0xFF, 0xFE] } else { Vec::new() };
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn can_detect_encodings() {
+        assert_eq!(detect(b"type,client,tx,amount\n"), UTF_8);
+        assert_eq!(detect(&utf16le("type,client\n", true)), UTF_16LE);
+        assert_eq!(detect(&utf16le("type,client\n", false)), UTF_16LE);
+        assert_eq!(
+            detect(b"type,client,memo\ndeposit,1,caf\xe9\n"),
+            WINDOWS_1252
+        );
+    }
+
+    #[test]
+    fn can_read_utf16_input() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            utf16le("type,client,tx,amount\r\ndeposit,1,1,1.5\r\n", true),
+        )
+        .unwrap();
+
+        let mut contents = String::new();
+        open(&file.path().to_string_lossy())
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "type,client,tx,amount\r\ndeposit,1,1,1.5\r\n");
+    }
+}
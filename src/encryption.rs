@@ -0,0 +1,151 @@
+// Optional AES-256-GCM encryption of saved engine state and checkpoints, which hold every
+// client's balances and transactions. The key is 64 hex digits, set in FUNDS_SNAPSHOT_KEY or
+// in a file named by FUNDS_SNAPSHOT_KEY_FILE. Files are only encrypted while a key is set, and
+// unencrypted files always load, so turning encryption on doesn't strand older snapshots.
+#[cfg(feature = "encryption")]
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+#[cfg(feature = "encryption")]
+use aes_gcm::{Aes256Gcm, Nonce};
+use std::{env, fs};
+
+const MAGIC: [u8; 4] = *b"FCEN";
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+
+pub const KEY_VARIABLE: &str = "FUNDS_SNAPSHOT_KEY";
+pub const KEY_FILE_VARIABLE: &str = "FUNDS_SNAPSHOT_KEY_FILE";
+
+// Only the cipher reads the key bytes, and that needs the encryption feature
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub struct Key([u8; 32]);
+
+impl Key {
+    // The key in the environment, the variable holding it directly wins over the key file
+    pub fn from_env() -> Result<Option<Key>, String> {
+        if let Ok(hex) = env::var(KEY_VARIABLE) {
+            return Key::from_hex(&hex)
+                .map(Some)
+                .map_err(|error| format!("{}: {}", KEY_VARIABLE, error));
+        }
+
+        match env::var(KEY_FILE_VARIABLE) {
+            Ok(path) => {
+                let hex =
+                    fs::read_to_string(&path).map_err(|error| format!("{}: {}", path, error))?;
+                Key::from_hex(hex.trim())
+                    .map(Some)
+                    .map_err(|error| format!("{}: {}", path, error))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Key, String> {
+        if hex.len() != 64 || !hex.chars().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err("Encryption key must be 64 hex digits".to_string());
+        }
+
+        let mut key = [0; 32];
+        for (index, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+                .map_err(|error| format!("Invalid encryption key: {}", error))?;
+        }
+        Ok(Key(key))
+    }
+
+    // A fresh random nonce is stored in front of every encrypted file
+    #[cfg(feature = "encryption")]
+    pub fn seal(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        let cipher = Aes256Gcm::new(&self.0.into());
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = cipher
+            .encrypt(&nonce, bytes)
+            .map_err(|_| "Error encrypting state".to_string())?;
+        Ok([&MAGIC[..], &nonce, &sealed].concat())
+    }
+
+    #[cfg(feature = "encryption")]
+    pub fn open(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        let sealed = bytes
+            .strip_prefix(&MAGIC)
+            .filter(|sealed| sealed.len() >= NONCE_LEN)
+            .ok_or("State isn't encrypted or is truncated")?;
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        Aes256Gcm::new(&self.0.into())
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| {
+                "Can't decrypt state, the key is wrong or the file is corrupted".to_string()
+            })
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn seal(&self, _bytes: &[u8]) -> Result<Vec<u8>, String> {
+        Err(format!(
+            "{} is set but encrypting state needs the encryption feature, rebuild with --features encryption",
+            KEY_VARIABLE
+        ))
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn open(&self, _bytes: &[u8]) -> Result<Vec<u8>, String> {
+        Err(
+            "Decrypting state needs the encryption feature, rebuild with --features encryption"
+                .to_string(),
+        )
+    }
+}
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+// Encrypts with the key in the environment, or leaves the bytes as they are without one
+pub fn seal(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    match Key::from_env()? {
+        Some(key) => key.seal(&bytes),
+        None => Ok(bytes),
+    }
+}
+
+pub fn open(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !is_encrypted(&bytes) {
+        return Ok(bytes);
+    }
+
+    match Key::from_env()? {
+        Some(key) => key.open(&bytes),
+        None => Err(format!(
+            "State is encrypted, set {} or {}",
+            KEY_VARIABLE, KEY_FILE_VARIABLE
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_hex_keys() {
+        assert!(Key::from_hex(&"0f".repeat(32)).is_ok());
+        assert!(Key::from_hex(&"0f".repeat(31)).is_err());
+        assert!(Key::from_hex(&format!("+f{}", "0f".repeat(31))).is_err());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypts_state() {
+        let key = Key::from_hex(&"0f".repeat(32)).unwrap();
+        let sealed = key.seal(b"client 1 has 5.0000").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(6).any(|window| window == b"client"));
+        assert_eq!(key.open(&sealed).unwrap(), b"client 1 has 5.0000");
+        assert_ne!(key.seal(b"client 1 has 5.0000").unwrap(), sealed);
+
+        let other = Key::from_hex(&"a0".repeat(32)).unwrap();
+        assert!(other.open(&sealed).is_err());
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(key.open(&tampered).is_err());
+    }
+}
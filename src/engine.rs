@@ -0,0 +1,1725 @@
+use crate::audit::AuditEntry;
+use crate::builder::EngineBuilder;
+use crate::client::{self, Client, ClientV1, ClientV2, ClientV3, Clients, IdHasher};
+use crate::codes::{self, CodedError, ErrorFormat, RejectionCode};
+use crate::history::TransactionHistory;
+use crate::ids::{ClientId, TxId};
+use crate::links::{self, LinkGraph};
+use crate::load::{self, Loads};
+use crate::money::Money;
+use crate::notify::{self, NotificationSink};
+use crate::reorder::ReorderBuffer;
+use crate::schedule::Schedule;
+use crate::snapshot::{self, add_header, Migration, Snapshot};
+use crate::sorted;
+use crate::transaction::{Transaction, TransactionType};
+use crate::wal::{Wal, WalEntry};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineConfig {
+    // Number of transactions held back per client so slightly out of order feeds
+    // can be applied in tx id order. 0 applies every transaction as it arrives.
+    pub reorder_window: usize,
+    // Types of transaction a dispute may reference, some schemes only allow disputing credits
+    #[serde(default = "default_disputable_types")]
+    pub disputable_types: Vec<TransactionType>,
+    // Types of transaction still applied to accounts locked by a chargeback
+    #[serde(default)]
+    pub locked_allows: Vec<TransactionType>,
+    // Types of transaction still applied to accounts an operator froze
+    #[serde(default = "default_frozen_allows")]
+    pub frozen_allows: Vec<TransactionType>,
+    #[serde(default)]
+    pub cross_client: CrossClientPolicy,
+    #[serde(default)]
+    pub ordering: OrderingPolicy,
+    #[serde(default)]
+    pub dispute_overdraft: DisputeOverdraftPolicy,
+    // Clients operating another client's account as joint owners, mapped to that account's
+    // client. Their transactions move the account's funds and are attributed to them
+    #[serde(default, serialize_with = "sorted::map")]
+    pub joint_accounts: HashMap<ClientId, ClientId>,
+    // Clients with a credit line and how far below zero their available funds may go
+    #[serde(default, serialize_with = "sorted::map")]
+    pub credit_limits: HashMap<ClientId, Money>,
+    // Applies transactions still waiting for their value date when the input ends instead of
+    // leaving them out
+    #[serde(default)]
+    pub release_scheduled: bool,
+    // Lets resolves through to locked accounts and unlocks them once their last open dispute
+    // is resolved
+    #[serde(default)]
+    pub auto_unlock: bool,
+    #[serde(default)]
+    pub unknown_clients: UnknownClientPolicy,
+    #[serde(default)]
+    pub resolve: ResolvePolicy,
+    // Withdrawal limit and fee of each client's tier, clients without a tier have neither
+    #[serde(default, serialize_with = "sorted::map")]
+    pub tiers: HashMap<ClientId, TierLimits>,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    // How rejections are reported on std err, it isn't part of the engine's state
+    #[serde(skip)]
+    pub error_format: ErrorFormat,
+}
+
+// The config as encoded before it had an unknown client policy, read when upgrading older
+// snapshots
+#[derive(Serialize, Deserialize)]
+struct EngineConfigV1(
+    usize,
+    Vec<TransactionType>,
+    Vec<TransactionType>,
+    Vec<TransactionType>,
+    CrossClientPolicy,
+    OrderingPolicy,
+    DisputeOverdraftPolicy,
+    HashMap<u16, u16>,
+    HashMap<u16, Money>,
+    bool,
+    bool,
+);
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            reorder_window: 0,
+            disputable_types: default_disputable_types(),
+            locked_allows: Vec::new(),
+            frozen_allows: default_frozen_allows(),
+            auto_unlock: false,
+            cross_client: CrossClientPolicy::default(),
+            ordering: OrderingPolicy::default(),
+            dispute_overdraft: DisputeOverdraftPolicy::default(),
+            release_scheduled: false,
+            credit_limits: HashMap::new(),
+            joint_accounts: HashMap::new(),
+            unknown_clients: UnknownClientPolicy::default(),
+            resolve: ResolvePolicy::default(),
+            tiers: HashMap::new(),
+            retention: RetentionPolicy::default(),
+            error_format: ErrorFormat::default(),
+        }
+    }
+}
+
+impl EngineConfig {
+    // Rejects policies that can't work together, checked by `EngineBuilder::build` and the
+    // command line. An engine built from such a config directly keeps every transaction
+    pub fn validate(&self) -> Result<(), String> {
+        if self.retention != RetentionPolicy::KeepAll && self.ordering == OrderingPolicy::Ignore {
+            return Err(
+                "Retention needs ordering to be enforced, a dropped tx id could be applied again"
+                    .to_string(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// What happens to disputes, resolves, chargebacks and refunds sent under another client id than
+// the transaction they reference
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum CrossClientPolicy {
+    // Each client only looks at its own transactions, the reference is unknown to it
+    #[default]
+    Ignore,
+    // Rejected, naming the client the transaction belongs to
+    Reject,
+    // Applied to the client the transaction belongs to
+    Route,
+}
+
+// Whether a client's deposits, withdrawals and settlements must come in increasing tx id order
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderingPolicy {
+    // Ids at or below the client's latest one are rejected as in the past
+    #[default]
+    Enforce,
+    // Applied whatever their id, e.g. for backfills of archives without monotonic ids. Ids the
+    // client already stores are still rejected
+    Ignore,
+}
+
+// What a dispute does when the deposit it holds is more than the client has available, e.g.
+// because the funds were withdrawn since
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum DisputeOverdraftPolicy {
+    // The whole amount is held and available goes negative
+    #[default]
+    Allow,
+    // Only what is available is held, raising a dispute capped notification
+    Cap,
+    // Rejected, naming the amount available
+    Reject,
+}
+
+// What happens to the first transaction of a client without an account when it isn't a
+// deposit, e.g. a dispute for a client never seen before
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum UnknownClientPolicy {
+    // An empty account is opened and the transaction applied to it
+    #[default]
+    CreateEmpty,
+    // Rejected, no account is opened
+    Reject,
+    // Set aside for review without an account being opened or an error being reported, see
+    // `TransactionEngine::quarantined`
+    Quarantine,
+}
+
+// Where the funds held by a disputed deposit go once the dispute is resolved
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum ResolvePolicy {
+    // Back to available, the deposit stands
+    #[default]
+    Release,
+    // Back to the payer, the client's total drops as if the deposit was refunded and a refund
+    // notification is raised
+    RefundToSource,
+}
+
+// What a client's tier allows, see `metadata::apply_tiers`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TierLimits {
+    pub tier: String,
+    // Largest single withdrawal
+    pub withdrawal_limit: Option<Money>,
+    // Taken from available on top of every withdrawal
+    pub withdrawal_fee: Money,
+}
+
+// Which applied transactions each client keeps for later disputes, resolves, chargebacks and
+// refunds, bounding memory for long running streams. Open disputes and transactions waiting for
+// their batch to settle are always kept. Only applied with `OrderingPolicy::Enforce`, without it
+// a dropped tx id could be applied again
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RetentionPolicy {
+    #[default]
+    KeepAll,
+    // The N with the highest tx ids, on top of the ones always kept
+    KeepLast(usize),
+    // Only deposits, dropping those timestamped this many seconds before the latest
+    // transaction's timestamp. Deposits without a timestamp never age
+    DepositsWithin(i64),
+}
+
+fn default_disputable_types() -> Vec<TransactionType> {
+    vec![TransactionType::Deposit, TransactionType::Withdrawal]
+}
+
+// Frozen accounts can't move money out, investigations and incoming funds carry on
+fn default_frozen_allows() -> Vec<TransactionType> {
+    vec![
+        TransactionType::Deposit,
+        TransactionType::Dispute,
+        TransactionType::Resolve,
+        TransactionType::Chargeback,
+        TransactionType::Refund,
+    ]
+}
+
+// What `submit_with_changes` did to the account a transaction was applied to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Changes {
+    pub client_id: ClientId,
+    pub entries: Vec<AuditEntry>,
+}
+
+// Balances a client would end up with, as worked out by `simulate`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccountSnapshot {
+    pub client: ClientId,
+    pub available: Money,
+    pub held: Money,
+    pub total: Money,
+    pub locked: bool,
+    pub frozen: bool,
+}
+
+impl AccountSnapshot {
+    fn new<S: BuildHasher + Default>(
+        client_id: ClientId,
+        client: &Client<S>,
+    ) -> Result<Self, CodedError> {
+        let funds = client.funds();
+        Ok(AccountSnapshot {
+            client: client_id,
+            available: funds.available(),
+            held: funds.held(),
+            total: funds.available().checked_add(funds.held())?,
+            locked: client.is_locked(),
+            frozen: client.is_frozen(),
+        })
+    }
+}
+
+// A transaction the engine refused and why
+#[derive(Debug, Clone)]
+pub struct Rejection {
+    pub tx: Transaction,
+    pub error: CodedError,
+}
+
+impl Rejection {
+    // Stays the same when the wording of the error changes
+    pub fn code(&self) -> RejectionCode {
+        self.error.code()
+    }
+}
+
+// `S` hashes the client and tx ids, see `IdHasher`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(serialize = "", deserialize = "S: BuildHasher + Default"))]
+pub struct TransactionEngine<S = IdHasher> {
+    config: EngineConfig,
+    #[serde(serialize_with = "sorted::map")]
+    clients: Clients<S>,
+    #[serde(serialize_with = "sorted::map")]
+    reorder_buffers: HashMap<ClientId, ReorderBuffer>,
+    #[serde(default)]
+    schedule: Schedule,
+    links: LinkGraph,
+    // Client of every deposit and withdrawal, only kept when cross client references are
+    // rejected or routed
+    #[serde(default, serialize_with = "sorted::map")]
+    owners: HashMap<u32, ClientId>,
+    #[serde(skip)]
+    history: Option<Box<dyn TransactionHistory>>,
+    #[serde(skip)]
+    sinks: Vec<Box<dyn NotificationSink>>,
+    // Only kept once asked for with `with_rejections`
+    #[serde(skip)]
+    rejections: Option<Vec<Rejection>>,
+    // Transactions set aside this run under `UnknownClientPolicy::Quarantine`
+    #[serde(skip)]
+    quarantined: Vec<Transaction>,
+    // State at the last `commit`, what `rollback` returns to
+    #[serde(skip)]
+    committed: Option<Vec<u8>>,
+    // Only kept once asked for with `with_load_tracking`
+    #[serde(skip)]
+    loads: Option<Loads>,
+    // Only kept once asked for with `with_wal`
+    #[serde(skip)]
+    wal: Option<Wal>,
+}
+
+// Version 2 added transaction counts to every client, the config in front of the clients and
+// everything after them are unchanged
+fn add_client_stats(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut rest = body.as_slice();
+    let config: EngineConfigV1 = snapshot::decode_from(&mut rest)?;
+    let clients: Vec<(u16, ClientV1)> = snapshot::decode_from(&mut rest)?;
+
+    let mut upgraded = snapshot::encode(&(config, client::add_stats(clients)))?;
+    upgraded.extend_from_slice(rest);
+    Ok(upgraded)
+}
+
+// Version 3 added when each client was first seen, last active and locked
+fn add_client_lifecycle(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut rest = body.as_slice();
+    let config: EngineConfigV1 = snapshot::decode_from(&mut rest)?;
+    let clients: Vec<(u16, ClientV2)> = snapshot::decode_from(&mut rest)?;
+
+    let mut upgraded = snapshot::encode(&(config, client::add_lifecycle(clients)))?;
+    upgraded.extend_from_slice(rest);
+    Ok(upgraded)
+}
+
+// Version 4 added the unknown client policy at the end of the config
+fn add_unknown_client_policy(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut rest = body.as_slice();
+    let config: EngineConfigV1 = snapshot::decode_from(&mut rest)?;
+
+    let mut upgraded = snapshot::encode(&(config, UnknownClientPolicy::default()))?;
+    upgraded.extend_from_slice(rest);
+    Ok(upgraded)
+}
+
+// Version 5 added the resolve policy after it
+fn add_resolve_policy(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut rest = body.as_slice();
+    let config: (EngineConfigV1, UnknownClientPolicy) = snapshot::decode_from(&mut rest)?;
+
+    let mut upgraded = snapshot::encode(&(config, ResolvePolicy::default()))?;
+    upgraded.extend_from_slice(rest);
+    Ok(upgraded)
+}
+
+// Version 6 added the tier limits after it
+fn add_tiers(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut rest = body.as_slice();
+    let config: (EngineConfigV1, UnknownClientPolicy, ResolvePolicy) =
+        snapshot::decode_from(&mut rest)?;
+
+    let mut upgraded = snapshot::encode(&(config, HashMap::<u16, TierLimits>::new()))?;
+    upgraded.extend_from_slice(rest);
+    Ok(upgraded)
+}
+
+// Version 7 added the retention policy after them
+fn add_retention(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut rest = body.as_slice();
+    let config: (
+        EngineConfigV1,
+        UnknownClientPolicy,
+        ResolvePolicy,
+        HashMap<u16, TierLimits>,
+    ) = snapshot::decode_from(&mut rest)?;
+
+    let mut upgraded = snapshot::encode(&(config, RetentionPolicy::default()))?;
+    upgraded.extend_from_slice(rest);
+    Ok(upgraded)
+}
+
+// Version 8 added the tally of the rows applied to each client
+fn add_client_tally(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let mut rest = body.as_slice();
+    let config: (
+        EngineConfigV1,
+        UnknownClientPolicy,
+        ResolvePolicy,
+        HashMap<u16, TierLimits>,
+        RetentionPolicy,
+    ) = snapshot::decode_from(&mut rest)?;
+    let clients: Vec<(u16, ClientV3)> = snapshot::decode_from(&mut rest)?;
+
+    let mut upgraded = snapshot::encode(&(config, client::add_tally(clients)))?;
+    upgraded.extend_from_slice(rest);
+    Ok(upgraded)
+}
+
+impl<S: BuildHasher + Default> Snapshot for TransactionEngine<S> {
+    const MIGRATIONS: &'static [Migration] = &[
+        add_header,
+        add_client_stats,
+        add_client_lifecycle,
+        add_unknown_client_policy,
+        add_resolve_policy,
+        add_tiers,
+        add_retention,
+        add_client_tally,
+    ];
+}
+
+impl TransactionEngine {
+    // Sets up an engine with every policy, backend and sink in one fluent chain
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
+    pub fn new(config: EngineConfig) -> Self {
+        TransactionEngine::with_hasher(config, IdHasher)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        snapshot::decode_versioned(bytes)
+    }
+}
+
+impl Default for TransactionEngine {
+    fn default() -> Self {
+        TransactionEngine::new(EngineConfig::default())
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> TransactionEngine<S> {
+    // Same as `new` with the client and tx id maps hashed by `hasher`, e.g. `RandomState` when
+    // ids come from a source that could craft colliding ones
+    pub fn with_hasher(config: EngineConfig, hasher: S) -> Self {
+        TransactionEngine {
+            config,
+            clients: Clients::with_hasher(hasher),
+            reorder_buffers: HashMap::new(),
+            schedule: Schedule::new(),
+            links: LinkGraph::new(),
+            owners: HashMap::new(),
+            history: None,
+            sinks: Vec::new(),
+            rejections: None,
+            quarantined: Vec::new(),
+            committed: None,
+            loads: None,
+            wal: None,
+        }
+    }
+
+    // Keeps applied transactions in `history` instead of memory, only paging them
+    // back in when a dispute, resolve or chargeback references them
+    pub fn with_history(mut self, history: Box<dyn TransactionHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    // Hands chargebacks and newly locked accounts to `sink` as they're applied, on top of
+    // any sinks added before
+    pub fn with_sink(mut self, sink: Box<dyn NotificationSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    // Keeps every transaction rejected while pushing, handed back by `finish_with_rejections`
+    pub fn with_rejections(mut self) -> Self {
+        self.rejections = Some(Vec::new());
+        self
+    }
+
+    // Starts a write-ahead log at `path` from the current state, every operation changing the
+    // engine from then on is appended to it before it's applied, see `wal::replay`
+    pub fn with_wal(mut self, path: &str) -> Result<Self, String> {
+        self.wal = Some(Wal::create(path, &self.to_bytes()?)?);
+        Ok(self)
+    }
+
+    // Counts the transactions applied to each client and times them, see `loads`
+    pub fn with_load_tracking(mut self) -> Self {
+        self.loads = Some(Loads::new());
+        self
+    }
+
+    // Sizes the client map for `clients` clients up front so large inputs don't keep rehashing
+    // it as it grows
+    pub fn with_expected_clients(mut self, clients: usize) -> Self {
+        self.clients
+            .reserve(clients.saturating_sub(self.clients.len()));
+        self
+    }
+
+    // Starts from previously computed clients instead of an empty book
+    pub fn with_clients(config: EngineConfig, clients: Clients<S>) -> Self {
+        TransactionEngine {
+            clients,
+            ..TransactionEngine::with_hasher(config, S::default())
+        }
+    }
+
+    pub fn clients(&self) -> &Clients<S> {
+        &self.clients
+    }
+
+    pub fn client(&self, client: ClientId) -> Option<&Client<S>> {
+        self.clients.get(&client)
+    }
+
+    // Freezes an account on an operator's request, e.g. during an investigation. Only the
+    // `frozen_allows` types are applied to it until it is unfrozen
+    pub fn freeze(&mut self, client_id: ClientId) -> Result<(), String> {
+        self.log(|| WalEntry::Freeze(client_id))?;
+        self.clients
+            .get_mut(&client_id)
+            .map(|client| client.set_frozen(true))
+            .ok_or(format!("No client {}", client_id))
+    }
+
+    pub fn unfreeze(&mut self, client_id: ClientId) -> Result<(), String> {
+        self.log(|| WalEntry::Unfreeze(client_id))?;
+        self.clients
+            .get_mut(&client_id)
+            .map(|client| client.set_frozen(false))
+            .ok_or(format!("No client {}", client_id))
+    }
+
+    // Credits every client with a positive available balance `rate` times that balance, e.g.
+    // 0.001 for 0.1% per period, as interest for the period ending `at`. Clients already
+    // credited for `at` or a later period are skipped. Returns the number of clients credited
+    pub fn accrue_interest(&mut self, rate: f64, at: DateTime<Utc>) -> Result<usize, String> {
+        self.log(|| WalEntry::AccrueInterest { rate, at })?;
+        let mut credited = 0;
+        for client in self.clients.values_mut() {
+            if client.accrue_interest(rate, at)? > Money::ZERO {
+                credited += 1;
+            }
+        }
+
+        Ok(credited)
+    }
+
+    // Disputes, resolves, chargebacks and refunds successfully applied against `tx_id`
+    pub fn related_transactions(&self, tx_id: TxId) -> &[Transaction] {
+        self.links.related_transactions(tx_id.value())
+    }
+
+    // Encodes the whole engine, including transactions still waiting in reorder buffers,
+    // so it can be shipped to another process and resumed there
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        snapshot::encode_versioned(self)
+    }
+
+    // Swaps in previously encoded state while keeping the attached history
+    pub fn restore(mut self, bytes: &[u8]) -> Result<Self, String> {
+        self.log(|| WalEntry::Restore(bytes.to_vec()))?;
+        let mut restored: Self = snapshot::decode_versioned(bytes)?;
+        restored.history = self.history;
+        restored.sinks = self.sinks;
+        restored.rejections = self.rejections;
+        restored.quarantined = self.quarantined;
+        restored.loads = self.loads;
+        restored.wal = self.wal;
+        Ok(restored)
+    }
+
+    // Marks a boundary between groups of transactions, the state so far is what `rollback`
+    // returns to
+    pub fn commit(&mut self) -> Result<(), String> {
+        self.committed = Some(self.to_bytes()?);
+        Ok(())
+    }
+
+    // Undoes every transaction applied since the last commit. Rejections and notifications
+    // already raised are kept
+    pub fn rollback(self) -> Result<Self, String> {
+        let Some(committed) = self.committed.clone() else {
+            return Err("Nothing was committed to roll back to".to_string());
+        };
+        let mut restored = self.restore(&committed)?;
+        restored.committed = Some(committed);
+        Ok(restored)
+    }
+
+    // Transactions rejected so far, empty unless kept with `with_rejections`
+    pub fn rejections(&self) -> &[Rejection] {
+        self.rejections.as_deref().unwrap_or_default()
+    }
+
+    // Work done per client this run, when tracked
+    pub fn loads(&self) -> Option<&Loads> {
+        self.loads.as_ref()
+    }
+
+    // Transactions dated in the future through a value date wait until a later timestamp
+    // passes it, those that became due are applied first
+    pub fn push(&mut self, tx: Transaction) {
+        self.log_or_warn(|| WalEntry::Push(tx.clone()));
+        if let Some(timestamp) = tx.timestamp() {
+            for due in self.schedule.advance(timestamp) {
+                self.enqueue(due);
+            }
+        }
+        if let Some(tx) = self.schedule.hold(tx) {
+            self.enqueue(tx);
+        }
+    }
+
+    // Same as pushing each transaction of `batch` in turn, faster for bulk ingestion: a client's
+    // transactions only depend on each other, so they are applied grouped by client, in their
+    // order, with the client looked up once per group. Batches whose order matters across
+    // clients, i.e. with timestamps, value dates, reorder windows, joint accounts, cross client
+    // policies, a history or notifications, are pushed one by one. Rejections are reported in
+    // client order
+    pub fn push_batch(&mut self, batch: &[Transaction]) {
+        if !self.can_group(batch) {
+            for tx in batch {
+                self.push(tx.clone());
+            }
+            return;
+        }
+
+        let mut grouped: Vec<&Transaction> = batch.iter().collect();
+        grouped.sort_by_key(|tx| tx.client_id());
+        for group in grouped.chunk_by(|a, b| a.client_id() == b.client_id()) {
+            self.apply_group(group);
+        }
+    }
+
+    fn can_group(&self, batch: &[Transaction]) -> bool {
+        self.config.reorder_window == 0
+            && self.config.joint_accounts.is_empty()
+            && self.config.cross_client == CrossClientPolicy::Ignore
+            && self.history.is_none()
+            && self.sinks.is_empty()
+            && self.wal.is_none()
+            && batch
+                .iter()
+                .all(|tx| tx.timestamp().is_none() && tx.value_date().is_none())
+    }
+
+    // What `submit` does for each of one client's transactions, without what `can_group` rules out
+    fn apply_group(&mut self, group: &[&Transaction]) {
+        let mut group = group.iter().map(|tx| (*tx).clone());
+        let Some(first) = group.next() else {
+            return;
+        };
+        // Left to `submit` when a new client starts with anything but a deposit, which is up to
+        // the policy and `Client::open`, or with a deposit without an amount
+        if !self.clients.contains_key(&first.client_id())
+            && (first.tx_type() != &TransactionType::Deposit || first.amount().is_none())
+        {
+            for tx in [first].into_iter().chain(group) {
+                self.apply(tx);
+            }
+            return;
+        }
+        let client_id = first.client_id();
+        let started = self.loads.is_some().then(Instant::now);
+        let (client, applied) = match self.clients.entry(client_id) {
+            Entry::Occupied(entry) => {
+                let client = entry.into_mut();
+                let applied = apply_to(
+                    client,
+                    first,
+                    &self.config,
+                    &mut self.links,
+                    &mut self.rejections,
+                );
+                (client, applied)
+            }
+            Entry::Vacant(entry) => (entry.insert(Client::new(first.tx_id(), first)), true),
+        };
+        load::record(&mut self.loads, client_id, applied, started);
+
+        for tx in group {
+            let started = self.loads.is_some().then(Instant::now);
+            let applied = apply_to(
+                client,
+                tx,
+                &self.config,
+                &mut self.links,
+                &mut self.rejections,
+            );
+            load::record(&mut self.loads, client_id, applied, started);
+        }
+    }
+
+    // Applies every transaction waiting for its value date, e.g. once the input ended
+    pub fn release_scheduled(&mut self) {
+        self.log_or_warn(|| WalEntry::ReleaseScheduled);
+        self.release_due();
+    }
+
+    fn release_due(&mut self) {
+        let scheduled: Vec<Transaction> = self.schedule.drain().collect();
+        for tx in scheduled {
+            self.enqueue(tx);
+        }
+    }
+
+    fn enqueue(&mut self, tx: Transaction) {
+        if self.config.reorder_window == 0 {
+            return self.apply(tx);
+        }
+
+        let window = self.config.reorder_window;
+        let ready = self
+            .reorder_buffers
+            .entry(tx.client_id())
+            .or_insert_with(|| ReorderBuffer::new(window))
+            .push(tx);
+
+        if let Some(tx) = ready {
+            self.apply(tx);
+        }
+    }
+
+    // Applies anything still waiting in the reorder buffers and hands back the final client state
+    pub fn finish(self) -> Clients<S> {
+        self.finish_with_rejections().0
+    }
+
+    // Same as `finish`, also handing back the transactions rejected along the way when they
+    // were kept
+    pub fn finish_with_rejections(mut self) -> (Clients<S>, Vec<Rejection>) {
+        if self.config.release_scheduled {
+            self.release_due();
+        } else if !self.schedule.is_empty() {
+            codes::report_warning(
+                self.config.error_format,
+                &format!(
+                    "{} transactions dated after the last timestamp were not applied",
+                    self.schedule.len()
+                ),
+            );
+        }
+
+        let buffers: Vec<ReorderBuffer> = self.reorder_buffers.drain().map(|(_, b)| b).collect();
+        for buffer in buffers {
+            for tx in buffer.drain() {
+                self.apply(tx);
+            }
+        }
+        if !self.quarantined.is_empty() {
+            codes::report_warning(
+                self.config.error_format,
+                &format!(
+                    "{} transactions for clients without an account were quarantined",
+                    self.quarantined.len()
+                ),
+            );
+        }
+
+        (self.clients, self.rejections.unwrap_or_default())
+    }
+
+    // Applies `tx` right away, bypassing the reorder buffers, and reports whether it was
+    // accepted, e.g. for transactions entered by hand. Only `push` holds transactions back
+    // for their value date, one that isn't due yet is rejected
+    pub fn submit(&mut self, tx: Transaction) -> Result<(), CodedError> {
+        self.log(|| WalEntry::Submit(tx.clone()))?;
+        self.check_due(&tx)?;
+        self.submit_tracked(tx, false).map(|_| ())
+    }
+
+    // Same as `submit`, also returning the account it changed and the audit entries it added.
+    // That isn't always the transaction's client: joint accounts and routed references land
+    // on another account
+    pub fn submit_with_changes(&mut self, tx: Transaction) -> Result<Changes, CodedError> {
+        self.log(|| WalEntry::Submit(tx.clone()))?;
+        self.check_due(&tx)?;
+        self.submit_tracked(tx, true)
+    }
+
+    fn log(&mut self, entry: impl FnOnce() -> WalEntry) -> Result<(), String> {
+        match &mut self.wal {
+            Some(wal) => wal.append(&entry()),
+            None => Ok(()),
+        }
+    }
+
+    // `push` has nowhere to return the error to, the log then misses the entry
+    fn log_or_warn(&mut self, entry: impl FnOnce() -> WalEntry) {
+        if let Err(error) = self.log(entry) {
+            codes::report_warning(self.config.error_format, &error);
+        }
+    }
+
+    fn check_due(&self, tx: &Transaction) -> Result<(), CodedError> {
+        match (self.schedule.is_due(tx), tx.value_date()) {
+            (false, Some(date)) => Err(CodedError::new(
+                RejectionCode::NotDue,
+                format!(
+                    "Tx {} has a value date of {}, it can't be applied before then",
+                    tx.tx_id(),
+                    date.to_rfc3339()
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // The account `tx` would be applied to, after joint accounts and cross client routing
+    pub fn account_of(&self, tx: &Transaction) -> ClientId {
+        let routed = links::references_parent(tx.tx_type())
+            && self.config.cross_client == CrossClientPolicy::Route;
+        match self.owners.get(&tx.raw_tx_id()) {
+            Some(owner) if routed => *owner,
+            _ => self
+                .config
+                .joint_accounts
+                .get(&tx.client_id())
+                .copied()
+                .unwrap_or(tx.client_id()),
+        }
+    }
+
+    // Audit entries are only copied out when `track` is set
+    fn submit_tracked(&mut self, tx: Transaction, track: bool) -> Result<Changes, CodedError> {
+        let started = self.loads.is_some().then(Instant::now);
+        let (tx, owner) = self.joint_account(tx);
+        let tx = self.route(tx)?;
+        let (client_id, tx_id, timestamp) = (tx.client_id(), tx.raw_tx_id(), tx.timestamp());
+        let owned = self.config.cross_client != CrossClientPolicy::Ignore
+            && !links::references_parent(tx.tx_type());
+
+        if !self.clients.contains_key(&client_id) && tx.tx_type() != &TransactionType::Deposit {
+            match self.config.unknown_clients {
+                UnknownClientPolicy::CreateEmpty => {}
+                UnknownClientPolicy::Reject => {
+                    return Err(CodedError::new(
+                        RejectionCode::UnknownClient,
+                        format!(
+                            "Client {} has no account and tx {} is a {}, only deposits open one",
+                            client_id,
+                            tx_id,
+                            tx.tx_type()
+                        ),
+                    ))
+                }
+                UnknownClientPolicy::Quarantine => {
+                    self.quarantined.push(tx);
+                    return Err(CodedError::new(
+                        RejectionCode::Quarantined,
+                        format!("Quarantined, client {} has no account", client_id),
+                    ));
+                }
+            }
+        }
+
+        if self.history.is_some() {
+            self.page_in(client_id, tx_id);
+        }
+
+        let was_locked = self.clients.get(&client_id).is_some_and(Client::is_locked);
+        let entries = self
+            .clients
+            .get(&client_id)
+            .map_or(0, |client| client.audit_trail().len());
+        let notifications = (!self.sinks.is_empty()).then(|| tx.clone());
+        // Only transactions referencing another are linked, the rest are moved into the client
+        let link = links::references_parent(tx.tx_type()).then(|| tx.clone());
+
+        let result = match self.clients.entry(client_id) {
+            Entry::Occupied(mut entry) => entry
+                .get_mut()
+                .handle_transaction_with_config(tx, &self.config)
+                .map(|()| {
+                    if let Some(tx) = link {
+                        self.links.record(tx);
+                    }
+                }),
+            Entry::Vacant(entry) => Client::open(TxId::new(tx_id), tx).map(|client| {
+                entry.insert(client);
+            }),
+        };
+
+        if let (Ok(()), Some(owner)) = (&result, owner) {
+            if let Some(client) = self.clients.get_mut(&client_id) {
+                client.attribute_to(entries, owner);
+            }
+        }
+        if let (Ok(()), Some(tx)) = (&result, notifications) {
+            self.notify(&tx, was_locked);
+        }
+        // Only stored transactions can be referenced, settlements are never owned
+        let stored = self
+            .clients
+            .get(&client_id)
+            .is_some_and(|client| client.transactions().contains_key(&tx_id));
+        if result.is_ok() && owned && stored {
+            self.owners.insert(tx_id, client_id);
+        }
+        let changes = Changes {
+            client_id,
+            entries: match (&result, self.clients.get(&client_id)) {
+                (Ok(()), Some(client)) if track => client.audit_trail()[entries..].to_vec(),
+                _ => Vec::new(),
+            },
+        };
+
+        if result.is_ok() {
+            self.retain(client_id, tx_id, timestamp);
+        }
+
+        if self.history.is_some() {
+            self.page_out(client_id, tx_id);
+        }
+        load::record(&mut self.loads, client_id, result.is_ok(), started);
+        result.map(|()| changes)
+    }
+
+    // Works out the balances `tx` would leave its client with without applying it, e.g. so a
+    // withdrawal can be checked before it is committed. Fails the way `submit` would
+    pub fn simulate(&self, tx: Transaction) -> Result<AccountSnapshot, CodedError> {
+        let (tx, _) = self.joint_account(tx);
+        let tx = self.route(tx)?;
+        let (client_id, tx_id) = (tx.client_id(), tx.raw_tx_id());
+
+        let client = match self.clients.get(&client_id) {
+            Some(client) => {
+                let mut client = client.clone();
+                if let Some(history) = &self.history {
+                    if !client.transactions().contains_key(&tx_id) {
+                        if let Some(referenced) = history.get(client_id, TxId::new(tx_id))? {
+                            client.restore_transaction(tx_id, referenced);
+                        }
+                    }
+                }
+                client.handle_transaction_with_config(tx, &self.config)?;
+                client
+            }
+            None => Client::open(TxId::new(tx_id), tx)?,
+        };
+
+        AccountSnapshot::new(client_id, &client)
+    }
+
+    // Moves transactions of joint owners onto the account they share, handing back the owner
+    fn joint_account(&self, tx: Transaction) -> (Transaction, Option<ClientId>) {
+        match self.config.joint_accounts.get(&tx.client_id()).copied() {
+            Some(account) => {
+                let owner = tx.client_id();
+                (tx.with_client_id(account), Some(owner))
+            }
+            None => (tx, None),
+        }
+    }
+
+    // Applies the cross client policy to transactions referencing another client's transaction
+    fn route(&self, tx: Transaction) -> Result<Transaction, CodedError> {
+        if !links::references_parent(tx.tx_type()) {
+            return Ok(tx);
+        }
+        let owner = match self.owners.get(&tx.raw_tx_id()) {
+            Some(owner) if *owner != tx.client_id() => *owner,
+            _ => return Ok(tx),
+        };
+
+        match self.config.cross_client {
+            CrossClientPolicy::Ignore => Ok(tx),
+            CrossClientPolicy::Reject => Err(CodedError::new(
+                RejectionCode::WrongClient,
+                format!(
+                    "Tx {} belongs to client {}, not client {}",
+                    tx.raw_tx_id(),
+                    owner,
+                    tx.client_id()
+                ),
+            )),
+            CrossClientPolicy::Route => Ok(tx.with_client_id(owner)),
+        }
+    }
+
+    fn apply(&mut self, tx: Transaction) {
+        let ids = codes::ids(&tx);
+        let rejected = self.rejections.is_some().then(|| tx.clone());
+        let quarantined = self.quarantined.len();
+        match self.submit_tracked(tx, false).map(|_| ()) {
+            Ok(()) => {}
+            // Quarantined transactions only go to the rejections, they aren't errors
+            Err(error) if self.quarantined.len() > quarantined => {
+                if let (Some(rejections), Some(tx)) = (&mut self.rejections, rejected) {
+                    rejections.push(Rejection { tx, error });
+                }
+            }
+            Err(error) => reject(
+                &mut self.rejections,
+                self.config.error_format,
+                ids,
+                rejected,
+                error,
+            ),
+        }
+    }
+
+    // Transactions set aside under `UnknownClientPolicy::Quarantine`, in the order they came in
+    pub fn quarantined(&self) -> &[Transaction] {
+        &self.quarantined
+    }
+
+    // Transactions read but not applied yet, waiting in a reorder buffer or for their value date
+    pub fn held_back(&self) -> usize {
+        let reordering: usize = self.reorder_buffers.values().map(ReorderBuffer::len).sum();
+        reordering + self.schedule.len()
+    }
+
+    fn notify(&mut self, tx: &Transaction, was_locked: bool) {
+        let Some(client) = self.clients.get(&tx.client_id()) else {
+            return;
+        };
+
+        for notification in notify::notifications(tx, client, was_locked) {
+            for sink in &mut self.sinks {
+                if let Err(error) = sink.send(&notification) {
+                    codes::report_warning(
+                        self.config.error_format,
+                        &format!("error sending notification: {}", error),
+                    );
+                }
+            }
+        }
+    }
+
+    // Forgets the links and owners of the transactions retention dropped from `client_id`
+    fn retain(&mut self, client_id: ClientId, tx_id: u32, now: Option<DateTime<Utc>>) {
+        let Some(client) = self.clients.get_mut(&client_id) else {
+            return;
+        };
+        for dropped in client.retain(&self.config, tx_id, now) {
+            self.links.forget(dropped);
+            self.owners.remove(&dropped);
+        }
+    }
+
+    fn page_in(&mut self, client_id: ClientId, tx_id: u32) {
+        let (Some(history), Some(client)) = (&self.history, self.clients.get_mut(&client_id))
+        else {
+            return;
+        };
+
+        if client.transactions().contains_key(&tx_id) {
+            return;
+        }
+
+        match history.get(client_id, TxId::new(tx_id)) {
+            Ok(Some(tx)) => client.restore_transaction(tx_id, tx),
+            Ok(None) => {}
+            Err(error) => codes::report_warning(
+                self.config.error_format,
+                &format!("error reading tx history: {}", error),
+            ),
+        }
+    }
+
+    fn page_out(&mut self, client_id: ClientId, tx_id: u32) {
+        let (Some(history), Some(client)) = (&mut self.history, self.clients.get_mut(&client_id))
+        else {
+            return;
+        };
+
+        if let Some(tx) = client.evict_transaction(tx_id) {
+            if let Err(error) = history.insert(client_id, TxId::new(tx_id), &tx) {
+                codes::report_warning(
+                    self.config.error_format,
+                    &format!("error writing tx history: {}", error),
+                );
+                client.restore_transaction(tx_id, tx);
+            }
+        }
+    }
+}
+
+// One transaction of a grouped batch, see `push_batch`, reporting whether it was applied
+fn apply_to<S: BuildHasher + Default>(
+    client: &mut Client<S>,
+    tx: Transaction,
+    config: &EngineConfig,
+    links: &mut LinkGraph,
+    rejections: &mut Option<Vec<Rejection>>,
+) -> bool {
+    let ids = codes::ids(&tx);
+    let timestamp = tx.timestamp();
+    let rejected = rejections.is_some().then(|| tx.clone());
+    let link = links::references_parent(tx.tx_type()).then(|| tx.clone());
+    match client.handle_transaction_with_config(tx, config) {
+        Ok(()) => {
+            if let Some(tx) = link {
+                links.record(tx);
+            }
+            for dropped in client.retain(config, ids.1, timestamp) {
+                links.forget(dropped);
+            }
+            true
+        }
+        Err(error) => {
+            reject(rejections, config.error_format, ids, rejected, error);
+            false
+        }
+    }
+}
+
+fn reject(
+    rejections: &mut Option<Vec<Rejection>>,
+    format: ErrorFormat,
+    ids: (u16, u32),
+    tx: Option<Transaction>,
+    error: CodedError,
+) {
+    codes::report_rejection(format, ids, &error);
+    if let (Some(rejections), Some(tx)) = (rejections, tx) {
+        rejections.push(Rejection { tx, error });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conservation::{self, Tally};
+    use crate::generate::{self, DatasetShape};
+    use crate::transaction::TransactionType;
+    use std::collections::hash_map::RandomState;
+
+    #[test]
+    fn unknown_client_policies() {
+        let dispute = || Transaction::raw(TransactionType::Dispute, 1, 7, None);
+
+        let mut engine = TransactionEngine::default();
+        engine.submit(dispute()).unwrap();
+        assert_eq!(
+            engine.clients()[&ClientId::new(7)].funds().available(),
+            Money::ZERO
+        );
+
+        let mut engine = TransactionEngine::new(EngineConfig {
+            unknown_clients: UnknownClientPolicy::Reject,
+            ..Default::default()
+        });
+        assert_eq!(
+            engine.submit(dispute()),
+            Err(CodedError::new(
+                RejectionCode::UnknownClient,
+                "Client 7 has no account and tx 1 is a dispute, only deposits open one"
+            ))
+        );
+        engine.push_batch(&[dispute()]);
+        assert!(engine.clients().is_empty());
+        assert!(engine.quarantined().is_empty());
+
+        let mut engine = TransactionEngine::new(EngineConfig {
+            unknown_clients: UnknownClientPolicy::Quarantine,
+            ..Default::default()
+        });
+        engine.push_batch(&[
+            dispute(),
+            Transaction::raw(TransactionType::Deposit, 2, 7, Some(1.0)),
+        ]);
+        assert_eq!(engine.quarantined().len(), 1);
+        assert_eq!(
+            engine.clients()[&ClientId::new(7)]
+                .funds()
+                .available()
+                .to_string(),
+            "1.0000"
+        );
+    }
+
+    #[test]
+    fn retention_forgets_links_and_owners_of_dropped_transactions() {
+        let mut engine = TransactionEngine::new(EngineConfig {
+            cross_client: CrossClientPolicy::Route,
+            retention: RetentionPolicy::KeepLast(1),
+            ..Default::default()
+        });
+        engine
+            .submit(Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)))
+            .unwrap();
+        engine
+            .submit(Transaction::raw(TransactionType::Dispute, 1, 1, None))
+            .unwrap();
+        engine
+            .submit(Transaction::raw(TransactionType::Resolve, 1, 1, None))
+            .unwrap();
+        assert_eq!(engine.related_transactions(TxId::new(1)).len(), 2);
+
+        engine
+            .submit(Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)))
+            .unwrap();
+        assert!(engine.related_transactions(TxId::new(1)).is_empty());
+        assert_eq!(engine.owners.keys().collect::<Vec<_>>(), [&2]);
+        assert_eq!(
+            engine.submit(Transaction::raw(TransactionType::Dispute, 1, 1, None)),
+            Err(CodedError::new(
+                RejectionCode::UnknownTransaction,
+                "Tx 1 does not exist for client"
+            ))
+        );
+    }
+
+    // The withdrawal never took anything, so charging it back mustn't credit it
+    #[test]
+    fn first_withdrawal_is_rejected_and_never_reversed() {
+        let feed = [
+            Transaction::raw(TransactionType::Withdrawal, 1, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Chargeback, 1, 1, None),
+        ];
+
+        let mut engine = TransactionEngine::default().with_rejections();
+        for tx in feed.clone() {
+            engine.push(tx);
+        }
+        let mut batched = TransactionEngine::default();
+        batched.push_batch(&feed);
+
+        let (clients, rejections) = engine.finish_with_rejections();
+        assert_eq!(
+            rejections[0].error.message(),
+            "Insufficient funds to withdraw 5.0000"
+        );
+        for clients in [clients, batched.finish()] {
+            let client = &clients[&ClientId::new(1)];
+            assert!(!client
+                .transactions()
+                .values()
+                .any(|tx| tx.tx_type() == &TransactionType::Withdrawal));
+            assert_eq!(client.funds().available(), Money::ZERO);
+            assert_eq!(client.funds().held(), Money::ZERO);
+            assert!(!client.is_locked());
+        }
+    }
+
+    #[test]
+    fn batches_apply_like_single_pushes() {
+        let transactions = generate::transactions(&DatasetShape {
+            transactions: 5_000,
+            clients: 50,
+            ..Default::default()
+        });
+        let mut single = TransactionEngine::default().with_rejections();
+        for tx in transactions.clone() {
+            single.push(tx);
+        }
+        let mut batched = TransactionEngine::default().with_rejections();
+        for batch in transactions.chunks(700) {
+            batched.push_batch(batch);
+        }
+
+        let (single, single_rejections) = single.finish_with_rejections();
+        let (batched, batched_rejections) = batched.finish_with_rejections();
+        assert_eq!(batched.len(), single.len());
+        for (client_id, client) in &single {
+            assert_eq!(
+                batched[client_id].get_record(*client_id),
+                client.get_record(*client_id)
+            );
+            assert_eq!(batched[client_id].stats(), client.stats());
+        }
+        assert!(!single_rejections.is_empty());
+        assert_eq!(batched_rejections.len(), single_rejections.len());
+    }
+
+    #[test]
+    fn applies_slightly_out_of_order_txs_within_window() {
+        let mut engine = TransactionEngine::new(EngineConfig {
+            reorder_window: 2,
+            ..Default::default()
+        });
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)));
+        engine.push(Transaction::raw(
+            TransactionType::Withdrawal,
+            3,
+            1,
+            Some(1.0),
+        ));
+        engine.push(Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)));
+
+        let clients = engine.finish();
+        assert_eq!(
+            clients[&ClientId::new(1)].get_record(ClientId::new(1)),
+            vec!["1", "2.0000", "0.0000", "2.0000", "false", "false", "0.0000"]
+        );
+    }
+
+    // A dispute carries the id of the deposit it references, it mustn't overtake the
+    // withdrawal that arrived before it
+    #[test]
+    fn reorder_window_keeps_disputes_in_arrival_position() {
+        let feed = || {
+            [
+                Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+                Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(5.0)),
+                Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            ]
+        };
+        let config = EngineConfig {
+            reorder_window: 2,
+            ..Default::default()
+        };
+
+        let mut engine = TransactionEngine::new(config.clone()).with_rejections();
+        for tx in feed() {
+            engine.push(tx);
+        }
+        let (clients, rejections) = engine.finish_with_rejections();
+        assert!(rejections.is_empty());
+
+        let concurrent = crate::ConcurrentEngine::new(config);
+        for tx in feed() {
+            concurrent.push(tx);
+        }
+        for clients in [clients, concurrent.finish()] {
+            assert_eq!(
+                clients[&ClientId::new(1)].funds().available(),
+                Money::from(-5.0)
+            );
+            assert_eq!(clients[&ClientId::new(1)].funds().held(), Money::from(5.0));
+        }
+    }
+
+    #[test]
+    fn simulates_without_applying() {
+        let mut engine = TransactionEngine::new(EngineConfig::default());
+        engine
+            .submit(Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)))
+            .unwrap();
+
+        let snapshot = engine
+            .simulate(Transaction::raw(
+                TransactionType::Withdrawal,
+                2,
+                1,
+                Some(0.5),
+            ))
+            .unwrap();
+        assert_eq!(snapshot.available.to_string(), "1.5000");
+        assert_eq!(snapshot.total.to_string(), "1.5000");
+        assert!(engine
+            .simulate(Transaction::raw(
+                TransactionType::Withdrawal,
+                2,
+                1,
+                Some(5.0),
+            ))
+            .is_err());
+
+        assert_eq!(
+            engine.clients()[&ClientId::new(1)]
+                .funds()
+                .available()
+                .to_string(),
+            "2.0000"
+        );
+        engine
+            .submit(Transaction::raw(
+                TransactionType::Withdrawal,
+                2,
+                1,
+                Some(0.5),
+            ))
+            .unwrap();
+    }
+
+    #[test]
+    fn can_resume_engine_from_bytes() {
+        let mut engine = TransactionEngine::new(EngineConfig {
+            reorder_window: 2,
+            ..Default::default()
+        });
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)));
+        engine.push(Transaction::raw(
+            TransactionType::Withdrawal,
+            3,
+            1,
+            Some(1.0),
+        ));
+
+        let mut resumed = TransactionEngine::from_bytes(&engine.to_bytes().unwrap()).unwrap();
+        resumed.push(Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)));
+
+        let clients = resumed.finish();
+        assert_eq!(
+            clients[&ClientId::new(1)].get_record(ClientId::new(1)),
+            vec!["1", "2.0000", "0.0000", "2.0000", "false", "false", "0.0000"]
+        );
+    }
+
+    #[test]
+    fn refunds_resolved_disputes_to_the_source() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut engine = TransactionEngine::builder()
+            .resolve(ResolvePolicy::RefundToSource)
+            .sink(Box::new(notify::ChannelSink(sender)))
+            .build()
+            .unwrap();
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Refund, 1, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(3.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Resolve, 1, 1, None),
+            // Nothing of the deposit is left to refund or dispute
+            Transaction::raw(TransactionType::Refund, 1, 1, Some(1.0)),
+        ] {
+            engine.push(tx);
+        }
+
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![notify::Notification::Refund {
+                client: 1,
+                tx: 1,
+                amount: Money::from(4.0),
+            }]
+        );
+        // Snapshots from before the policy existed resolve as they always did
+        let bytes = engine.to_bytes().unwrap();
+        let mut rest = &bytes[6..];
+        let config: (
+            EngineConfigV1,
+            UnknownClientPolicy,
+            ResolvePolicy,
+            HashMap<u16, TierLimits>,
+            RetentionPolicy,
+        ) = snapshot::decode_from(&mut rest).unwrap();
+        // Clients without their tally, which came later
+        let clients: Vec<(u16, (ClientV3, Tally))> = snapshot::decode_from(&mut rest).unwrap();
+        let clients: Vec<(u16, ClientV3)> = clients
+            .into_iter()
+            .map(|(client_id, (client, _))| (client_id, client))
+            .collect();
+        let mut version_4 = b"FCSN\x04\x00".to_vec();
+        version_4.extend(snapshot::encode(&(config.0, config.1)).unwrap());
+        version_4.extend(snapshot::encode(&clients).unwrap());
+        version_4.extend(rest);
+        let loaded = TransactionEngine::from_bytes(&version_4).unwrap();
+        assert_eq!(loaded.config.resolve, ResolvePolicy::Release);
+
+        let clients = engine.finish();
+        assert_eq!(
+            clients[&ClientId::new(1)].funds().available(),
+            Money::from(3.0)
+        );
+        assert_eq!(clients[&ClientId::new(1)].funds().held(), Money::ZERO);
+        assert_eq!(conservation::check(&clients).1, vec![]);
+    }
+
+    #[derive(Debug, Default)]
+    struct MemoryHistory(HashMap<(ClientId, TxId), Transaction>);
+
+    impl TransactionHistory for MemoryHistory {
+        fn get(&self, client_id: ClientId, tx_id: TxId) -> Result<Option<Transaction>, String> {
+            Ok(self.0.get(&(client_id, tx_id)).cloned())
+        }
+
+        fn insert(
+            &mut self,
+            client_id: ClientId,
+            tx_id: TxId,
+            tx: &Transaction,
+        ) -> Result<(), String> {
+            self.0.insert((client_id, tx_id), tx.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keeps_only_disputed_txs_in_memory_with_history() {
+        let mut engine = TransactionEngine::new(EngineConfig::default())
+            .with_history(Box::<MemoryHistory>::default());
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)));
+        engine.push(Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)));
+        assert!(engine.clients()[&ClientId::new(1)]
+            .transactions()
+            .is_empty());
+
+        engine.push(Transaction::raw(TransactionType::Dispute, 1, 1, None));
+        assert_eq!(engine.clients()[&ClientId::new(1)].transactions().len(), 1);
+
+        engine.push(Transaction::raw(TransactionType::Chargeback, 1, 1, None));
+        let clients = engine.finish();
+        assert_eq!(
+            clients[&ClientId::new(1)].get_record(ClientId::new(1)),
+            vec!["1", "1.0000", "0.0000", "1.0000", "true", "false", "0.0000"]
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_order_txs_without_window() {
+        let mut engine = TransactionEngine::new(EngineConfig::default());
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)));
+        engine.push(Transaction::raw(
+            TransactionType::Withdrawal,
+            3,
+            1,
+            Some(1.0),
+        ));
+        engine.push(Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)));
+
+        let clients = engine.finish();
+        assert_eq!(
+            clients[&ClientId::new(1)].get_record(ClientId::new(1)),
+            vec!["1", "1.0000", "0.0000", "1.0000", "false", "false", "0.0000"]
+        );
+    }
+
+    #[test]
+    fn frozen_and_locked_accounts_apply_only_allowed_types() {
+        let mut engine = TransactionEngine::new(EngineConfig {
+            locked_allows: vec![TransactionType::Deposit],
+            ..Default::default()
+        });
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)));
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 2, Some(5.0)));
+        assert!(engine.freeze(ClientId::new(3)).is_err());
+
+        engine.freeze(ClientId::new(1)).unwrap();
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(1.0));
+        assert!(engine.submit(withdrawal).is_err());
+        engine
+            .submit(Transaction::raw(TransactionType::Deposit, 3, 1, Some(1.0)))
+            .unwrap();
+        engine.unfreeze(ClientId::new(1)).unwrap();
+        engine
+            .submit(Transaction::raw(
+                TransactionType::Withdrawal,
+                4,
+                1,
+                Some(1.0),
+            ))
+            .unwrap();
+
+        engine.push(Transaction::raw(TransactionType::Dispute, 1, 2, None));
+        engine.push(Transaction::raw(TransactionType::Chargeback, 1, 2, None));
+        engine
+            .submit(Transaction::raw(TransactionType::Deposit, 5, 2, Some(1.0)))
+            .unwrap();
+        let withdrawal = Transaction::raw(TransactionType::Withdrawal, 6, 2, Some(1.0));
+        assert!(engine.submit(withdrawal).is_err());
+
+        let clients = engine.finish();
+        assert_eq!(
+            clients[&ClientId::new(1)].get_record(ClientId::new(1)),
+            vec!["1", "5.0000", "0.0000", "5.0000", "false", "false", "0.0000"]
+        );
+        assert_eq!(
+            clients[&ClientId::new(2)].get_record(ClientId::new(2)),
+            vec!["2", "1.0000", "0.0000", "1.0000", "true", "false", "0.0000"]
+        );
+    }
+
+    #[test]
+    fn holds_future_dated_transactions_until_their_value_date() {
+        let at = |date: &str| date.parse::<DateTime<Utc>>().unwrap();
+        let mut engine = TransactionEngine::new(EngineConfig::default());
+        engine.push(
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0))
+                .with_timestamp(at("2024-01-01T00:00:00Z")),
+        );
+        engine.push(
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(5.0))
+                .with_timestamp(at("2024-01-01T00:00:00Z"))
+                .with_value_date(at("2024-01-03T00:00:00Z")),
+        );
+        assert_eq!(
+            engine.clients()[&ClientId::new(1)]
+                .funds()
+                .available()
+                .to_string(),
+            "2.0000"
+        );
+
+        engine.push(
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(6.0))
+                .with_timestamp(at("2024-01-03T00:00:00Z")),
+        );
+        engine.push(
+            Transaction::raw(TransactionType::Deposit, 4, 1, Some(1.0))
+                .with_value_date(at("2024-02-01T00:00:00Z")),
+        );
+        // Submitted transactions aren't held, only those already due are applied
+        let early = Transaction::raw(TransactionType::Deposit, 5, 1, Some(1.0))
+            .with_value_date(at("2024-01-04T00:00:00Z"));
+        assert!(engine.submit(early.clone()).is_err());
+        assert!(engine.submit_with_changes(early.clone()).is_err());
+        engine
+            .submit(early.with_timestamp(at("2024-01-04T00:00:00Z")))
+            .unwrap();
+        engine
+            .submit(
+                Transaction::raw(TransactionType::Withdrawal, 6, 1, Some(1.0))
+                    .with_value_date(at("2024-01-02T00:00:00Z")),
+            )
+            .unwrap();
+
+        let clients = engine.finish();
+        assert_eq!(
+            clients[&ClientId::new(1)].get_record(ClientId::new(1)),
+            vec!["1", "1.0000", "0.0000", "1.0000", "false", "false", "0.0000"]
+        );
+    }
+
+    #[test]
+    fn accrues_interest_once_per_period() {
+        let mut engine = TransactionEngine::new(EngineConfig::default());
+        engine.push(Transaction::raw(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(100.0),
+        ));
+        engine.push(Transaction::raw(TransactionType::Deposit, 2, 2, Some(1.0)));
+        engine.push(Transaction::raw(
+            TransactionType::Withdrawal,
+            3,
+            2,
+            Some(1.0),
+        ));
+
+        let january = "2024-01-31T00:00:00Z".parse().unwrap();
+        assert_eq!(engine.accrue_interest(0.015, january), Ok(1));
+        assert_eq!(engine.accrue_interest(0.015, january), Ok(0));
+
+        let entry = engine.clients()[&ClientId::new(1)]
+            .audit_trail()
+            .last()
+            .unwrap();
+        assert_eq!(entry.operation, TransactionType::Interest);
+        assert_eq!(entry.timestamp, Some(january));
+        assert_eq!(entry.available.to_string(), "101.5000");
+    }
+
+    #[test]
+    fn routes_or_rejects_disputes_sent_under_another_client() {
+        let transactions = [
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            Transaction::raw(TransactionType::Deposit, 2, 2, Some(3.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 2, None),
+        ];
+
+        let mut engine = TransactionEngine::new(EngineConfig {
+            cross_client: CrossClientPolicy::Route,
+            ..Default::default()
+        });
+        for tx in transactions.clone() {
+            engine.submit(tx).unwrap();
+        }
+        assert!(engine.clients()[&ClientId::new(1)]
+            .disputed_transactions()
+            .contains(&1));
+
+        let mut engine = TransactionEngine::new(EngineConfig {
+            cross_client: CrossClientPolicy::Reject,
+            ..Default::default()
+        });
+        let [first, second, dispute] = transactions;
+        engine.submit(first).unwrap();
+        engine.submit(second).unwrap();
+        assert_eq!(
+            engine.submit(dispute),
+            Err(CodedError::new(
+                RejectionCode::WrongClient,
+                "Tx 1 belongs to client 1, not client 2"
+            ))
+        );
+    }
+
+    #[test]
+    fn links_transactions_to_the_tx_they_reference() {
+        let mut engine = TransactionEngine::new(EngineConfig::default());
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)));
+        engine.push(Transaction::raw(TransactionType::Refund, 1, 1, Some(0.5)));
+        engine.push(Transaction::raw(TransactionType::Dispute, 1, 1, None));
+        engine.push(Transaction::raw(TransactionType::Resolve, 1, 1, None));
+        // Rejected, tx 1 is no longer disputed
+        engine.push(Transaction::raw(TransactionType::Chargeback, 1, 1, None));
+
+        let related: Vec<&TransactionType> = engine
+            .related_transactions(TxId::new(1))
+            .iter()
+            .map(Transaction::tx_type)
+            .collect();
+        assert_eq!(
+            related,
+            vec![
+                &TransactionType::Refund,
+                &TransactionType::Dispute,
+                &TransactionType::Resolve
+            ]
+        );
+        assert!(engine.related_transactions(TxId::new(2)).is_empty());
+    }
+
+    #[test]
+    fn hashes_ids_with_the_hasher_it_was_given() {
+        let mut engine =
+            TransactionEngine::with_hasher(EngineConfig::default(), RandomState::new());
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)));
+        engine.push(Transaction::raw(TransactionType::Dispute, 1, 1, None));
+        engine.push(Transaction::raw(TransactionType::Chargeback, 1, 1, None));
+
+        // Snapshots don't depend on the hasher
+        let restored = TransactionEngine::from_bytes(&engine.to_bytes().unwrap()).unwrap();
+        let clients: Clients<RandomState> = engine.finish();
+        assert!(clients[&ClientId::new(1)].is_locked());
+        assert_eq!(
+            restored.clients()[&ClientId::new(1)].funds(),
+            clients[&ClientId::new(1)].funds()
+        );
+    }
+}
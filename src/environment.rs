@@ -0,0 +1,225 @@
+// Overrides of `EngineConfig` fields from FUNDS_CALCULATOR_<FIELD> environment variables, e.g.
+// FUNDS_CALCULATOR_REORDER_WINDOW=10, so containers can be configured without mounting files.
+// They are applied last and win over command line options and the clients and tiers files.
+// Lists are comma separated, maps comma separated `key=value` pairs, and a variable replaces
+// the whole field rather than adding to it:
+//
+//     FUNDS_CALCULATOR_DISPUTABLE_TYPES=deposit
+//     FUNDS_CALCULATOR_CREDIT_LIMITS=1=100.0,2=50
+//     FUNDS_CALCULATOR_TIERS=1=gold:500.0:0.25,2=basic::1.0
+//     FUNDS_CALCULATOR_RETENTION=keep-last:1000
+use crate::engine::{EngineConfig, RetentionPolicy, TierLimits};
+use crate::money::Money;
+use crate::transaction::TransactionType;
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use std::collections::HashMap;
+use std::env;
+use std::hash::Hash;
+use std::str::FromStr;
+
+pub const PREFIX: &str = "FUNDS_CALCULATOR_";
+
+// Applies every FUNDS_CALCULATOR_ variable set in the environment to `config`
+pub fn apply(config: &mut EngineConfig) -> Result<(), String> {
+    apply_vars(config, env::vars())
+}
+
+// Same as `apply` for variables from anywhere, e.g. an env file. Unknown FUNDS_CALCULATOR_
+// variables are an error so a misspelled one doesn't go unnoticed, others are ignored
+pub fn apply_vars(
+    config: &mut EngineConfig,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<(), String> {
+    for (name, value) in vars {
+        let Some(field) = name.strip_prefix(PREFIX) else {
+            continue;
+        };
+        apply_field(config, field, value.trim()).map_err(|error| format!("{}: {}", name, error))?;
+    }
+
+    Ok(())
+}
+
+fn apply_field(config: &mut EngineConfig, field: &str, value: &str) -> Result<(), String> {
+    match field {
+        "REORDER_WINDOW" => config.reorder_window = parse(value)?,
+        "DISPUTABLE_TYPES" => config.disputable_types = list(value)?,
+        "LOCKED_ALLOWS" => config.locked_allows = list(value)?,
+        "FROZEN_ALLOWS" => config.frozen_allows = list(value)?,
+        "CROSS_CLIENT" => config.cross_client = variant(value)?,
+        "ORDERING" => config.ordering = variant(value)?,
+        "DISPUTE_OVERDRAFT" => config.dispute_overdraft = variant(value)?,
+        "JOINT_ACCOUNTS" => config.joint_accounts = map(value, parse)?,
+        "CREDIT_LIMITS" => config.credit_limits = map(value, parse)?,
+        "RELEASE_SCHEDULED" => config.release_scheduled = flag(value)?,
+        "AUTO_UNLOCK" => config.auto_unlock = flag(value)?,
+        "UNKNOWN_CLIENTS" => config.unknown_clients = variant(value)?,
+        "RESOLVE" => config.resolve = variant(value)?,
+        "TIERS" => config.tiers = map(value, tier)?,
+        "RETENTION" => config.retention = retention(value)?,
+        _ => return Err("Not an engine config field".to_string()),
+    }
+
+    Ok(())
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: ToString,
+{
+    value
+        .parse()
+        .map_err(|error: T::Err| format!("Invalid value {:?}, {}", value, error.to_string()))
+}
+
+// Policies are named as on the command line, e.g. refund-to-source
+fn variant<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    T::deserialize(value.into_deserializer())
+        .map_err(|error: serde::de::value::Error| error.to_string())
+}
+
+fn flag(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(format!("Expected true or false, got {:?}", value)),
+    }
+}
+
+// Empty for an empty list, e.g. FUNDS_CALCULATOR_LOCKED_ALLOWS=
+fn list(value: &str) -> Result<Vec<TransactionType>, String> {
+    items(value).map(parse).collect()
+}
+
+fn map<K: FromStr + Eq + Hash, V>(
+    value: &str,
+    parse_value: fn(&str) -> Result<V, String>,
+) -> Result<HashMap<K, V>, String>
+where
+    K::Err: ToString,
+{
+    items(value)
+        .map(|item| {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or(format!("Expected key=value, got {:?}", item))?;
+            Ok((parse(key.trim())?, parse_value(value.trim())?))
+        })
+        .collect()
+}
+
+fn items(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+// `name:withdrawal limit:withdrawal fee`, the limit left empty for none and the fee for zero
+fn tier(value: &str) -> Result<TierLimits, String> {
+    let mut parts = value.split(':');
+    let tier = parts.next().unwrap_or_default().to_string();
+    let withdrawal_limit = match parts.next() {
+        Some(limit) if !limit.is_empty() => Some(parse::<Money>(limit)?),
+        _ => None,
+    };
+    let withdrawal_fee = match parts.next() {
+        Some(fee) if !fee.is_empty() => parse(fee)?,
+        _ => Money::ZERO,
+    };
+    if tier.is_empty() || parts.next().is_some() {
+        return Err(format!("Expected name:limit:fee, got {:?}", value));
+    }
+
+    Ok(TierLimits {
+        tier,
+        withdrawal_limit,
+        withdrawal_fee,
+    })
+}
+
+// keep-all, keep-last:N or deposits-within-days:DAYS, as --retain-last and --retain-deposits-days
+fn retention(value: &str) -> Result<RetentionPolicy, String> {
+    match value.split_once(':') {
+        None if value == "keep-all" => Ok(RetentionPolicy::KeepAll),
+        Some(("keep-last", count)) => Ok(RetentionPolicy::KeepLast(parse(count)?)),
+        Some(("deposits-within-days", days)) => Ok(RetentionPolicy::DepositsWithin(
+            i64::from(parse::<u32>(days)?) * 86_400,
+        )),
+        _ => Err(format!(
+            "Expected keep-all, keep-last:N or deposits-within-days:DAYS, got {:?}",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{CrossClientPolicy, OrderingPolicy, ResolvePolicy};
+    use crate::ids::ClientId;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn overrides_config_fields() {
+        let mut config = EngineConfig {
+            reorder_window: 3,
+            ..Default::default()
+        };
+        apply_vars(
+            &mut config,
+            vars(&[
+                ("PATH", "/usr/bin"),
+                ("FUNDS_CALCULATOR_REORDER_WINDOW", "10"),
+                ("FUNDS_CALCULATOR_LOCKED_ALLOWS", " deposit, refund "),
+                ("FUNDS_CALCULATOR_FROZEN_ALLOWS", ""),
+                ("FUNDS_CALCULATOR_CROSS_CLIENT", "route"),
+                ("FUNDS_CALCULATOR_ORDERING", "ignore"),
+                ("FUNDS_CALCULATOR_RESOLVE", "refund-to-source"),
+                ("FUNDS_CALCULATOR_AUTO_UNLOCK", "true"),
+                ("FUNDS_CALCULATOR_CREDIT_LIMITS", "1=100.0,2=50"),
+                ("FUNDS_CALCULATOR_TIERS", "1=gold:500:0.25,2=basic::"),
+                ("FUNDS_CALCULATOR_RETENTION", "deposits-within-days:2"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.reorder_window, 10);
+        assert_eq!(
+            config.locked_allows,
+            [TransactionType::Deposit, TransactionType::Refund]
+        );
+        assert!(config.frozen_allows.is_empty());
+        assert_eq!(config.cross_client, CrossClientPolicy::Route);
+        assert_eq!(config.ordering, OrderingPolicy::Ignore);
+        assert_eq!(config.resolve, ResolvePolicy::RefundToSource);
+        assert!(config.auto_unlock);
+        assert_eq!(config.credit_limits[&ClientId::new(2)], Money::from(50.0));
+        assert_eq!(
+            config.tiers[&ClientId::new(1)].withdrawal_limit,
+            Some(Money::from(500.0))
+        );
+        assert_eq!(
+            config.tiers[&ClientId::new(1)].withdrawal_fee,
+            Money::from(0.25)
+        );
+        assert_eq!(config.tiers[&ClientId::new(2)].withdrawal_limit, None);
+        assert_eq!(config.retention, RetentionPolicy::DepositsWithin(172_800));
+
+        for (name, value) in [
+            ("FUNDS_CALCULATOR_REORDER_WINDOWS", "1"),
+            ("FUNDS_CALCULATOR_REORDER_WINDOW", "-1"),
+            ("FUNDS_CALCULATOR_DISPUTE_OVERDRAFT", "deny"),
+            ("FUNDS_CALCULATOR_JOINT_ACCOUNTS", "1"),
+            ("FUNDS_CALCULATOR_RETENTION", "keep-last"),
+        ] {
+            let error = apply_vars(&mut config, vars(&[(name, value)])).unwrap_err();
+            assert!(error.starts_with(name), "{}", error);
+        }
+    }
+}
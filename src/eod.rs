@@ -0,0 +1,102 @@
+// Cuts the stream into days the way settlement systems do: whenever a transaction's timestamp
+// falls on a later UTC date than the ones before it, the accounts as they stand are written
+// as the end of day snapshot of the previous date.
+use crate::client::{Clients, NumberFormat};
+use crate::engine::TransactionEngine;
+use crate::transaction::Transaction;
+use crate::write_accounts;
+use chrono::NaiveDate;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+// Writes `accounts-YYYY-MM-DD.csv` into `dir` for every date seen, the last one once the input
+// is exhausted. Transactions without a timestamp belong to the current date. Returns the final
+// clients and the snapshot paths.
+pub fn process(
+    mut engine: TransactionEngine,
+    transactions: impl IntoIterator<Item = Transaction>,
+    dir: &str,
+    format: NumberFormat,
+) -> Result<(Clients, Vec<PathBuf>), String> {
+    let mut paths = Vec::new();
+    let mut current: Option<NaiveDate> = None;
+
+    for tx in transactions {
+        if let Some(date) = tx.timestamp().map(|timestamp| timestamp.date_naive()) {
+            match current {
+                Some(day) if date > day => {
+                    paths.push(write_snapshot(engine.clients(), dir, day, format)?);
+                    current = Some(date);
+                }
+                None => current = Some(date),
+                _ => {}
+            }
+        }
+
+        engine.push(tx);
+    }
+
+    let clients = engine.finish();
+    if let Some(day) = current {
+        paths.push(write_snapshot(&clients, dir, day, format)?);
+    }
+
+    Ok((clients, paths))
+}
+
+fn write_snapshot(
+    clients: &Clients,
+    dir: &str,
+    day: NaiveDate,
+    format: NumberFormat,
+) -> Result<PathBuf, String> {
+    let path = Path::new(dir).join(format!("accounts-{}.csv", day.format("%Y-%m-%d")));
+    let file = File::create(&path).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+    write_accounts(clients, file, format)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::transaction::TransactionType;
+    use std::fs;
+
+    #[test]
+    fn writes_a_snapshot_per_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0))
+                .with_timestamp("2024-01-01T09:00:00Z".parse().unwrap()),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(2.0))
+                .with_timestamp("2024-01-03T09:00:00Z".parse().unwrap()),
+        ];
+
+        let (_, paths) = process(
+            TransactionEngine::new(EngineConfig::default()),
+            transactions,
+            dir.path().to_str().unwrap(),
+            NumberFormat::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            paths,
+            vec![
+                dir.path().join("accounts-2024-01-01.csv"),
+                dir.path().join("accounts-2024-01-03.csv")
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(&paths[0]).unwrap(),
+            "client,available,held,total,locked,frozen,credit_used\n1,6.0000,0.0000,6.0000,false,false,0.0000\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&paths[1]).unwrap(),
+            "client,available,held,total,locked,frozen,credit_used\n1,4.0000,0.0000,4.0000,false,false,0.0000\n"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+// Applies transactions as they arrive, e.g. `tail -f transactions.csv | transactions --follow`,
+// writing a client's account row every time a transaction changes it instead of all accounts
+// once the input ends. Each row is flushed right away so a consumer sees balances as they move.
+use crate::client::{Clients, NumberFormat};
+use crate::engine::TransactionEngine;
+use crate::source::InputSource;
+use std::io;
+
+// Runs until `source` ends or Ctrl-C is pressed, returning the clients and the number of
+// transactions read
+pub fn follow<S: InputSource, W: io::Write>(
+    mut engine: TransactionEngine,
+    mut source: S,
+    writer: W,
+    format: NumberFormat,
+) -> Result<(Clients, u64), String> {
+    let write_error = |error: csv::Error| format!("Error writing to std out: {}", error);
+    let flush_error = |error: io::Error| format!("Error writing to std out: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record([
+        "client",
+        "available",
+        "held",
+        "total",
+        "locked",
+        "frozen",
+        "credit_used",
+    ])
+    .map_err(write_error)?;
+    wtr.flush().map_err(flush_error)?;
+
+    let mut processed = 0;
+    while let Some(tx) = source.next_transaction() {
+        let tx = tx?;
+        processed += 1;
+
+        // Rejected transactions, and ones held back by the reorder window, leave the row as it
+        // was. Joint accounts and routed references change another client than the row names
+        let client_id = engine.account_of(&tx);
+        let row = |engine: &TransactionEngine| {
+            engine
+                .clients()
+                .get(&client_id)
+                .map(|client| client.get_record_with_format(client_id, format))
+        };
+        let before = row(&engine);
+        engine.push(tx);
+        match row(&engine) {
+            Some(after) if Some(&after) != before.as_ref() => {
+                wtr.write_record(&after).map_err(write_error)?;
+                wtr.flush().map_err(flush_error)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok((engine.finish(), processed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::ids::ClientId;
+    use crate::source::MemorySource;
+    use crate::transaction::{Transaction, TransactionType};
+    use std::collections::HashMap;
+
+    #[test]
+    fn writes_a_row_per_change() {
+        let source = MemorySource::from(vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            Transaction::raw(TransactionType::Deposit, 2, 2, Some(1.0)),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+        ]);
+        let mut output = Vec::new();
+        let (clients, processed) = follow(
+            TransactionEngine::new(EngineConfig::default()),
+            source,
+            &mut output,
+            NumberFormat::default(),
+        )
+        .unwrap();
+
+        assert_eq!(processed, 4);
+        assert_eq!(clients.len(), 2);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked,frozen,credit_used\n\
+             1,2.0000,0.0000,2.0000,false,false,0.0000\n\
+             2,1.0000,0.0000,1.0000,false,false,0.0000\n\
+             1,0.0000,2.0000,2.0000,false,false,0.0000\n"
+        );
+    }
+
+    #[test]
+    fn writes_the_row_of_a_joint_account() {
+        let source = MemorySource::from(vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            Transaction::raw(TransactionType::Deposit, 2, 2, Some(1.0)),
+        ]);
+        let mut output = Vec::new();
+        follow(
+            TransactionEngine::new(EngineConfig {
+                joint_accounts: HashMap::from([(ClientId::new(2), ClientId::new(1))]),
+                ..Default::default()
+            }),
+            source,
+            &mut output,
+            NumberFormat::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked,frozen,credit_used\n\
+             1,2.0000,0.0000,2.0000,false,false,0.0000\n\
+             1,3.0000,0.0000,3.0000,false,false,0.0000\n"
+        );
+    }
+}
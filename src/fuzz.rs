@@ -0,0 +1,251 @@
+// Entry points for fuzzing the crate, e.g. with cargo-fuzz. Any input is accepted: rows that
+// don't parse and transactions the engine rejects are part of normal operation. An `Err` means
+// an invariant broke and is what a fuzz target should fail on:
+//
+//     fuzz_target!(|data: &[u8]| transactions::fuzz::apply_arbitrary_bytes(data).unwrap());
+//
+// The invariants are that funds are conserved, held never goes below zero, encoding and
+// decoding the engine keeps every balance and `push_batch` agrees with pushing one at a time.
+use crate::client::Clients;
+use crate::conservation;
+use crate::engine::TransactionEngine;
+use crate::money::{self, Money};
+use crate::transaction::{Transaction, TransactionType};
+use crate::Amount;
+#[cfg(feature = "csv")]
+use crate::{input::CsvSource, source::InputSource};
+
+// A transaction built from raw fuzzer input. Ids are kept small so disputes, resolves and
+// chargebacks often find the transaction they reference
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct FuzzTransaction {
+    pub kind: u8,
+    pub client: u8,
+    pub tx: u8,
+    // In minor units, see `Money`
+    pub amount: i64,
+    pub scale: u8,
+}
+
+impl FuzzTransaction {
+    pub fn to_transaction(&self) -> Transaction {
+        let tx_type = match self.kind % 6 {
+            0 => TransactionType::Deposit,
+            1 => TransactionType::Withdrawal,
+            2 => TransactionType::Dispute,
+            3 => TransactionType::Resolve,
+            4 => TransactionType::Chargeback,
+            _ => TransactionType::Refund,
+        };
+        // Amounts read from text never have more decimals than they were written with
+        let scale = self.scale % 5;
+        let unit = 10_i64.pow(money::SCALE - u32::from(scale));
+        let amount = match tx_type {
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Refund => {
+                Some(Amount::new(
+                    Money::from_minor_units(self.amount - self.amount % unit),
+                    scale,
+                ))
+            }
+            _ => None,
+        };
+
+        Transaction::new(
+            tx_type,
+            u32::from(self.tx).into(),
+            u16::from(self.client).into(),
+            amount,
+        )
+    }
+}
+
+// Reads `data` as a transactions csv, skipping rows that don't parse, and applies the rest
+#[cfg(feature = "csv")]
+pub fn apply_arbitrary_bytes(data: &[u8]) -> Result<(), String> {
+    let Ok(mut source) = CsvSource::from_reader(data, Default::default()) else {
+        return Ok(());
+    };
+    let mut transactions = Vec::new();
+    while let Some(tx) = source.next_transaction() {
+        transactions.extend(tx.ok());
+    }
+
+    apply_sequence(&transactions)
+}
+
+pub fn apply_arbitrary_tx_sequence(txs: &[FuzzTransaction]) -> Result<(), String> {
+    let transactions: Vec<Transaction> = txs.iter().map(FuzzTransaction::to_transaction).collect();
+    apply_sequence(&transactions)
+}
+
+fn apply_sequence(transactions: &[Transaction]) -> Result<(), String> {
+    let mut engine = TransactionEngine::default();
+    for tx in transactions {
+        engine.push(tx.clone());
+    }
+    let mut batched = TransactionEngine::default();
+    batched.push_batch(transactions);
+
+    let bytes = engine.to_bytes()?;
+    let clients = engine.finish();
+    check_clients(&clients)?;
+    same_funds(
+        &clients,
+        &TransactionEngine::from_bytes(&bytes)?.finish(),
+        "decoded",
+    )?;
+    same_funds(&clients, &batched.finish(), "batched")
+}
+
+fn check_clients(clients: &Clients) -> Result<(), String> {
+    if let Some((client_id, _)) = clients
+        .iter()
+        .find(|(_, client)| client.funds().held() < Money::ZERO)
+    {
+        return Err(format!("Client {} holds less than zero", client_id));
+    }
+
+    match conservation::check(clients).1.first() {
+        Some(leak) => Err(leak.to_string()),
+        None => Ok(()),
+    }
+}
+
+fn same_funds(clients: &Clients, other: &Clients, name: &str) -> Result<(), String> {
+    if clients.len() != other.len() {
+        return Err(format!(
+            "{} clients, but {} once {}",
+            clients.len(),
+            other.len(),
+            name
+        ));
+    }
+
+    for (client_id, client) in clients {
+        let funds = other.get(client_id).map(|other| other.funds());
+        if funds != Some(client.funds()) {
+            return Err(format!(
+                "Client {} has {:?}, but {:?} once {}",
+                client_id,
+                client.funds(),
+                funds,
+                name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{Client, IdHasher};
+    use crate::ids::{ClientId, TxId};
+
+    // A cheap stand-in for a fuzzer, so obvious panics show up in the regular test run
+    #[test]
+    fn survives_pseudo_random_input() {
+        let mut state: u64 = 42;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            state >> 33
+        };
+
+        for _ in 0..200 {
+            let txs: Vec<FuzzTransaction> = (0..50)
+                .map(|_| FuzzTransaction {
+                    kind: next() as u8,
+                    client: (next() % 4) as u8,
+                    tx: (next() % 16) as u8,
+                    amount: match next() % 10 {
+                        0 => i64::MAX,
+                        1 => -(next() as i64),
+                        _ => (next() % 100_000) as i64,
+                    },
+                    scale: next() as u8,
+                })
+                .collect();
+            assert_eq!(apply_arbitrary_tx_sequence(&txs), Ok(()));
+        }
+
+        #[cfg(feature = "csv")]
+        for data in [
+            &b""[..],
+            b"type,client,tx,amount\ndeposit,1,1,1e9999\nwithdrawal,1,2,\n\xff,,\n",
+            b"type,client,tx,amount\ndeposit,1,1,2\ndispute,1,1,\nchargeback,1,1,\n",
+        ] {
+            assert_eq!(apply_arbitrary_bytes(data), Ok(()));
+        }
+    }
+
+    // Malformed transactions a library caller could build, every one has to be rejected
+    // without a panic
+    fn adversarial_transactions() -> Vec<Transaction> {
+        let max = Amount::new(Money::from_minor_units(i64::MAX), 4);
+        vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, None),
+            Transaction::raw(TransactionType::Withdrawal, 2, 2, None),
+            Transaction::raw(TransactionType::Deposit, 3, 3, Some(1.0)),
+            Transaction::raw(TransactionType::Deposit, 4, 3, None),
+            Transaction::raw(TransactionType::Withdrawal, 5, 3, None),
+            Transaction::raw(TransactionType::Refund, 3, 3, None),
+            Transaction::raw(TransactionType::Settlement, 6, 3, None),
+            Transaction::raw(TransactionType::Interest, 7, 3, Some(1.0)),
+            Transaction::new(
+                TransactionType::Deposit,
+                u32::MAX.into(),
+                3.into(),
+                Some(max),
+            ),
+            Transaction::new(
+                TransactionType::Deposit,
+                8.into(),
+                u16::MAX.into(),
+                Some(max),
+            ),
+            Transaction::raw(TransactionType::Dispute, 8, u16::MAX, None),
+            Transaction::raw(TransactionType::Chargeback, 8, u16::MAX, None),
+            Transaction::raw(TransactionType::Chargeback, 8, u16::MAX, None),
+            Transaction::raw(TransactionType::Resolve, 9, 4, None),
+        ]
+    }
+
+    #[test]
+    fn public_api_never_panics_on_adversarial_input() {
+        let transactions = adversarial_transactions();
+        assert_eq!(apply_sequence(&transactions), Ok(()));
+        assert!(Client::<IdHasher>::open(TxId::new(1), transactions[0].clone()).is_err());
+
+        let mut engine = TransactionEngine::default();
+        for tx in &transactions {
+            let _ = engine.simulate(tx.clone());
+            let _ = engine.submit(tx.clone());
+        }
+        assert!(!engine.clients().contains_key(&ClientId::new(1)));
+        assert!(!engine.clients().contains_key(&ClientId::new(2)));
+        assert_eq!(
+            engine.clients()[&ClientId::new(3)].funds().available(),
+            Money::from(1.0)
+        );
+
+        let concurrent = crate::ConcurrentEngine::new(Default::default());
+        for tx in &transactions {
+            concurrent.push(tx.clone());
+        }
+        assert!(!concurrent.finish().contains_key(&ClientId::new(1)));
+        assert!(crate::process_transactions(transactions, Default::default()).is_ok());
+
+        #[cfg(feature = "csv")]
+        {
+            let data = b"type,client,tx,amount\ndeposit,1,1,\nwithdrawal,1,2,\nrefund,1,1,\n";
+            assert_eq!(apply_arbitrary_bytes(data), Ok(()));
+            let mut output = Vec::new();
+            let clients = engine.finish();
+            crate::write_accounts(&clients, &mut output, Default::default()).unwrap();
+        }
+    }
+}
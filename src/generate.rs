@@ -0,0 +1,143 @@
+// Synthetic transaction datasets for benchmarks and load tests. The same shape always gives
+// the same dataset, so runs measured on different machines or commits are comparable.
+use crate::transaction::{Transaction, TransactionType};
+#[cfg(feature = "csv")]
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DatasetShape {
+    pub transactions: usize,
+    pub clients: u16,
+    // Fraction of rows that are withdrawals, disputes, resolves and chargebacks, the rest
+    // are deposits
+    pub withdrawal_rate: f64,
+    pub dispute_rate: f64,
+    pub resolve_rate: f64,
+    pub chargeback_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for DatasetShape {
+    fn default() -> Self {
+        DatasetShape {
+            transactions: 100_000,
+            clients: 1_000,
+            withdrawal_rate: 0.3,
+            dispute_rate: 0.02,
+            resolve_rate: 0.01,
+            chargeback_rate: 0.005,
+            seed: 1,
+        }
+    }
+}
+
+// Deposits and withdrawals of random clients with disputes, resolves and chargebacks of
+// earlier deposits mixed in. Tx ids only grow, so nothing is rejected for being in the past,
+// though some withdrawals and references fail the way real feeds do
+pub fn transactions(shape: &DatasetShape) -> Vec<Transaction> {
+    let mut random = XorShift(shape.seed.max(1));
+    let mut deposits: Vec<(u32, u16)> = Vec::new();
+    let mut transactions = Vec::with_capacity(shape.transactions);
+    let mut next_tx = 1;
+
+    while transactions.len() < shape.transactions {
+        let roll = random.fraction();
+        let referencing = [
+            (shape.dispute_rate, TransactionType::Dispute),
+            (shape.resolve_rate, TransactionType::Resolve),
+            (shape.chargeback_rate, TransactionType::Chargeback),
+        ];
+        let mut threshold = shape.withdrawal_rate;
+        let referenced = referencing.into_iter().find(|(rate, _)| {
+            threshold += rate;
+            roll < threshold
+        });
+
+        let client = (random.next() % u64::from(shape.clients.max(1))) as u16 + 1;
+        let amount = Some((random.next() % 100_000 + 1) as f32 / 100.0);
+        let tx = match referenced {
+            Some((_, tx_type)) if !deposits.is_empty() => {
+                let (tx_id, client) = deposits[random.next() as usize % deposits.len()];
+                Transaction::raw(tx_type, tx_id, client, None)
+            }
+            _ if roll < shape.withdrawal_rate => {
+                next_tx += 1;
+                Transaction::raw(TransactionType::Withdrawal, next_tx, client, amount)
+            }
+            _ => {
+                next_tx += 1;
+                deposits.push((next_tx, client));
+                Transaction::raw(TransactionType::Deposit, next_tx, client, amount)
+            }
+        };
+        transactions.push(tx);
+    }
+
+    transactions
+}
+
+// Writes the dataset as a transactions csv the binary reads
+#[cfg(feature = "csv")]
+pub fn write_csv<W: io::Write>(shape: &DatasetShape, writer: W) -> Result<(), String> {
+    let write_error = |error: csv::Error| format!("Error writing dataset: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    wtr.write_record(["type", "client", "tx", "amount"])
+        .map_err(write_error)?;
+    for tx in transactions(shape) {
+        wtr.write_record([
+            tx.tx_type().to_string(),
+            tx.raw_client_id().to_string(),
+            tx.raw_tx_id().to_string(),
+            tx.raw_amount()
+                .map(|amount| amount.to_string())
+                .unwrap_or_default(),
+        ])
+        .map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing dataset: {}", error))
+}
+
+// Small and fast, the datasets only need to look random. The seed must not be 0
+pub(crate) struct XorShift(pub(crate) u64);
+
+impl XorShift {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub(crate) fn fraction(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_deterministic() {
+        let shape = DatasetShape {
+            transactions: 1_000,
+            clients: 10,
+            ..Default::default()
+        };
+
+        let dataset = transactions(&shape);
+        assert_eq!(dataset.len(), 1_000);
+        // Transactions aren't comparable, their debug output is
+        let debug = |shape: &DatasetShape| format!("{:?}", transactions(shape));
+        assert_eq!(debug(&shape), debug(&shape));
+        assert!(dataset
+            .iter()
+            .any(|tx| tx.tx_type() == &TransactionType::Dispute));
+        assert!(dataset
+            .iter()
+            .all(|tx| (1..=10).contains(&tx.raw_client_id())));
+    }
+}
@@ -0,0 +1,29 @@
+// Where applied transactions are kept for later dispute lookups when they shouldn't all live
+// in memory. Backends sit behind cargo features, like the client stores.
+use crate::ids::{ClientId, TxId};
+use crate::transaction::Transaction;
+use std::fmt;
+
+#[cfg(feature = "rocksdb")]
+mod rocksdb;
+
+#[cfg(feature = "rocksdb")]
+pub use self::rocksdb::RocksDbHistory;
+
+pub trait TransactionHistory: fmt::Debug + Send {
+    fn get(&self, client_id: ClientId, tx_id: TxId) -> Result<Option<Transaction>, String>;
+    fn insert(&mut self, client_id: ClientId, tx_id: TxId, tx: &Transaction) -> Result<(), String>;
+}
+
+#[cfg(feature = "rocksdb")]
+pub fn open(path: &str) -> Result<Box<dyn TransactionHistory>, String> {
+    Ok(Box::new(RocksDbHistory::open(path)?))
+}
+
+#[cfg(not(feature = "rocksdb"))]
+pub fn open(path: &str) -> Result<Box<dyn TransactionHistory>, String> {
+    Err(format!(
+        "Transaction history {} needs the rocksdb feature, rebuild with --features rocksdb",
+        path
+    ))
+}
@@ -0,0 +1,59 @@
+// Transaction history in RocksDB keyed by (client id, tx id), letting the engine serve dispute
+// lookups over billions of historical transactions with bounded memory.
+use super::TransactionHistory;
+use crate::ids::{ClientId, TxId};
+use crate::snapshot;
+use crate::transaction::Transaction;
+use rocksdb::DB;
+use std::fmt;
+
+pub struct RocksDbHistory {
+    path: String,
+    db: DB,
+}
+
+impl RocksDbHistory {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let db = DB::open_default(path).map_err(rocksdb_error)?;
+
+        Ok(RocksDbHistory {
+            path: path.to_string(),
+            db,
+        })
+    }
+}
+
+impl fmt::Debug for RocksDbHistory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RocksDbHistory")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl TransactionHistory for RocksDbHistory {
+    fn get(&self, client_id: ClientId, tx_id: TxId) -> Result<Option<Transaction>, String> {
+        match self.db.get(key(client_id, tx_id)).map_err(rocksdb_error)? {
+            Some(bytes) => snapshot::decode(&bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn insert(&mut self, client_id: ClientId, tx_id: TxId, tx: &Transaction) -> Result<(), String> {
+        self.db
+            .put(key(client_id, tx_id), snapshot::encode(tx)?)
+            .map_err(rocksdb_error)
+    }
+}
+
+// Big endian so a client's transactions sit next to each other in tx id order
+fn key(client_id: ClientId, tx_id: TxId) -> [u8; 6] {
+    let mut key = [0u8; 6];
+    key[..2].copy_from_slice(&client_id.value().to_be_bytes());
+    key[2..].copy_from_slice(&tx_id.value().to_be_bytes());
+    key
+}
+
+fn rocksdb_error(error: rocksdb::Error) -> String {
+    format!("RocksDB history error: {}", error)
+}
@@ -0,0 +1,171 @@
+// Standalone HTML report of a run for people who won't read csv: a summary, the accounts in a
+// table that sorts by any column when its header is clicked, and every rejected transaction.
+// Styles and script are inlined so the file can be opened or mailed on its own.
+use crate::client::{Clients, NumberFormat};
+use crate::engine::Rejection;
+use crate::ids::ClientId;
+use crate::summary::Summary;
+use std::io;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:right}\
+th{background:#eee;cursor:pointer}\
+td.text{text-align:left}";
+
+// Sorts the table body by the clicked column's data-sort values, numerically when they all
+// are numbers, toggling the direction on every click
+const SCRIPT: &str = "document.querySelectorAll('table.sortable th').forEach((th, column) => {\
+th.addEventListener('click', () => {\
+const body = th.closest('table').tBodies[0];\
+const rows = Array.from(body.rows);\
+const key = row => row.cells[column].dataset.sort;\
+const numeric = rows.every(row => key(row) !== '' && !isNaN(key(row)));\
+const direction = th.dataset.direction === 'asc' ? -1 : 1;\
+th.dataset.direction = direction === 1 ? 'asc' : 'desc';\
+rows.sort((a, b) => direction * (numeric ? key(a) - key(b) : key(a).localeCompare(key(b))));\
+rows.forEach(row => body.appendChild(row));\
+});\
+});";
+
+pub fn write_report<W: io::Write>(
+    clients: &Clients,
+    rejections: &[Rejection],
+    processed: u64,
+    format: NumberFormat,
+    mut writer: W,
+) -> Result<(), String> {
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+
+    let summary = Summary::new(clients, processed, rejections.len())?;
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Accounts</title>\n");
+    html.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", STYLE));
+
+    html.push_str("<h1>Summary</h1>\n<table>\n");
+    for (label, value) in summary.rows()? {
+        html.push_str(&format!(
+            "<tr><th>{}</th><td>{}</td></tr>\n",
+            label,
+            escape(&value)
+        ));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h1>Accounts</h1>\n<table class=\"sortable\">\n<thead><tr>");
+    for header in [
+        "client",
+        "available",
+        "held",
+        "total",
+        "locked",
+        "frozen",
+        "credit_used",
+    ] {
+        html.push_str(&format!("<th>{}</th>", header));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for client_id in client_ids {
+        let client = &clients[client_id];
+        let funds = client.funds();
+        // Sorting goes by the unformatted values, separators would break it
+        let sort_keys = [
+            client_id.to_string(),
+            funds.available().to_string(),
+            funds.held().to_string(),
+            funds.available().saturating_add(funds.held()).to_string(),
+            client.is_locked().to_string(),
+            client.is_frozen().to_string(),
+            funds.credit_used(client.credit_limit()).to_string(),
+        ];
+        html.push_str("<tr>");
+        for (value, key) in client
+            .get_record_with_format(*client_id, format)
+            .iter()
+            .zip(sort_keys)
+        {
+            html.push_str(&format!(
+                "<td data-sort=\"{}\">{}</td>",
+                escape(&key),
+                escape(value)
+            ));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    html.push_str("<h1>Rejected transactions</h1>\n");
+    if rejections.is_empty() {
+        html.push_str("<p>None</p>\n");
+    } else {
+        html.push_str("<table class=\"sortable\">\n<thead><tr>");
+        html.push_str("<th>tx</th><th>client</th><th>type</th><th>error</th>");
+        html.push_str("</tr></thead>\n<tbody>\n");
+        for rejection in rejections {
+            let tx = &rejection.tx;
+            html.push_str(&format!(
+                "<tr><td data-sort=\"{0}\">{0}</td><td data-sort=\"{1}\">{1}</td>\
+                 <td class=\"text\" data-sort=\"{2}\">{2}</td>\
+                 <td class=\"text\" data-sort=\"{3}\">{3}</td></tr>\n",
+                tx.raw_tx_id(),
+                tx.raw_client_id(),
+                tx.tx_type(),
+                escape(rejection.error.message())
+            ));
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+
+    html.push_str(&format!("<script>{}</script>\n</body>\n</html>\n", SCRIPT));
+
+    writer
+        .write_all(html.as_bytes())
+        .map_err(|error| format!("Error writing to std out: {}", error))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{EngineConfig, TransactionEngine};
+    use crate::summary::process;
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[test]
+    fn reports_accounts_and_rejections() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(9.0)),
+            Transaction::raw(TransactionType::Deposit, 3, 2, Some(1.5)),
+        ];
+        let engine = TransactionEngine::new(EngineConfig::default());
+        let (clients, rejections) = process(engine, transactions);
+        assert_eq!(rejections.len(), 1);
+        assert_eq!(rejections[0].tx.raw_tx_id(), 2);
+
+        let mut output = Vec::new();
+        write_report(
+            &clients,
+            &rejections,
+            3,
+            NumberFormat::default(),
+            &mut output,
+        )
+        .unwrap();
+        let html = String::from_utf8(output).unwrap();
+
+        assert!(html.contains("<tr><th>Transactions rejected</th><td>1</td></tr>"));
+        assert!(html.contains("<tr><th>Total</th><td>6.5000</td></tr>"));
+        assert!(html.contains("<td data-sort=\"2\">2</td><td data-sort=\"1.5000\">1.5000</td>"));
+        assert!(html.contains("<tr><td data-sort=\"2\">2</td><td data-sort=\"1\">1</td>"));
+    }
+}
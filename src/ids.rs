@@ -0,0 +1,110 @@
+// Client and transaction ids as their own types, so a library caller can't pass a tx id where
+// a client id belongs. Both (de)serialize as the bare number and convert to and from it, so the
+// underlying integers can be widened later without changing the signatures using them.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct ClientId(u16);
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct TxId(u32);
+
+impl ClientId {
+    pub const fn new(id: u16) -> Self {
+        ClientId(id)
+    }
+
+    pub const fn value(self) -> u16 {
+        self.0
+    }
+}
+
+impl TxId {
+    pub const fn new(id: u32) -> Self {
+        TxId(id)
+    }
+
+    pub const fn value(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for TxId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for ClientId {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        text.trim()
+            .parse()
+            .map(ClientId)
+            .map_err(|error| format!("Invalid client id {:?}: {}", text, error))
+    }
+}
+
+impl FromStr for TxId {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        text.trim()
+            .parse()
+            .map(TxId)
+            .map_err(|error| format!("Invalid tx id {:?}: {}", text, error))
+    }
+}
+
+impl From<u16> for ClientId {
+    fn from(id: u16) -> Self {
+        ClientId(id)
+    }
+}
+
+impl From<ClientId> for u16 {
+    fn from(id: ClientId) -> Self {
+        id.0
+    }
+}
+
+impl From<u32> for TxId {
+    fn from(id: u32) -> Self {
+        TxId(id)
+    }
+}
+
+impl From<TxId> for u32 {
+    fn from(id: TxId) -> Self {
+        id.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_serializes_as_numbers() {
+        assert_eq!("42".parse::<ClientId>(), Ok(ClientId(42)));
+        assert!("70000".parse::<ClientId>().is_err());
+        assert_eq!(" 7 ".parse::<TxId>(), Ok(TxId(7)));
+        assert_eq!(serde_json::to_string(&TxId(7)).unwrap(), "7");
+        assert_eq!(TxId(7).to_string(), "7");
+    }
+}
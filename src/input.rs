@@ -0,0 +1,270 @@
+// Reads transactions from the input csv. Feeds are not always clean, so lines that aren't
+// transactions can be skipped and amounts can be normalized before they're parsed.
+use crate::amount::{Amount, AmountParser};
+use crate::codes::{CodedError, RejectionCode};
+use crate::encoding::InputReader;
+use crate::source::InputSource;
+use crate::transaction::{Transaction, TransactionType};
+use crate::{csv_reader, csv_reader_from, interrupt};
+use csv::StringRecord;
+use std::io;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOptions {
+    // Normalizes amounts before they're parsed, e.g. "$1,234.50"
+    pub amounts: Option<AmountParser>,
+    // Skips lines starting with '#'
+    pub skip_comments: bool,
+    // Skips lines without any value, e.g. "   " or ",,,". Empty lines are always skipped
+    pub skip_blank_lines: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SkippedLines {
+    pub comments: u64,
+    pub blank: u64,
+}
+
+// Transactions read from a csv file, std in or any other reader
+pub struct CsvSource<R> {
+    records: csv::StringRecordsIntoIter<R>,
+    headers: StringRecord,
+    type_column: Option<usize>,
+    amount_column: Option<usize>,
+    options: ReadOptions,
+    skipped: SkippedLines,
+}
+
+impl CsvSource<InputReader> {
+    pub fn open(path: &str, options: ReadOptions) -> Result<Self, CodedError> {
+        CsvSource::new(csv_reader(path)?, options)
+    }
+}
+
+impl CsvSource<io::Stdin> {
+    pub fn stdin(options: ReadOptions) -> Result<Self, CodedError> {
+        CsvSource::from_reader(io::stdin(), options)
+    }
+}
+
+impl<R: io::Read> CsvSource<R> {
+    pub fn from_reader(reader: R, options: ReadOptions) -> Result<Self, CodedError> {
+        CsvSource::new(csv_reader_from(reader), options)
+    }
+
+    fn new(mut rdr: csv::Reader<R>, options: ReadOptions) -> Result<Self, CodedError> {
+        let headers = rdr
+            .headers()
+            .map_err(|error| {
+                CodedError::new(
+                    RejectionCode::MissingHeaders,
+                    format!("Error reading csv headers: {}", error),
+                )
+            })?
+            .clone();
+        let type_column = headers.iter().position(|header| header == "type");
+        let amount_column = headers.iter().position(|header| header == "amount");
+
+        Ok(CsvSource {
+            records: rdr.into_records(),
+            headers,
+            type_column,
+            amount_column,
+            options,
+            skipped: SkippedLines::default(),
+        })
+    }
+
+    // Lines skipped so far
+    pub fn skipped(&self) -> &SkippedLines {
+        &self.skipped
+    }
+
+    // The type and amount are checked on their own first so they are reported with their code,
+    // anything else wrong with the row is a malformed row
+    fn parse(&self, mut record: StringRecord) -> Result<Transaction, CodedError> {
+        if let (Some(parser), Some(column)) = (self.options.amounts, self.amount_column) {
+            record = normalize_amount(record, column, parser)?;
+        }
+        let line = record.position().map_or(0, csv::Position::line);
+        if let Some(name) = self.type_column.and_then(|column| record.get(column)) {
+            TransactionType::from_str(name).map_err(|error| {
+                CodedError::new(
+                    RejectionCode::UnknownTransactionType,
+                    format!("Error parsing csv line {}: {}", line, error),
+                )
+            })?;
+        }
+        if let Some(amount) = self.amount_column.and_then(|column| record.get(column)) {
+            if !amount.is_empty() {
+                Amount::from_str(amount).map_err(|error| {
+                    CodedError::new(
+                        RejectionCode::InvalidAmount,
+                        format!("Error parsing csv line {}: {}", line, error),
+                    )
+                })?;
+            }
+        }
+
+        record.deserialize(Some(&self.headers)).map_err(|error| {
+            CodedError::new(
+                RejectionCode::MalformedRow,
+                format!("Error parsing csv line: {}", error),
+            )
+        })
+    }
+
+    // Same as `next_transaction`, keeping the code of a row that couldn't be read
+    pub fn next_row(&mut self) -> Option<Result<Transaction, CodedError>> {
+        loop {
+            // Stop reading on Ctrl-C, whatever was read so far still gets processed
+            if interrupt::requested() {
+                return None;
+            }
+
+            let record = match self.records.next()? {
+                Ok(record) => record,
+                Err(error) => {
+                    return Some(Err(CodedError::new(
+                        RejectionCode::MalformedRow,
+                        format!("Error parsing csv line: {}", error),
+                    )))
+                }
+            };
+            if self.options.skip_comments
+                && record.get(0).is_some_and(|field| field.starts_with('#'))
+            {
+                self.skipped.comments += 1;
+                continue;
+            }
+            if self.options.skip_blank_lines && record.iter().all(str::is_empty) {
+                self.skipped.blank += 1;
+                continue;
+            }
+
+            return Some(self.parse(record));
+        }
+    }
+}
+
+impl<R: io::Read> InputSource for CsvSource<R> {
+    fn next_transaction(&mut self) -> Option<Result<Transaction, String>> {
+        self.next_row().map(|row| row.map_err(String::from))
+    }
+}
+
+// An input of "-" is read from std in
+pub(crate) fn read_transactions(
+    file: &str,
+    options: ReadOptions,
+) -> Result<(Vec<Transaction>, SkippedLines), CodedError> {
+    match file {
+        "-" => read_all(CsvSource::stdin(options)?),
+        _ => read_all(CsvSource::open(file, options)?),
+    }
+}
+
+fn read_all<R: io::Read>(
+    mut source: CsvSource<R>,
+) -> Result<(Vec<Transaction>, SkippedLines), CodedError> {
+    let mut transactions = Vec::new();
+    while let Some(tx) = source.next_row() {
+        transactions.push(tx?);
+    }
+
+    Ok((transactions, source.skipped))
+}
+
+fn normalize_amount(
+    record: StringRecord,
+    column: usize,
+    parser: AmountParser,
+) -> Result<StringRecord, CodedError> {
+    let amount = match record.get(column) {
+        Some(amount) if !amount.is_empty() => parser.normalize(amount),
+        _ => return Ok(record),
+    };
+    let line = record.position().map_or(0, csv::Position::line);
+    let amount = amount.map_err(|error| {
+        CodedError::new(
+            RejectionCode::InvalidAmount,
+            format!("Error parsing csv line {}: {}", line, error),
+        )
+    })?;
+
+    let mut normalized: StringRecord = record
+        .iter()
+        .enumerate()
+        .map(|(index, field)| if index == column { &amount } else { field })
+        .collect();
+    normalized.set_position(record.position().cloned());
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::ClientId;
+    use crate::transaction::TransactionType;
+    use crate::{process_source, MemorySource};
+    use std::io::Write;
+
+    #[test]
+    fn skips_and_counts_comments_and_blank_lines() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "type,client,tx,amount\n# exported 2024-01-01\ndeposit,1,1,1.0\n\n ,,,\n# end\n"
+        )
+        .unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let options = ReadOptions {
+            skip_comments: true,
+            skip_blank_lines: true,
+            ..Default::default()
+        };
+        let (transactions, skipped) = read_transactions(path, options).unwrap();
+
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            skipped,
+            SkippedLines {
+                comments: 2,
+                blank: 1
+            }
+        );
+        assert!(read_transactions(path, ReadOptions::default()).is_err());
+    }
+
+    #[test]
+    fn reads_from_any_source() {
+        let csv = "type,client,tx,amount\ndeposit,1,1,2.0\nwithdrawal,1,2,0.5\n";
+        let source = CsvSource::from_reader(csv.as_bytes(), ReadOptions::default()).unwrap();
+        let clients = process_source(source, Default::default()).unwrap();
+        assert_eq!(
+            clients[&ClientId::new(1)].funds().available().to_string(),
+            "1.5000"
+        );
+
+        let source = MemorySource::from(vec![Transaction::raw(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(2.0),
+        )]);
+        let clients = process_source(source, Default::default()).unwrap();
+        assert_eq!(
+            clients[&ClientId::new(1)].funds().available().to_string(),
+            "2.0000"
+        );
+
+        let invalid = CsvSource::from_reader(
+            "type,client,tx,amount\nteleport,1,1,2.0\n".as_bytes(),
+            ReadOptions::default(),
+        )
+        .unwrap();
+        assert!(process_source(invalid, Default::default()).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+// Human readable view of saved engine state, e.g. to look at an account during an incident
+// without reprocessing any input.
+use crate::client::{Client, Clients};
+use crate::engine::TransactionEngine;
+use crate::ids::ClientId;
+use crate::transaction::Transaction;
+use std::io;
+
+// Writes every client, or only `client`, in client id order
+pub fn write_state<W: io::Write>(
+    clients: &Clients,
+    client: Option<ClientId>,
+    mut writer: W,
+) -> Result<(), String> {
+    let mut client_ids: Vec<ClientId> = match client {
+        Some(client_id) if !clients.contains_key(&client_id) => {
+            return Err(format!("Client {} is not in the snapshot", client_id))
+        }
+        Some(client_id) => vec![client_id],
+        None => clients.keys().copied().collect(),
+    };
+    client_ids.sort();
+
+    let write_error = |error: io::Error| format!("Error writing to std out: {}", error);
+    for client_id in client_ids {
+        write_client(&mut writer, client_id, &clients[&client_id]).map_err(write_error)?;
+    }
+
+    writer.flush().map_err(write_error)
+}
+
+// Applies `transactions` up to and including the first row with `tx_id`, the deposit or
+// withdrawal itself rather than a dispute of it, so the state at that point can be looked at
+pub fn stop_at(
+    mut engine: TransactionEngine,
+    transactions: Vec<Transaction>,
+    tx_id: u32,
+) -> Result<(TransactionEngine, usize), String> {
+    let row = transactions
+        .iter()
+        .position(|tx| tx.raw_tx_id() == tx_id)
+        .ok_or(format!("Tx {} is not in the input", tx_id))?;
+    for tx in transactions.into_iter().take(row + 1) {
+        engine.push(tx);
+    }
+
+    Ok((engine, row + 1))
+}
+
+// Writes every client followed by what the engine hasn't applied yet
+pub fn write_engine<W: io::Write>(engine: &TransactionEngine, mut writer: W) -> Result<(), String> {
+    write_state(engine.clients(), None, &mut writer)?;
+
+    let write_error = |error: io::Error| format!("Error writing to std out: {}", error);
+    writeln!(writer, "held back       {}", engine.held_back()).map_err(write_error)?;
+    writeln!(writer, "quarantined     {}", engine.quarantined().len()).map_err(write_error)?;
+    writer.flush().map_err(write_error)
+}
+
+fn write_client<W: io::Write>(
+    writer: &mut W,
+    client_id: ClientId,
+    client: &Client,
+) -> io::Result<()> {
+    let funds = client.funds();
+    let mut disputes: Vec<&u32> = client.disputed_transactions().iter().collect();
+    disputes.sort();
+    let disputes = match disputes.is_empty() {
+        true => "none".to_string(),
+        false => disputes
+            .iter()
+            .map(|tx| tx.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+
+    writeln!(writer, "client {}", client_id)?;
+    writeln!(writer, "  available     {}", funds.available())?;
+    writeln!(writer, "  held          {}", funds.held())?;
+    writeln!(
+        writer,
+        "  total         {}",
+        funds.available().saturating_add(funds.held())
+    )?;
+    writeln!(writer, "  locked        {}", client.is_locked())?;
+    writeln!(writer, "  frozen        {}", client.is_frozen())?;
+    writeln!(writer, "  open disputes {}", disputes)?;
+    writeln!(writer, "  stored txs    {}", client.transactions().len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::{ClientId, TxId};
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[test]
+    fn prints_one_client() {
+        let mut client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 42, Some(2.0)),
+        );
+        client
+            .handle_transaction(Transaction::raw(TransactionType::Dispute, 1, 42, None))
+            .unwrap();
+        let clients = Clients::from_iter([(ClientId::new(42), client)]);
+
+        let mut output = Vec::new();
+        write_state(&clients, Some(ClientId::new(42)), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client 42\n  \
+             available     0.0000\n  \
+             held          2.0000\n  \
+             total         2.0000\n  \
+             locked        false\n  \
+             frozen        false\n  \
+             open disputes 1\n  \
+             stored txs    1\n"
+        );
+        assert_eq!(
+            write_state(&clients, Some(ClientId::new(7)), Vec::new()),
+            Err("Client 7 is not in the snapshot".to_string())
+        );
+    }
+
+    #[test]
+    fn stops_at_a_tx() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Dispute, 2, 1, None),
+            Transaction::raw(TransactionType::Deposit, 3, 1, Some(7.0)),
+        ];
+        let engine = TransactionEngine::new(Default::default());
+        let (engine, applied) = stop_at(engine, transactions.clone(), 2).unwrap();
+        assert_eq!(applied, 2);
+
+        let mut output = Vec::new();
+        write_engine(&engine, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client 1\n  \
+             available     4.0000\n  \
+             held          0.0000\n  \
+             total         4.0000\n  \
+             locked        false\n  \
+             frozen        false\n  \
+             open disputes none\n  \
+             stored txs    2\n\
+             held back       0\n\
+             quarantined     0\n"
+        );
+        assert_eq!(
+            stop_at(TransactionEngine::new(Default::default()), transactions, 9).err(),
+            Some("Tx 9 is not in the input".to_string())
+        );
+    }
+}
@@ -0,0 +1,21 @@
+// Ctrl-C handling. The first SIGINT only raises a flag: ingestion stops at the next row and
+// the accounts computed so far are still written. A second SIGINT exits straight away.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// Only the command line tool takes over the process's signal handling
+#[cfg(feature = "cli")]
+pub fn install() -> Result<(), String> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        eprintln!("Interrupted, stopping after the current transaction (Ctrl-C again to abort)");
+    })
+    .map_err(|error| format!("Error installing Ctrl-C handler: {}", error))
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
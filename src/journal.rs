@@ -0,0 +1,263 @@
+// Plain-text accounting exports of every change to the funds. Each client has an Available and
+// a Held account, what enters or leaves them is balanced against an equity account named after
+// the operation, so every entry is a balanced double-entry transaction.
+use crate::audit::AuditEntry;
+use crate::client::Clients;
+use crate::ids::ClientId;
+use crate::money::Money;
+use crate::transaction::TransactionType;
+use chrono::{Days, NaiveDate};
+use std::collections::BTreeSet;
+use std::io;
+
+struct JournalEntry<'a> {
+    client_id: ClientId,
+    date: NaiveDate,
+    entry: &'a AuditEntry,
+}
+
+impl JournalEntry<'_> {
+    // (account, amount) pairs that sum up to zero, given how client accounts are named
+    fn postings(&self, client_account: impl Fn(ClientId, &str) -> String) -> Vec<(String, Money)> {
+        let entry = self.entry;
+        let counter = Money::ZERO
+            .checked_sub(entry.available_change)
+            .and_then(|counter| counter.checked_sub(entry.held_change))
+            .unwrap_or_default();
+
+        [
+            (
+                client_account(self.client_id, "Available"),
+                entry.available_change,
+            ),
+            (client_account(self.client_id, "Held"), entry.held_change),
+            (counter_account(&entry.operation).to_string(), counter),
+        ]
+        .into_iter()
+        .filter(|(_, amount)| *amount != Money::ZERO)
+        .collect()
+    }
+
+    fn payee(&self) -> String {
+        format!("{} tx {}", self.entry.operation, self.entry.tx_id)
+    }
+}
+
+// Ordered by client, then by the order the changes were applied in. Changes without a
+// timestamp are dated `default_date`.
+fn journal_entries(
+    clients: &Clients,
+    default_date: Option<NaiveDate>,
+) -> Result<Vec<JournalEntry<'_>>, String> {
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+
+    let mut entries = Vec::new();
+    for client_id in client_ids {
+        for entry in clients[client_id].audit_trail() {
+            let date = match (entry.timestamp, default_date) {
+                (Some(timestamp), _) => timestamp.date_naive(),
+                (None, Some(date)) => date,
+                (None, None) => {
+                    return Err(format!(
+                        "Tx {} has no timestamp, pass --date to date such entries",
+                        entry.tx_id
+                    ))
+                }
+            };
+
+            entries.push(JournalEntry {
+                client_id: *client_id,
+                date,
+                entry,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+fn counter_account(operation: &TransactionType) -> &'static str {
+    match operation {
+        TransactionType::Deposit => "Equity:Deposits",
+        TransactionType::Withdrawal => "Equity:Withdrawals",
+        TransactionType::Chargeback => "Equity:Chargebacks",
+        TransactionType::Refund => "Equity:Refunds",
+        TransactionType::Settlement => "Equity:Settlements",
+        TransactionType::Interest => "Equity:Interest",
+        TransactionType::Dispute | TransactionType::Resolve => "Equity:Adjustments",
+    }
+}
+
+fn write_error(error: io::Error) -> String {
+    format!("Error writing journal: {}", error)
+}
+
+// ledger / hledger journal
+pub fn write_ledger<W: io::Write>(
+    clients: &Clients,
+    default_date: Option<NaiveDate>,
+    mut writer: W,
+) -> Result<(), String> {
+    for journal_entry in journal_entries(clients, default_date)? {
+        writeln!(
+            writer,
+            "{} {}",
+            journal_entry.date.format("%Y-%m-%d"),
+            journal_entry.payee()
+        )
+        .map_err(write_error)?;
+        if let Some(memo) = &journal_entry.entry.memo {
+            writeln!(writer, "    ; {}", memo).map_err(write_error)?;
+        }
+
+        let postings = journal_entry
+            .postings(|client_id, balance| format!("Assets:Clients:{}:{}", client_id, balance));
+        for (account, amount) in postings {
+            writeln!(writer, "    {:<40}  {}", account, amount).map_err(write_error)?;
+        }
+        writeln!(writer).map_err(write_error)?;
+    }
+
+    writer.flush().map_err(write_error)
+}
+
+// Beancount file: open directives for every account, the transactions, and balance assertions
+// of every client account the day after the last entry. Beancount account components can't be
+// bare numbers, so clients are named `C<id>`.
+pub fn write_beancount<W: io::Write>(
+    clients: &Clients,
+    default_date: Option<NaiveDate>,
+    currency: &str,
+    mut writer: W,
+) -> Result<(), String> {
+    let client_account =
+        |client_id: ClientId, balance: &str| format!("Assets:Clients:C{}:{}", client_id, balance);
+    let entries = journal_entries(clients, default_date)?;
+    let Some(first) = entries.iter().map(|entry| entry.date).min() else {
+        return Ok(());
+    };
+    let last = entries
+        .iter()
+        .map(|entry| entry.date)
+        .max()
+        .unwrap_or(first);
+
+    let mut accounts = BTreeSet::new();
+    for entry in &entries {
+        for (account, _) in entry.postings(client_account) {
+            accounts.insert(account);
+        }
+    }
+    for account in &accounts {
+        writeln!(writer, "{} open {}", first.format("%Y-%m-%d"), account).map_err(write_error)?;
+    }
+    writeln!(writer).map_err(write_error)?;
+
+    for entry in &entries {
+        writeln!(
+            writer,
+            "{} * \"{}\"",
+            entry.date.format("%Y-%m-%d"),
+            entry.payee()
+        )
+        .map_err(write_error)?;
+        if let Some(memo) = &entry.entry.memo {
+            writeln!(writer, "  memo: \"{}\"", memo.replace('"', "'")).map_err(write_error)?;
+        }
+        for (account, amount) in entry.postings(client_account) {
+            writeln!(writer, "  {:<40}  {} {}", account, amount, currency).map_err(write_error)?;
+        }
+        writeln!(writer).map_err(write_error)?;
+    }
+
+    // Balance assertions hold at the start of their date
+    let assert_date = last.checked_add_days(Days::new(1)).unwrap_or(last);
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+    for client_id in client_ids {
+        let funds = clients[client_id].funds();
+        for (balance, amount) in [("Available", funds.available()), ("Held", funds.held())] {
+            let account = client_account(*client_id, balance);
+            if accounts.contains(&account) {
+                writeln!(
+                    writer,
+                    "{} balance {}  {} {}",
+                    assert_date.format("%Y-%m-%d"),
+                    account,
+                    amount,
+                    currency
+                )
+                .map_err(write_error)?;
+            }
+        }
+    }
+
+    writer.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::transaction::Transaction;
+
+    fn clients() -> Clients {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0))
+                .with_timestamp("2024-01-05T10:00:00Z".parse().unwrap()),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None).with_memo("stolen card"),
+        ];
+        crate::process_transactions(transactions, EngineConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn writes_balanced_ledger_entries() {
+        let mut output = Vec::new();
+        write_ledger(&clients(), NaiveDate::from_ymd_opt(2024, 1, 6), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2024-01-05 deposit tx 1\n    \
+             Assets:Clients:1:Available                5.0000\n    \
+             Equity:Deposits                           -5.0000\n\n\
+             2024-01-06 dispute tx 1\n    \
+             ; stolen card\n    \
+             Assets:Clients:1:Available                -5.0000\n    \
+             Assets:Clients:1:Held                     5.0000\n\n"
+        );
+    }
+
+    #[test]
+    fn writes_beancount_with_opens_and_balance_assertions() {
+        let mut output = Vec::new();
+        write_beancount(
+            &clients(),
+            NaiveDate::from_ymd_opt(2024, 1, 6),
+            "USD",
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2024-01-05 open Assets:Clients:C1:Available\n\
+             2024-01-05 open Assets:Clients:C1:Held\n\
+             2024-01-05 open Equity:Deposits\n\n\
+             2024-01-05 * \"deposit tx 1\"\n  \
+             Assets:Clients:C1:Available               5.0000 USD\n  \
+             Equity:Deposits                           -5.0000 USD\n\n\
+             2024-01-06 * \"dispute tx 1\"\n  \
+             memo: \"stolen card\"\n  \
+             Assets:Clients:C1:Available               -5.0000 USD\n  \
+             Assets:Clients:C1:Held                    5.0000 USD\n\n\
+             2024-01-07 balance Assets:Clients:C1:Available  0.0000 USD\n\
+             2024-01-07 balance Assets:Clients:C1:Held  5.0000 USD\n"
+        );
+    }
+
+    #[test]
+    fn fails_without_dates() {
+        assert!(write_ledger(&clients(), None, io::sink()).is_err());
+    }
+}
@@ -1,80 +1,377 @@
-use std::collections::HashMap;
-use std::env;
+#[cfg(feature = "csv")]
 use std::io;
 
+mod actor;
+mod amount;
+#[cfg(feature = "csv")]
+pub mod anonymize;
+pub mod audit;
+#[cfg(feature = "csv")]
+pub mod balance_check;
+mod builder;
+#[cfg(feature = "csv")]
+pub mod checkpoint;
+#[cfg(feature = "cli")]
+pub mod cli;
 mod client;
+pub mod codes;
+#[cfg(feature = "csv")]
+pub mod commits;
+pub mod compression;
+mod concurrent;
+pub mod conservation;
+#[cfg(feature = "csv")]
+pub mod convert;
+#[cfg(feature = "cli")]
+pub mod dashboard;
+#[cfg(feature = "cli")]
+pub mod debugger;
+#[cfg(feature = "csv")]
+pub mod encoding;
+pub mod encryption;
+mod engine;
+pub mod environment;
+#[cfg(feature = "csv")]
+pub mod eod;
+#[cfg(feature = "csv")]
+pub mod follow;
+pub mod fuzz;
+pub mod generate;
+pub mod history;
+pub mod html;
+mod ids;
+#[cfg(feature = "csv")]
+mod input;
+pub mod inspect;
+pub mod interrupt;
+pub mod journal;
+mod links;
+pub mod load;
+pub mod markdown;
+#[cfg(feature = "csv")]
+pub mod metadata;
+mod money;
+pub mod notify;
+#[cfg(feature = "csv")]
+pub mod output;
+#[cfg(feature = "csv")]
+pub mod presort;
+#[cfg(feature = "cli")]
+pub mod profile;
+#[cfg(feature = "csv")]
+pub mod pseudonym;
+#[cfg(feature = "cli")]
+pub mod publish;
+#[cfg(feature = "csv")]
+pub mod rejects;
+mod reorder;
+#[cfg(feature = "cli")]
+pub mod repl;
+#[cfg(feature = "csv")]
+pub mod report;
+#[cfg(feature = "csv")]
+pub mod sample;
+mod schedule;
+pub mod search;
+#[cfg(feature = "csv")]
+pub mod shard;
+#[cfg(feature = "csv")]
+pub mod signature;
+pub mod snapshot;
+mod sorted;
+pub mod source;
+pub mod store;
+#[cfg(feature = "cli")]
+pub mod subscribe;
+pub mod summary;
+#[cfg(feature = "cli")]
+pub mod table;
+#[cfg(feature = "csv")]
+pub mod tenant;
+#[cfg(feature = "csv")]
+pub mod testing;
+#[cfg(feature = "csv")]
+pub mod timeseries;
 mod transaction;
+pub mod wal;
 
-use crate::client::{Client, Clients};
-use crate::transaction::Transaction;
-
-pub fn process_transactions(transactions: Vec<Transaction>) -> Result<Clients, String> {
-    let mut clients: Clients = HashMap::new();
-
-    for tx in transactions.into_iter() {
-        clients
-            .entry(tx.client_id())
-            .and_modify(|client| {
-                if let Err(error) = client.handle_transaction(tx.clone()) {
-                    eprintln!("error handling tx: {}", error)
-                }
-            })
-            .or_insert(Client::new(tx.tx_id(), tx));
+pub use crate::actor::ActorEngine;
+pub use crate::amount::{Amount, AmountParser};
+pub use crate::builder::EngineBuilder;
+pub use crate::client::{
+    Activity, Client, Clients, IdHasher, Lifecycle, NumberFormat, Precision, TransactionStats,
+};
+pub use crate::codes::{CodedError, ErrorFormat, RejectionCode};
+pub use crate::concurrent::ConcurrentEngine;
+#[cfg(feature = "csv")]
+use crate::encoding::InputReader;
+pub use crate::engine::{
+    AccountSnapshot, Changes, CrossClientPolicy, DisputeOverdraftPolicy, EngineConfig,
+    OrderingPolicy, Rejection, ResolvePolicy, RetentionPolicy, TierLimits, TransactionEngine,
+    UnknownClientPolicy,
+};
+pub use crate::ids::{ClientId, TxId};
+#[cfg(feature = "csv")]
+pub use crate::input::{CsvSource, ReadOptions, SkippedLines};
+pub use crate::money::Money;
+#[cfg(feature = "csv")]
+pub use crate::output::{CsvSink, JsonSink, OutputSink};
+pub use crate::source::{InputSource, MemorySource};
+pub use crate::transaction::{Transaction, TransactionType};
+
+pub fn process_transactions(
+    transactions: impl IntoIterator<Item = Transaction>,
+    config: EngineConfig,
+) -> Result<Clients, String> {
+    resume_transactions(Clients::default(), transactions, config, 1)
+}
+
+// Same as `process_transactions` for transactions from any source, stopping at its first error
+pub fn process_source<S: InputSource>(
+    mut source: S,
+    config: EngineConfig,
+) -> Result<Clients, String> {
+    let mut engine = TransactionEngine::new(config);
+    while let Some(tx) = source.next_transaction() {
+        engine.push(tx?);
     }
 
-    Ok(clients)
+    Ok(engine.finish())
 }
 
-pub fn write_client_funds(clients: Clients) -> Result<(), String> {
-    let mut wtr = csv::Writer::from_writer(io::stdout());
-
-    let headers = ["client", "available", "held", "total", "locked"];
-    wtr.write_record(&headers)
-        .map_err(|e| return Err::<(), String>(format!("Error writing to std out: {}", e)))
-        .unwrap();
-
-    for (client_id, client) in clients {
-        let record = client.get_record(client_id);
-        wtr.write_record(&record)
-            .map_err(|e| return Err::<(), String>(format!("Error writing to std out: {}", e)))
-            .unwrap();
+// Same as `process_transactions` but spreads clients over `workers` threads
+pub fn process_transactions_concurrently(
+    transactions: impl IntoIterator<Item = Transaction>,
+    config: EngineConfig,
+    workers: usize,
+) -> Result<Clients, String> {
+    resume_transactions(Clients::default(), transactions, config, workers)
+}
+
+// Pushes every transaction through an engine that was already set up, e.g. with a history
+pub fn run_engine(
+    mut engine: TransactionEngine,
+    transactions: impl IntoIterator<Item = Transaction>,
+) -> Clients {
+    for tx in transactions {
+        engine.push(tx);
     }
 
-    wtr.flush()
-        .map_err(|e| return Err::<(), String>(format!("Error writing to std out: {}", e)))
-        .unwrap();
+    engine.finish()
+}
+
+// Applies the transactions on top of existing clients, e.g. ones loaded from a store
+pub fn resume_transactions(
+    clients: Clients,
+    transactions: impl IntoIterator<Item = Transaction>,
+    config: EngineConfig,
+    workers: usize,
+) -> Result<Clients, String> {
+    if workers <= 1 {
+        let engine = TransactionEngine::with_clients(config, clients);
+        return Ok(run_engine(engine, transactions));
+    }
+
+    let engine = ActorEngine::with_clients(config, workers, clients);
+
+    for tx in transactions {
+        engine.push(tx)?;
+    }
 
-    Ok(())
+    engine.finish()
 }
 
-pub fn read_transaction_file() -> Result<String, String> {
-    let args: Vec<String> = env::args().skip(1).collect();
+#[cfg(feature = "csv")]
+pub fn write_client_funds(clients: Clients) -> Result<(), String> {
+    write_client_funds_to(clients, io::stdout())
+}
+
+#[cfg(feature = "csv")]
+pub fn write_client_funds_to<W: io::Write>(clients: Clients, writer: W) -> Result<(), String> {
+    write_client_funds_with(clients, writer, NumberFormat::default())
+}
 
-    if args.len() < 1 {
-        Err(format!(
-            "Correct Usage: cargo run -- /absolute/path/to/Records.csv > accounts.csv"
-        ))
-    } else {
-        Ok(args[0].clone())
+#[cfg(feature = "csv")]
+pub fn write_client_funds_with<W: io::Write>(
+    clients: Clients,
+    writer: W,
+    format: NumberFormat,
+) -> Result<(), String> {
+    write_accounts(&clients, writer, format)
+}
+
+// Same as `write_client_funds_with` without giving up the clients, e.g. for intermediate snapshots
+#[cfg(feature = "csv")]
+pub fn write_accounts<W: io::Write>(
+    clients: &Clients,
+    writer: W,
+    format: NumberFormat,
+) -> Result<(), String> {
+    write_accounts_as(
+        clients,
+        writer,
+        format,
+        AccountColumns::default(),
+        ClientId::to_string,
+    )
+}
+
+// Optional columns after the balances in the accounts csv
+#[cfg(feature = "csv")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AccountColumns {
+    // Each client's applied deposits, withdrawals, disputes, resolves and chargebacks
+    pub stats: bool,
+    // The tx id and timestamp each client was first seen, last active and locked at
+    pub lifecycle: bool,
+}
+
+#[cfg(feature = "csv")]
+impl AccountColumns {
+    fn headers(&self) -> Vec<&'static str> {
+        let mut headers = Vec::new();
+        if self.stats {
+            headers.extend([
+                "deposits",
+                "withdrawals",
+                "disputes",
+                "resolves",
+                "chargebacks",
+            ]);
+        }
+        if self.lifecycle {
+            headers.extend([
+                "first_seen_tx",
+                "first_seen_at",
+                "last_activity_tx",
+                "last_activity_at",
+                "locked_tx",
+                "locked_at",
+            ]);
+        }
+        headers
+    }
+
+    fn record(&self, client: &Client) -> Vec<String> {
+        let mut record = Vec::new();
+        if self.stats {
+            record.extend(client.stats().record());
+        }
+        if self.lifecycle {
+            record.extend(client.lifecycle().record());
+        }
+        record
     }
 }
 
-pub fn parse_transactions(file: String) -> Result<Vec<Transaction>, String> {
-    let mut rdr = csv::ReaderBuilder::new()
-        .flexible(true)
-        .trim(csv::Trim::All)
-        .from_path(file)
-        .map_err(|error| return error.to_string())
-        .unwrap();
+// Same as `write_accounts` with each client id written as `client_id` maps it, e.g. to a
+// pseudonym, and the optional `columns` after the balances
+#[cfg(feature = "csv")]
+pub fn write_accounts_as<W: io::Write, F: Fn(&ClientId) -> String>(
+    clients: &Clients,
+    writer: W,
+    format: NumberFormat,
+    columns: AccountColumns,
+    client_id: F,
+) -> Result<(), String> {
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    // Clients with sub-accounts get a row per account and one for the client as a whole
+    if clients.values().any(|client| !client.accounts().is_empty()) {
+        return write_account_funds(clients, wtr, format, columns, client_id);
+    }
+    let write_error = |error: csv::Error| format!("Error writing to std out: {}", error);
+
+    let mut headers = vec![
+        "client",
+        "available",
+        "held",
+        "total",
+        "locked",
+        "frozen",
+        "credit_used",
+    ];
+    headers.extend(columns.headers());
+    wtr.write_record(headers).map_err(write_error)?;
+
+    for (id, client) in clients {
+        let mut record = client.get_record_with_format(*id, format);
+        record[0] = client_id(id);
+        record.extend(columns.record(client));
+        wtr.write_record(&record).map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing to std out: {}", error))
+}
 
-    let mut transactions = Vec::new();
+#[cfg(feature = "csv")]
+fn write_account_funds<W: io::Write, F: Fn(&ClientId) -> String>(
+    clients: &Clients,
+    mut wtr: csv::Writer<W>,
+    format: NumberFormat,
+    columns: AccountColumns,
+    client_id: F,
+) -> Result<(), String> {
+    let write_error = |error: csv::Error| format!("Error writing to std out: {}", error);
 
-    for result in rdr.deserialize() {
-        match result {
-            Ok(tx) => transactions.push(tx),
-            Err(error) => return Err(format!("Error parsing csv line: {}", error)),
+    let mut headers = vec![
+        "client",
+        "account",
+        "available",
+        "held",
+        "total",
+        "locked",
+        "frozen",
+        "credit_used",
+    ];
+    headers.extend(columns.headers());
+    let extra = columns.headers().len();
+    wtr.write_record(headers).map_err(write_error)?;
+
+    for (id, client) in clients {
+        let records = client.get_account_records(*id, format);
+        let rollup = records.len() - 1;
+        for (index, mut record) in records.into_iter().enumerate() {
+            record[0] = client_id(id);
+            // The extra columns are the client's, so only its rollup row has them
+            match index == rollup {
+                true => record.extend(columns.record(client)),
+                false => record.extend(vec![String::new(); extra]),
+            }
+            wtr.write_record(&record).map_err(write_error)?;
         }
     }
 
-    Ok(transactions)
+    wtr.flush()
+        .map_err(|error| format!("Error writing to std out: {}", error))
+}
+
+#[cfg(feature = "csv")]
+pub(crate) fn csv_reader(file: &str) -> Result<csv::Reader<InputReader>, String> {
+    let input = encoding::open(file).map_err(|error| format!("{}: {}", file, error))?;
+    Ok(csv_reader_from(input))
+}
+
+#[cfg(feature = "csv")]
+pub(crate) fn csv_reader_from<R: io::Read>(input: R) -> csv::Reader<R> {
+    csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(input)
+}
+
+#[cfg(feature = "csv")]
+pub fn parse_transactions(file: String) -> Result<Vec<Transaction>, CodedError> {
+    // Nothing is skipped without options
+    parse_transactions_with(file, ReadOptions::default()).map(|(transactions, _)| transactions)
+}
+
+// Reads the input as `options` say, handing back the lines skipped for the caller to report
+#[cfg(feature = "csv")]
+pub fn parse_transactions_with(
+    file: String,
+    options: ReadOptions,
+) -> Result<(Vec<Transaction>, SkippedLines), CodedError> {
+    input::read_transactions(&file, options)
 }
@@ -0,0 +1,49 @@
+// Disputes, resolves, chargebacks and refunds all reference an earlier transaction through its tx id.
+// The link graph keeps, per referenced tx, every transaction that was applied against it so the
+// whole life of a deposit or withdrawal can be followed.
+use crate::sorted;
+use crate::transaction::{Transaction, TransactionType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LinkGraph {
+    #[serde(serialize_with = "sorted::map")]
+    children: HashMap<u32, Vec<Transaction>>,
+}
+
+impl LinkGraph {
+    pub fn new() -> Self {
+        LinkGraph::default()
+    }
+
+    // Links `tx` to the transaction it references, transactions that reference nothing are ignored
+    pub fn record(&mut self, tx: Transaction) {
+        if references_parent(tx.tx_type()) {
+            self.children.entry(tx.raw_tx_id()).or_default().push(tx);
+        }
+    }
+
+    // Drops what was applied against `tx_id` once that transaction is no longer kept
+    pub(crate) fn forget(&mut self, tx_id: u32) {
+        self.children.remove(&tx_id);
+    }
+
+    // Transactions applied against `tx_id`, in the order they were applied
+    pub fn related_transactions(&self, tx_id: u32) -> &[Transaction] {
+        self.children.get(&tx_id).map_or(&[], Vec::as_slice)
+    }
+}
+
+pub(crate) fn references_parent(tx_type: &TransactionType) -> bool {
+    match tx_type {
+        TransactionType::Deposit
+        | TransactionType::Withdrawal
+        | TransactionType::Settlement
+        | TransactionType::Interest => false,
+        TransactionType::Dispute
+        | TransactionType::Resolve
+        | TransactionType::Chargeback
+        | TransactionType::Refund => true,
+    }
+}
@@ -0,0 +1,107 @@
+// How much work each client caused the engine, for capacity planning: clients that dominate
+// the processing time are the ones to give a shard of their own. Only tracked once asked for
+// with `TransactionEngine::with_load_tracking`, and only for the current run, timings of
+// another process say nothing about this one.
+use crate::ids::ClientId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ClientLoad {
+    pub applied: u64,
+    // Time spent on the client's transactions, rejected ones included
+    pub busy: Duration,
+}
+
+impl ClientLoad {
+    // Mean time per applied transaction, zero before any was applied
+    pub fn latency(&self) -> Duration {
+        match self.applied {
+            0 => Duration::ZERO,
+            applied => self.busy.div_f64(applied as f64),
+        }
+    }
+}
+
+pub type Loads = HashMap<ClientId, ClientLoad>;
+
+// Adds a transaction of `client_id` that started at `started` to `loads`, when tracked
+pub(crate) fn record(
+    loads: &mut Option<Loads>,
+    client_id: ClientId,
+    applied: bool,
+    started: Option<Instant>,
+) {
+    let (Some(loads), Some(started)) = (loads, started) else {
+        return;
+    };
+
+    let load = loads.entry(client_id).or_default();
+    load.busy += started.elapsed();
+    load.applied += u64::from(applied);
+}
+
+// The `top` clients the engine was busiest with, busiest first and ties by client id
+pub fn hottest(loads: &Loads, top: usize) -> Vec<(ClientId, ClientLoad)> {
+    let mut hottest: Vec<(ClientId, ClientLoad)> = loads
+        .iter()
+        .map(|(client_id, load)| (*client_id, *load))
+        .collect();
+    hottest.sort_by(|(a_id, a), (b_id, b)| b.busy.cmp(&a.busy).then(a_id.cmp(b_id)));
+    hottest.truncate(top);
+    hottest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::TransactionEngine;
+    use crate::ids::ClientId;
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[test]
+    fn tracks_the_busiest_clients() {
+        let mut engine = TransactionEngine::default();
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(1.0)));
+        assert!(engine.loads().is_none());
+
+        let mut engine = engine.with_load_tracking();
+        let mut batch = vec![Transaction::raw(
+            TransactionType::Withdrawal,
+            2,
+            1,
+            Some(5.0),
+        )];
+        batch.extend((3..8).map(|tx| Transaction::raw(TransactionType::Deposit, tx, 2, Some(1.0))));
+        engine.push_batch(&batch);
+        engine.push(Transaction::raw(TransactionType::Deposit, 8, 3, Some(1.0)));
+
+        let loads = engine.loads().unwrap();
+        assert_eq!(loads.len(), 3);
+        assert_eq!(loads[&ClientId::new(1)].applied, 0);
+        assert_eq!(loads[&ClientId::new(2)].applied, 5);
+        assert_eq!(loads[&ClientId::new(3)].applied, 1);
+        assert_eq!(loads[&ClientId::new(1)].latency(), Duration::ZERO);
+
+        let loads = Loads::from([
+            (ClientId::new(1), ClientLoad::default()),
+            (
+                ClientId::new(2),
+                ClientLoad {
+                    applied: 4,
+                    busy: Duration::from_micros(20),
+                },
+            ),
+            (
+                ClientId::new(3),
+                ClientLoad {
+                    applied: 1,
+                    busy: Duration::from_micros(20),
+                },
+            ),
+        ]);
+        let ids: Vec<ClientId> = hottest(&loads, 2).iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [ClientId::new(2), ClientId::new(3)]);
+        assert_eq!(loads[&ClientId::new(2)].latency(), Duration::from_micros(5));
+    }
+}
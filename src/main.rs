@@ -1,14 +1,635 @@
+use clap::Parser;
+use std::fs::{self, File};
+use std::io::{self, Stdout};
+use transactions::anonymize::{self, Anonymization};
+use transactions::cli::{Args, Cli, Command, OutputFormat, Report};
+use transactions::load::Loads;
+use transactions::notify::{LogSink, Webhook};
+use transactions::presort::{self, presort};
+use transactions::pseudonym::Pseudonyms;
+use transactions::publish::{PublishMode, PublisherSink};
+use transactions::signature::{self, SigningWriter};
+use transactions::summary::Summary;
 use transactions::{
-    parse_transactions, process_transactions, read_transaction_file, write_client_funds,
+    audit, balance_check, checkpoint, codes, commits, compression, conservation, convert,
+    dashboard, debugger, eod, follow, history, html, inspect, interrupt, journal, markdown,
+    metadata, profile, publish, rejects, repl, report, sample, shard, snapshot, store, subscribe,
+    summary, table, tenant, timeseries, wal,
+};
+use transactions::{
+    parse_transactions, parse_transactions_with, process_transactions, resume_transactions,
+    run_engine, write_accounts, write_accounts_as, AccountColumns, ClientId, Clients, CodedError,
+    CsvSource, ErrorFormat, JsonSink, NumberFormat, OutputSink, Rejection, Transaction,
+    TransactionEngine,
 };
 
-fn main() {
-    if let Err(error) = read_transaction_file()
-        .and_then(parse_transactions)
-        .and_then(process_transactions)
-        .and_then(write_client_funds)
+// Errors keep their code, e.g. a row that couldn't be parsed, for --error-format json
+fn run(args: Args) -> Result<(), CodedError> {
+    let number_format = args.number_format()?;
+    let mut output = open_output(&args)?;
+    if args.hash_client_ids && args.output_format != OutputFormat::Csv {
+        return Err("--hash-client-ids only applies to --output-format csv".into());
+    }
+    let columns = args.account_columns();
+    if columns != AccountColumns::default() && args.output_format != OutputFormat::Csv {
+        return Err(
+            "--stats-columns and --lifecycle-columns only apply to --output-format csv".into(),
+        );
+    }
+    if args.follow
+        && (args.output_format != OutputFormat::Csv
+            || args.hash_client_ids
+            || columns != AccountColumns::default())
+    {
+        return Err(
+            "--follow writes plain csv rows, it can't be combined with --output-format, --hash-client-ids, --stats-columns or --lifecycle-columns"
+                .into(),
+        );
+    }
+    let pseudonyms = args
+        .hash_client_ids
+        .then(Pseudonyms::from_env)
+        .transpose()?;
+
+    // Without a subscription or --follow clap makes sure there is an input
+    let input = args.input.first().cloned().unwrap_or_default();
+    // Kept alive until processing finishes, the sorted temp file is removed on drop
+    let sorted = match (args.presort, args.merge_by) {
+        (_, Some(key)) => Some(presort::merge(&args.input, key)?),
+        _ if args.input.len() > 1 => {
+            return Err("Several inputs need --merge-by tx or --merge-by timestamp".into())
+        }
+        (Some(key), None) => Some(presort(&input, key, args.presort_chunk_size)?),
+        (None, None) => None,
+    };
+    let input = match &sorted {
+        Some(file) => file.path().to_string_lossy().into_owned(),
+        None => input,
+    };
+
+    let mut store = args.store.as_deref().map(store::open).transpose()?;
+    let clients = match &mut store {
+        Some(store) => store.load()?,
+        None => Clients::default(),
+    };
+
+    let mut publisher = args.publish.as_deref().map(publish::open).transpose()?;
+
+    // Rejected transactions are only kept for the html and markdown summaries, which need them
+    // all in one engine
+    let summarised = matches!(
+        args.output_format,
+        OutputFormat::Html | OutputFormat::Markdown
+    );
+    if summarised
+        && (args.subscribe.is_some()
+            || args.checkpoint.is_some()
+            || args.workers > 1
+            || args.tui
+            || args.eod_snapshots.is_some())
     {
+        return Err("--output-format html and markdown can't be combined with --subscribe, --checkpoint, --workers, --tui or --eod-snapshots".into());
+    }
+    let keeps_rejections = summarised || args.rejects.is_some() || args.quarantine.is_some();
+    let mut rejections = Vec::new();
+
+    let (clients, processed) = match (&args.subscribe, &args.checkpoint) {
+        (Some(url), _) => {
+            // Changes are published as they're made instead of once the subscription ends
+            let changes = match args.publish_mode {
+                PublishMode::Changes => publisher.take(),
+                PublishMode::Snapshots => None,
+            };
+            subscribe::consume(build_engine(&args, clients)?, url, changes)?
+        }
+        (None, Some(path)) => checkpoint::process(
+            &input,
+            build_engine(&args, clients)?,
+            path,
+            args.checkpoint_every,
+        )?,
+        (None, None) if args.commits => {
+            let (clients, summary) = commits::process(
+                &input,
+                build_engine(&args, clients)?,
+                args.commit_snapshot.as_deref(),
+            )?;
+            eprintln!(
+                "{} commits, {} rows applied and {} rolled back",
+                summary.commits, summary.applied, summary.rolled_back
+            );
+            (clients, summary.applied + summary.rolled_back)
+        }
+        (None, None) if args.follow => follow::follow(
+            build_engine(&args, clients)?,
+            CsvSource::stdin(args.read_options())?,
+            &mut output,
+            number_format,
+        )?,
+        (None, None) => {
+            // Reading stops on Ctrl-C, everything read before it is still applied
+            let transactions = match &args.profile {
+                Some(name) => profile::load(name, &args.profiles_dir)?.read_transactions(&input)?,
+                None => {
+                    let (transactions, skipped) =
+                        parse_transactions_with(input, args.read_options())?;
+                    if skipped.comments > 0 || skipped.blank > 0 {
+                        codes::report_warning(
+                            args.error_format,
+                            &format!(
+                                "Skipped {} comment and {} blank lines",
+                                skipped.comments, skipped.blank
+                            ),
+                        );
+                    }
+                    transactions
+                }
+            };
+            let processed = transactions.len() as u64;
+
+            if args.tenant.is_some() || transactions.iter().any(|tx| tx.tenant().is_some()) {
+                return Ok(run_tenants(&args, transactions, processed, output)?);
+            }
+            if let Some(tx_id) = args.stop_at_tx {
+                let engine = build_engine(&args, clients)?;
+                let (engine, applied) = inspect::stop_at(engine, transactions, tx_id)?;
+                eprintln!(
+                    "Stopped at tx {} after {} of {} rows",
+                    tx_id, applied, processed
+                );
+                inspect::write_engine(&engine, &mut output)?;
+                return Ok(finish_output(&args, output)?);
+            }
+
+            let clients = match (&args.eod_snapshots, &args.history_db) {
+                _ if args.tui => dashboard::process(build_engine(&args, clients)?, transactions)?,
+                _ if keeps_rejections => {
+                    let (clients, rejected) =
+                        summary::process(build_engine(&args, clients)?, transactions);
+                    rejections = rejected;
+                    clients
+                }
+                (Some(dir), _) => {
+                    let engine = build_engine(&args, clients)?;
+                    eod::process(engine, transactions, dir, number_format)?.0
+                }
+                (None, Some(_)) => run_engine(build_engine(&args, clients)?, transactions),
+                (None, None)
+                    if args.webhook.is_some()
+                        || args.log_notifications
+                        || args.publish_notifications.is_some() =>
+                {
+                    run_engine(build_engine(&args, clients)?, transactions)
+                }
+                (None, None) => {
+                    resume_transactions(clients, transactions, args.engine_config()?, args.workers)?
+                }
+            };
+            (clients, processed)
+        }
+    };
+
+    if let Some(store) = &mut store {
+        store.save(&clients)?;
+    }
+
+    if let Some(path) = &args.audit_trail {
+        let file = File::create(path).map_err(|error| format!("{}: {}", path, error))?;
+        audit::write_report(&clients, file)?;
+    }
+
+    if let Some(path) = &args.rejects {
+        let file = File::create(path).map_err(|error| format!("{}: {}", path, error))?;
+        rejects::write_report(&rejections, file)?;
+    }
+
+    if let Some(path) = &args.quarantine {
+        let file = File::create(path).map_err(|error| format!("{}: {}", path, error))?;
+        rejects::write_transactions(&rejections, file)?;
+    }
+
+    if let Some(path) = &args.balance_history {
+        timeseries::write(&clients, path)?;
+    }
+
+    if let Some(publisher) = &mut publisher {
+        publish::publish(&clients, args.publish_mode, publisher.as_mut())?;
+    }
+
+    match args.output_format {
+        // Every change was already written as it was made
+        _ if args.follow => {}
+        OutputFormat::Csv => write_accounts_as(
+            &clients,
+            &mut output,
+            number_format,
+            columns,
+            |id| match &pseudonyms {
+                Some(pseudonyms) => pseudonyms.of(*id),
+                None => id.to_string(),
+            },
+        )?,
+        OutputFormat::Json => JsonSink::new(&mut output).write_accounts(&clients)?,
+        OutputFormat::Ledger => journal::write_ledger(&clients, args.date, &mut output)?,
+        OutputFormat::Beancount => {
+            journal::write_beancount(&clients, args.date, &args.currency, &mut output)?
+        }
+        OutputFormat::Html => {
+            html::write_report(&clients, &rejections, processed, number_format, &mut output)?
+        }
+        OutputFormat::Table => table::write_accounts(&clients, number_format, &mut output)?,
+        OutputFormat::Markdown => markdown::write_report(
+            &clients,
+            &Summary::new(&clients, processed, rejections.len())?,
+            number_format,
+            &mut output,
+        )?,
+    }
+    finish_output(&args, output)?;
+
+    if let (Some(dir), Some(path)) = (&args.tier_output, &args.clients) {
+        let client_id = |id: &ClientId| match &pseudonyms {
+            Some(pseudonyms) => pseudonyms.of(*id),
+            None => id.to_string(),
+        };
+        let tiers = metadata::split_by_tier(&metadata::load(path)?, &clients);
+        for (tier, clients) in &tiers {
+            let path = format!("{}/accounts_{}.csv", dir, tier);
+            let file = File::create(&path).map_err(|error| format!("{}: {}", path, error))?;
+            write_accounts_as(clients, file, number_format, columns, client_id)?;
+        }
+    }
+
+    if args.verify_conservation {
+        verify_conservation(&clients)?;
+    }
+
+    if interrupt::requested() {
+        report_interrupted(processed);
+        return Ok(());
+    }
+
+    match &args.checkpoint {
+        Some(path) => Ok(checkpoint::remove(path)?),
+        None => Ok(()),
+    }
+}
+
+fn run_tenants(
+    args: &Args,
+    transactions: Vec<Transaction>,
+    processed: u64,
+    mut output: Output,
+) -> Result<(), String> {
+    if args.store.is_some()
+        || args.history_db.is_some()
+        || args.audit_trail.is_some()
+        || args.rejects.is_some()
+        || args.quarantine.is_some()
+        || args.stop_at_tx.is_some()
+        || args.verify_conservation
+        || args.balance_history.is_some()
+        || args.eod_snapshots.is_some()
+        || args.output_format != OutputFormat::Csv
+        || args.hash_client_ids
+        || args.account_columns() != AccountColumns::default()
+    {
+        return Err("The tenant column can't be combined with --store, --history-db, --audit-trail, --rejects, --quarantine, --stop-at-tx, --verify-conservation, --balance-history, --eod-snapshots, --output-format, --hash-client-ids, --stats-columns or --lifecycle-columns".to_string());
+    }
+
+    let number_format = args.number_format()?;
+    let tenants = tenant::process(
+        transactions,
+        args.tenant.as_deref(),
+        args.engine_config()?,
+        args.workers,
+    )?;
+    tenant::write_tenant_funds(tenants, &mut output, number_format)?;
+    finish_output(args, output)?;
+
+    if interrupt::requested() {
+        report_interrupted(processed);
+    }
+    Ok(())
+}
+
+// The accounts go to std out, compressed with --compress-output
+type Output = compression::Writer<SigningWriter<Stdout>>;
+
+// Std out for the accounts, signed when there is a --signature file. The key is read up front
+// so a missing one fails before any processing, and so does compressing without the feature
+fn open_output(args: &Args) -> Result<Output, String> {
+    let key = match &args.signature {
+        Some(_) => Some(signature::key_from_env()?),
+        None => None,
+    };
+    compression::Writer::new(
+        SigningWriter::new(io::stdout(), key.as_deref()),
+        args.compress_output,
+    )
+}
+
+// Signs the compressed bytes, the ones actually written
+fn finish_output(args: &Args, output: Output) -> Result<(), String> {
+    match (&args.signature, output.finish()?.signature()) {
+        (Some(path), Some(hex)) => {
+            fs::write(path, format!("{}\n", hex)).map_err(|error| format!("{}: {}", path, error))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Reports the system wide totals on std err, the accounts are already written either way
+fn verify_conservation(clients: &Clients) -> Result<(), String> {
+    let (totals, leaks) = conservation::check(clients);
+    eprintln!(
+        "Deposits {} - withdrawals {} - chargebacks {} + refunds, settlements, interest and disputed withdrawals {} = {}, clients hold {}",
+        totals.deposits,
+        totals.withdrawals,
+        totals.chargebacks,
+        totals.other,
+        totals.expected(),
+        totals.clients
+    );
+    for leak in &leaks {
+        eprintln!("{}", leak);
+    }
+
+    match leaks.len() {
+        0 => Ok(()),
+        count => Err(format!("Funds are not conserved, {} leaks found", count)),
+    }
+}
+
+fn report_interrupted(processed: u64) {
+    eprintln!(
+        "Interrupted after processing {} rows, the accounts written are partial",
+        processed
+    );
+}
+
+fn build_engine(args: &Args, clients: Clients) -> Result<TransactionEngine, String> {
+    let mut builder = TransactionEngine::builder()
+        .config(args.engine_config()?)
+        .clients(clients);
+    if let Some(url) = &args.webhook {
+        builder = builder.sink(Box::new(Webhook::new(url, args.webhook_retries)));
+    }
+    if args.log_notifications {
+        builder = builder.sink(Box::new(LogSink));
+    }
+    if let Some(url) = &args.publish_notifications {
+        builder = builder.sink(Box::new(PublisherSink(publish::open(url)?)));
+    }
+    if let Some(path) = &args.history_db {
+        builder = builder.history(history::open(path)?);
+    }
+
+    builder.build()
+}
+
+fn run_command(command: Command) -> Result<(), String> {
+    match command {
+        Command::Split {
+            input,
+            shards,
+            out_dir,
+        } => {
+            for path in shard::split(&input, shards, &out_dir)? {
+                println!("{}", path.display());
+            }
+            Ok(())
+        }
+        Command::MergeAccounts { files } => shard::merge_accounts(&files, io::stdout()),
+        Command::Repl { snapshot, wal } => {
+            let mut engine = log_to(repl::open(snapshot.as_deref())?, wal.as_deref())?;
+            repl::run(
+                &mut engine,
+                io::stdin().lock(),
+                io::stdout(),
+                snapshot.as_deref(),
+            )
+        }
+        Command::Debug { input, snapshot } => debugger::run(
+            repl::open(snapshot.as_deref())?,
+            parse_transactions(input)?,
+            io::stdin().lock(),
+            io::stdout(),
+        ),
+        Command::AccrueInterest { snapshot, rate, at } => {
+            let bytes = snapshot::read_file(&snapshot)?;
+            let mut engine = TransactionEngine::from_bytes(&bytes)?;
+            let credited = engine.accrue_interest(rate, at)?;
+            snapshot::write_file(&snapshot, engine.to_bytes()?)?;
+            eprintln!("Credited interest to {} clients", credited);
+            write_accounts(engine.clients(), io::stdout(), NumberFormat::default())
+        }
+        Command::Convert { input, output } => convert::convert(&input, &output),
+        Command::Anonymize {
+            input,
+            seed,
+            perturb_amounts,
+        } => {
+            let options = Anonymization {
+                seed,
+                perturb_amounts,
+            };
+            anonymize::anonymize(&input, options, io::stdout())
+        }
+        Command::Sample {
+            input,
+            clients,
+            seed,
+        } => sample::sample(&input, clients, seed, io::stdout()),
+        Command::Process {
+            input,
+            snapshot,
+            dry_run,
+            wal,
+        } => {
+            let mut engine =
+                log_to(repl::open(Some(&snapshot))?, wal.as_deref())?.with_rejections();
+            for tx in parse_transactions(input)? {
+                engine.push(tx);
+            }
+            if !dry_run {
+                snapshot::write_file(&snapshot, engine.to_bytes()?)?;
+            }
+
+            let (clients, rejections) = engine.finish_with_rejections();
+            print_rejections(&rejections);
+            if dry_run {
+                eprintln!(
+                    "Dry run, {} rejected and {} left unchanged",
+                    rejections.len(),
+                    snapshot
+                );
+            }
+            write_accounts(&clients, io::stdout(), NumberFormat::default())
+        }
+        Command::ReplayRejects {
+            rejects,
+            snapshot,
+            remaining,
+            dry_run,
+            wal,
+        } => {
+            // The error column of a --rejects report is ignored like any other extra column
+            let transactions = parse_transactions(rejects)?;
+            let bytes = snapshot::read_file(&snapshot)?;
+            let mut engine =
+                log_to(TransactionEngine::from_bytes(&bytes)?, wal.as_deref())?.with_rejections();
+            let attempted = transactions.len();
+            for tx in transactions {
+                engine.push(tx);
+            }
+            if !dry_run {
+                snapshot::write_file(&snapshot, engine.to_bytes()?)?;
+            }
+
+            let (clients, rejections) = engine.finish_with_rejections();
+            print_rejections(&rejections);
+            eprintln!(
+                "{} of {} transactions applied{}",
+                attempted - rejections.len(),
+                attempted,
+                if dry_run {
+                    ", dry run left the snapshot unchanged"
+                } else {
+                    ""
+                }
+            );
+            if let Some(path) = &remaining {
+                let file = File::create(path).map_err(|error| format!("{}: {}", path, error))?;
+                rejects::write_transactions(&rejections, file)?;
+            }
+            write_accounts(&clients, io::stdout(), NumberFormat::default())
+        }
+        Command::Inspect { snapshot, client } => {
+            let bytes = snapshot::read_file(&snapshot)?;
+            let engine = TransactionEngine::from_bytes(&bytes)?;
+            inspect::write_state(engine.clients(), client, io::stdout())
+        }
+        Command::Replay { wal, snapshot } => {
+            let replayed = wal::replay(&wal)?;
+            let saved = TransactionEngine::from_bytes(&snapshot::read_file(&snapshot)?)?;
+            write_accounts(replayed.clients(), io::stdout(), NumberFormat::default())?;
+
+            let differences = wal::differences(&replayed, &saved)?;
+            for difference in &differences {
+                eprintln!("{}", difference);
+            }
+            match differences.len() {
+                0 => Ok(()),
+                _ => Err(format!("{} doesn't lead to {}", wal, snapshot)),
+            }
+        }
+        Command::CheckBalances { log, snapshot } => {
+            let bytes = snapshot::read_file(&snapshot)?;
+            let engine = TransactionEngine::from_bytes(&bytes)?;
+            let balances = balance_check::read_balances(&log)?;
+            balance_check::write_balances(&balances, io::stdout())?;
+
+            let mismatches = balance_check::mismatches(&balances, engine.clients());
+            for mismatch in &mismatches {
+                eprintln!("{}", mismatch);
+            }
+            match mismatches.len() {
+                0 => Ok(()),
+                count => Err(format!("{} clients don't match {}", count, snapshot)),
+            }
+        }
+        Command::Verify { file, signature } => {
+            signature::verify(&file, &signature, &signature::key_from_env()?)?;
+            eprintln!("{} matches its signature", file);
+            Ok(())
+        }
+        Command::Report {
+            report: Report::Periods { input, granularity },
+        } => {
+            let clients = process_transactions(parse_transactions(input)?, Default::default())?;
+            report::write_period_report(&clients, granularity, io::stdout())
+        }
+        Command::Report {
+            report: Report::Tax { input, year },
+        } => {
+            let clients = process_transactions(parse_transactions(input)?, Default::default())?;
+            report::write_tax_report(&clients, year, io::stdout())
+        }
+        Command::Report {
+            report: Report::Settlements { input },
+        } => {
+            let clients = process_transactions(parse_transactions(input)?, Default::default())?;
+            report::write_settlement_report(&clients, io::stdout())
+        }
+        Command::Report {
+            report: Report::DisputeGraph { input, format },
+        } => {
+            let clients = process_transactions(parse_transactions(input)?, Default::default())?;
+            report::write_dispute_graph(&clients, format, io::stdout())
+        }
+        Command::Report {
+            report: Report::HotClients { input, top },
+        } => {
+            let mut engine = TransactionEngine::default().with_load_tracking();
+            engine.push_batch(&parse_transactions(input)?);
+            report::write_hot_clients(engine.loads().unwrap_or(&Loads::new()), top, io::stdout())
+        }
+        Command::Report {
+            report: Report::Ledger { input, date },
+        } => {
+            let clients = process_transactions(parse_transactions(input)?, Default::default())?;
+            journal::write_ledger(&clients, date, io::stdout())
+        }
+    }
+}
+
+fn print_rejections(rejections: &[Rejection]) {
+    for rejection in rejections {
+        let tx = &rejection.tx;
+        eprintln!(
+            "Rejected {} tx {} of client {}: {}",
+            tx.tx_type(),
+            tx.tx_id(),
+            tx.client_id(),
+            rejection.error
+        );
+    }
+}
+
+// Logs everything the engine does from here on to `wal` when given, see `replay`
+fn log_to(engine: TransactionEngine, wal: Option<&str>) -> Result<TransactionEngine, String> {
+    match wal {
+        Some(path) => engine.with_wal(path),
+        None => Ok(engine),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(error) = interrupt::install() {
         eprintln!("{error}");
+    }
+
+    let (format, result) = match (cli.command, cli.process) {
+        (Some(command), _) => (
+            ErrorFormat::Text,
+            run_command(command).map_err(CodedError::from),
+        ),
+        (None, Some(args)) => (args.error_format, run(args)),
+        (None, None) => (
+            ErrorFormat::Text,
+            Err("Nothing to do, pass a transactions csv or a command".into()),
+        ),
+    };
+
+    if let Err(error) = result {
+        codes::report_error(format, &error);
         std::process::exit(1);
     }
+
+    // Same status a shell reports for SIGINT, so scripts can tell the output is partial
+    if interrupt::requested() {
+        std::process::exit(130);
+    }
 }
@@ -0,0 +1,74 @@
+// The accounts and a summary of the run as GitHub-flavored Markdown tables, for pasting into
+// tickets and runbooks.
+use crate::client::{Clients, NumberFormat};
+use crate::ids::ClientId;
+use crate::summary::Summary;
+use std::io;
+
+pub fn write_report<W: io::Write>(
+    clients: &Clients,
+    summary: &Summary,
+    format: NumberFormat,
+    mut writer: W,
+) -> Result<(), String> {
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+
+    let mut markdown = String::from("## Summary\n\n| | |\n| --- | ---: |\n");
+    for (label, value) in summary.rows()? {
+        markdown.push_str(&format!("| {} | {} |\n", label, value));
+    }
+
+    markdown.push_str("\n## Accounts\n\n");
+    markdown.push_str("| client | available | held | total | locked | frozen | credit_used |\n");
+    markdown.push_str("| ---: | ---: | ---: | ---: | --- | --- | ---: |\n");
+    for client_id in client_ids {
+        let record = clients[client_id].get_record_with_format(*client_id, format);
+        markdown.push_str(&format!("| {} |\n", record.join(" | ")));
+    }
+
+    writer
+        .write_all(markdown.as_bytes())
+        .map_err(|error| format!("Error writing to std out: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[test]
+    fn writes_tables() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 2, Some(1.5)),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(5.0)),
+        ];
+        let clients = crate::process_transactions(transactions, EngineConfig::default()).unwrap();
+        let summary = Summary::new(&clients, 3, 1).unwrap();
+
+        let mut output = Vec::new();
+        write_report(&clients, &summary, NumberFormat::default(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "## Summary\n\n\
+             | | |\n\
+             | --- | ---: |\n\
+             | Transactions processed | 3 |\n\
+             | Transactions rejected | 1 |\n\
+             | Clients | 2 |\n\
+             | Locked accounts | 0 |\n\
+             | Frozen accounts | 0 |\n\
+             | Total available | 6.5000 |\n\
+             | Total held | 0.0000 |\n\
+             | Total | 6.5000 |\n\
+             \n\
+             ## Accounts\n\n\
+             | client | available | held | total | locked | frozen | credit_used |\n\
+             | ---: | ---: | ---: | ---: | --- | --- | ---: |\n\
+             | 1 | 5.0000 | 0.0000 | 5.0000 | false | false | 0.0000 |\n\
+             | 2 | 1.5000 | 0.0000 | 1.5000 | false | false | 0.0000 |\n"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+// Per client settings kept apart from the transactions, read from a csv with a client column
+// and a column per setting, e.g.
+//
+//     client,credit_limit,joint_account,tier
+//     1,500.00,,gold
+//     2,,1,
+//
+// Settings left empty keep their default. What a tier allows comes from a second csv with a
+// tier column, see `Tier`.
+use crate::amount::Amount;
+use crate::client::Clients;
+use crate::engine::{EngineConfig, TierLimits};
+use crate::ids::ClientId;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// Name `split_by_tier` files clients under when the metadata doesn't give them a tier, they
+// have no tier limits
+pub const UNTIERED: &str = "untiered";
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct ClientMetadata {
+    pub client: ClientId,
+    // Lets available go this far below zero
+    pub credit_limit: Option<Amount>,
+    // Client whose account this client operates as a joint owner
+    pub joint_account: Option<ClientId>,
+    pub tier: Option<String>,
+}
+
+// Settings shared by every client of a tier, e.g.
+//
+//     tier,credit_limit,withdrawal_limit,withdrawal_fee
+//     gold,1000.00,,
+//     standard,,500.00,0.25
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Tier {
+    pub tier: String,
+    // Used by clients of the tier without a credit limit of their own
+    pub credit_limit: Option<Amount>,
+    // Larger withdrawals are rejected
+    pub withdrawal_limit: Option<Amount>,
+    // Charged on every withdrawal
+    pub withdrawal_fee: Option<Amount>,
+}
+
+pub fn load(path: &str) -> Result<Vec<ClientMetadata>, String> {
+    read(path)
+}
+
+pub fn load_tiers(path: &str) -> Result<Vec<Tier>, String> {
+    read(path)
+}
+
+fn read<T: DeserializeOwned>(path: &str) -> Result<Vec<T>, String> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|error| format!("{}: {}", path, error))?;
+
+    rdr.deserialize()
+        .map(|row| row.map_err(|error| format!("Error parsing {}: {}", path, error)))
+        .collect()
+}
+
+// Copies every client's settings into `config`
+pub fn apply(metadata: &[ClientMetadata], config: &mut EngineConfig) {
+    for client in metadata {
+        if let Some(limit) = client.credit_limit {
+            config.credit_limits.insert(client.client, limit.value());
+        }
+        if let Some(account) = client.joint_account {
+            config.joint_accounts.insert(client.client, account);
+        }
+    }
+}
+
+// Copies the limits and fees of every client's tier into `config`, call it after `apply` so
+// credit limits set per client win over the tier's. Tiers that aren't in `tiers` are an error
+pub fn apply_tiers(
+    metadata: &[ClientMetadata],
+    tiers: &[Tier],
+    config: &mut EngineConfig,
+) -> Result<(), String> {
+    for client in metadata {
+        let Some(name) = &client.tier else {
+            continue;
+        };
+        let tier = tiers.iter().find(|tier| &tier.tier == name).ok_or(format!(
+            "Client {} is in unknown tier {}",
+            client.client, name
+        ))?;
+
+        if let Some(limit) = tier.credit_limit {
+            config
+                .credit_limits
+                .entry(client.client)
+                .or_insert(limit.value());
+        }
+        config.tiers.insert(
+            client.client,
+            TierLimits {
+                tier: name.clone(),
+                withdrawal_limit: tier.withdrawal_limit.map(|limit| limit.value()),
+                withdrawal_fee: tier
+                    .withdrawal_fee
+                    .map(|fee| fee.value())
+                    .unwrap_or_default(),
+            },
+        );
+    }
+
+    Ok(())
+}
+
+// The clients of each tier, by tier name
+pub fn split_by_tier(metadata: &[ClientMetadata], clients: &Clients) -> BTreeMap<String, Clients> {
+    let mut tiers: BTreeMap<String, Clients> = BTreeMap::new();
+    for (client_id, client) in clients {
+        let tier = metadata
+            .iter()
+            .find(|metadata| metadata.client == *client_id)
+            .and_then(|metadata| metadata.tier.clone())
+            .unwrap_or(UNTIERED.to_string());
+        tiers
+            .entry(tier)
+            .or_default()
+            .insert(*client_id, client.clone());
+    }
+    tiers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codes::{CodedError, RejectionCode};
+    use crate::engine::TransactionEngine;
+    use crate::ids::ClientId;
+    use crate::money::Money;
+    use crate::transaction::{Transaction, TransactionType};
+    use std::io::Write;
+
+    #[test]
+    fn withdrawals_may_use_the_credit_line() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "client,credit_limit\n1,5.0\n2,\n").unwrap();
+        let metadata = load(file.path().to_str().unwrap()).unwrap();
+
+        let mut config = EngineConfig::default();
+        apply(&metadata, &mut config);
+        let mut engine = TransactionEngine::new(config);
+        for client in [1, 2] {
+            let deposit =
+                Transaction::raw(TransactionType::Deposit, client as u32, client, Some(1.0));
+            engine.submit(deposit).unwrap();
+        }
+
+        let withdraw = |tx, client, amount| {
+            Transaction::raw(TransactionType::Withdrawal, tx, client, Some(amount))
+        };
+        engine.submit(withdraw(3, 1, 4.0)).unwrap();
+        assert!(engine.submit(withdraw(4, 1, 3.0)).is_err());
+        assert!(engine.submit(withdraw(5, 2, 2.0)).is_err());
+
+        assert_eq!(
+            engine.clients()[&ClientId::new(1)].get_record(ClientId::new(1)),
+            vec!["1", "-3.0000", "0.0000", "-3.0000", "false", "false", "3.0000"]
+        );
+    }
+
+    #[test]
+    fn tiers_set_limits_and_fees() {
+        let mut clients = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            clients,
+            "client,credit_limit,tier\n1,,gold\n2,1.0,standard\n3,,\n"
+        )
+        .unwrap();
+        let mut tiers = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            tiers,
+            "tier,credit_limit,withdrawal_limit,withdrawal_fee\n\
+             gold,5.0,,\n\
+             standard,,3.0,0.5\n"
+        )
+        .unwrap();
+        let metadata = load(clients.path().to_str().unwrap()).unwrap();
+        let tiers = load_tiers(tiers.path().to_str().unwrap()).unwrap();
+
+        let mut config = EngineConfig::default();
+        apply(&metadata, &mut config);
+        apply_tiers(&metadata, &tiers, &mut config).unwrap();
+        let mut engine = TransactionEngine::new(config);
+        for client in [1, 2, 3] {
+            let deposit =
+                Transaction::raw(TransactionType::Deposit, client as u32, client, Some(4.0));
+            engine.submit(deposit).unwrap();
+        }
+
+        let withdraw = |tx, client, amount| {
+            Transaction::raw(TransactionType::Withdrawal, tx, client, Some(amount))
+        };
+        engine.submit(withdraw(4, 1, 8.0)).unwrap();
+        assert_eq!(
+            engine.submit(withdraw(5, 2, 3.5)),
+            Err(CodedError::new(
+                RejectionCode::WithdrawalLimitExceeded,
+                "Withdrawal of 3.5000 is over the 3.0000 limit of tier standard"
+            ))
+        );
+        // The client's own credit line covers the fee
+        engine.submit(withdraw(6, 2, 3.0)).unwrap();
+        engine.submit(withdraw(7, 3, 4.0)).unwrap();
+
+        let clients = engine.finish();
+        assert_eq!(
+            clients[&ClientId::new(1)].funds().available(),
+            Money::from(-4.0)
+        );
+        assert_eq!(
+            clients[&ClientId::new(2)].funds().available(),
+            Money::from(0.5)
+        );
+        assert_eq!(clients[&ClientId::new(3)].funds().available(), Money::ZERO);
+        let split = split_by_tier(&metadata, &clients);
+        let by_tier: Vec<(&str, Vec<u16>)> = split
+            .iter()
+            .map(|(tier, clients)| {
+                let mut ids: Vec<u16> = clients.keys().map(|id| id.value()).collect();
+                ids.sort();
+                (tier.as_str(), ids)
+            })
+            .collect();
+        assert_eq!(
+            by_tier,
+            vec![
+                ("gold", vec![1]),
+                ("standard", vec![2]),
+                ("untiered", vec![3])
+            ]
+        );
+
+        let unknown = [ClientMetadata {
+            client: ClientId::new(9),
+            credit_limit: None,
+            joint_account: None,
+            tier: Some("platinum".to_string()),
+        }];
+        assert_eq!(
+            apply_tiers(&unknown, &tiers, &mut EngineConfig::default()),
+            Err("Client 9 is in unknown tier platinum".to_string())
+        );
+    }
+
+    #[test]
+    fn joint_owners_share_the_account() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "client,joint_account
+2,1
+"
+        )
+        .unwrap();
+
+        let mut config = EngineConfig::default();
+        apply(&load(file.path().to_str().unwrap()).unwrap(), &mut config);
+        let mut engine = TransactionEngine::new(config);
+        engine
+            .submit(Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)))
+            .unwrap();
+        engine
+            .submit(Transaction::raw(
+                TransactionType::Withdrawal,
+                2,
+                2,
+                Some(2.0),
+            ))
+            .unwrap();
+        engine
+            .submit(Transaction::raw(TransactionType::Dispute, 1, 2, None))
+            .unwrap();
+
+        let clients = engine.finish();
+        assert_eq!(clients.len(), 1);
+        let owners: Vec<Option<ClientId>> = clients[&ClientId::new(1)]
+            .audit_trail()
+            .iter()
+            .map(|entry| entry.owner)
+            .collect();
+        assert_eq!(
+            owners,
+            vec![None, Some(ClientId::new(2)), Some(ClientId::new(2))]
+        );
+        assert_eq!(
+            clients[&ClientId::new(1)].funds().held().to_string(),
+            "5.0000"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+// Money is kept as an integer number of 1/10000 units so balance math is exact, converting
+// from and to decimal text only when parsing input and writing output.
+use crate::codes::{CodedError, RejectionCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// Number of decimals a minor unit represents
+pub const SCALE: u32 = 4;
+const UNIT: i64 = 10_i64.pow(SCALE);
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub fn from_minor_units(units: i64) -> Self {
+        Money(units)
+    }
+
+    pub fn minor_units(&self) -> i64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, CodedError> {
+        self.0.checked_add(other.0).map(Money).ok_or_else(|| {
+            CodedError::new(
+                RejectionCode::Overflow,
+                format!("Adding {} to {} overflows", other, self),
+            )
+        })
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, CodedError> {
+        self.0.checked_sub(other.0).map(Money).ok_or_else(|| {
+            CodedError::new(
+                RejectionCode::Overflow,
+                format!("Subtracting {} from {} overflows", other, self),
+            )
+        })
+    }
+
+    // Only used where both sides are already known to fit, e.g. a client's total
+    pub(crate) fn saturating_add(self, other: Money) -> Money {
+        Money(self.0.saturating_add(other.0))
+    }
+
+    pub(crate) fn saturating_sub(self, other: Money) -> Money {
+        Money(self.0.saturating_sub(other.0))
+    }
+}
+
+// Rounds to the nearest minor unit, out of range values saturate
+impl From<f32> for Money {
+    fn from(value: f32) -> Self {
+        Money((f64::from(value) * UNIT as f64).round() as i64)
+    }
+}
+
+impl FromStr for Money {
+    type Err = String;
+
+    // Digits past the fourth decimal are rounded half away from zero
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid amount {:?}", text);
+
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if (whole.is_empty() && fraction.is_empty())
+            || !whole
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let out_of_range = || format!("Amount {:?} is out of range", text);
+        let fraction = fraction.as_bytes();
+        let mut units: i64 = 0;
+        for digit in whole
+            .bytes()
+            .chain((0..SCALE as usize).map(|i| fraction.get(i).copied().unwrap_or(b'0')))
+        {
+            units = units
+                .checked_mul(10)
+                .and_then(|units| units.checked_add(i64::from(digit - b'0')))
+                .ok_or_else(out_of_range)?;
+        }
+        if fraction
+            .get(SCALE as usize)
+            .is_some_and(|digit| *digit >= b'5')
+        {
+            units = units.checked_add(1).ok_or_else(out_of_range)?;
+        }
+
+        Ok(Money(if negative { -units } else { units }))
+    }
+}
+
+// Formats with 4 decimals unless a precision is given, e.g. `{:.2}`, rounding half away from zero
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decimals = f.precision().unwrap_or(SCALE as usize);
+        let shown = decimals.min(SCALE as usize) as u32;
+
+        let mut units = i128::from(self.0);
+        if shown < SCALE {
+            let divisor = 10_i128.pow(SCALE - shown);
+            let rounding = if units < 0 { -divisor / 2 } else { divisor / 2 };
+            units = (units + rounding) / divisor;
+        }
+
+        let unit = 10_i128.pow(shown);
+        let sign = if units < 0 { "-" } else { "" };
+        write!(f, "{}{}", sign, units.abs() / unit)?;
+        if decimals > 0 {
+            write!(
+                f,
+                ".{:0width$}{:0<padding$}",
+                units.abs() % unit,
+                "",
+                width = shown as usize,
+                padding = decimals - shown as usize
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_formats_exactly() {
+        let money: Money = "2.0001".parse().unwrap();
+        assert_eq!(money.minor_units(), 20_001);
+        assert_eq!(money.to_string(), "2.0001");
+        assert_eq!(format!("{:.2}", money), "2.00");
+        assert_eq!(format!("{:.6}", money), "2.000100");
+        assert_eq!(format!("{:.0}", "-1.5".parse::<Money>().unwrap()), "-2");
+        assert_eq!("0.12345".parse::<Money>().unwrap().minor_units(), 1_235);
+        assert_eq!(
+            "0.1"
+                .parse::<Money>()
+                .unwrap()
+                .checked_add("0.2".parse().unwrap()),
+            Ok("0.3".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn reports_overflow() {
+        let max = Money::from_minor_units(i64::MAX);
+        assert!(max.checked_add(Money::from_minor_units(1)).is_err());
+        assert!(Money::from_minor_units(i64::MIN)
+            .checked_sub(Money::from_minor_units(1))
+            .is_err());
+        assert!("99999999999999999999".parse::<Money>().is_err());
+        assert!("1.2.3".parse::<Money>().is_err());
+    }
+}
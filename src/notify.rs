@@ -0,0 +1,254 @@
+// Notifications about what risk systems need to hear about as it happens: chargebacks, accounts
+// getting locked, disputes held short of their amount and resolves refunded to the payer. The engine raises them as transactions are applied and hands them
+// to every sink it was given, embedders can route them anywhere by implementing a sink.
+use crate::client::Client;
+use crate::money::Money;
+use crate::transaction::{Transaction, TransactionType};
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::mpsc::Sender;
+
+mod webhook;
+
+pub use webhook::Webhook;
+
+pub trait NotificationSink: fmt::Debug + Send {
+    fn send(&mut self, notification: &Notification) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    // `amount` is how much the client's total changed by, reversed withdrawals are positive
+    Chargeback {
+        client: u16,
+        tx: u32,
+        #[serde(serialize_with = "decimal")]
+        amount: Money,
+    },
+    Locked {
+        client: u16,
+        tx: u32,
+    },
+    // Only `held` of the disputed deposit was available to hold
+    DisputeCapped {
+        client: u16,
+        tx: u32,
+        #[serde(serialize_with = "decimal")]
+        held: Money,
+    },
+    // A resolve returned the disputed deposit's `amount` to the payer, see
+    // `ResolvePolicy::RefundToSource`
+    Refund {
+        client: u16,
+        tx: u32,
+        #[serde(serialize_with = "decimal")]
+        amount: Money,
+    },
+}
+
+impl fmt::Display for Notification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Notification::Chargeback { client, tx, amount } => write!(
+                f,
+                "chargeback of tx {} changed client {} by {}",
+                tx, client, amount
+            ),
+            Notification::Locked { client, tx } => {
+                write!(f, "client {} locked by tx {}", client, tx)
+            }
+            Notification::DisputeCapped { client, tx, held } => write!(
+                f,
+                "dispute of tx {} capped at the {} client {} had available",
+                tx, held, client
+            ),
+            Notification::Refund { client, tx, amount } => write!(
+                f,
+                "resolve of tx {} refunded {} of client {} to the payer",
+                tx, amount, client
+            ),
+        }
+    }
+}
+
+// Writes each notification as a line on std err
+#[derive(Debug, Default)]
+pub struct LogSink;
+
+impl NotificationSink for LogSink {
+    fn send(&mut self, notification: &Notification) -> Result<(), String> {
+        eprintln!("notification: {}", notification);
+        Ok(())
+    }
+}
+
+// Hands notifications to another thread of the embedding program
+#[derive(Debug)]
+pub struct ChannelSink(pub Sender<Notification>);
+
+impl NotificationSink for ChannelSink {
+    fn send(&mut self, notification: &Notification) -> Result<(), String> {
+        self.0
+            .send(notification.clone())
+            .map_err(|_| "Notification channel closed".to_string())
+    }
+}
+
+// Amounts are sent as decimal text, "-2.0000", rather than in minor units
+fn decimal<S: Serializer>(amount: &Money, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(amount)
+}
+
+// What applying `tx` to `client` raised, `was_locked` being the lock status before it
+pub fn notifications<S: BuildHasher + Default>(
+    tx: &Transaction,
+    client: &Client<S>,
+    was_locked: bool,
+) -> Vec<Notification> {
+    let (client_id, tx_id) = (tx.raw_client_id(), tx.raw_tx_id());
+    let mut notifications = Vec::new();
+
+    if *tx.tx_type() == TransactionType::Chargeback {
+        let amount = client
+            .audit_trail()
+            .last()
+            .map(|entry| entry.available_change.saturating_add(entry.held_change))
+            .unwrap_or_default();
+        notifications.push(Notification::Chargeback {
+            client: client_id,
+            tx: tx_id,
+            amount,
+        });
+    }
+    if *tx.tx_type() == TransactionType::Dispute {
+        if let Some(held) = client.capped_holds().get(&tx_id) {
+            notifications.push(Notification::DisputeCapped {
+                client: client_id,
+                tx: tx_id,
+                held: *held,
+            });
+        }
+    }
+    if *tx.tx_type() == TransactionType::Resolve {
+        if let Some(entry) = client
+            .audit_trail()
+            .last()
+            .filter(|entry| entry.operation == TransactionType::Refund)
+        {
+            notifications.push(Notification::Refund {
+                client: client_id,
+                tx: tx_id,
+                amount: Money::from_minor_units(entry.held_change.minor_units().saturating_neg()),
+            });
+        }
+    }
+    if client.is_locked() && !was_locked {
+        notifications.push(Notification::Locked {
+            client: client_id,
+            tx: tx_id,
+        });
+    }
+
+    notifications
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{DisputeOverdraftPolicy, EngineConfig, TransactionEngine};
+    use crate::ids::TxId;
+    use std::sync::mpsc;
+
+    #[test]
+    fn raises_chargebacks_and_locks() {
+        let mut client: Client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+        );
+        let config = EngineConfig::default();
+        let dispute = Transaction::raw(TransactionType::Dispute, 1, 1, None);
+        client
+            .handle_transaction_with_config(dispute.clone(), &config)
+            .unwrap();
+        assert!(notifications(&dispute, &client, false).is_empty());
+
+        let chargeback = Transaction::raw(TransactionType::Chargeback, 1, 1, None);
+        client
+            .handle_transaction_with_config(chargeback.clone(), &config)
+            .unwrap();
+        assert_eq!(
+            notifications(&chargeback, &client, false),
+            vec![
+                Notification::Chargeback {
+                    client: 1,
+                    tx: 1,
+                    amount: "-2".parse().unwrap()
+                },
+                Notification::Locked { client: 1, tx: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn raises_capped_disputes() {
+        let mut client: Client = Client::new(
+            TxId::new(1),
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+        );
+        let config = EngineConfig {
+            dispute_overdraft: DisputeOverdraftPolicy::Cap,
+            ..Default::default()
+        };
+        client
+            .handle_transaction(Transaction::raw(
+                TransactionType::Withdrawal,
+                2,
+                1,
+                Some(1.5),
+            ))
+            .unwrap();
+        let dispute = Transaction::raw(TransactionType::Dispute, 1, 1, None);
+        client
+            .handle_transaction_with_config(dispute.clone(), &config)
+            .unwrap();
+
+        let raised = notifications(&dispute, &client, false);
+        assert_eq!(
+            raised,
+            vec![Notification::DisputeCapped {
+                client: 1,
+                tx: 1,
+                held: "0.5".parse().unwrap()
+            }]
+        );
+        assert_eq!(
+            serde_json::to_string(&raised[0]).unwrap(),
+            r#"{"event":"dispute_capped","client":1,"tx":1,"held":"0.5000"}"#
+        );
+    }
+
+    #[test]
+    fn engine_delivers_to_its_sinks() {
+        let (sender, receiver) = mpsc::channel();
+        let mut engine = TransactionEngine::new(EngineConfig::default())
+            .with_sink(Box::new(ChannelSink(sender)));
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Chargeback, 1, 1, None),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)),
+        ] {
+            engine.push(tx);
+        }
+        drop(engine);
+
+        let notifications: Vec<Notification> = receiver.iter().collect();
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(
+            notifications[1].to_string(),
+            "client 1 locked by tx 1".to_string()
+        );
+    }
+}
@@ -0,0 +1,110 @@
+// POSTs every notification as JSON to a URL, retrying failed deliveries with a doubling delay
+use super::{Notification, NotificationSink};
+use std::thread;
+use std::time::Duration;
+
+const FIRST_RETRY_AFTER: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    retries: u32,
+}
+
+impl Webhook {
+    pub fn new(url: &str, retries: u32) -> Self {
+        Webhook {
+            url: url.to_string(),
+            retries,
+        }
+    }
+}
+
+impl NotificationSink for Webhook {
+    fn send(&mut self, notification: &Notification) -> Result<(), String> {
+        let mut delay = FIRST_RETRY_AFTER;
+        let mut attempt = 0;
+
+        loop {
+            match post(&self.url, notification) {
+                Ok(()) => return Ok(()),
+                Err(error) if attempt == self.retries => {
+                    return Err(format!(
+                        "Error posting to {} after {} attempts: {}",
+                        self.url,
+                        attempt + 1,
+                        error
+                    ))
+                }
+                Err(_) => {
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "webhook")]
+fn post(url: &str, notification: &Notification) -> Result<(), String> {
+    let body = serde_json::to_string(notification).map_err(|error| error.to_string())?;
+
+    ureq::post(url)
+        .header("Content-Type", "application/json")
+        .send(body.as_str())
+        .map(|_| ())
+        .map_err(|error| error.to_string())
+}
+
+#[cfg(not(feature = "webhook"))]
+fn post(_url: &str, _notification: &Notification) -> Result<(), String> {
+    Err("Webhooks need the webhook feature, rebuild with --features webhook".to_string())
+}
+
+#[cfg(all(test, feature = "webhook"))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn retries_until_delivered() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/alerts", listener.local_addr().unwrap());
+
+        // Fails the first request and accepts the second, handing back its body
+        let server = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in ["500 Internal Server Error", "200 OK"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+                write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            }
+            bodies
+        });
+
+        Webhook::new(&url, 1)
+            .send(&Notification::Locked { client: 7, tx: 3 })
+            .unwrap();
+
+        assert_eq!(
+            server.join().unwrap()[1],
+            r#"{"event":"locked","client":7,"tx":3}"#
+        );
+    }
+}
@@ -0,0 +1,160 @@
+// Where the final accounts go. Embedders sending them to a database or a queue implement
+// `OutputSink` instead of reformatting `Clients` themselves, the csv and JSON sinks write to
+// std out, a file or any other writer.
+use crate::client::{Client, Clients, NumberFormat};
+use crate::ids::ClientId;
+use crate::write_accounts;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Stdout, Write};
+
+pub trait OutputSink {
+    fn write_accounts(&mut self, clients: &Clients) -> Result<(), String>;
+}
+
+impl<S: OutputSink + ?Sized> OutputSink for Box<S> {
+    fn write_accounts(&mut self, clients: &Clients) -> Result<(), String> {
+        (**self).write_accounts(clients)
+    }
+}
+
+// The accounts csv the command line tool writes
+pub struct CsvSink<W> {
+    writer: W,
+    format: NumberFormat,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W, format: NumberFormat) -> Self {
+        CsvSink { writer, format }
+    }
+}
+
+impl CsvSink<Stdout> {
+    pub fn stdout(format: NumberFormat) -> Self {
+        CsvSink::new(io::stdout(), format)
+    }
+}
+
+impl CsvSink<BufWriter<File>> {
+    pub fn create(path: &str, format: NumberFormat) -> Result<Self, String> {
+        Ok(CsvSink::new(create(path)?, format))
+    }
+}
+
+impl<W: Write> OutputSink for CsvSink<W> {
+    fn write_accounts(&mut self, clients: &Clients) -> Result<(), String> {
+        write_accounts(clients, &mut self.writer, self.format)
+    }
+}
+
+// A JSON object per client on its own line, in client id order
+pub struct JsonSink<W> {
+    writer: W,
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonSink { writer }
+    }
+}
+
+impl JsonSink<Stdout> {
+    pub fn stdout() -> Self {
+        JsonSink::new(io::stdout())
+    }
+}
+
+impl JsonSink<BufWriter<File>> {
+    pub fn create(path: &str) -> Result<Self, String> {
+        Ok(JsonSink::new(create(path)?))
+    }
+}
+
+impl<W: Write> OutputSink for JsonSink<W> {
+    fn write_accounts(&mut self, clients: &Clients) -> Result<(), String> {
+        let write_error = |error: io::Error| format!("Error writing JSON: {}", error);
+        let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+        client_ids.sort();
+
+        for client_id in client_ids {
+            let account = AccountJson::new(*client_id, &clients[client_id]);
+            serde_json::to_writer(&mut self.writer, &account)
+                .map_err(|error| format!("Error writing JSON: {}", error))?;
+            writeln!(self.writer).map_err(write_error)?;
+        }
+
+        self.writer.flush().map_err(write_error)
+    }
+}
+
+// A client's final balances and status, amounts as decimal text so none are rounded
+#[derive(Debug, Serialize)]
+pub(crate) struct AccountJson {
+    client: ClientId,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+    frozen: bool,
+    credit_used: String,
+}
+
+impl AccountJson {
+    pub(crate) fn new(client_id: ClientId, client: &Client) -> Self {
+        let funds = client.funds();
+        AccountJson {
+            client: client_id,
+            available: funds.available().to_string(),
+            held: funds.held().to_string(),
+            total: funds.available().saturating_add(funds.held()).to_string(),
+            locked: client.is_locked(),
+            frozen: client.is_frozen(),
+            credit_used: funds.credit_used(client.credit_limit()).to_string(),
+        }
+    }
+}
+
+fn create(path: &str) -> Result<BufWriter<File>, String> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|error| format!("{}: {}", path, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_transactions;
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[test]
+    fn writes_accounts_to_any_sink() {
+        let clients = process_transactions(
+            [
+                Transaction::raw(TransactionType::Deposit, 2, 2, Some(1.0)),
+                Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.5)),
+            ],
+            Default::default(),
+        )
+        .unwrap();
+
+        let mut csv = Vec::new();
+        let mut sink: Box<dyn OutputSink> =
+            Box::new(CsvSink::new(&mut csv, NumberFormat::default()));
+        sink.write_accounts(&clients).unwrap();
+        drop(sink);
+        assert!(String::from_utf8(csv)
+            .unwrap()
+            .starts_with("client,available,held,total,locked,frozen,credit_used\n"));
+
+        let mut json = Vec::new();
+        JsonSink::new(&mut json).write_accounts(&clients).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"client\":1,\"available\":\"2.5000\",\"held\":\"0.0000\",\"total\":\"2.5000\",\
+             \"locked\":false,\"frozen\":false,\"credit_used\":\"0.0000\"}\n\
+             {\"client\":2,\"available\":\"1.0000\",\"held\":\"0.0000\",\"total\":\"1.0000\",\
+             \"locked\":false,\"frozen\":false,\"credit_used\":\"0.0000\"}\n"
+        );
+    }
+}
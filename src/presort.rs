@@ -0,0 +1,289 @@
+use crate::csv_reader;
+use crate::encoding::InputReader;
+use chrono::{DateTime, Utc};
+use csv::{StringRecord, Writer, WriterBuilder};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use tempfile::NamedTempFile;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum SortKey {
+    Tx,
+    Timestamp,
+}
+
+impl SortKey {
+    fn column_name(&self) -> &'static str {
+        match self {
+            SortKey::Tx => "tx",
+            SortKey::Timestamp => "timestamp",
+        }
+    }
+
+    fn column(&self, headers: &StringRecord) -> Result<usize, String> {
+        headers
+            .iter()
+            .position(|header| header == self.column_name())
+            .ok_or(format!(
+                "Input has no {} column to sort by",
+                self.column_name()
+            ))
+    }
+
+    fn value(&self, record: &StringRecord, column: usize) -> Result<i64, String> {
+        let field = record.get(column).unwrap_or_default();
+
+        match self {
+            SortKey::Tx => field
+                .parse::<u32>()
+                .map(i64::from)
+                .map_err(|error| format!("Invalid tx id {:?}: {}", field, error)),
+            SortKey::Timestamp => field
+                .parse::<DateTime<Utc>>()
+                .map(|timestamp| timestamp.timestamp_micros())
+                .map_err(|error| format!("Invalid timestamp {:?}: {}", field, error)),
+        }
+    }
+}
+
+// Sorts the input by `key` into a temporary csv file, so unordered dumps are applied deterministically.
+// At most `chunk_size` rows are held in memory: each sorted chunk is spilled to its own temp file and
+// the chunks are then k-way merged. Rows with equal keys keep their input order.
+pub fn presort(input: &str, key: SortKey, chunk_size: usize) -> Result<NamedTempFile, String> {
+    let mut rdr = csv_reader(input)?;
+    let headers = rdr
+        .headers()
+        .map_err(|error| format!("Error reading csv headers: {}", error))?
+        .clone();
+    let column = key.column(&headers)?;
+
+    let mut chunks = Vec::new();
+    let mut rows = Vec::new();
+
+    for result in rdr.records() {
+        let record = result.map_err(|error| format!("Error parsing csv line: {}", error))?;
+        rows.push((key.value(&record, column)?, record));
+
+        if rows.len() >= chunk_size.max(1) {
+            chunks.push(write_chunk(&headers, &mut rows)?);
+        }
+    }
+
+    if !rows.is_empty() || chunks.is_empty() {
+        chunks.push(write_chunk(&headers, &mut rows)?);
+    }
+
+    if chunks.len() == 1 {
+        return Ok(chunks.remove(0));
+    }
+
+    let paths: Vec<String> = chunks
+        .iter()
+        .map(|chunk| chunk.path().to_string_lossy().into_owned())
+        .collect();
+    merge_sorted(&headers, &paths, key, column)
+}
+
+// Interleaves inputs that are each already in `key` order, e.g. daily files from several
+// sources, into one temporary csv in global `key` order. Rows with equal keys are taken from
+// the earlier input first. Every input needs the same columns
+pub fn merge(inputs: &[String], key: SortKey) -> Result<NamedTempFile, String> {
+    let mut headers: Option<StringRecord> = None;
+    for input in inputs {
+        let input_headers = csv_reader(input)?
+            .headers()
+            .map_err(|error| format!("Error reading csv headers: {}", error))?
+            .clone();
+        match &headers {
+            Some(headers) if *headers != input_headers => {
+                return Err(format!("{} has other columns than {}", input, inputs[0]))
+            }
+            Some(_) => {}
+            None => headers = Some(input_headers),
+        }
+    }
+    let headers = headers.ok_or("No inputs to merge".to_string())?;
+    let column = key.column(&headers)?;
+
+    merge_sorted(&headers, inputs, key, column)
+}
+
+fn temp_writer() -> Result<(NamedTempFile, Writer<File>), String> {
+    let file =
+        NamedTempFile::new().map_err(|error| format!("Error creating temp file: {}", error))?;
+    let handle = file
+        .reopen()
+        .map_err(|error| format!("Error opening temp file: {}", error))?;
+    let wtr = WriterBuilder::new().flexible(true).from_writer(handle);
+
+    Ok((file, wtr))
+}
+
+fn write_chunk(
+    headers: &StringRecord,
+    rows: &mut Vec<(i64, StringRecord)>,
+) -> Result<NamedTempFile, String> {
+    // Stable sort keeps rows sharing a key (a deposit and its dispute) in input order
+    rows.sort_by_key(|(value, _)| *value);
+
+    let (file, mut wtr) = temp_writer()?;
+    wtr.write_record(headers)
+        .map_err(|error| format!("Error writing sorted chunk: {}", error))?;
+    for (_, record) in rows.drain(..) {
+        wtr.write_record(&record)
+            .map_err(|error| format!("Error writing sorted chunk: {}", error))?;
+    }
+    wtr.flush()
+        .map_err(|error| format!("Error writing sorted chunk: {}", error))?;
+
+    Ok(file)
+}
+
+fn merge_sorted(
+    headers: &StringRecord,
+    paths: &[String],
+    key: SortKey,
+    column: usize,
+) -> Result<NamedTempFile, String> {
+    let mut readers = Vec::new();
+    for path in paths {
+        readers.push(csv_reader(path)?.into_records());
+    }
+
+    // Ties are broken by file index, which preserves input order across chunks
+    let mut heap = BinaryHeap::new();
+    let mut heads = Vec::new();
+    for (index, records) in readers.iter_mut().enumerate() {
+        let head = next_row(records, key, column)?;
+        if let Some((value, _)) = &head {
+            heap.push(Reverse((*value, index)));
+        }
+        heads.push(head);
+    }
+
+    let (file, mut wtr) = temp_writer()?;
+    wtr.write_record(headers)
+        .map_err(|error| format!("Error writing sorted file: {}", error))?;
+
+    while let Some(Reverse((previous, index))) = heap.pop() {
+        if let Some((_, record)) = heads[index].take() {
+            wtr.write_record(&record)
+                .map_err(|error| format!("Error writing sorted file: {}", error))?;
+        }
+
+        heads[index] = next_row(&mut readers[index], key, column)?;
+        if let Some((value, record)) = &heads[index] {
+            if *value < previous {
+                return Err(format!(
+                    "{} is not in {} order at line {}, sort it with --presort first",
+                    paths[index],
+                    key.column_name(),
+                    record.position().map_or(0, |position| position.line())
+                ));
+            }
+            heap.push(Reverse((*value, index)));
+        }
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing sorted file: {}", error))?;
+
+    Ok(file)
+}
+
+fn next_row(
+    records: &mut csv::StringRecordsIntoIter<InputReader>,
+    key: SortKey,
+    column: usize,
+) -> Result<Option<(i64, StringRecord)>, String> {
+    match records.next() {
+        Some(Ok(record)) => Ok(Some((key.value(&record, column)?, record))),
+        Some(Err(error)) => Err(format!("Error reading sorted chunk: {}", error)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn sorted_rows(contents: &str, key: SortKey, chunk_size: usize) -> Vec<String> {
+        let mut input = NamedTempFile::new().unwrap();
+        input.write_all(contents.as_bytes()).unwrap();
+
+        let sorted = presort(&input.path().to_string_lossy(), key, chunk_size).unwrap();
+        std::fs::read_to_string(sorted.path())
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn can_sort_by_tx_across_chunks() {
+        let rows = sorted_rows(
+            "type,client,tx,amount\n\
+             deposit,1,3,1.0\n\
+             deposit,1,1,1.0\n\
+             dispute,1,1\n\
+             deposit,2,2,1.0\n\
+             withdrawal,1,4,1.0\n",
+            SortKey::Tx,
+            2,
+        );
+
+        assert_eq!(
+            rows,
+            vec![
+                "type,client,tx,amount",
+                "deposit,1,1,1.0",
+                "dispute,1,1",
+                "deposit,2,2,1.0",
+                "deposit,1,3,1.0",
+                "withdrawal,1,4,1.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_sorted_files_by_tx() {
+        let write = |contents: &str| {
+            let mut input = NamedTempFile::new().unwrap();
+            input.write_all(contents.as_bytes()).unwrap();
+            input
+        };
+        let first = write("type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,4,1.0\ndispute,1,4\n");
+        let second = write("type,client,tx,amount\ndeposit,2,2,1.0\ndeposit,2,3,1.0\n");
+        let unsorted = write("type,client,tx,amount\ndeposit,3,6,1.0\ndeposit,3,5,1.0\n");
+        let path = |file: &NamedTempFile| file.path().to_string_lossy().into_owned();
+
+        let merged = merge(&[path(&first), path(&second)], SortKey::Tx).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(merged.path()).unwrap(),
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n\
+             deposit,2,2,1.0\n\
+             deposit,2,3,1.0\n\
+             deposit,1,4,1.0\n\
+             dispute,1,4\n"
+        );
+        assert!(merge(&[path(&first), path(&unsorted)], SortKey::Tx).is_err());
+    }
+
+    #[test]
+    fn can_sort_by_timestamp() {
+        let rows = sorted_rows(
+            "type,client,tx,amount,timestamp\n\
+             deposit,1,1,1.0,2024-01-02T00:00:00Z\n\
+             deposit,1,2,1.0,2024-01-01T00:00:00Z\n",
+            SortKey::Timestamp,
+            1,
+        );
+
+        assert_eq!(rows[1], "deposit,1,2,1.0,2024-01-01T00:00:00Z");
+        assert_eq!(rows[2], "deposit,1,1,1.0,2024-01-02T00:00:00Z");
+    }
+}
@@ -0,0 +1,294 @@
+// Import profiles describe how a bank's csv export maps onto transactions: which column holds
+// what, how dates are written, whether the amount's sign or a type column tells deposits from
+// withdrawals and what the bank calls each type. Profiles are TOML or YAML files, e.g.
+//
+//     has_headers = true
+//     date_format = "%m/%d/%Y"
+//     sign = "signed"
+//     decimal_separator = "."
+//
+//     [columns]
+//     client = 0
+//     tx = 1
+//     amount = 3
+//     date = 2
+use crate::amount::{Amount, AmountParser};
+use crate::encoding;
+use crate::ids::{ClientId, TxId};
+use crate::interrupt;
+use crate::money::Money;
+use crate::transaction::{Transaction, TransactionType};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use csv::StringRecord;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Zero based column positions
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Columns {
+    #[serde(rename = "type")]
+    pub tx_type: Option<usize>,
+    pub client: usize,
+    pub tx: usize,
+    pub amount: Option<usize>,
+    pub date: Option<usize>,
+    pub memo: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignConvention {
+    // A type column says what each row is, amounts are positive
+    #[default]
+    Typed,
+    // Positive amounts are deposits and negative ones withdrawals
+    Signed,
+    // Negative amounts are deposits and positive ones withdrawals, as on card statements
+    Inverted,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default = "has_headers")]
+    pub has_headers: bool,
+    pub columns: Columns,
+    // chrono format of the date column, dates without a time are taken as midnight UTC
+    pub date_format: Option<String>,
+    #[serde(default)]
+    pub sign: SignConvention,
+    #[serde(default = "decimal_separator")]
+    pub decimal_separator: char,
+    // The bank's names for types, e.g. DEBIT = "withdrawal". Names not listed here must be
+    // the canonical type names
+    #[serde(default)]
+    pub types: HashMap<String, TransactionType>,
+}
+
+fn has_headers() -> bool {
+    true
+}
+
+fn decimal_separator() -> char {
+    '.'
+}
+
+// `name` is a profile file, or the name of one in `dir` ending in .toml, .yaml or .yml
+pub fn load(name: &str, dir: &str) -> Result<Profile, String> {
+    let path = if Path::new(name).is_file() {
+        PathBuf::from(name)
+    } else {
+        ["toml", "yaml", "yml"]
+            .iter()
+            .map(|extension| Path::new(dir).join(format!("{}.{}", name, extension)))
+            .find(|path| path.is_file())
+            .ok_or(format!("No profile {} in {}", name, dir))?
+    };
+
+    let text =
+        fs::read_to_string(&path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    let profile = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => toml::from_str(&text).map_err(|error| error.to_string()),
+        _ => serde_yaml::from_str(&text).map_err(|error| error.to_string()),
+    };
+
+    profile.map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+impl Profile {
+    pub fn read_transactions(&self, file: &str) -> Result<Vec<Transaction>, String> {
+        let input = encoding::open(file).map_err(|error| format!("{}: {}", file, error))?;
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(self.has_headers)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(input);
+
+        let mut transactions = Vec::new();
+        for result in rdr.records() {
+            // Stop reading on Ctrl-C, whatever was read so far still gets processed
+            if interrupt::requested() {
+                break;
+            }
+
+            let record = result.map_err(|error| format!("Error parsing csv line: {}", error))?;
+            let line = record.position().map_or(0, csv::Position::line);
+            let tx = self
+                .transaction(&record)
+                .map_err(|error| format!("Error parsing csv line {}: {}", line, error))?;
+            transactions.push(tx);
+        }
+
+        Ok(transactions)
+    }
+
+    fn transaction(&self, record: &StringRecord) -> Result<Transaction, String> {
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .filter(|value| !value.is_empty())
+        };
+        let required = |column: usize, name: &str| {
+            field(Some(column)).ok_or(format!("Missing {} in column {}", name, column))
+        };
+
+        let client_id = required(self.columns.client, "client")?
+            .parse::<u16>()
+            .map_err(|error| format!("Invalid client: {}", error))?;
+        let tx_id = required(self.columns.tx, "tx")?
+            .parse::<u32>()
+            .map_err(|error| format!("Invalid tx: {}", error))?;
+
+        let parser = AmountParser {
+            decimal_separator: self.decimal_separator,
+        };
+        let amount = field(self.columns.amount)
+            .map(|amount| parser.normalize(amount)?.parse::<Amount>())
+            .transpose()?;
+        let (tx_type, amount) = self.classify(field(self.columns.tx_type), amount)?;
+
+        let mut tx = Transaction::new(tx_type, TxId::new(tx_id), ClientId::new(client_id), amount);
+        if let Some(date) = field(self.columns.date) {
+            tx = tx.with_timestamp(self.parse_date(date)?);
+        }
+        if let Some(memo) = field(self.columns.memo) {
+            tx = tx.with_memo(memo);
+        }
+
+        Ok(tx)
+    }
+
+    // The canonical type and positive amount of a row
+    fn classify(
+        &self,
+        tx_type: Option<&str>,
+        amount: Option<Amount>,
+    ) -> Result<(TransactionType, Option<Amount>), String> {
+        if let Some(name) = tx_type {
+            let tx_type = match self.types.get(name) {
+                Some(tx_type) => tx_type.clone(),
+                None => name.to_lowercase().parse()?,
+            };
+            if self.sign == SignConvention::Typed {
+                return Ok((tx_type, amount));
+            }
+            // Disputes and the like reference a transaction and have no amount to sign
+            if amount.is_none() {
+                return Ok((tx_type, None));
+            }
+        }
+
+        let amount = amount.ok_or("A signed row needs an amount or a type")?;
+        let credit = match self.sign {
+            SignConvention::Inverted => amount.value() < Money::ZERO,
+            _ => amount.value() >= Money::ZERO,
+        };
+        let positive = if amount.value() < Money::ZERO {
+            Amount::new(Money::ZERO.checked_sub(amount.value())?, amount.scale())
+        } else {
+            amount
+        };
+
+        let tx_type = if credit {
+            TransactionType::Deposit
+        } else {
+            TransactionType::Withdrawal
+        };
+        Ok((tx_type, Some(positive)))
+    }
+
+    fn parse_date(&self, date: &str) -> Result<DateTime<Utc>, String> {
+        let invalid = |error: chrono::ParseError| format!("Invalid date {}: {}", date, error);
+
+        match &self.date_format {
+            Some(format) => match NaiveDateTime::parse_from_str(date, format) {
+                Ok(timestamp) => Ok(timestamp.and_utc()),
+                Err(_) => NaiveDate::parse_from_str(date, format)
+                    .map(|day| day.and_time(Default::default()).and_utc())
+                    .map_err(invalid),
+            },
+            None => date.parse().map_err(invalid),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn maps_a_signed_export_onto_transactions() {
+        let profile: Profile = toml::from_str(
+            r#"
+            date_format = "%m/%d/%Y"
+            sign = "signed"
+            decimal_separator = ","
+
+            [columns]
+            client = 0
+            tx = 1
+            date = 2
+            amount = 3
+            type = 4
+
+            [types]
+            CB = "chargeback"
+            "#,
+        )
+        .unwrap();
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "Account,Ref,Date,Amount,Kind\n\
+             7,1,01/31/2024,\"1.000,50\",\n\
+             7,2,02/01/2024,\"-0,50\",\n\
+             7,1,02/02/2024,,CB\n"
+        )
+        .unwrap();
+        let transactions = profile
+            .read_transactions(file.path().to_str().unwrap())
+            .unwrap();
+
+        let summary: Vec<_> = transactions
+            .iter()
+            .map(|tx| {
+                (
+                    tx.tx_type().clone(),
+                    tx.raw_amount().map(|amount| amount.to_string()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (TransactionType::Deposit, Some("1000.5000".to_string())),
+                (TransactionType::Withdrawal, Some("0.5000".to_string())),
+                (TransactionType::Chargeback, None),
+            ]
+        );
+        assert_eq!(
+            transactions[0].timestamp(),
+            Some("2024-01-31T00:00:00Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn loads_yaml_profiles_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("chase.yaml"),
+            "has_headers: false\ncolumns: {type: 0, client: 1, tx: 2, amount: 3}\n",
+        )
+        .unwrap();
+
+        let profile = load("chase", dir.path().to_str().unwrap()).unwrap();
+        assert!(!profile.has_headers);
+        assert_eq!(profile.sign, SignConvention::Typed);
+        assert!(load("citi", dir.path().to_str().unwrap()).is_err());
+    }
+}
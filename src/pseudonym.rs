@@ -0,0 +1,57 @@
+// Keyed hashes of client ids for output shared with people who mustn't see the real ids. The
+// same key always gives a client the same pseudonym, so reports from different runs still join,
+// while without the key the ids can't be recovered by hashing all 65536 of them.
+use crate::ids::ClientId;
+use crate::signature::{self, new_mac, to_hex, HmacSha256};
+use hmac::Mac;
+
+pub const KEY_VARIABLE: &str = "FUNDS_PSEUDONYM_KEY";
+pub const KEY_FILE_VARIABLE: &str = "FUNDS_PSEUDONYM_KEY_FILE";
+
+pub struct Pseudonyms {
+    mac: HmacSha256,
+}
+
+impl Pseudonyms {
+    pub fn new(key: &[u8]) -> Self {
+        Pseudonyms { mac: new_mac(key) }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        let key = signature::read_key(KEY_VARIABLE, KEY_FILE_VARIABLE).ok_or(format!(
+            "Hashing client ids needs a key, set {} or {}",
+            KEY_VARIABLE, KEY_FILE_VARIABLE
+        ))??;
+        Ok(Pseudonyms::new(&key))
+    }
+
+    // The first 64 bits of the HMAC-SHA256 of the id, as 16 hex digits
+    pub fn of(&self, client_id: ClientId) -> String {
+        let mut mac = self.mac.clone();
+        mac.update(&client_id.value().to_be_bytes());
+        to_hex(&mac.finalize().into_bytes()[..8])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_ids_by_key() {
+        let pseudonyms = Pseudonyms::new(b"analytics");
+        assert_eq!(
+            pseudonyms.of(ClientId::new(1)),
+            Pseudonyms::new(b"analytics").of(ClientId::new(1))
+        );
+        assert_eq!(pseudonyms.of(ClientId::new(1)).len(), 16);
+        assert_ne!(
+            pseudonyms.of(ClientId::new(1)),
+            pseudonyms.of(ClientId::new(2))
+        );
+        assert_ne!(
+            pseudonyms.of(ClientId::new(1)),
+            Pseudonyms::new(b"other").of(ClientId::new(1))
+        );
+    }
+}
@@ -0,0 +1,207 @@
+// Publishes results to a message broker so the calculator can run as one node of an event
+// pipeline: every change to an account as it was applied, or each account's final state.
+// Messages are JSON keyed by client id, so brokers that partition by key keep each client's
+// changes in order. Brokers are selected by url scheme, each behind its own cargo feature.
+use crate::client::Clients;
+use crate::ids::ClientId;
+use crate::notify::{Notification, NotificationSink};
+use crate::output::AccountJson;
+use crate::timeseries::{self, BalancePoint};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt;
+
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "nats")]
+mod nats;
+
+#[cfg(feature = "kafka")]
+pub use self::kafka::KafkaPublisher;
+#[cfg(feature = "nats")]
+pub use self::nats::NatsPublisher;
+
+pub trait Publisher: fmt::Debug + Send {
+    fn publish(&mut self, key: &str, payload: &[u8]) -> Result<(), String>;
+    // Waits until everything published was delivered
+    fn flush(&mut self) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PublishMode {
+    // A message per applied transaction with the balances after it
+    Changes,
+    // A message per client with its final balances
+    Snapshots,
+}
+
+#[derive(Debug, Serialize)]
+struct AccountChange {
+    client: ClientId,
+    tx: u32,
+    timestamp: Option<DateTime<Utc>>,
+    available: String,
+    held: String,
+    total: String,
+}
+
+// `url` names the broker and the topic, e.g. kafka://localhost:9092/accounts
+pub fn open(url: &str) -> Result<Box<dyn Publisher>, String> {
+    let (scheme, rest) = url
+        .split_once("://")
+        .ok_or(format!("Unsupported publisher {}", url))?;
+    let (_servers, _topic) = rest
+        .rsplit_once('/')
+        .filter(|(servers, topic)| !servers.is_empty() && !topic.is_empty())
+        .ok_or(format!(
+            "{} has no topic, e.g. {}://host:port/topic",
+            url, scheme
+        ))?;
+
+    match scheme {
+        #[cfg(feature = "kafka")]
+        "kafka" => Ok(Box::new(KafkaPublisher::open(_servers, _topic)?)),
+        #[cfg(not(feature = "kafka"))]
+        "kafka" => Err(feature_disabled(url, "kafka")),
+        #[cfg(feature = "nats")]
+        "nats" => Ok(Box::new(NatsPublisher::open(_servers, _topic)?)),
+        #[cfg(not(feature = "nats"))]
+        "nats" => Err(feature_disabled(url, "nats")),
+        _ => Err(format!("Unsupported publisher {}", url)),
+    }
+}
+
+#[allow(dead_code)]
+fn feature_disabled(url: &str, feature: &str) -> String {
+    format!(
+        "Publisher {} needs the {} feature, rebuild with --features {}",
+        url, feature, feature
+    )
+}
+
+pub fn publish(
+    clients: &Clients,
+    mode: PublishMode,
+    publisher: &mut dyn Publisher,
+) -> Result<(), String> {
+    let encode_error = |error: serde_json::Error| format!("Error encoding message: {}", error);
+
+    match mode {
+        PublishMode::Changes => {
+            for point in timeseries::balance_points(clients) {
+                publish_change(publisher, &point)?;
+            }
+        }
+        PublishMode::Snapshots => {
+            let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+            client_ids.sort();
+
+            for client_id in client_ids {
+                let snapshot = AccountJson::new(*client_id, &clients[client_id]);
+                let payload = serde_json::to_vec(&snapshot).map_err(encode_error)?;
+                publisher.publish(&client_id.to_string(), &payload)?;
+            }
+        }
+    }
+
+    publisher.flush()
+}
+
+pub(crate) fn publish_change(
+    publisher: &mut dyn Publisher,
+    point: &BalancePoint,
+) -> Result<(), String> {
+    let change = AccountChange {
+        client: point.client_id,
+        tx: point.tx_id,
+        timestamp: point.timestamp,
+        available: point.available.to_string(),
+        held: point.held.to_string(),
+        total: point.available.saturating_add(point.held).to_string(),
+    };
+    let payload = serde_json::to_vec(&change)
+        .map_err(|error| format!("Error encoding message: {}", error))?;
+
+    publisher.publish(&point.client_id.to_string(), &payload)
+}
+
+// Publishes notifications as JSON as they're raised, keyed by client
+#[derive(Debug)]
+pub struct PublisherSink(pub Box<dyn Publisher>);
+
+impl NotificationSink for PublisherSink {
+    fn send(&mut self, notification: &Notification) -> Result<(), String> {
+        let client = match notification {
+            Notification::Chargeback { client, .. }
+            | Notification::Locked { client, .. }
+            | Notification::DisputeCapped { client, .. }
+            | Notification::Refund { client, .. } => client,
+        };
+        let payload = serde_json::to_vec(notification)
+            .map_err(|error| format!("Error encoding notification: {}", error))?;
+
+        self.0.publish(&client.to_string(), &payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[derive(Debug, Default)]
+    struct Recorded(Vec<(String, String)>);
+
+    impl Publisher for Recorded {
+        fn publish(&mut self, key: &str, payload: &[u8]) -> Result<(), String> {
+            self.0.push((
+                key.to_string(),
+                String::from_utf8_lossy(payload).into_owned(),
+            ));
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn publishes_changes_and_snapshots() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(0.5)),
+        ];
+        let clients = crate::process_transactions(transactions, EngineConfig::default()).unwrap();
+
+        let mut changes = Recorded::default();
+        publish(&clients, PublishMode::Changes, &mut changes).unwrap();
+        assert_eq!(
+            changes.0[1],
+            (
+                "1".to_string(),
+                r#"{"client":1,"tx":2,"timestamp":null,"available":"1.5000","held":"0.0000","total":"1.5000"}"#
+                    .to_string()
+            )
+        );
+
+        let mut snapshots = Recorded::default();
+        publish(&clients, PublishMode::Snapshots, &mut snapshots).unwrap();
+        assert_eq!(
+            snapshots.0,
+            vec![(
+                "1".to_string(),
+                r#"{"client":1,"available":"1.5000","held":"0.0000","total":"1.5000","locked":false,"frozen":false,"credit_used":"0.0000"}"#
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn needs_a_topic() {
+        assert!(open("kafka://localhost:9092").is_err());
+        assert!(open("mqtt://localhost/accounts").is_err());
+    }
+}
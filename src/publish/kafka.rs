@@ -0,0 +1,62 @@
+use super::Publisher;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{BaseProducer, BaseRecord, Producer};
+use std::fmt;
+use std::time::Duration;
+
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct KafkaPublisher {
+    producer: BaseProducer,
+    topic: String,
+}
+
+impl fmt::Debug for KafkaPublisher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KafkaPublisher")
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
+impl KafkaPublisher {
+    // `servers` is a comma separated list of bootstrap servers
+    pub fn open(servers: &str, topic: &str) -> Result<Self, String> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", servers)
+            .create()
+            .map_err(|error| format!("Error connecting to kafka {}: {}", servers, error))?;
+
+        Ok(KafkaPublisher {
+            producer,
+            topic: topic.to_string(),
+        })
+    }
+}
+
+impl Publisher for KafkaPublisher {
+    fn publish(&mut self, key: &str, payload: &[u8]) -> Result<(), String> {
+        let mut record = BaseRecord::to(&self.topic).key(key).payload(payload);
+
+        loop {
+            match self.producer.send(record) {
+                Ok(()) => return Ok(()),
+                // The local queue is full, serve delivery reports to make room and try again
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned)) => {
+                    self.producer.poll(Duration::from_millis(100));
+                    record = returned;
+                }
+                Err((error, _)) => {
+                    return Err(format!("Error publishing to {}: {}", self.topic, error))
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.producer
+            .flush(FLUSH_TIMEOUT)
+            .map_err(|error| format!("Error flushing {}: {}", self.topic, error))
+    }
+}
@@ -0,0 +1,51 @@
+use super::Publisher;
+use std::fmt;
+use tokio::runtime::Runtime;
+
+// NATS messages have no key, each goes to `<subject>.<key>` instead so subscribers can pick
+// clients with wildcards, e.g. accounts.>
+pub struct NatsPublisher {
+    runtime: Runtime,
+    client: async_nats::Client,
+    subject: String,
+}
+
+impl fmt::Debug for NatsPublisher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NatsPublisher")
+            .field("subject", &self.subject)
+            .finish()
+    }
+}
+
+impl NatsPublisher {
+    pub fn open(server: &str, subject: &str) -> Result<Self, String> {
+        let nats_error = |error: &dyn fmt::Display| format!("NATS {}: {}", server, error);
+        let runtime = Runtime::new().map_err(|error| nats_error(&error))?;
+        let client = runtime
+            .block_on(async_nats::connect(server))
+            .map_err(|error| nats_error(&error))?;
+
+        Ok(NatsPublisher {
+            runtime,
+            client,
+            subject: subject.to_string(),
+        })
+    }
+}
+
+impl Publisher for NatsPublisher {
+    fn publish(&mut self, key: &str, payload: &[u8]) -> Result<(), String> {
+        let subject = format!("{}.{}", self.subject, key);
+
+        self.runtime
+            .block_on(self.client.publish(subject, payload.to_vec().into()))
+            .map_err(|error| format!("Error publishing to {}: {}", self.subject, error))
+    }
+
+    fn flush(&mut self) -> Result<(), String> {
+        self.runtime
+            .block_on(self.client.flush())
+            .map_err(|error| format!("Error flushing {}: {}", self.subject, error))
+    }
+}
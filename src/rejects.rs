@@ -0,0 +1,169 @@
+// Every transaction the engine refused or quarantined with the reason, as a csv that can be
+// reviewed, fixed up and fed back in.
+use crate::engine::Rejection;
+use crate::transaction::Transaction;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::io;
+
+pub fn write_report<W: io::Write>(rejections: &[Rejection], writer: W) -> Result<(), String> {
+    let write_error = |error: csv::Error| format!("Error writing rejects: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    wtr.write_record(["type", "client", "tx", "amount", "code", "error"])
+        .map_err(write_error)?;
+    for rejection in rejections {
+        let tx = &rejection.tx;
+        wtr.write_record([
+            tx.tx_type().to_string(),
+            tx.raw_client_id().to_string(),
+            tx.raw_tx_id().to_string(),
+            tx.raw_amount()
+                .map(|amount| amount.to_string())
+                .unwrap_or_default(),
+            rejection.code().code().to_string(),
+            rejection.error.to_string(),
+        ])
+        .map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing rejects: {}", error))
+}
+
+// The same transactions without the reasons, written as an input csv so they can be replayed
+// once whatever held them up is fixed. Optional columns are only written when a row uses them
+pub fn write_transactions<W: io::Write>(rejections: &[Rejection], writer: W) -> Result<(), String> {
+    let write_error = |error: csv::Error| format!("Error writing quarantine: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    let columns: Vec<&str> = ["type", "client", "tx", "amount"]
+        .into_iter()
+        .chain(OPTIONAL_COLUMNS.into_iter().filter(|column| {
+            rejections
+                .iter()
+                .any(|rejection| !field(&rejection.tx, column).is_empty())
+        }))
+        .collect();
+    wtr.write_record(&columns).map_err(write_error)?;
+    for rejection in rejections {
+        wtr.write_record(columns.iter().map(|column| field(&rejection.tx, column)))
+            .map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing quarantine: {}", error))
+}
+
+const OPTIONAL_COLUMNS: [&str; 6] = [
+    "timestamp",
+    "memo",
+    "tenant",
+    "account",
+    "value_date",
+    "batch",
+];
+
+// Amounts keep the decimals they were written with
+fn field(tx: &Transaction, column: &str) -> String {
+    let date = |date: Option<DateTime<Utc>>| {
+        date.map(|date| date.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            .unwrap_or_default()
+    };
+    match column {
+        "type" => tx.tx_type().to_string(),
+        "client" => tx.raw_client_id().to_string(),
+        "tx" => tx.raw_tx_id().to_string(),
+        "amount" => tx
+            .raw_amount()
+            .map(|amount| format!("{:.*}", usize::from(tx.amount_scale()), amount))
+            .unwrap_or_default(),
+        "timestamp" => date(tx.timestamp()),
+        "memo" => tx.memo().unwrap_or_default().to_string(),
+        "tenant" => tx.tenant().unwrap_or_default().to_string(),
+        "account" => tx.account().unwrap_or_default().to_string(),
+        "value_date" => date(tx.value_date()),
+        "batch" => tx
+            .batch()
+            .map(|batch| batch.to_string())
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{EngineConfig, TransactionEngine, UnknownClientPolicy};
+    use crate::ids::ClientId;
+    use crate::input::CsvSource;
+    use crate::source::InputSource;
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[test]
+    fn lists_rejected_and_quarantined_transactions() {
+        let mut engine = TransactionEngine::new(EngineConfig {
+            unknown_clients: UnknownClientPolicy::Quarantine,
+            ..Default::default()
+        })
+        .with_rejections();
+        for tx in [
+            Transaction::raw(TransactionType::Dispute, 1, 7, None),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(5.0)),
+        ] {
+            engine.push(tx);
+        }
+        assert_eq!(engine.quarantined().len(), 1);
+
+        let (clients, rejections) = engine.finish_with_rejections();
+        assert!(!clients.contains_key(&ClientId::new(7)));
+        let mut output = Vec::new();
+        write_report(&rejections, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "type,client,tx,amount,code,error\n\
+             dispute,7,1,,E1021,\"Quarantined, client 7 has no account\"\n\
+             withdrawal,1,3,5.0000,E1001,Insufficient funds to withdraw 5.0000\n"
+        );
+    }
+
+    #[test]
+    fn quarantines_rows_that_can_be_replayed() {
+        let at = "2024-03-01T09:30:00Z".parse().unwrap();
+        let mut engine = TransactionEngine::new(EngineConfig::default()).with_rejections();
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Chargeback, 1, 1, None),
+            Transaction::raw(TransactionType::Deposit, 2, 1, None)
+                .with_amount("2.50".parse().unwrap())
+                .with_timestamp(at)
+                .with_memo("payroll"),
+            Transaction::raw(TransactionType::Deposit, 3, 2, Some(1.0)),
+            Transaction::raw(TransactionType::Dispute, 9, 2, None),
+        ] {
+            engine.push(tx);
+        }
+        let (_, rejections) = engine.finish_with_rejections();
+
+        let mut output = Vec::new();
+        write_transactions(&rejections, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output.clone()).unwrap(),
+            "type,client,tx,amount,timestamp,memo\n\
+             deposit,1,2,2.50,2024-03-01T09:30:00Z,payroll\n\
+             dispute,2,9,,,\n"
+        );
+
+        let mut source = CsvSource::from_reader(output.as_slice(), Default::default()).unwrap();
+        let replayed = source.next_transaction().unwrap().unwrap();
+        assert_eq!(replayed.amount_scale(), 2);
+        assert_eq!(replayed.timestamp(), Some(at));
+        assert_eq!(replayed.memo(), Some("payroll"));
+        assert_eq!(
+            source.next_transaction().unwrap().unwrap().tx_type(),
+            &TransactionType::Dispute
+        );
+        assert!(source.next_transaction().is_none());
+    }
+}
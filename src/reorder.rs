@@ -0,0 +1,124 @@
+use crate::links;
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Holds up to `window` transactions for a single client and releases deposits, withdrawals and
+// settlements in tx id order. Disputes, resolves, chargebacks and refunds carry the id of the
+// transaction they reference, so they keep their arrival position instead: they're released
+// after everything that arrived before them. Transactions sharing a position keep their arrival
+// order.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReorderBuffer {
+    window: usize,
+    arrivals: u64,
+    pending: BTreeMap<(u32, u64), Transaction>,
+}
+
+impl ReorderBuffer {
+    pub fn new(window: usize) -> Self {
+        ReorderBuffer {
+            window,
+            arrivals: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    // Buffers the tx and, once the window is full, returns the lowest pending one
+    pub fn push(&mut self, tx: Transaction) -> Option<Transaction> {
+        // Only the lowest pending transaction is ever released, so the highest pending id is
+        // at least that of anything that arrived before
+        let position = match links::references_parent(tx.tx_type()) {
+            true => self.pending.last_key_value().map_or(0, |((id, _), _)| *id),
+            false => tx.raw_tx_id(),
+        };
+        self.pending.insert((position, self.arrivals), tx);
+        self.arrivals += 1;
+
+        if self.pending.len() > self.window {
+            self.pending.pop_first().map(|(_, tx)| tx)
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn drain(self) -> impl Iterator<Item = Transaction> {
+        self.pending.into_values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TransactionType;
+
+    #[test]
+    fn releases_lowest_tx_once_window_is_full() {
+        let mut buffer = ReorderBuffer::new(2);
+
+        assert!(buffer
+            .push(Transaction::raw(TransactionType::Deposit, 3, 1, Some(1.0)))
+            .is_none());
+        assert!(buffer
+            .push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(1.0)))
+            .is_none());
+
+        let released = buffer
+            .push(Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)))
+            .unwrap();
+        assert_eq!(released.raw_tx_id(), 1);
+
+        let remaining: Vec<u32> = buffer.drain().map(|tx| tx.raw_tx_id()).collect();
+        assert_eq!(remaining, vec![2, 3]);
+    }
+
+    #[test]
+    fn keeps_arrival_order_for_same_tx_id() {
+        let mut buffer = ReorderBuffer::new(3);
+        buffer.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(1.0)));
+        buffer.push(Transaction::raw(TransactionType::Dispute, 1, 1, None));
+        buffer.push(Transaction::raw(TransactionType::Resolve, 1, 1, None));
+
+        let types: Vec<TransactionType> = buffer.drain().map(|tx| tx.tx_type().clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                TransactionType::Deposit,
+                TransactionType::Dispute,
+                TransactionType::Resolve
+            ]
+        );
+    }
+
+    #[test]
+    fn releases_references_after_what_arrived_before_them() {
+        let mut buffer = ReorderBuffer::new(4);
+        buffer.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)));
+        buffer.push(Transaction::raw(TransactionType::Deposit, 3, 1, Some(1.0)));
+        buffer.push(Transaction::raw(TransactionType::Dispute, 1, 1, None));
+        buffer.push(Transaction::raw(
+            TransactionType::Withdrawal,
+            2,
+            1,
+            Some(5.0),
+        ));
+
+        let order: Vec<(TransactionType, u32)> = buffer
+            .drain()
+            .map(|tx| (tx.tx_type().clone(), tx.raw_tx_id()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (TransactionType::Deposit, 1),
+                (TransactionType::Withdrawal, 2),
+                (TransactionType::Deposit, 3),
+                (TransactionType::Dispute, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+// Interactive prompt for entering transactions by hand, e.g. support staff making manual
+// adjustments. Every line is one command, transactions are applied as soon as they're entered
+// and the engine state can be saved to a snapshot to pick up from later.
+use crate::amount::Amount;
+use crate::client::NumberFormat;
+use crate::engine::TransactionEngine;
+use crate::ids::ClientId;
+use crate::transaction::{Transaction, TransactionType};
+use crate::write_accounts;
+use crate::{interrupt, snapshot};
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+const HELP: &str = "\
+deposit|withdrawal|refund CLIENT TX AMOUNT [MEMO]
+dispute|resolve|chargeback CLIENT TX [MEMO]
+settlement CLIENT TX BATCH [MEMO]
+balance [CLIENT]    accounts of one or every client
+freeze|unfreeze CLIENT
+save [PATH]         write the engine state to PATH or the --snapshot file
+help
+quit";
+
+// Loads the engine saved in `snapshot` when there is one
+pub fn open(snapshot: Option<&str>) -> Result<TransactionEngine, String> {
+    match snapshot {
+        Some(path) if Path::new(path).exists() => {
+            TransactionEngine::from_bytes(&snapshot::read_file(path)?)
+        }
+        _ => Ok(TransactionEngine::default()),
+    }
+}
+
+// Runs commands read from `input` until it ends, `quit` is entered or Ctrl-C is pressed
+pub fn run<R: BufRead, W: Write>(
+    engine: &mut TransactionEngine,
+    input: R,
+    mut output: W,
+    snapshot: Option<&str>,
+) -> Result<(), String> {
+    let write_error = |error: std::io::Error| format!("Error writing to std out: {}", error);
+
+    write!(output, "> ").map_err(write_error)?;
+    output.flush().map_err(write_error)?;
+
+    for line in input.lines() {
+        let line = line.map_err(|error| format!("Error reading input: {}", error))?;
+        if interrupt::requested() {
+            return Ok(());
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let reply = match words.as_slice() {
+            [] => Ok(String::new()),
+            ["quit" | "exit"] => return Ok(()),
+            ["help"] => Ok(HELP.to_string()),
+            ["balance", client] => parse::<ClientId>(client, "client").and_then(|client_id| {
+                engine
+                    .clients()
+                    .get(&client_id)
+                    .map(|client| client.get_record(client_id).join(","))
+                    .ok_or(format!("No client {}", client_id))
+            }),
+            ["balance"] => {
+                let mut accounts = Vec::new();
+                write_accounts(engine.clients(), &mut accounts, NumberFormat::default())
+                    .map(|()| String::from_utf8_lossy(&accounts).trim_end().to_string())
+            }
+            ["freeze", client] => parse::<ClientId>(client, "client")
+                .and_then(|client_id| engine.freeze(client_id))
+                .map(|()| "frozen".to_string()),
+            ["unfreeze", client] => parse::<ClientId>(client, "client")
+                .and_then(|client_id| engine.unfreeze(client_id))
+                .map(|()| "unfrozen".to_string()),
+            ["save", path] => save(engine, path),
+            ["save"] => match snapshot {
+                Some(path) => save(engine, path),
+                None => Err("No --snapshot file, use save PATH".to_string()),
+            },
+            [command, rest @ ..] => transaction(command, rest)
+                .and_then(|tx| engine.submit(tx).map_err(String::from))
+                .map(|()| "ok".to_string()),
+        };
+
+        match reply {
+            Ok(reply) if reply.is_empty() => {}
+            Ok(reply) => writeln!(output, "{}", reply).map_err(write_error)?,
+            Err(error) => writeln!(output, "error: {}", error).map_err(write_error)?,
+        }
+        write!(output, "> ").map_err(write_error)?;
+        output.flush().map_err(write_error)?;
+    }
+
+    Ok(())
+}
+
+fn save(engine: &TransactionEngine, path: &str) -> Result<String, String> {
+    snapshot::write_file(path, engine.to_bytes()?)?;
+    Ok(format!("saved {}", path))
+}
+
+pub(crate) fn parse<T: std::str::FromStr>(text: &str, name: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("Invalid {} {}, see help", name, text))
+}
+
+fn transaction(command: &str, args: &[&str]) -> Result<Transaction, String> {
+    let tx_type: TransactionType = command
+        .parse()
+        .map_err(|_| format!("Unknown command {}, see help", command))?;
+    let references_tx = matches!(
+        tx_type,
+        TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+    );
+
+    // The amount, or the batch of a settlement
+    let (client, tx, value, memo) = match (references_tx, args) {
+        (false, [client, tx, value, memo @ ..]) => (client, tx, Some(value), memo),
+        (true, [client, tx, memo @ ..]) => (client, tx, None, memo),
+        _ => return Err(format!("Missing arguments to {}, see help", command)),
+    };
+
+    let settlement = tx_type == TransactionType::Settlement;
+    let mut tx = Transaction::new(tx_type, parse(tx, "tx")?, parse(client, "client")?, None);
+    match value {
+        Some(batch) if settlement => tx = tx.with_batch(parse(batch, "batch")?),
+        Some(amount) => tx = tx.with_amount(parse::<Amount>(amount, "amount")?),
+        None => {}
+    }
+    if !memo.is_empty() {
+        tx = tx.with_memo(&memo.join(" "));
+    }
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_commands_and_saves_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = dir.path().join("state.bin");
+        let snapshot = snapshot.to_str().unwrap();
+
+        let mut engine = open(Some(snapshot)).unwrap();
+        let input = "deposit 1 1 10.5\n\
+                     withdrawal 1 2 20\n\
+                     dispute 1 1 customer called\n\
+                     balance 1\n\
+                     bogus\n\
+                     save\n\
+                     quit\n\
+                     deposit 1 3 1\n";
+        let mut output = Vec::new();
+        run(&mut engine, input.as_bytes(), &mut output, Some(snapshot)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "> ok\n\
+                 > error: Insufficient funds to withdraw 20.0000\n\
+                 > ok\n\
+                 > 1,0.0000,10.5000,10.5000,false,false,0.0000\n\
+                 > error: Unknown command bogus, see help\n\
+                 > saved {}\n\
+                 > ",
+                snapshot
+            )
+        );
+
+        let restored = open(Some(snapshot)).unwrap();
+        assert!(restored.clients()[&ClientId::new(1)]
+            .disputed_transactions()
+            .contains(&1));
+    }
+}
@@ -0,0 +1,445 @@
+// Reports built from the clients' audit trails once the input has been processed.
+use crate::audit::AuditEntry;
+use crate::client::Clients;
+use crate::ids::ClientId;
+use crate::links::references_parent;
+use crate::load::{self, Loads};
+use crate::money::Money;
+use crate::transaction::TransactionType;
+use chrono::{DateTime, Datelike, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Granularity {
+    Day,
+    Month,
+    Year,
+}
+
+impl Granularity {
+    fn period(&self, timestamp: DateTime<Utc>) -> String {
+        let format = match self {
+            Granularity::Day => "%Y-%m-%d",
+            Granularity::Month => "%Y-%m",
+            Granularity::Year => "%Y",
+        };
+
+        timestamp.format(format).to_string()
+    }
+}
+
+// Money moved in one period, every amount is positive
+#[derive(Debug, Default, PartialEq)]
+pub struct PeriodTotals {
+    pub deposits: Money,
+    pub withdrawals: Money,
+    pub disputes_opened: Money,
+    pub chargebacks: Money,
+}
+
+impl PeriodTotals {
+    fn add(&mut self, entry: &AuditEntry) -> Result<(), String> {
+        match entry.operation {
+            TransactionType::Deposit => {
+                self.deposits = self.deposits.checked_add(entry.available_change)?
+            }
+            TransactionType::Withdrawal => {
+                self.withdrawals = self.withdrawals.checked_sub(entry.available_change)?
+            }
+            TransactionType::Dispute => {
+                self.disputes_opened = self.disputes_opened.checked_add(entry.held_change)?
+            }
+            TransactionType::Chargeback => {
+                self.chargebacks = self.chargebacks.checked_sub(entry.held_change)?
+            }
+            // Settled batches count towards deposits or withdrawals by the sign of their net
+            TransactionType::Settlement if entry.available_change >= Money::ZERO => {
+                self.deposits = self.deposits.checked_add(entry.available_change)?
+            }
+            TransactionType::Settlement => {
+                self.withdrawals = self.withdrawals.checked_sub(entry.available_change)?
+            }
+            TransactionType::Resolve | TransactionType::Refund | TransactionType::Interest => {}
+        }
+
+        Ok(())
+    }
+}
+
+// Totals per client and period, both in ascending order. Needs every applied transaction to have a timestamp
+pub fn period_totals(
+    clients: &Clients,
+    granularity: Granularity,
+) -> Result<BTreeMap<(ClientId, String), PeriodTotals>, String> {
+    let mut totals: BTreeMap<(ClientId, String), PeriodTotals> = BTreeMap::new();
+
+    for (client_id, client) in clients {
+        for entry in client.audit_trail() {
+            let timestamp = entry.timestamp.ok_or(format!(
+                "Tx {} has no timestamp, period reports need a timestamp column",
+                entry.tx_id
+            ))?;
+
+            totals
+                .entry((*client_id, granularity.period(timestamp)))
+                .or_default()
+                .add(entry)?;
+        }
+    }
+
+    Ok(totals)
+}
+
+pub fn write_period_report<W: io::Write>(
+    clients: &Clients,
+    granularity: Granularity,
+    writer: W,
+) -> Result<(), String> {
+    let totals = period_totals(clients, granularity)?;
+    let write_error = |error: csv::Error| format!("Error writing report: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    wtr.write_record([
+        "client",
+        "period",
+        "deposits",
+        "withdrawals",
+        "disputes_opened",
+        "chargebacks",
+    ])
+    .map_err(write_error)?;
+
+    for ((client_id, period), totals) in totals {
+        wtr.write_record([
+            client_id.to_string(),
+            period,
+            totals.deposits.to_string(),
+            totals.withdrawals.to_string(),
+            totals.disputes_opened.to_string(),
+            totals.chargebacks.to_string(),
+        ])
+        .map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing report: {}", error))
+}
+
+// What a client's funds did over one calendar year. The net change covers every operation,
+// refunds and reversed withdrawals included, so it always matches the change of the total
+#[derive(Debug, Default, PartialEq)]
+pub struct YearTotals {
+    pub totals: PeriodTotals,
+    pub net_change: Money,
+}
+
+// Totals per client with activity in `year`, in ascending client order. Needs every applied
+// transaction to have a timestamp
+pub fn year_totals(clients: &Clients, year: i32) -> Result<BTreeMap<ClientId, YearTotals>, String> {
+    let mut totals: BTreeMap<ClientId, YearTotals> = BTreeMap::new();
+
+    for (client_id, client) in clients {
+        for entry in client.audit_trail() {
+            let timestamp = entry.timestamp.ok_or(format!(
+                "Tx {} has no timestamp, tax reports need a timestamp column",
+                entry.tx_id
+            ))?;
+            if timestamp.year() != year {
+                continue;
+            }
+
+            let year_totals = totals.entry(*client_id).or_default();
+            year_totals.totals.add(entry)?;
+            year_totals.net_change = year_totals
+                .net_change
+                .checked_add(entry.available_change)?
+                .checked_add(entry.held_change)?;
+        }
+    }
+
+    Ok(totals)
+}
+
+pub fn write_tax_report<W: io::Write>(
+    clients: &Clients,
+    year: i32,
+    writer: W,
+) -> Result<(), String> {
+    let totals = year_totals(clients, year)?;
+    let write_error = |error: csv::Error| format!("Error writing report: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    wtr.write_record([
+        "client",
+        "year",
+        "deposits",
+        "withdrawals",
+        "net_change",
+        "chargebacks",
+    ])
+    .map_err(write_error)?;
+
+    for (client_id, year_totals) in totals {
+        wtr.write_record([
+            client_id.to_string(),
+            year.to_string(),
+            year_totals.totals.deposits.to_string(),
+            year_totals.totals.withdrawals.to_string(),
+            year_totals.net_change.to_string(),
+            year_totals.totals.chargebacks.to_string(),
+        ])
+        .map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing report: {}", error))
+}
+
+// Every batched deposit and withdrawal with its batch and whether the batch has settled
+pub fn write_settlement_report<W: io::Write>(clients: &Clients, writer: W) -> Result<(), String> {
+    let mut rows = BTreeMap::new();
+    for (client_id, client) in clients {
+        for (tx_id, batch) in client.settled_transactions() {
+            rows.insert((*client_id, *tx_id), (*batch, "settled"));
+        }
+        for (tx_id, batch) in client.unsettled_transactions() {
+            rows.insert((*client_id, *tx_id), (*batch, "unsettled"));
+        }
+    }
+
+    let mut wtr = csv::Writer::from_writer(writer);
+    let write_error = |error: csv::Error| format!("Error writing report: {}", error);
+
+    wtr.write_record(["client", "tx", "batch", "status"])
+        .map_err(write_error)?;
+    for ((client_id, tx_id), (batch, status)) in rows {
+        wtr.write_record([
+            client_id.to_string(),
+            tx_id.to_string(),
+            batch.to_string(),
+            status.to_string(),
+        ])
+        .map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing report: {}", error))
+}
+
+// The `top` clients the engine spent the most time on with their applied transactions, the
+// time spent on them in microseconds, the mean per applied transaction and their share of the
+// time spent on all clients
+pub fn write_hot_clients<W: io::Write>(loads: &Loads, top: usize, writer: W) -> Result<(), String> {
+    let total: Duration = loads.values().map(|load| load.busy).sum();
+
+    let mut wtr = csv::Writer::from_writer(writer);
+    let write_error = |error: csv::Error| format!("Error writing report: {}", error);
+
+    wtr.write_record(["client", "applied", "busy_us", "latency_us", "share"])
+        .map_err(write_error)?;
+    for (client_id, load) in load::hottest(loads, top) {
+        let share = match total.is_zero() {
+            true => 0.0,
+            false => load.busy.as_secs_f64() / total.as_secs_f64() * 100.0,
+        };
+        wtr.write_record([
+            client_id.to_string(),
+            load.applied.to_string(),
+            load.busy.as_micros().to_string(),
+            format!("{:.3}", load.latency().as_secs_f64() * 1e6),
+            format!("{:.2}%", share),
+        ])
+        .map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing report: {}", error))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum GraphFormat {
+    /// Graphviz, render with e.g. `dot -Tsvg`
+    Dot,
+}
+
+// Every disputed or refunded transaction with the chain of operations applied against it, one
+// cluster per client. Transactions nothing referenced are left out to keep the graph readable
+pub fn write_dispute_graph<W: io::Write>(
+    clients: &Clients,
+    format: GraphFormat,
+    writer: W,
+) -> Result<(), String> {
+    match format {
+        GraphFormat::Dot => write_dot_graph(clients, writer),
+    }
+}
+
+fn write_dot_graph<W: io::Write>(clients: &Clients, mut writer: W) -> Result<(), String> {
+    let write_error = |error: io::Error| format!("Error writing report: {}", error);
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+
+    writeln!(writer, "digraph disputes {{").map_err(write_error)?;
+    writeln!(writer, "  node [shape=box];").map_err(write_error)?;
+    for client_id in client_ids {
+        let mut originals: HashMap<u32, &AuditEntry> = HashMap::new();
+        let mut chains: BTreeMap<u32, Vec<&AuditEntry>> = BTreeMap::new();
+        for entry in clients[client_id].audit_trail() {
+            if references_parent(&entry.operation) {
+                chains.entry(entry.tx_id).or_default().push(entry);
+            } else {
+                originals.entry(entry.tx_id).or_insert(entry);
+            }
+        }
+        if chains.is_empty() {
+            continue;
+        }
+
+        writeln!(writer, "  subgraph cluster_{} {{", client_id).map_err(write_error)?;
+        writeln!(writer, "    label=\"client {}\";", client_id).map_err(write_error)?;
+        for (tx_id, chain) in chains {
+            // Clients loaded from a store may not have the original in their audit trail
+            let label = match originals.get(&tx_id) {
+                Some(entry) => format!(
+                    "tx {}\\n{} {}",
+                    tx_id, entry.operation, entry.available_change
+                ),
+                None => format!("tx {}", tx_id),
+            };
+            let mut previous = format!("c{}_tx{}", client_id, tx_id);
+            writeln!(writer, "    {} [label=\"{}\"];", previous, label).map_err(write_error)?;
+
+            for (step, entry) in chain.into_iter().enumerate() {
+                let node = format!("c{}_tx{}_{}", client_id, tx_id, step + 1);
+                let color = match entry.operation {
+                    TransactionType::Chargeback => ", color=red",
+                    _ => "",
+                };
+                writeln!(
+                    writer,
+                    "    {} [label=\"{}\", shape=ellipse{}];",
+                    node, entry.operation, color
+                )
+                .map_err(write_error)?;
+                writeln!(writer, "    {} -> {};", previous, node).map_err(write_error)?;
+                previous = node;
+            }
+        }
+        writeln!(writer, "  }}").map_err(write_error)?;
+    }
+    writeln!(writer, "}}").map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::transaction::Transaction;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        timestamp.parse().unwrap()
+    }
+
+    #[test]
+    fn sums_each_month() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0))
+                .with_timestamp(at("2024-01-05T10:00:00Z")),
+            Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(1.0))
+                .with_timestamp(at("2024-01-20T10:00:00Z")),
+            Transaction::raw(TransactionType::Deposit, 3, 1, Some(2.0))
+                .with_timestamp(at("2024-02-01T10:00:00Z")),
+            Transaction::raw(TransactionType::Dispute, 3, 1, None)
+                .with_timestamp(at("2024-02-02T10:00:00Z")),
+            Transaction::raw(TransactionType::Chargeback, 3, 1, None)
+                .with_timestamp(at("2024-02-03T10:00:00Z")),
+        ];
+        let clients = crate::process_transactions(transactions, EngineConfig::default()).unwrap();
+
+        let mut output = Vec::new();
+        write_period_report(&clients, Granularity::Month, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,period,deposits,withdrawals,disputes_opened,chargebacks\n\
+             1,2024-01,5.0000,1.0000,0.0000,0.0000\n\
+             1,2024-02,2.0000,0.0000,2.0000,2.0000\n"
+        );
+    }
+
+    #[test]
+    fn sums_the_calendar_year() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0))
+                .with_timestamp(at("2023-12-31T23:59:59Z")),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(3.0))
+                .with_timestamp(at("2024-01-01T00:00:00Z")),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(1.0))
+                .with_timestamp(at("2024-06-01T00:00:00Z")),
+            Transaction::raw(TransactionType::Dispute, 2, 1, None)
+                .with_timestamp(at("2024-07-01T00:00:00Z")),
+            Transaction::raw(TransactionType::Chargeback, 2, 1, None)
+                .with_timestamp(at("2024-07-02T00:00:00Z")),
+            Transaction::raw(TransactionType::Deposit, 4, 2, Some(1.0))
+                .with_timestamp(at("2025-01-01T00:00:00Z")),
+        ];
+        let clients = crate::process_transactions(transactions, EngineConfig::default()).unwrap();
+
+        let mut output = Vec::new();
+        write_tax_report(&clients, 2024, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,year,deposits,withdrawals,net_change,chargebacks\n\
+             1,2024,3.0000,1.0000,-1.0000,3.0000\n"
+        );
+    }
+
+    #[test]
+    fn graphs_dispute_chains() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Resolve, 1, 1, None),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+            Transaction::raw(TransactionType::Chargeback, 1, 1, None),
+            Transaction::raw(TransactionType::Deposit, 3, 2, Some(1.0)),
+        ];
+        let clients = crate::process_transactions(transactions, EngineConfig::default()).unwrap();
+
+        let mut output = Vec::new();
+        write_dispute_graph(&clients, GraphFormat::Dot, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "digraph disputes {\n  \
+               node [shape=box];\n  \
+               subgraph cluster_1 {\n    \
+                 label=\"client 1\";\n    \
+                 c1_tx1 [label=\"tx 1\\ndeposit 5.0000\"];\n    \
+                 c1_tx1_1 [label=\"dispute\", shape=ellipse];\n    \
+                 c1_tx1 -> c1_tx1_1;\n    \
+                 c1_tx1_2 [label=\"resolve\", shape=ellipse];\n    \
+                 c1_tx1_1 -> c1_tx1_2;\n    \
+                 c1_tx1_3 [label=\"dispute\", shape=ellipse];\n    \
+                 c1_tx1_2 -> c1_tx1_3;\n    \
+                 c1_tx1_4 [label=\"chargeback\", shape=ellipse, color=red];\n    \
+                 c1_tx1_3 -> c1_tx1_4;\n  \
+               }\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn fails_without_timestamps() {
+        let transactions = vec![Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0))];
+        let clients = crate::process_transactions(transactions, EngineConfig::default()).unwrap();
+
+        assert!(period_totals(&clients, Granularity::Day).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+// Small repros from huge inputs: a seeded random subset of clients with every row of theirs.
+// Clients whose disputes, resolves, chargebacks or refunds reference another client's
+// transaction are kept together, so every reference in the sample still points at its row.
+use crate::csv_reader;
+use crate::encoding::InputReader;
+use crate::generate::XorShift;
+use crate::links::references_parent;
+use crate::transaction::TransactionType;
+use csv::{StringRecord, WriterBuilder};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io;
+
+pub fn sample<W: io::Write>(
+    input: &str,
+    clients: usize,
+    seed: u64,
+    writer: W,
+) -> Result<(), String> {
+    let (all_clients, linked) = read_clients(input)?;
+
+    // Partial Fisher-Yates shuffle, the first `clients` end up picked
+    let mut candidates: Vec<u16> = all_clients.into_iter().collect();
+    let mut random = XorShift(seed.max(1));
+    let picked = clients.min(candidates.len());
+    for index in 0..picked {
+        let other = index + (random.next() % (candidates.len() - index) as u64) as usize;
+        candidates.swap(index, other);
+    }
+
+    let mut sampled = HashSet::new();
+    let mut pending: Vec<u16> = candidates[..picked].to_vec();
+    while let Some(client) = pending.pop() {
+        if sampled.insert(client) {
+            pending.extend(linked.get(&client).into_iter().flatten());
+        }
+    }
+
+    let mut rdr = csv_reader(input)?;
+    let headers = read_headers(&mut rdr)?;
+    let [_, client_column, _] = columns(&headers)?;
+
+    let write_error = |error: csv::Error| format!("Error writing to std out: {}", error);
+    let mut wtr = WriterBuilder::new().flexible(true).from_writer(writer);
+    wtr.write_record(&headers).map_err(write_error)?;
+
+    for result in rdr.records() {
+        let record = result.map_err(|error| format!("Error parsing csv line: {}", error))?;
+        if sampled.contains(&parse(&record, client_column)?) {
+            wtr.write_record(&record).map_err(write_error)?;
+        }
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing to std out: {}", error))
+}
+
+// Every client in the input and the other clients each one references transactions of, both
+// ways round
+type Links = BTreeMap<u16, BTreeSet<u16>>;
+
+fn read_clients(input: &str) -> Result<(BTreeSet<u16>, Links), String> {
+    let mut rdr = csv_reader(input)?;
+    let headers = read_headers(&mut rdr)?;
+    let [type_column, client_column, tx_column] = columns(&headers)?;
+
+    let mut clients = BTreeSet::new();
+    let mut owners: HashMap<u32, u16> = HashMap::new();
+    let mut links = Links::new();
+    for result in rdr.records() {
+        let record = result.map_err(|error| format!("Error parsing csv line: {}", error))?;
+        // Rows of unknown types are left to the engine to reject
+        let references = record
+            .get(type_column)
+            .and_then(|name| name.parse::<TransactionType>().ok())
+            .is_some_and(|tx_type| references_parent(&tx_type));
+        let client = parse(&record, client_column)?;
+        let tx = parse(&record, tx_column)?;
+        clients.insert(client);
+
+        if !references {
+            owners.entry(tx).or_insert(client);
+            continue;
+        }
+        if let Some(&owner) = owners.get(&tx).filter(|owner| **owner != client) {
+            links.entry(client).or_default().insert(owner);
+            links.entry(owner).or_default().insert(client);
+        }
+    }
+
+    Ok((clients, links))
+}
+
+fn read_headers(rdr: &mut csv::Reader<InputReader>) -> Result<StringRecord, String> {
+    rdr.headers()
+        .cloned()
+        .map_err(|error| format!("Error reading csv headers: {}", error))
+}
+
+fn columns(headers: &StringRecord) -> Result<[usize; 3], String> {
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or(format!("Input has no {} column", name))
+    };
+    Ok([column("type")?, column("client")?, column("tx")?])
+}
+
+fn parse<T: std::str::FromStr>(record: &StringRecord, column: usize) -> Result<T, String> {
+    let field = record.get(column).unwrap_or_default();
+    field.parse().map_err(|_| format!("Invalid id {:?}", field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn keeps_referenced_clients_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("txs.csv");
+        fs::write(
+            &input,
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n\
+             deposit,2,2,1.0\n\
+             deposit,3,3,1.0\n\
+             dispute,3,2,\n\
+             withdrawal,1,4,0.5\n",
+        )
+        .unwrap();
+        let input = input.to_string_lossy().into_owned();
+        let pick = |clients, seed| {
+            let mut output = Vec::new();
+            sample(&input, clients, seed, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let samples: HashSet<String> = (1..20).map(|seed| pick(1, seed)).collect();
+        assert_eq!(
+            samples,
+            HashSet::from([
+                "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,4,0.5\n".to_string(),
+                "type,client,tx,amount\ndeposit,2,2,1.0\ndeposit,3,3,1.0\ndispute,3,2,\n"
+                    .to_string(),
+            ])
+        );
+        assert_eq!(pick(1, 7), pick(1, 7));
+        assert_eq!(pick(5, 1).lines().count(), 6);
+    }
+}
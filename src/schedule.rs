@@ -0,0 +1,100 @@
+use crate::transaction::Transaction;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Holds transactions with a value date after the stream's clock, the latest timestamp seen so
+// far, until the clock passes it. Transactions due on the same date keep their arrival order.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    clock: Option<DateTime<Utc>>,
+    arrivals: u64,
+    pending: BTreeMap<(DateTime<Utc>, u64), Transaction>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Schedule::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    // Moves the clock forward to `timestamp` and returns the transactions now due, oldest first
+    pub fn advance(&mut self, timestamp: DateTime<Utc>) -> Vec<Transaction> {
+        if self.clock.is_some_and(|clock| clock >= timestamp) {
+            return Vec::new();
+        }
+        self.clock = Some(timestamp);
+
+        let later = self.pending.split_off(&(timestamp, u64::MAX));
+        std::mem::replace(&mut self.pending, later)
+            .into_values()
+            .collect()
+    }
+
+    // Holds `tx` if its value date is still ahead of the clock, otherwise hands it back.
+    // Without a clock every value dated transaction is in the future
+    pub fn hold(&mut self, tx: Transaction) -> Option<Transaction> {
+        match tx.value_date() {
+            Some(date) if self.clock.is_none_or(|clock| date > clock) => {
+                self.pending.insert((date, self.arrivals), tx);
+                self.arrivals += 1;
+                None
+            }
+            _ => Some(tx),
+        }
+    }
+
+    // Whether `tx` can be applied now, i.e. has no value date or one the clock or its own
+    // timestamp already reached
+    pub fn is_due(&self, tx: &Transaction) -> bool {
+        match tx.value_date() {
+            Some(date) => self
+                .clock
+                .max(tx.timestamp())
+                .is_some_and(|now| date <= now),
+            None => true,
+        }
+    }
+
+    // Every held transaction whatever its date, oldest first
+    pub fn drain(&mut self) -> impl Iterator<Item = Transaction> {
+        std::mem::take(&mut self.pending).into_values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TransactionType;
+
+    fn deposit(tx_id: u32, value_date: &str) -> Transaction {
+        Transaction::raw(TransactionType::Deposit, tx_id, 1, Some(1.0))
+            .with_value_date(value_date.parse().unwrap())
+    }
+
+    #[test]
+    fn releases_transactions_once_the_clock_passes_their_date() {
+        let mut schedule = Schedule::new();
+        schedule.advance("2024-01-01T00:00:00Z".parse().unwrap());
+
+        assert!(schedule.hold(deposit(1, "2024-01-03T00:00:00Z")).is_none());
+        assert!(schedule.hold(deposit(2, "2024-01-02T00:00:00Z")).is_none());
+        assert!(schedule.hold(deposit(3, "2023-12-31T00:00:00Z")).is_some());
+
+        let due: Vec<u32> = schedule
+            .advance("2024-01-02T00:00:00Z".parse().unwrap())
+            .iter()
+            .map(Transaction::raw_tx_id)
+            .collect();
+        assert_eq!(due, vec![2]);
+        assert_eq!(schedule.len(), 1);
+        assert_eq!(schedule.drain().count(), 1);
+    }
+}
@@ -0,0 +1,124 @@
+// Looks up stored transactions by what they are rather than by id, e.g. for support tooling
+// finding a client's disputed deposits over some amount.
+use crate::client::Clients;
+use crate::ids::ClientId;
+use crate::money::Money;
+use crate::transaction::{Transaction, TransactionType};
+use chrono::{DateTime, Utc};
+
+// Every condition set must hold, an empty filter matches every stored transaction
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    // Any of these types, all types when empty
+    pub types: Vec<TransactionType>,
+    pub client: Option<ClientId>,
+    // Inclusive bounds on the amount, transactions without one never match them
+    pub min_amount: Option<Money>,
+    pub max_amount: Option<Money>,
+    // Whether a dispute is open on the transaction
+    pub disputed: Option<bool>,
+    // Inclusive bounds on the timestamp, transactions without one never match them
+    pub from: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+pub trait Search {
+    // Stored transactions matching `filter` in client and tx id order. Only deposits and
+    // withdrawals are stored, those paged out to a history database aren't searched
+    fn search(&self, filter: &Filter) -> Vec<&Transaction>;
+}
+
+impl Search for Clients {
+    fn search(&self, filter: &Filter) -> Vec<&Transaction> {
+        let mut matches: Vec<&Transaction> = self
+            .iter()
+            .filter(|(client_id, _)| filter.client.is_none_or(|client| client == **client_id))
+            .flat_map(|(_, client)| {
+                client.transactions().iter().filter(|(tx_id, tx)| {
+                    let disputed = client.disputed_transactions().contains(tx_id);
+                    filter.matches(tx) && filter.disputed.is_none_or(|wanted| wanted == disputed)
+                })
+            })
+            .map(|(_, tx)| tx)
+            .collect();
+        matches.sort_by_key(|tx| (tx.raw_client_id(), tx.raw_tx_id()));
+        matches
+    }
+}
+
+impl Filter {
+    fn matches(&self, tx: &Transaction) -> bool {
+        let (amount, timestamp) = (tx.raw_amount(), tx.timestamp());
+
+        (self.types.is_empty() || self.types.contains(tx.tx_type()))
+            && self
+                .min_amount
+                .is_none_or(|min| amount.is_some_and(|amount| amount >= min))
+            && self
+                .max_amount
+                .is_none_or(|max| amount.is_some_and(|amount| amount <= max))
+            && self
+                .from
+                .is_none_or(|from| timestamp.is_some_and(|at| at >= from))
+            && self
+                .until
+                .is_none_or(|until| timestamp.is_some_and(|at| at <= until))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::ids::ClientId;
+
+    #[test]
+    fn combines_conditions() {
+        let at = |timestamp: &str| timestamp.parse::<DateTime<Utc>>().unwrap();
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0))
+                .with_timestamp(at("2024-01-01T00:00:00Z")),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(50.0))
+                .with_timestamp(at("2024-02-01T00:00:00Z")),
+            Transaction::raw(TransactionType::Deposit, 3, 2, Some(70.0))
+                .with_timestamp(at("2024-02-01T00:00:00Z")),
+            Transaction::raw(TransactionType::Withdrawal, 4, 2, Some(20.0))
+                .with_timestamp(at("2024-03-01T00:00:00Z")),
+            Transaction::raw(TransactionType::Dispute, 2, 1, None),
+        ];
+        let clients = crate::process_transactions(transactions, EngineConfig::default()).unwrap();
+        let ids = |filter: Filter| -> Vec<u32> {
+            clients
+                .search(&filter)
+                .iter()
+                .map(|tx| tx.raw_tx_id())
+                .collect()
+        };
+
+        assert_eq!(ids(Filter::default()), vec![1, 2, 3, 4]);
+        assert_eq!(
+            ids(Filter {
+                types: vec![TransactionType::Deposit],
+                min_amount: Some("10".parse().unwrap()),
+                ..Default::default()
+            }),
+            vec![2, 3]
+        );
+        assert_eq!(
+            ids(Filter {
+                disputed: Some(true),
+                ..Default::default()
+            }),
+            vec![2]
+        );
+        assert_eq!(
+            ids(Filter {
+                client: Some(ClientId::new(2)),
+                from: Some(at("2024-02-01T00:00:00Z")),
+                until: Some(at("2024-02-28T00:00:00Z")),
+                ..Default::default()
+            }),
+            vec![3]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+// Map-reduce style processing of inputs too big for one run: `split` partitions the rows by
+// client id into shard files that can be processed independently, `merge_accounts` combines
+// the accounts each shard produced. Clients never span shards so no balances need adding up.
+use crate::csv_reader;
+use csv::{StringRecord, Writer, WriterBuilder};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub fn split(input: &str, shards: usize, out_dir: &str) -> Result<Vec<PathBuf>, String> {
+    if shards == 0 {
+        return Err("Need at least one shard to split into".to_string());
+    }
+
+    let mut rdr = csv_reader(input)?;
+    let headers = rdr
+        .headers()
+        .map_err(|error| format!("Error reading csv headers: {}", error))?
+        .clone();
+    let client_column = headers
+        .iter()
+        .position(|header| header == "client")
+        .ok_or("Input has no client column to split on")?;
+
+    let stem = Path::new(input)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "transactions".to_string());
+
+    let mut paths = Vec::new();
+    let mut writers: Vec<Writer<File>> = Vec::new();
+    for shard in 0..shards {
+        let path = Path::new(out_dir).join(format!("{}.shard-{}.csv", stem, shard));
+        let mut wtr = WriterBuilder::new()
+            .flexible(true)
+            .from_path(&path)
+            .map_err(|error| format!("Error creating {}: {}", path.display(), error))?;
+        wtr.write_record(&headers)
+            .map_err(|error| format!("Error writing {}: {}", path.display(), error))?;
+
+        paths.push(path);
+        writers.push(wtr);
+    }
+
+    // Rows are streamed in input order, so every client keeps its own order within its shard
+    for result in rdr.records() {
+        let record = result.map_err(|error| format!("Error parsing csv line: {}", error))?;
+        let client_id = client_id(&record, client_column)?;
+
+        writers[client_id as usize % shards]
+            .write_record(&record)
+            .map_err(|error| format!("Error writing shard: {}", error))?;
+    }
+
+    for wtr in writers.iter_mut() {
+        wtr.flush()
+            .map_err(|error| format!("Error writing shard: {}", error))?;
+    }
+
+    Ok(paths)
+}
+
+pub fn merge_accounts<W: io::Write>(files: &[String], writer: W) -> Result<(), String> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    let mut headers: Option<StringRecord> = None;
+    let mut seen_clients = HashSet::new();
+
+    for file in files {
+        let mut rdr = csv_reader(file)?;
+        let file_headers = rdr
+            .headers()
+            .map_err(|error| format!("Error reading {} headers: {}", file, error))?
+            .clone();
+
+        match &headers {
+            Some(headers) if *headers != file_headers => {
+                return Err(format!(
+                    "{} has different columns than the other shards",
+                    file
+                ))
+            }
+            Some(_) => {}
+            None => {
+                wtr.write_record(&file_headers)
+                    .map_err(|error| format!("Error writing to std out: {}", error))?;
+                headers = Some(file_headers);
+            }
+        }
+
+        for result in rdr.records() {
+            let record = result.map_err(|error| format!("Error parsing {}: {}", file, error))?;
+            let client = record.get(0).unwrap_or_default().to_string();
+
+            if !seen_clients.insert(client.clone()) {
+                return Err(format!("Client {} appears in more than one shard", client));
+            }
+
+            wtr.write_record(&record)
+                .map_err(|error| format!("Error writing to std out: {}", error))?;
+        }
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing to std out: {}", error))
+}
+
+fn client_id(record: &StringRecord, column: usize) -> Result<u16, String> {
+    let field = record.get(column).unwrap_or_default();
+
+    field
+        .parse()
+        .map_err(|error| format!("Invalid client id {:?}: {}", field, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn can_split_by_client_and_merge_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("txs.csv");
+        fs::write(
+            &input,
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n\
+             deposit,2,2,1.0\n\
+             withdrawal,1,3,0.5\n\
+             deposit,3,4,1.0\n",
+        )
+        .unwrap();
+
+        let out_dir = dir.path().to_string_lossy().into_owned();
+        let shards = split(&input.to_string_lossy(), 2, &out_dir).unwrap();
+        assert_eq!(shards.len(), 2);
+        assert_eq!(
+            fs::read_to_string(&shards[1]).unwrap(),
+            "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,3,0.5\ndeposit,3,4,1.0\n"
+        );
+
+        let accounts: Vec<String> = ["1", "2"]
+            .iter()
+            .map(|client| {
+                let path = dir.path().join(format!("accounts-{}.csv", client));
+                fs::write(&path, format!("client,available\n{},1.0000\n", client)).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+
+        let mut merged = Vec::new();
+        merge_accounts(&accounts, &mut merged).unwrap();
+        assert_eq!(
+            String::from_utf8(merged).unwrap(),
+            "client,available\n1,1.0000\n2,1.0000\n"
+        );
+    }
+}
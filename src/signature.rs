@@ -0,0 +1,141 @@
+// Tamper evidence for the accounts output: an HMAC-SHA256 over exactly the bytes written, kept
+// as hex in a detached file so the output itself stays plain csv. Producer and consumers share
+// the key through FUNDS_SIGNING_KEY or a file named by FUNDS_SIGNING_KEY_FILE.
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::io::{self, Write};
+use std::{env, fs};
+
+pub const KEY_VARIABLE: &str = "FUNDS_SIGNING_KEY";
+pub const KEY_FILE_VARIABLE: &str = "FUNDS_SIGNING_KEY_FILE";
+
+pub(crate) type HmacSha256 = Hmac<Sha256>;
+
+pub fn key_from_env() -> Result<Vec<u8>, String> {
+    read_key(KEY_VARIABLE, KEY_FILE_VARIABLE).ok_or(format!(
+        "Signing needs a key, set {} or {}",
+        KEY_VARIABLE, KEY_FILE_VARIABLE
+    ))?
+}
+
+// The secret in `variable`, or else in the file named by `file_variable`, if either is set
+pub(crate) fn read_key(variable: &str, file_variable: &str) -> Option<Result<Vec<u8>, String>> {
+    if let Ok(key) = env::var(variable) {
+        return Some(Ok(key.into_bytes()));
+    }
+
+    let path = env::var(file_variable).ok()?;
+    Some(
+        fs::read_to_string(&path)
+            .map(|key| key.trim().as_bytes().to_vec())
+            .map_err(|error| format!("{}: {}", path, error)),
+    )
+}
+
+// Passes everything through to `inner`, signing it on the way when there is a key
+pub struct SigningWriter<W> {
+    inner: W,
+    mac: Option<HmacSha256>,
+}
+
+impl<W: Write> SigningWriter<W> {
+    pub fn new(inner: W, key: Option<&[u8]>) -> Self {
+        SigningWriter {
+            inner,
+            mac: key.map(new_mac),
+        }
+    }
+
+    // Hex HMAC of everything written so far, if signing
+    pub fn signature(self) -> Option<String> {
+        self.mac.map(|mac| to_hex(&mac.finalize().into_bytes()))
+    }
+}
+
+impl<W: Write> Write for SigningWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(mac) = &mut self.mac {
+            mac.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn sign(bytes: &[u8], key: &[u8]) -> String {
+    let mut mac = new_mac(key);
+    mac.update(bytes);
+    to_hex(&mac.finalize().into_bytes())
+}
+
+// Checks `path` against the signature file written alongside it
+pub fn verify(path: &str, signature_path: &str, key: &[u8]) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+    let signature = fs::read_to_string(signature_path)
+        .map_err(|error| format!("{}: {}", signature_path, error))?;
+    let signature =
+        from_hex(signature.trim()).ok_or(format!("{} is not a hex signature", signature_path))?;
+
+    let mut mac = new_mac(key);
+    mac.update(&bytes);
+    mac.verify_slice(&signature)
+        .map_err(|_| format!("{} doesn't match its signature {}", path, signature_path))
+}
+
+pub(crate) fn new_mac(key: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(key).expect("HMAC accepts every key length")
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() != 64 || !hex.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_what_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let accounts = dir.path().join("accounts.csv");
+        let signature = dir.path().join("accounts.csv.sig");
+        let (accounts, signature) = (accounts.to_str().unwrap(), signature.to_str().unwrap());
+
+        let csv = "client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n";
+        let mut writer = SigningWriter::new(Vec::new(), Some(b"secret"));
+        writer.write_all(csv.as_bytes()).unwrap();
+        let hex = writer.signature().unwrap();
+        assert_eq!(hex, sign(csv.as_bytes(), b"secret"));
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign(b"what do ya want for nothing?", b"Jefe"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        fs::write(accounts, csv).unwrap();
+        fs::write(signature, format!("{}\n", hex)).unwrap();
+        assert_eq!(verify(accounts, signature, b"secret"), Ok(()));
+        assert!(verify(accounts, signature, b"other").is_err());
+
+        fs::write(
+            accounts,
+            "client,available,held,total,locked\n1,9.0000,0.0000,9.0000,false\n",
+        )
+        .unwrap();
+        assert!(verify(accounts, signature, b"secret").is_err());
+    }
+}
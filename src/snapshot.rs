@@ -0,0 +1,229 @@
+// Compact binary encoding of engine state (bincode), used to ship `Clients` or a whole
+// `TransactionEngine` between processes, e.g. from sharded workers to a merger.
+//
+// Bincode isn't self-describing, so state saved to disk is written behind a header with its
+// format version. Loading runs the body through the migrations from that version on before
+// decoding it, so snapshots written by older builds keep loading after the structs change.
+use crate::client::{self, ClientV1, ClientV2, ClientV3, Clients};
+use crate::{compression, encryption};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+
+const MAGIC: [u8; 4] = *b"FCSN";
+
+// Turns the body of one format version into the body of the next
+pub type Migration = fn(Vec<u8>) -> Result<Vec<u8>, String>;
+
+// State saved across builds. A change to its encoding adds a migration from the previous
+// version, the format version is the number of migrations
+pub trait Snapshot: Serialize + DeserializeOwned {
+    // `MIGRATIONS[n]` upgrades version n to n + 1, version 0 is a snapshot without a header
+    const MIGRATIONS: &'static [Migration];
+
+    fn version() -> u16 {
+        Self::MIGRATIONS.len() as u16
+    }
+}
+
+// Version 1 added the header in front of an unchanged body
+pub(crate) fn add_header(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    Ok(body)
+}
+
+// Version 2 added transaction counts to every client
+fn add_client_stats(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let clients: Vec<(u16, ClientV1)> = decode(&body)?;
+    encode(&client::add_stats(clients))
+}
+
+// Version 3 added when each client was first seen, last active and locked
+fn add_client_lifecycle(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let clients: Vec<(u16, ClientV2)> = decode(&body)?;
+    encode(&client::add_lifecycle(clients))
+}
+
+// Version 4 added the tally of the rows applied to each client
+fn add_client_tally(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let clients: Vec<(u16, ClientV3)> = decode(&body)?;
+    encode(&client::add_tally(clients))
+}
+
+impl Snapshot for Clients {
+    const MIGRATIONS: &'static [Migration] = &[
+        add_header,
+        add_client_stats,
+        add_client_lifecycle,
+        add_client_tally,
+    ];
+}
+
+pub fn encode<T: Serialize>(state: &T) -> Result<Vec<u8>, String> {
+    bincode::serialize(state).map_err(|error| format!("Error encoding state: {}", error))
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, String> {
+    bincode::deserialize(bytes).map_err(|error| format!("Error decoding state: {}", error))
+}
+
+// Decodes a value from the start of `bytes` and moves past it, for migrations that only need
+// to change what comes first
+pub(crate) fn decode_from<T: DeserializeOwned>(bytes: &mut &[u8]) -> Result<T, String> {
+    bincode::deserialize_from(bytes).map_err(|error| format!("Error decoding state: {}", error))
+}
+
+pub fn encode_versioned<T: Snapshot>(state: &T) -> Result<Vec<u8>, String> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&T::version().to_le_bytes());
+    bytes.extend(encode(state)?);
+    Ok(bytes)
+}
+
+pub fn decode_versioned<T: Snapshot>(bytes: &[u8]) -> Result<T, String> {
+    let (version, body) = match bytes.strip_prefix(&MAGIC) {
+        Some([low, high, body @ ..]) => (u16::from_le_bytes([*low, *high]), body),
+        Some(_) => return Err("Snapshot header is truncated".to_string()),
+        None => (0, bytes),
+    };
+    if version > T::version() {
+        return Err(format!(
+            "Snapshot is format version {}, this build reads up to version {}",
+            version,
+            T::version()
+        ));
+    }
+
+    let mut body = body.to_vec();
+    for migration in &T::MIGRATIONS[usize::from(version)..] {
+        body = migration(body)?;
+    }
+    decode(&body)
+}
+
+// Saved state on disk, compressed with the zstd feature and encrypted while a key is set, see
+// `compression` and `encryption`
+pub fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+    open(bytes).map_err(|error| format!("{}: {}", path, error))
+}
+
+pub fn write_file(path: &str, bytes: Vec<u8>) -> Result<(), String> {
+    fs::write(path, seal(bytes)?).map_err(|error| format!("{}: {}", path, error))
+}
+
+pub(crate) fn seal(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    encryption::seal(compression::compress(bytes)?)
+}
+
+pub(crate) fn open(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    compression::decompress(encryption::open(bytes)?)
+}
+
+pub fn encode_clients(clients: &Clients) -> Result<Vec<u8>, String> {
+    encode_versioned(clients)
+}
+
+pub fn decode_clients(bytes: &[u8]) -> Result<Clients, String> {
+    decode_versioned(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::ids::{ClientId, TxId};
+    use crate::transaction::{Transaction, TransactionType};
+    use serde::Deserialize;
+
+    #[test]
+    fn loads_older_snapshots() {
+        let clients = Clients::from_iter([(
+            ClientId::new(1),
+            Client::new(
+                TxId::new(1),
+                Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            ),
+        )]);
+        let versioned = encode_clients(&clients).unwrap();
+        assert_eq!(&versioned[..6], b"FCSN\x04\x00");
+        let loaded = decode_clients(&versioned).unwrap();
+        assert_eq!(loaded[&ClientId::new(1)].stats().deposits, 1);
+        assert_eq!(
+            loaded[&ClientId::new(1)].lifecycle(),
+            clients[&ClientId::new(1)].lifecycle()
+        );
+        assert_eq!(
+            loaded[&ClientId::new(1)].tally().totals(),
+            clients[&ClientId::new(1)].tally().totals()
+        );
+
+        // The only client's tally comes last, its lifecycle before that: first seen and last
+        // active at tx 1 without a timestamp and never locked, and its five counts before that
+        let body = encode(&clients).unwrap();
+        let version_3_body =
+            &body[..body.len() - encode(clients[&ClientId::new(1)].tally()).unwrap().len()];
+        let version_2_body = &version_3_body[..version_3_body.len() - (6 + 6 + 1)];
+        let legacy = version_2_body[..version_2_body.len() - 5 * 8].to_vec();
+        let mut version_1 = b"FCSN\x01\x00".to_vec();
+        version_1.extend(&legacy);
+        let mut version_2 = b"FCSN\x02\x00".to_vec();
+        version_2.extend(version_2_body);
+        let mut version_3 = b"FCSN\x03\x00".to_vec();
+        version_3.extend(version_3_body);
+
+        let loaded = decode_clients(&version_3).unwrap();
+        assert_eq!(
+            loaded[&ClientId::new(1)].lifecycle(),
+            clients[&ClientId::new(1)].lifecycle()
+        );
+        assert_eq!(
+            loaded[&ClientId::new(1)].tally().totals(),
+            &Default::default()
+        );
+
+        let loaded = decode_clients(&version_2).unwrap();
+        assert_eq!(loaded[&ClientId::new(1)].stats().deposits, 1);
+        assert_eq!(loaded[&ClientId::new(1)].lifecycle(), &Default::default());
+        for bytes in [legacy, version_1] {
+            let loaded = decode_clients(&bytes).unwrap();
+            assert_eq!(
+                loaded[&ClientId::new(1)].funds(),
+                clients[&ClientId::new(1)].funds()
+            );
+            assert_eq!(loaded[&ClientId::new(1)].stats(), &Default::default());
+            assert_eq!(loaded[&ClientId::new(1)].lifecycle(), &Default::default());
+        }
+    }
+
+    // Version 1 stored a balance in whole units, version 2 in cents
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Balance {
+        cents: u64,
+    }
+
+    fn to_cents(body: Vec<u8>) -> Result<Vec<u8>, String> {
+        let units: u64 = decode(&body)?;
+        encode(&(units * 100))
+    }
+
+    impl Snapshot for Balance {
+        const MIGRATIONS: &'static [Migration] = &[add_header, to_cents];
+    }
+
+    #[test]
+    fn migrates_older_versions() {
+        let mut version_1 = b"FCSN\x01\x00".to_vec();
+        version_1.extend(encode(&3_u64).unwrap());
+        assert_eq!(
+            decode_versioned::<Balance>(&version_1),
+            Ok(Balance { cents: 300 })
+        );
+
+        let mut version_3 = b"FCSN\x03\x00".to_vec();
+        version_3.extend(encode(&3_u64).unwrap());
+        assert_eq!(
+            decode_versioned::<Balance>(&version_3),
+            Err("Snapshot is format version 3, this build reads up to version 2".to_string())
+        );
+    }
+}
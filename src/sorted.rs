@@ -0,0 +1,22 @@
+// Serializes hash maps and sets in key order, so the same state always encodes to the same bytes
+// whatever order its entries were inserted in, e.g. for `replay` to compare engines byte by byte.
+// They decode as before, snapshots written unsorted still load.
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+pub fn map<K, V, S, Z>(map: &HashMap<K, V, S>, serializer: Z) -> Result<Z::Ok, Z::Error>
+where
+    K: Ord + Serialize,
+    V: Serialize,
+    Z: Serializer,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+pub fn set<T, S, Z>(set: &HashSet<T, S>, serializer: Z) -> Result<Z::Ok, Z::Error>
+where
+    T: Ord + Serialize,
+    Z: Serializer,
+{
+    set.iter().collect::<BTreeSet<_>>().serialize(serializer)
+}
@@ -0,0 +1,32 @@
+// Where transactions come from. A source hands out one transaction at a time, so new backends,
+// e.g. Kafka, HTTP or S3, only implement `InputSource` and run through `process_source` like
+// csv files, std in or transactions already in memory.
+use crate::transaction::Transaction;
+use std::vec;
+
+pub trait InputSource {
+    // None once the source is exhausted, an error stops processing
+    fn next_transaction(&mut self) -> Option<Result<Transaction, String>>;
+}
+
+impl<S: InputSource + ?Sized> InputSource for Box<S> {
+    fn next_transaction(&mut self) -> Option<Result<Transaction, String>> {
+        (**self).next_transaction()
+    }
+}
+
+// Transactions built by the caller, e.g. in tests or from another system's API
+#[derive(Debug, Default)]
+pub struct MemorySource(vec::IntoIter<Transaction>);
+
+impl From<Vec<Transaction>> for MemorySource {
+    fn from(transactions: Vec<Transaction>) -> Self {
+        MemorySource(transactions.into_iter())
+    }
+}
+
+impl InputSource for MemorySource {
+    fn next_transaction(&mut self) -> Option<Result<Transaction, String>> {
+        self.0.next().map(Ok)
+    }
+}
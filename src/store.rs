@@ -0,0 +1,42 @@
+// Persistent homes for client state outside the process. Stores are selected by url scheme
+// and each backend sits behind its own cargo feature so the default build stays lean.
+use crate::client::Clients;
+
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "postgres")]
+pub use self::postgres::PostgresStore;
+#[cfg(feature = "redis")]
+pub use self::redis::RedisStore;
+
+pub trait ClientStore {
+    fn load(&mut self) -> Result<Clients, String>;
+    fn save(&mut self, clients: &Clients) -> Result<(), String>;
+}
+
+pub fn open(url: &str) -> Result<Box<dyn ClientStore>, String> {
+    let scheme = url.split_once("://").map(|(scheme, _)| scheme);
+
+    match scheme {
+        #[cfg(feature = "postgres")]
+        Some("postgres" | "postgresql") => Ok(Box::new(PostgresStore::open(url)?)),
+        #[cfg(not(feature = "postgres"))]
+        Some("postgres" | "postgresql") => Err(feature_disabled(url, "postgres")),
+        #[cfg(feature = "redis")]
+        Some("redis" | "rediss") => Ok(Box::new(RedisStore::open(url)?)),
+        #[cfg(not(feature = "redis"))]
+        Some("redis" | "rediss") => Err(feature_disabled(url, "redis")),
+        _ => Err(format!("Unsupported store {}", url)),
+    }
+}
+
+#[allow(dead_code)]
+fn feature_disabled(url: &str, feature: &str) -> String {
+    format!(
+        "Store {} needs the {} feature, rebuild with --features {}",
+        url, feature, feature
+    )
+}
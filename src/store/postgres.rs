@@ -0,0 +1,217 @@
+// Persists clients, their transactions and dispute status in PostgreSQL, turning a run into
+// a durable settlement step. The schema lives in `migrations/` and is applied on open.
+use super::ClientStore;
+use crate::amount::Amount;
+use crate::client::{Client, Clients, DisputedTransactions, Funds, Holds, Refunds, Transactions};
+use crate::ids::ClientId;
+use crate::money::Money;
+use crate::transaction::Transaction;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::Row;
+use tokio::runtime::Runtime;
+
+pub struct PostgresStore {
+    runtime: Runtime,
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub fn open(url: &str) -> Result<Self, String> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|error| format!("Error starting postgres runtime: {}", error))?;
+
+        let pool = runtime.block_on(async {
+            let pool = PgPoolOptions::new()
+                .max_connections(1)
+                .connect(url)
+                .await
+                .map_err(postgres_error)?;
+            sqlx::migrate!()
+                .run(&pool)
+                .await
+                .map_err(|error| format!("Error migrating postgres store: {}", error))?;
+
+            Ok::<PgPool, String>(pool)
+        })?;
+
+        Ok(PostgresStore { runtime, pool })
+    }
+
+    async fn load_clients(&self) -> Result<Clients, String> {
+        let mut transactions: std::collections::HashMap<
+            ClientId,
+            (Transactions, DisputedTransactions, Refunds, Holds),
+        > = std::collections::HashMap::new();
+
+        let rows = sqlx::query(
+            "SELECT client_id, tx_id, tx_type, amount, amount_scale, timestamp, memo, disputed, refunded,
+                capped_hold
+             FROM transactions",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(postgres_error)?;
+
+        for row in rows {
+            let client_id = client_id(row.get("client_id"))?;
+            let tx_id = tx_id(row.get("tx_id"))?;
+            let tx_type: String = row.get("tx_type");
+            let timestamp: Option<DateTime<Utc>> = row.get("timestamp");
+
+            let amount: Option<i64> = row.get("amount");
+            let amount_scale: i16 = row.get("amount_scale");
+
+            let mut tx = Transaction::raw(tx_type.parse()?, tx_id, client_id.value(), None);
+            if let Some(amount) = amount {
+                tx = tx.with_amount(Amount::new(
+                    Money::from_minor_units(amount),
+                    u8::try_from(amount_scale).unwrap_or(u8::MAX),
+                ));
+            }
+            if let Some(timestamp) = timestamp {
+                tx = tx.with_timestamp(timestamp);
+            }
+            if let Some(memo) = row.get::<Option<String>, _>("memo") {
+                tx = tx.with_memo(&memo);
+            }
+
+            let (client_transactions, disputed, refunds, capped_holds) =
+                transactions.entry(client_id).or_default();
+            client_transactions.insert(tx_id, tx);
+            if row.get::<bool, _>("disputed") {
+                disputed.insert(tx_id);
+            }
+            let refunded: i64 = row.get("refunded");
+            if refunded != 0 {
+                refunds.insert(tx_id, Money::from_minor_units(refunded));
+            }
+            if let Some(held) = row.get::<Option<i64>, _>("capped_hold") {
+                capped_holds.insert(tx_id, Money::from_minor_units(held));
+            }
+        }
+
+        let rows =
+            sqlx::query("SELECT client_id, available, held, locked, frozen, past_tx FROM clients")
+                .fetch_all(&self.pool)
+                .await
+                .map_err(postgres_error)?;
+
+        let mut clients = Clients::default();
+        for row in rows {
+            let client_id = client_id(row.get("client_id"))?;
+            let (client_transactions, disputed, refunds, capped_holds) =
+                transactions.remove(&client_id).unwrap_or_default();
+
+            clients.insert(
+                client_id,
+                Client::from_parts(
+                    Funds::from_balances(
+                        Money::from_minor_units(row.get("available")),
+                        Money::from_minor_units(row.get("held")),
+                    ),
+                    client_transactions,
+                    disputed,
+                    tx_id(row.get("past_tx"))?,
+                    row.get("locked"),
+                )
+                .with_frozen(row.get("frozen"))
+                .with_refunds(refunds)
+                .with_capped_holds(capped_holds),
+            );
+        }
+
+        Ok(clients)
+    }
+
+    // Upserts everything inside one database transaction so a failed save leaves the previous state intact
+    async fn save_clients(&self, clients: &Clients) -> Result<(), String> {
+        let mut db = self.pool.begin().await.map_err(postgres_error)?;
+
+        for (client_id, client) in clients {
+            sqlx::query(
+                "INSERT INTO clients (client_id, available, held, locked, frozen, past_tx)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (client_id) DO UPDATE SET
+                    available = EXCLUDED.available,
+                    held = EXCLUDED.held,
+                    locked = EXCLUDED.locked,
+                    frozen = EXCLUDED.frozen,
+                    past_tx = EXCLUDED.past_tx",
+            )
+            .bind(i32::from(client_id.value()))
+            .bind(client.funds().available().minor_units())
+            .bind(client.funds().held().minor_units())
+            .bind(client.is_locked())
+            .bind(client.is_frozen())
+            .bind(i64::from(client.past_tx()))
+            .execute(&mut *db)
+            .await
+            .map_err(postgres_error)?;
+
+            for (tx_id, tx) in client.transactions() {
+                sqlx::query(
+                    "INSERT INTO transactions
+                        (client_id, tx_id, tx_type, amount, amount_scale, timestamp, memo, disputed, refunded,
+                         capped_hold)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                     ON CONFLICT (client_id, tx_id) DO UPDATE SET
+                        disputed = EXCLUDED.disputed,
+                        refunded = EXCLUDED.refunded,
+                        capped_hold = EXCLUDED.capped_hold",
+                )
+                .bind(i32::from(client_id.value()))
+                .bind(i64::from(*tx_id))
+                .bind(tx.tx_type().to_string())
+                .bind(tx.raw_amount().map(|amount| amount.minor_units()))
+                .bind(i16::from(tx.amount_scale()))
+                .bind(tx.timestamp())
+                .bind(tx.memo())
+                .bind(client.disputed_transactions().contains(tx_id))
+                .bind(
+                    client
+                        .refunds()
+                        .get(tx_id)
+                        .map_or(0, |refunded| refunded.minor_units()),
+                )
+                .bind(
+                    client
+                        .capped_holds()
+                        .get(tx_id)
+                        .map(|held| held.minor_units()),
+                )
+                .execute(&mut *db)
+                .await
+                .map_err(postgres_error)?;
+            }
+        }
+
+        db.commit().await.map_err(postgres_error)
+    }
+}
+
+impl ClientStore for PostgresStore {
+    fn load(&mut self) -> Result<Clients, String> {
+        self.runtime.block_on(self.load_clients())
+    }
+
+    fn save(&mut self, clients: &Clients) -> Result<(), String> {
+        self.runtime.block_on(self.save_clients(clients))
+    }
+}
+
+fn client_id(value: i32) -> Result<ClientId, String> {
+    u16::try_from(value)
+        .map(ClientId::new)
+        .map_err(|_| format!("Stored client id {} is out of range", value))
+}
+
+fn tx_id(value: i64) -> Result<u32, String> {
+    u32::try_from(value).map_err(|_| format!("Stored tx id {} is out of range", value))
+}
+
+fn postgres_error(error: sqlx::Error) -> String {
+    format!("Postgres store error: {}", error)
+}
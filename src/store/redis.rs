@@ -0,0 +1,173 @@
+// Keeps client state in Redis so several engine instances can share it and it survives restarts.
+//
+// Layout, under a configurable prefix (default "funds"):
+//   {prefix}:clients                     set of client ids
+//   {prefix}:client:{id}                 hash of available, held, locked, frozen and past_tx
+//   {prefix}:client:{id}:disputed        set of disputed tx ids
+//   {prefix}:client:{id}:transactions    hash of tx id to the bincode encoded transaction
+//   {prefix}:client:{id}:refunds         hash of deposit tx id to the amount refunded so far
+//   {prefix}:client:{id}:capped_holds    hash of disputed tx id to the amount a capped dispute holds
+use super::ClientStore;
+use crate::client::{Client, Clients, DisputedTransactions, Funds, Holds, Refunds, Transactions};
+use crate::ids::ClientId;
+use crate::snapshot;
+use redis::Commands;
+use std::collections::HashMap;
+
+const DEFAULT_PREFIX: &str = "funds";
+
+pub struct RedisStore {
+    connection: redis::Connection,
+    prefix: String,
+}
+
+impl RedisStore {
+    pub fn open(url: &str) -> Result<Self, String> {
+        Self::with_prefix(url, DEFAULT_PREFIX)
+    }
+
+    pub fn with_prefix(url: &str, prefix: &str) -> Result<Self, String> {
+        let connection = redis::Client::open(url)
+            .and_then(|client| client.get_connection())
+            .map_err(redis_error)?;
+
+        Ok(RedisStore {
+            connection,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn clients_key(&self) -> String {
+        format!("{}:clients", self.prefix)
+    }
+
+    fn client_key(&self, client_id: ClientId) -> String {
+        format!("{}:client:{}", self.prefix, client_id)
+    }
+
+    fn load_client(&mut self, client_id: ClientId) -> Result<Client, String> {
+        let key = self.client_key(client_id);
+
+        let fields: HashMap<String, String> = self.connection.hgetall(&key).map_err(redis_error)?;
+        let disputed: Vec<u32> = self
+            .connection
+            .smembers(format!("{}:disputed", key))
+            .map_err(redis_error)?;
+        let encoded: HashMap<u32, Vec<u8>> = self
+            .connection
+            .hgetall(format!("{}:transactions", key))
+            .map_err(redis_error)?;
+
+        let refunded: HashMap<u32, String> = self
+            .connection
+            .hgetall(format!("{}:refunds", key))
+            .map_err(redis_error)?;
+        let capped: HashMap<u32, String> = self
+            .connection
+            .hgetall(format!("{}:capped_holds", key))
+            .map_err(redis_error)?;
+
+        let mut transactions = Transactions::default();
+        for (tx_id, bytes) in encoded {
+            transactions.insert(tx_id, snapshot::decode(&bytes)?);
+        }
+
+        let mut refunds = Refunds::default();
+        for (tx_id, amount) in refunded {
+            refunds.insert(tx_id, amount.parse()?);
+        }
+
+        let mut capped_holds = Holds::default();
+        for (tx_id, amount) in capped {
+            capped_holds.insert(tx_id, amount.parse()?);
+        }
+
+        Ok(Client::from_parts(
+            Funds::from_balances(field(&fields, "available")?, field(&fields, "held")?),
+            transactions,
+            disputed.into_iter().collect::<DisputedTransactions>(),
+            field(&fields, "past_tx")?,
+            field(&fields, "locked")?,
+        )
+        .with_frozen(field(&fields, "frozen").unwrap_or(false))
+        .with_refunds(refunds)
+        .with_capped_holds(capped_holds))
+    }
+}
+
+impl ClientStore for RedisStore {
+    fn load(&mut self) -> Result<Clients, String> {
+        let client_ids: Vec<u16> = self
+            .connection
+            .smembers(self.clients_key())
+            .map_err(redis_error)?;
+
+        let mut clients = Clients::default();
+        for client_id in client_ids.into_iter().map(ClientId::new) {
+            clients.insert(client_id, self.load_client(client_id)?);
+        }
+
+        Ok(clients)
+    }
+
+    // Writes every client in one MULTI/EXEC so other instances never see a half saved book
+    fn save(&mut self, clients: &Clients) -> Result<(), String> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+
+        for (client_id, client) in clients {
+            let key = self.client_key(*client_id);
+            let disputed_key = format!("{}:disputed", key);
+            let transactions_key = format!("{}:transactions", key);
+            let refunds_key = format!("{}:refunds", key);
+            let capped_holds_key = format!("{}:capped_holds", key);
+
+            pipe.sadd(self.clients_key(), client_id.value()).ignore();
+            pipe.hset_multiple(
+                &key,
+                &[
+                    ("available", client.funds().available().to_string()),
+                    ("held", client.funds().held().to_string()),
+                    ("locked", client.is_locked().to_string()),
+                    ("frozen", client.is_frozen().to_string()),
+                    ("past_tx", client.past_tx().to_string()),
+                ],
+            )
+            .ignore();
+
+            pipe.del(&disputed_key).ignore();
+            for tx_id in client.disputed_transactions() {
+                pipe.sadd(&disputed_key, *tx_id).ignore();
+            }
+
+            pipe.del(&capped_holds_key).ignore();
+            for (tx_id, held) in client.capped_holds() {
+                pipe.hset(&capped_holds_key, *tx_id, held.to_string())
+                    .ignore();
+            }
+
+            for (tx_id, refunded) in client.refunds() {
+                pipe.hset(&refunds_key, *tx_id, refunded.to_string())
+                    .ignore();
+            }
+
+            for (tx_id, tx) in client.transactions() {
+                pipe.hset(&transactions_key, *tx_id, snapshot::encode(tx)?)
+                    .ignore();
+            }
+        }
+
+        pipe.query::<()>(&mut self.connection).map_err(redis_error)
+    }
+}
+
+fn field<T: std::str::FromStr>(fields: &HashMap<String, String>, name: &str) -> Result<T, String> {
+    fields
+        .get(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or(format!("Stored client is missing a valid {}", name))
+}
+
+fn redis_error(error: redis::RedisError) -> String {
+    format!("Redis store error: {}", error)
+}
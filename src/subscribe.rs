@@ -0,0 +1,148 @@
+// Takes transactions from a message broker instead of a file, one JSON transaction per message,
+// e.g. {"type":"deposit","client":1,"tx":1,"amount":"2.5"}. Amounts are strings so they keep
+// their exact decimals. Messages are applied as they arrive until Ctrl-C is pressed or the
+// subscription ends. Brokers are selected by url scheme, each behind its own cargo feature.
+use crate::client::Clients;
+use crate::engine::TransactionEngine;
+use crate::publish::{self, Publisher};
+use crate::timeseries::BalancePoint;
+use crate::transaction::Transaction;
+
+#[cfg(feature = "amqp")]
+mod amqp;
+#[cfg(feature = "nats")]
+mod nats;
+
+// `url` names the broker and the subject or queue, e.g. nats://localhost:4222/transactions. Every change
+// to an account is published to `changes` as soon as it's made. Returns the clients and the
+// number of messages received
+pub fn consume(
+    engine: TransactionEngine,
+    url: &str,
+    changes: Option<Box<dyn Publisher>>,
+) -> Result<(Clients, u64), String> {
+    let (scheme, rest) = url
+        .split_once("://")
+        .ok_or(format!("Unsupported subscription {}", url))?;
+    let (_server, _subject) = rest
+        .rsplit_once('/')
+        .filter(|(server, subject)| !server.is_empty() && !subject.is_empty())
+        .ok_or(format!(
+            "{} has no subject, e.g. {}://host:port/subject",
+            url, scheme
+        ))?;
+
+    match scheme {
+        #[cfg(feature = "amqp")]
+        "amqp" => amqp::consume(engine, _server, _subject, changes),
+        #[cfg(not(feature = "amqp"))]
+        "amqp" => {
+            drop((engine, changes));
+            Err(format!(
+                "Subscription {} needs the amqp feature, rebuild with --features amqp",
+                url
+            ))
+        }
+        #[cfg(feature = "nats")]
+        "nats" => nats::consume(engine, _server, _subject, changes),
+        #[cfg(not(feature = "nats"))]
+        "nats" => {
+            drop((engine, changes));
+            Err(format!(
+                "Subscription {} needs the nats feature, rebuild with --features nats",
+                url
+            ))
+        }
+        _ => Err(format!("Unsupported subscription {}", url)),
+    }
+}
+
+// Applies the transaction in `payload`, returning the changes it made to the client's balances
+#[allow(dead_code)]
+fn apply_message(
+    engine: &mut TransactionEngine,
+    payload: &[u8],
+) -> Result<Vec<BalancePoint>, String> {
+    let tx: Transaction = serde_json::from_slice(payload)
+        .map_err(|error| format!("Invalid transaction message: {}", error))?;
+    // Joint accounts and routed references change another client than the message names
+    let changes = engine.submit_with_changes(tx)?;
+
+    Ok(changes
+        .entries
+        .iter()
+        .map(|entry| BalancePoint::new(changes.client_id, entry))
+        .collect())
+}
+
+#[allow(dead_code)]
+fn publish_changes(
+    changes: Option<&mut (dyn Publisher + '_)>,
+    points: &[BalancePoint],
+) -> Result<(), String> {
+    if let Some(publisher) = changes {
+        for point in points {
+            publish::publish_change(publisher, point)?;
+        }
+        publisher.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{CrossClientPolicy, EngineConfig};
+    use crate::ids::ClientId;
+    use std::collections::HashMap;
+
+    #[test]
+    fn applies_messages_and_returns_their_changes() {
+        let mut engine = TransactionEngine::new(EngineConfig::default());
+
+        let changes = apply_message(
+            &mut engine,
+            br#"{"type":"deposit","client":1,"tx":1,"amount":"2.5"}"#,
+        )
+        .unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].tx_id, 1);
+        assert_eq!(changes[0].available.to_string(), "2.5000");
+
+        assert!(apply_message(
+            &mut engine,
+            br#"{"type":"withdrawal","client":1,"tx":2,"amount":"9"}"#,
+        )
+        .is_err());
+        assert!(apply_message(&mut engine, b"deposit,1,3,1.0").is_err());
+        assert_eq!(engine.clients()[&ClientId::new(1)].audit_trail().len(), 1);
+    }
+
+    #[test]
+    fn reports_changes_to_the_account_actually_changed() {
+        let mut engine = TransactionEngine::new(EngineConfig {
+            joint_accounts: HashMap::from([(ClientId::new(2), ClientId::new(1))]),
+            cross_client: CrossClientPolicy::Route,
+            ..Default::default()
+        });
+
+        let changes = apply_message(
+            &mut engine,
+            br#"{"type":"deposit","client":2,"tx":1,"amount":"2.5"}"#,
+        )
+        .unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].client_id, ClientId::new(1));
+
+        apply_message(
+            &mut engine,
+            br#"{"type":"deposit","client":3,"tx":2,"amount":"1"}"#,
+        )
+        .unwrap();
+        let changes =
+            apply_message(&mut engine, br#"{"type":"dispute","client":3,"tx":1}"#).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].client_id, ClientId::new(1));
+        assert_eq!(changes[0].held.to_string(), "2.5000");
+    }
+}
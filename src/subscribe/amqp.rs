@@ -0,0 +1,81 @@
+// Each message is acked once the engine has applied it. Invalid and rejected transactions are
+// nacked without requeueing, so a queue with a dead letter exchange hands them on to it
+use super::{apply_message, publish_changes};
+use crate::client::Clients;
+use crate::engine::TransactionEngine;
+use crate::interrupt;
+use crate::publish::Publisher;
+use futures_util::StreamExt;
+use lapin::options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions};
+use lapin::types::FieldTable;
+use lapin::{Connection, ConnectionProperties};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+// How long to wait for a message before checking for Ctrl-C again
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+// Unacked messages the broker sends ahead
+const PREFETCH: u16 = 100;
+
+pub fn consume(
+    mut engine: TransactionEngine,
+    server: &str,
+    queue: &str,
+    mut changes: Option<Box<dyn Publisher>>,
+) -> Result<(Clients, u64), String> {
+    let amqp_error = |error: &dyn std::fmt::Display| format!("AMQP {}: {}", server, error);
+    let runtime = Runtime::new().map_err(|error| amqp_error(&error))?;
+
+    let uri = format!("amqp://{}", server);
+    let connection = runtime
+        .block_on(Connection::connect(&uri, ConnectionProperties::default()))
+        .map_err(|error| amqp_error(&error))?;
+    let channel = runtime
+        .block_on(connection.create_channel())
+        .map_err(|error| amqp_error(&error))?;
+    runtime
+        .block_on(channel.basic_qos(PREFETCH, BasicQosOptions::default()))
+        .map_err(|error| amqp_error(&error))?;
+    let mut consumer = runtime
+        .block_on(channel.basic_consume(
+            queue,
+            "funds_calculator",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        ))
+        .map_err(|error| amqp_error(&error))?;
+
+    let mut received = 0;
+    while !interrupt::requested() {
+        let delivery = match runtime.block_on(tokio::time::timeout(POLL_INTERVAL, consumer.next()))
+        {
+            Ok(Some(delivery)) => delivery.map_err(|error| amqp_error(&error))?,
+            Ok(None) => break,
+            Err(_) => continue,
+        };
+
+        received += 1;
+        let settled = match apply_message(&mut engine, &delivery.data) {
+            Ok(points) => {
+                if let Err(error) = publish_changes(changes.as_deref_mut(), &points) {
+                    eprintln!("error publishing changes: {}", error);
+                }
+                runtime.block_on(delivery.ack(BasicAckOptions::default()))
+            }
+            Err(error) => {
+                eprintln!("error handling message: {}", error);
+                let options = BasicNackOptions {
+                    multiple: false,
+                    requeue: false,
+                };
+                runtime.block_on(delivery.nack(options))
+            }
+        };
+        settled.map_err(|error| amqp_error(&error))?;
+    }
+
+    runtime
+        .block_on(connection.close(0, "done"))
+        .map_err(|error| amqp_error(&error))?;
+    Ok((engine.finish(), received))
+}
@@ -0,0 +1,47 @@
+use super::{apply_message, publish_changes};
+use crate::client::Clients;
+use crate::engine::TransactionEngine;
+use crate::interrupt;
+use crate::publish::Publisher;
+use futures_util::StreamExt;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+// How long to wait for a message before checking for Ctrl-C again
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub fn consume(
+    mut engine: TransactionEngine,
+    server: &str,
+    subject: &str,
+    mut changes: Option<Box<dyn Publisher>>,
+) -> Result<(Clients, u64), String> {
+    let nats_error = |error: &dyn std::fmt::Display| format!("NATS {}: {}", server, error);
+    let runtime = Runtime::new().map_err(|error| nats_error(&error))?;
+
+    let client = runtime
+        .block_on(async_nats::connect(server))
+        .map_err(|error| nats_error(&error))?;
+    let mut subscriber = runtime
+        .block_on(client.subscribe(subject.to_string()))
+        .map_err(|error| nats_error(&error))?;
+
+    let mut received = 0;
+    while !interrupt::requested() {
+        let message = match runtime.block_on(tokio::time::timeout(POLL_INTERVAL, subscriber.next()))
+        {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(_) => continue,
+        };
+
+        received += 1;
+        let applied = apply_message(&mut engine, &message.payload)
+            .and_then(|points| publish_changes(changes.as_deref_mut(), &points));
+        if let Err(error) = applied {
+            eprintln!("error handling message: {}", error);
+        }
+    }
+
+    Ok((engine.finish(), received))
+}
@@ -0,0 +1,64 @@
+// Figures about a whole run, shown above the accounts by the html and markdown outputs.
+use crate::client::Clients;
+use crate::engine::{Rejection, TransactionEngine};
+use crate::money::Money;
+use crate::transaction::Transaction;
+
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub processed: u64,
+    pub rejected: usize,
+    pub clients: usize,
+    pub locked: usize,
+    pub frozen: usize,
+    pub available: Money,
+    pub held: Money,
+}
+
+impl Summary {
+    pub fn new(clients: &Clients, processed: u64, rejected: usize) -> Result<Self, String> {
+        let mut available = Money::ZERO;
+        let mut held = Money::ZERO;
+        for client in clients.values() {
+            available = available.checked_add(client.funds().available())?;
+            held = held.checked_add(client.funds().held())?;
+        }
+
+        Ok(Summary {
+            processed,
+            rejected,
+            clients: clients.len(),
+            locked: clients.values().filter(|client| client.is_locked()).count(),
+            frozen: clients.values().filter(|client| client.is_frozen()).count(),
+            available,
+            held,
+        })
+    }
+
+    // Label and value of every figure, in the order they're shown
+    pub fn rows(&self) -> Result<Vec<(&'static str, String)>, String> {
+        Ok(vec![
+            ("Transactions processed", self.processed.to_string()),
+            ("Transactions rejected", self.rejected.to_string()),
+            ("Clients", self.clients.to_string()),
+            ("Locked accounts", self.locked.to_string()),
+            ("Frozen accounts", self.frozen.to_string()),
+            ("Total available", self.available.to_string()),
+            ("Total held", self.held.to_string()),
+            ("Total", self.available.checked_add(self.held)?.to_string()),
+        ])
+    }
+}
+
+// Processes `transactions` keeping the rejected ones for the summary
+pub fn process(
+    engine: TransactionEngine,
+    transactions: impl IntoIterator<Item = Transaction>,
+) -> (Clients, Vec<Rejection>) {
+    let mut engine = engine.with_rejections();
+    for tx in transactions {
+        engine.push(tx);
+    }
+
+    engine.finish_with_rejections()
+}
@@ -0,0 +1,68 @@
+// The accounts as an aligned table for people reading the result in a terminal.
+use crate::client::{Clients, NumberFormat};
+use crate::ids::ClientId;
+use comfy_table::{CellAlignment, Table};
+use std::io;
+
+const HEADERS: [&str; 7] = [
+    "client",
+    "available",
+    "held",
+    "total",
+    "locked",
+    "frozen",
+    "credit_used",
+];
+
+pub fn write_accounts<W: io::Write>(
+    clients: &Clients,
+    format: NumberFormat,
+    mut writer: W,
+) -> Result<(), String> {
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+
+    let mut table = Table::new();
+    table.set_header(HEADERS);
+    for client_id in client_ids {
+        table.add_row(clients[client_id].get_record_with_format(*client_id, format));
+    }
+    // Numbers line up on the right, the flags on the left
+    for (index, column) in table.column_iter_mut().enumerate() {
+        if !matches!(HEADERS[index], "locked" | "frozen") {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
+    }
+
+    writeln!(writer, "{}", table).map_err(|error| format!("Error writing to std out: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[test]
+    fn aligns_the_columns() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 2, Some(1.5)),
+            Transaction::raw(TransactionType::Deposit, 2, 10, Some(1500.0)),
+        ];
+        let clients = crate::process_transactions(transactions, EngineConfig::default()).unwrap();
+
+        let mut output = Vec::new();
+        write_accounts(&clients, NumberFormat::default(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "+--------+-----------+--------+-----------+--------+--------+-------------+\n\
+             | client | available |   held |     total | locked | frozen | credit_used |\n\
+             +=========================================================================+\n\
+             |      2 |    1.5000 | 0.0000 |    1.5000 | false  | false  |      0.0000 |\n\
+             |--------+-----------+--------+-----------+--------+--------+-------------|\n\
+             |     10 | 1500.0000 | 0.0000 | 1500.0000 | false  | false  |      0.0000 |\n\
+             +--------+-----------+--------+-----------+--------+--------+-------------+\n"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+// Lets one run process several partners' data. Rows are grouped by their `tenant` and every tenant
+// is processed by its own engine, so client ids of different tenants never share an account.
+use crate::client::{Clients, NumberFormat};
+use crate::engine::EngineConfig;
+use crate::transaction::Transaction;
+use std::collections::BTreeMap;
+use std::io;
+
+pub type TenantClients = BTreeMap<String, Clients>;
+
+// Rows without a tenant belong to `default_tenant`, or to the unnamed tenant "" without one
+pub fn group_by_tenant(
+    transactions: impl IntoIterator<Item = Transaction>,
+    default_tenant: Option<&str>,
+) -> BTreeMap<String, Vec<Transaction>> {
+    let mut tenants: BTreeMap<String, Vec<Transaction>> = BTreeMap::new();
+
+    for tx in transactions {
+        let tenant = tx.tenant().or(default_tenant).unwrap_or_default();
+        match tenants.get_mut(tenant) {
+            Some(transactions) => transactions.push(tx),
+            None => {
+                tenants.insert(tenant.to_string(), vec![tx]);
+            }
+        }
+    }
+
+    tenants
+}
+
+pub fn process(
+    transactions: impl IntoIterator<Item = Transaction>,
+    default_tenant: Option<&str>,
+    config: EngineConfig,
+    workers: usize,
+) -> Result<TenantClients, String> {
+    let mut tenants = TenantClients::default();
+
+    for (tenant, transactions) in group_by_tenant(transactions, default_tenant) {
+        let clients =
+            crate::process_transactions_concurrently(transactions, config.clone(), workers)?;
+        tenants.insert(tenant, clients);
+    }
+
+    Ok(tenants)
+}
+
+// Same columns as the accounts output with the tenant in front, grouped by tenant
+pub fn write_tenant_funds<W: io::Write>(
+    tenants: TenantClients,
+    writer: W,
+    format: NumberFormat,
+) -> Result<(), String> {
+    let write_error = |error: csv::Error| format!("Error writing to std out: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    wtr.write_record([
+        "tenant",
+        "client",
+        "available",
+        "held",
+        "total",
+        "locked",
+        "frozen",
+        "credit_used",
+    ])
+    .map_err(write_error)?;
+
+    for (tenant, clients) in tenants {
+        for (client_id, client) in clients {
+            let mut record = vec![tenant.clone()];
+            record.extend(client.get_record_with_format(client_id, format));
+            wtr.write_record(&record).map_err(write_error)?;
+        }
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing to std out: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TransactionType;
+
+    #[test]
+    fn keeps_tenants_apart() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)).with_tenant("acme"),
+            Transaction::raw(TransactionType::Deposit, 2, 1, Some(5.0)).with_tenant("globex"),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(1.0)),
+        ];
+
+        let tenants = process(transactions, Some("acme"), EngineConfig::default(), 1).unwrap();
+
+        let mut output = Vec::new();
+        write_tenant_funds(tenants, &mut output, NumberFormat::default()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "tenant,client,available,held,total,locked,frozen,credit_used\n\
+             acme,1,1.0000,0.0000,1.0000,false,false,0.0000\n\
+             globex,1,5.0000,0.0000,5.0000,false,false,0.0000\n"
+        );
+    }
+}
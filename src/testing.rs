@@ -0,0 +1,141 @@
+// Helpers for end-to-end regression tests: run a transactions csv through the full
+// pipeline and compare the produced accounts with an expected (golden) accounts csv.
+use crate::engine::EngineConfig;
+use crate::{parse_transactions, process_transactions, write_client_funds_to};
+use std::collections::BTreeMap;
+
+// Differences smaller than this are treated as formatting, e.g. "1.5" vs "1.5000"
+const AMOUNT_TOLERANCE: f64 = 0.00005;
+
+type AccountRow = BTreeMap<String, String>;
+
+// Processes `input` and returns the accounts csv that would have been written to std out
+pub fn run_fixture(input: &str, config: EngineConfig) -> Result<String, String> {
+    let transactions = parse_transactions(input.to_string())?;
+    let clients = process_transactions(transactions, config)?;
+
+    let mut output = Vec::new();
+    write_client_funds_to(clients, &mut output)?;
+
+    String::from_utf8(output).map_err(|error| error.to_string())
+}
+
+// Compares two accounts csvs ignoring row order and number formatting.
+// Rows are matched on their client column and every column must agree.
+pub fn compare_accounts(actual: &str, expected: &str) -> Result<(), String> {
+    let actual = accounts_by_client(actual)?;
+    let expected = accounts_by_client(expected)?;
+
+    let mut differences = Vec::new();
+
+    for (client, expected_row) in &expected {
+        match actual.get(client) {
+            Some(actual_row) => {
+                for (column, expected_value) in expected_row {
+                    let actual_value = actual_row.get(column).map(String::as_str);
+                    if !values_match(actual_value, expected_value) {
+                        differences.push(format!(
+                            "client {}: {} is {:?}, expected {:?}",
+                            client, column, actual_value, expected_value
+                        ));
+                    }
+                }
+                for column in actual_row.keys().filter(|c| !expected_row.contains_key(*c)) {
+                    differences.push(format!("client {}: unexpected column {}", client, column));
+                }
+            }
+            None => differences.push(format!("client {}: missing from output", client)),
+        }
+    }
+
+    for client in actual
+        .keys()
+        .filter(|client| !expected.contains_key(*client))
+    {
+        differences.push(format!("client {}: not in expected output", client));
+    }
+
+    if differences.is_empty() {
+        Ok(())
+    } else {
+        Err(differences.join("\n"))
+    }
+}
+
+// Runs `input` with the default config and panics with every difference if the
+// accounts don't match the `expected` accounts file
+pub fn assert_golden(input: &str, expected: &str) {
+    assert_golden_with_config(input, expected, EngineConfig::default())
+}
+
+pub fn assert_golden_with_config(input: &str, expected: &str, config: EngineConfig) {
+    let actual = run_fixture(input, config)
+        .unwrap_or_else(|error| panic!("failed to process {}: {}", input, error));
+    let expected_accounts = std::fs::read_to_string(expected)
+        .unwrap_or_else(|error| panic!("failed to read {}: {}", expected, error));
+
+    if let Err(differences) = compare_accounts(&actual, &expected_accounts) {
+        panic!("{} does not match {}:\n{}", input, expected, differences);
+    }
+}
+
+fn accounts_by_client(accounts: &str) -> Result<BTreeMap<String, AccountRow>, String> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(accounts.as_bytes());
+    let headers = rdr
+        .headers()
+        .map_err(|error| format!("Error reading accounts headers: {}", error))?
+        .clone();
+
+    let mut rows = BTreeMap::new();
+    for result in rdr.records() {
+        let record = result.map_err(|error| format!("Error reading accounts line: {}", error))?;
+        let row: AccountRow = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, value)| (header.to_string(), value.to_string()))
+            .collect();
+        let client = row
+            .get("client")
+            .cloned()
+            .ok_or("Accounts are missing a client column")?;
+
+        rows.insert(client, row);
+    }
+
+    Ok(rows)
+}
+
+fn values_match(actual: Option<&str>, expected: &str) -> bool {
+    match actual {
+        Some(actual) => match (actual.parse::<f64>(), expected.parse::<f64>()) {
+            (Ok(actual), Ok(expected)) => (actual - expected).abs() < AMOUNT_TOLERANCE,
+            _ => actual == expected,
+        },
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_row_order_and_precision() {
+        let actual = "client,available,held,total,locked\n2,1.0000,0.0000,1.0000,false\n1,0.5000,0.0000,0.5000,false\n";
+        let expected = "client,available,held,total,locked\n1,0.5,0,0.5,false\n2,1,0,1,false\n";
+
+        assert!(compare_accounts(actual, expected).is_ok());
+    }
+
+    #[test]
+    fn reports_mismatched_and_missing_clients() {
+        let actual = "client,available,held,total,locked\n1,0.5000,0.0000,0.5000,true\n";
+        let expected = "client,available,held,total,locked\n1,0.5,0,0.5,false\n2,1,0,1,false\n";
+
+        let differences = compare_accounts(actual, expected).unwrap_err();
+        assert!(differences.contains("client 1: locked"));
+        assert!(differences.contains("client 2: missing from output"));
+    }
+}
@@ -0,0 +1,127 @@
+// Exports each client's balance after every applied transaction as a time series, e.g. for
+// plotting or anomaly detection. Written as csv, or as Parquet with the parquet feature.
+use crate::audit::AuditEntry;
+use crate::client::Clients;
+use crate::ids::ClientId;
+use crate::money::Money;
+use chrono::{DateTime, Utc};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+#[cfg(feature = "parquet")]
+mod parquet;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalancePoint {
+    pub client_id: ClientId,
+    pub tx_id: u32,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub available: Money,
+    pub held: Money,
+}
+
+impl BalancePoint {
+    // Balances of `client_id` right after `entry` was applied
+    pub fn new(client_id: ClientId, entry: &AuditEntry) -> Self {
+        BalancePoint {
+            client_id,
+            tx_id: entry.tx_id,
+            timestamp: entry.timestamp,
+            available: entry.available,
+            held: entry.held,
+        }
+    }
+}
+
+// Ordered by client, then by the order the transactions were applied in
+pub fn balance_points(clients: &Clients) -> Vec<BalancePoint> {
+    let mut client_ids: Vec<&ClientId> = clients.keys().collect();
+    client_ids.sort();
+
+    client_ids
+        .into_iter()
+        .flat_map(|client_id| {
+            clients[client_id]
+                .audit_trail()
+                .iter()
+                .map(|entry| BalancePoint::new(*client_id, entry))
+        })
+        .collect()
+}
+
+// Picks the format from the extension, `.parquet` files are written as Parquet and anything else as csv
+pub fn write(clients: &Clients, path: &str) -> Result<(), String> {
+    let points = balance_points(clients);
+    let file = File::create(path).map_err(|error| format!("{}: {}", path, error))?;
+
+    match Path::new(path).extension() {
+        Some(extension) if extension == "parquet" => write_parquet(&points, file),
+        _ => write_csv(&points, file),
+    }
+}
+
+pub fn write_csv<W: io::Write>(points: &[BalancePoint], writer: W) -> Result<(), String> {
+    let write_error = |error: csv::Error| format!("Error writing balance history: {}", error);
+    let mut wtr = csv::Writer::from_writer(writer);
+
+    wtr.write_record(["client", "tx", "timestamp", "available", "held", "total"])
+        .map_err(write_error)?;
+
+    for point in points {
+        wtr.write_record([
+            point.client_id.to_string(),
+            point.tx_id.to_string(),
+            point
+                .timestamp
+                .map(|timestamp| timestamp.to_rfc3339())
+                .unwrap_or_default(),
+            point.available.to_string(),
+            point.held.to_string(),
+            point.available.saturating_add(point.held).to_string(),
+        ])
+        .map_err(write_error)?;
+    }
+
+    wtr.flush()
+        .map_err(|error| format!("Error writing balance history: {}", error))
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(points: &[BalancePoint], file: File) -> Result<(), String> {
+    parquet::write(points, file)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_points: &[BalancePoint], _file: File) -> Result<(), String> {
+    Err("Writing Parquet needs the parquet feature, rebuild with --features parquet".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::EngineConfig;
+    use crate::transaction::{Transaction, TransactionType};
+
+    #[test]
+    fn has_a_point_per_applied_transaction() {
+        let transactions = vec![
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(5.0)),
+            Transaction::raw(TransactionType::Withdrawal, 2, 1, Some(9.0)),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(1.0)),
+            Transaction::raw(TransactionType::Dispute, 1, 1, None),
+        ];
+        let clients = crate::process_transactions(transactions, EngineConfig::default()).unwrap();
+
+        let mut output = Vec::new();
+        write_csv(&balance_points(&clients), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,tx,timestamp,available,held,total\n\
+             1,1,,5.0000,0.0000,5.0000\n\
+             1,3,,4.0000,0.0000,4.0000\n\
+             1,1,,-1.0000,5.0000,4.0000\n"
+        );
+    }
+}
@@ -0,0 +1,105 @@
+// Balances are stored as DECIMAL(18, 4) over their 1/10000 units, timestamps as UTC milliseconds
+use super::BalancePoint;
+use parquet::data_type::{Int32Type, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use std::fs::File;
+use std::sync::Arc;
+
+const SCHEMA: &str = "
+message balance_history {
+    REQUIRED INT32 client (INTEGER(16, false));
+    REQUIRED INT32 tx (INTEGER(32, false));
+    OPTIONAL INT64 timestamp (TIMESTAMP(MILLIS, true));
+    REQUIRED INT64 available (DECIMAL(18, 4));
+    REQUIRED INT64 held (DECIMAL(18, 4));
+}
+";
+
+pub fn write(points: &[BalancePoint], file: File) -> Result<(), String> {
+    write_columns(points, file).map_err(|error| format!("Error writing parquet: {}", error))
+}
+
+fn write_columns(points: &[BalancePoint], file: File) -> Result<(), ParquetError> {
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let mut writer =
+        SerializedFileWriter::new(file, schema, Arc::new(WriterProperties::builder().build()))?;
+    let mut row_group = writer.next_row_group()?;
+
+    let clients: Vec<i32> = points
+        .iter()
+        .map(|p| i32::from(p.client_id.value()))
+        .collect();
+    // Unsigned 32 bit values are stored in INT32 columns with the same bits
+    let tx_ids: Vec<i32> = points.iter().map(|p| p.tx_id as i32).collect();
+    let timestamps: Vec<i64> = points
+        .iter()
+        .filter_map(|p| p.timestamp.map(|timestamp| timestamp.timestamp_millis()))
+        .collect();
+    let timestamp_levels: Vec<i16> = points
+        .iter()
+        .map(|p| i16::from(p.timestamp.is_some()))
+        .collect();
+    let available: Vec<i64> = points.iter().map(|p| p.available.minor_units()).collect();
+    let held: Vec<i64> = points.iter().map(|p| p.held.minor_units()).collect();
+
+    let mut column = 0;
+    while let Some(mut writer) = row_group.next_column()? {
+        match column {
+            0 => writer
+                .typed::<Int32Type>()
+                .write_batch(&clients, None, None)?,
+            1 => writer
+                .typed::<Int32Type>()
+                .write_batch(&tx_ids, None, None)?,
+            2 => writer.typed::<Int64Type>().write_batch(
+                &timestamps,
+                Some(&timestamp_levels),
+                None,
+            )?,
+            3 => writer
+                .typed::<Int64Type>()
+                .write_batch(&available, None, None)?,
+            _ => writer.typed::<Int64Type>().write_batch(&held, None, None)?,
+        };
+        writer.close()?;
+        column += 1;
+    }
+
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ids::ClientId;
+    use crate::money::Money;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn writes_a_row_per_point() {
+        let point = BalancePoint {
+            client_id: ClientId::new(1),
+            tx_id: u32::MAX,
+            timestamp: None,
+            available: Money::from_minor_units(15_000),
+            held: Money::ZERO,
+        };
+        let file = tempfile::tempfile().unwrap();
+        write(&[point.clone(), point], file.try_clone().unwrap()).unwrap();
+
+        let reader = SerializedFileReader::new(file).unwrap();
+        let rows: Vec<String> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().to_string())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].contains("tx: 4294967295"), "{}", rows[0]);
+        assert!(rows[0].contains("available: 1.5000"), "{}", rows[0]);
+    }
+}
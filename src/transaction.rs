@@ -1,6 +1,12 @@
-use serde::Deserialize;
+use crate::amount::Amount;
+use crate::ids::{ClientId, TxId};
+use crate::money::Money;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -8,9 +14,50 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    // Returns part or all of an earlier deposit, `tx` is the deposit being refunded
+    Refund,
+    // Moves the net of every deposit and withdrawal in `batch` at once, `tx` is its own id
+    Settlement,
+    // Credited by the engine when it accrues interest, never read from the input
+    Interest,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Refund => "refund",
+            TransactionType::Settlement => "settlement",
+            TransactionType::Interest => "interest",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TransactionType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "deposit" => Ok(TransactionType::Deposit),
+            "withdrawal" => Ok(TransactionType::Withdrawal),
+            "dispute" => Ok(TransactionType::Dispute),
+            "resolve" => Ok(TransactionType::Resolve),
+            "chargeback" => Ok(TransactionType::Chargeback),
+            "refund" => Ok(TransactionType::Refund),
+            "settlement" => Ok(TransactionType::Settlement),
+            "interest" => Ok(TransactionType::Interest),
+            _ => Err(format!("Unknown transaction type {}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Transaction {
     #[serde(rename = "type")]
     tx_type: TransactionType,
@@ -18,32 +65,155 @@ pub struct Transaction {
     client_id: u16,
     #[serde(rename = "tx")]
     tx_id: u32,
-    amount: Option<f32>,
+    amount: Option<Amount>,
+    timestamp: Option<DateTime<Utc>>,
+    // Free text passed through from the input, never affects balances
+    memo: Option<String>,
+    // Partner the row belongs to, client ids are only unique within a tenant
+    tenant: Option<String>,
+    // Sub-account of the client, e.g. checking or savings
+    account: Option<String>,
+    // Date the transaction takes effect, it's held back until the stream reaches it
+    value_date: Option<DateTime<Utc>>,
+    // Settlement batch of a deposit or withdrawal, it only moves funds once the batch is settled
+    batch: Option<u32>,
 }
 
 impl Transaction {
-    pub fn new(tx_type: TransactionType, tx_id: u32, client_id: u16, amount: Option<f32>) -> Self {
+    pub fn new(
+        tx_type: TransactionType,
+        tx_id: TxId,
+        client_id: ClientId,
+        amount: Option<Amount>,
+    ) -> Self {
         Transaction {
             tx_type,
-            tx_id,
-            client_id,
+            tx_id: tx_id.value(),
+            client_id: client_id.value(),
             amount,
+            timestamp: None,
+            memo: None,
+            tenant: None,
+            account: None,
+            value_date: None,
+            batch: None,
         }
     }
 
+    // Replaces the amount, keeping the scale it was written with
+    pub fn with_amount(mut self, amount: Amount) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    // Moves the transaction to another client, e.g. a dispute sent under the wrong client id
+    pub(crate) fn with_client_id(mut self, client_id: ClientId) -> Self {
+        self.client_id = client_id.value();
+        self
+    }
+
+    pub fn with_value_date(mut self, value_date: DateTime<Utc>) -> Self {
+        self.value_date = Some(value_date);
+        self
+    }
+
+    pub fn with_batch(mut self, batch: u32) -> Self {
+        self.batch = Some(batch);
+        self
+    }
+
+    pub fn with_memo(mut self, memo: &str) -> Self {
+        self.memo = Some(memo.to_string());
+        self
+    }
+
+    pub fn with_tenant(mut self, tenant: &str) -> Self {
+        self.tenant = Some(tenant.to_string());
+        self
+    }
+
+    pub fn with_account(mut self, account: &str) -> Self {
+        self.account = Some(account.to_string());
+        self
+    }
+
+    // Same as `new` with the bare ids and a float amount, for tests and fixtures in the crate
+    pub(crate) fn raw(
+        tx_type: TransactionType,
+        tx_id: u32,
+        client_id: u16,
+        amount: Option<f32>,
+    ) -> Self {
+        Transaction::new(
+            tx_type,
+            TxId::new(tx_id),
+            ClientId::new(client_id),
+            amount.map(Amount::from),
+        )
+    }
+
     pub fn tx_type(&self) -> &TransactionType {
         &self.tx_type
     }
 
-    pub fn client_id(&self) -> u16 {
+    pub fn client_id(&self) -> ClientId {
+        ClientId::new(self.client_id)
+    }
+
+    pub fn tx_id(&self) -> TxId {
+        TxId::new(self.tx_id)
+    }
+
+    // The bare ids, as the engine keys its maps by them
+    pub(crate) fn raw_client_id(&self) -> u16 {
         self.client_id
     }
 
-    pub fn tx_id(&self) -> u32 {
+    pub(crate) fn raw_tx_id(&self) -> u32 {
         self.tx_id
     }
 
-    pub fn amount(&self) -> &Option<f32> {
-        &self.amount
+    // The bare value of the amount, what balances are moved by
+    pub(crate) fn raw_amount(&self) -> Option<Money> {
+        self.amount.map(|amount| amount.value())
+    }
+
+    // The amount as written in the input, with its scale
+    pub fn amount(&self) -> Option<Amount> {
+        self.amount
+    }
+
+    // Decimals the amount was written with in the input, 0 when there is no amount
+    pub fn amount_scale(&self) -> u8 {
+        self.amount.map(|amount| amount.scale()).unwrap_or(0)
+    }
+
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.timestamp
+    }
+
+    pub fn value_date(&self) -> Option<DateTime<Utc>> {
+        self.value_date
+    }
+
+    pub fn batch(&self) -> Option<u32> {
+        self.batch
+    }
+
+    pub fn memo(&self) -> Option<&str> {
+        self.memo.as_deref()
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_deref()
     }
 }
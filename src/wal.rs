@@ -0,0 +1,221 @@
+// Write-ahead log of everything that changes an engine, e.g. to rebuild its state after an
+// incident and check it against a snapshot. The log starts with the state the engine had when
+// it was attached, then every operation is appended before the engine applies it, rejected
+// ones included. Replaying it through a fresh engine leads to the same `to_bytes()`.
+use crate::client::Client;
+use crate::engine::TransactionEngine;
+use crate::ids::ClientId;
+use crate::snapshot;
+use crate::transaction::Transaction;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum WalEntry {
+    Push(Transaction),
+    Submit(Transaction),
+    ReleaseScheduled,
+    Freeze(ClientId),
+    Unfreeze(ClientId),
+    AccrueInterest { rate: f64, at: DateTime<Utc> },
+    // The encoded state swapped in, e.g. by a rollback
+    Restore(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub struct Wal {
+    path: String,
+    file: File,
+}
+
+impl Wal {
+    // Starts a new log at `path` from `state`, replacing any log already there
+    pub fn create(path: &str, state: &[u8]) -> Result<Self, String> {
+        let file = File::create(path).map_err(|error| format!("{}: {}", path, error))?;
+        let mut wal = Wal {
+            path: path.to_string(),
+            file,
+        };
+        wal.write(&state)?;
+        Ok(wal)
+    }
+
+    pub fn append(&mut self, entry: &WalEntry) -> Result<(), String> {
+        self.write(entry)
+    }
+
+    // One write per record, so a crash can only cut off the last one
+    fn write<T: Serialize>(&mut self, record: &T) -> Result<(), String> {
+        self.file
+            .write_all(&snapshot::encode(record)?)
+            .map_err(|error| format!("Error writing WAL {}: {}", self.path, error))
+    }
+}
+
+// The state a log starts from and its entries in order
+pub fn read(path: &str) -> Result<(Vec<u8>, Vec<WalEntry>), String> {
+    let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+    let mut rest = bytes.as_slice();
+    let state: Vec<u8> = snapshot::decode_from(&mut rest)
+        .map_err(|error| format!("{} has no starting state: {}", path, error))?;
+
+    let mut entries = Vec::new();
+    while !rest.is_empty() {
+        let entry = snapshot::decode_from(&mut rest)
+            .map_err(|error| format!("{} entry {}: {}", path, entries.len() + 1, error))?;
+        entries.push(entry);
+    }
+
+    Ok((state, entries))
+}
+
+// Rebuilds the engine the log at `path` was written by, as it was after its last entry.
+// Operations that failed then fail the same way now and are skipped again
+pub fn replay(path: &str) -> Result<TransactionEngine, String> {
+    let (state, entries) = read(path)?;
+    let mut engine = TransactionEngine::from_bytes(&state)?;
+    for entry in entries {
+        match entry {
+            WalEntry::Push(tx) => engine.push(tx),
+            WalEntry::Submit(tx) => {
+                let _ = engine.submit(tx);
+            }
+            WalEntry::ReleaseScheduled => engine.release_scheduled(),
+            WalEntry::Freeze(client_id) => {
+                let _ = engine.freeze(client_id);
+            }
+            WalEntry::Unfreeze(client_id) => {
+                let _ = engine.unfreeze(client_id);
+            }
+            WalEntry::AccrueInterest { rate, at } => {
+                let _ = engine.accrue_interest(rate, at);
+            }
+            WalEntry::Restore(bytes) => engine = engine.restore(&bytes)?,
+        }
+    }
+
+    Ok(engine)
+}
+
+// How `replayed` differs from `saved`, described per client, empty when their whole state is
+// the same
+pub fn differences(
+    replayed: &TransactionEngine,
+    saved: &TransactionEngine,
+) -> Result<Vec<String>, String> {
+    if replayed.to_bytes()? == saved.to_bytes()? {
+        return Ok(Vec::new());
+    }
+
+    let mut client_ids: Vec<ClientId> = replayed
+        .clients()
+        .keys()
+        .chain(saved.clients().keys())
+        .copied()
+        .collect();
+    client_ids.sort();
+    client_ids.dedup();
+
+    let mut differences = Vec::new();
+    for client_id in client_ids {
+        let (logged, kept) = (replayed.client(client_id), saved.client(client_id));
+        if snapshot::encode(&logged)? == snapshot::encode(&kept)? {
+            continue;
+        }
+        let describe = |client: Option<&Client>| match client {
+            Some(client) => format!(
+                "available {} held {}{}",
+                client.funds().available(),
+                client.funds().held(),
+                if client.is_locked() { " locked" } else { "" }
+            ),
+            None => "no account".to_string(),
+        };
+        let (logged, kept) = (describe(logged), describe(kept));
+        differences.push(if logged == kept {
+            format!(
+                "client {}: balances match, its disputes or stored transactions differ",
+                client_id
+            )
+        } else {
+            format!(
+                "client {}: log leads to {}, snapshot has {}",
+                client_id, logged, kept
+            )
+        });
+    }
+    if differences.is_empty() {
+        differences.push(
+            "clients match, the engine config or the held back transactions differ".to_string(),
+        );
+    }
+
+    Ok(differences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{CrossClientPolicy, EngineConfig};
+    use crate::transaction::TransactionType;
+    use chrono::TimeZone;
+
+    #[test]
+    fn replays_to_the_same_state() {
+        let path = std::env::temp_dir().join("replays_to_the_same_state.wal");
+        let path = path.to_str().unwrap();
+        let mut engine = TransactionEngine::new(EngineConfig {
+            cross_client: CrossClientPolicy::Route,
+            ..EngineConfig::default()
+        })
+        .with_wal(path)
+        .unwrap();
+
+        for tx in [
+            Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)),
+            Transaction::raw(TransactionType::Deposit, 2, 2, Some(3.0)),
+            Transaction::raw(TransactionType::Withdrawal, 3, 1, Some(5.0)),
+        ] {
+            engine.push(tx);
+        }
+        engine.commit().unwrap();
+        engine
+            .submit(Transaction::raw(TransactionType::Dispute, 2, 2, None))
+            .unwrap();
+        engine = engine.rollback().unwrap();
+        engine.freeze(ClientId::new(2)).unwrap();
+        assert!(engine.freeze(ClientId::new(9)).is_err());
+        engine
+            .accrue_interest(0.01, Utc.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).unwrap())
+            .unwrap();
+        engine.push(Transaction::raw(TransactionType::Dispute, 1, 3, None));
+
+        let replayed = replay(path).unwrap();
+        assert_eq!(replayed.to_bytes().unwrap(), engine.to_bytes().unwrap());
+        let saved = TransactionEngine::from_bytes(&engine.to_bytes().unwrap()).unwrap();
+        assert!(differences(&replayed, &saved).unwrap().is_empty());
+
+        engine.push(Transaction::raw(TransactionType::Deposit, 4, 3, Some(1.0)));
+        assert_eq!(
+            differences(&replayed, &engine).unwrap(),
+            vec!["client 3: log leads to no account, snapshot has available 1.0000 held 0.0000"]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn stops_at_a_cut_off_entry() {
+        let path = std::env::temp_dir().join("stops_at_a_cut_off_entry.wal");
+        let path = path.to_str().unwrap();
+        let mut engine = TransactionEngine::default().with_wal(path).unwrap();
+        engine.push(Transaction::raw(TransactionType::Deposit, 1, 1, Some(2.0)));
+        engine.push(Transaction::raw(TransactionType::Deposit, 2, 1, Some(2.0)));
+
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::write(path, &bytes[..bytes.len() - 3]).unwrap();
+        assert!(replay(path).unwrap_err().contains("entry 2"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,14 @@
+use transactions::testing::assert_golden;
+
+#[test]
+fn all_transaction_types() {
+    assert_golden("tests/fixtures/all.csv", "tests/fixtures/all.accounts.csv");
+}
+
+#[test]
+fn out_of_order_transactions() {
+    assert_golden(
+        "tests/fixtures/out_of_order.csv",
+        "tests/fixtures/out_of_order.accounts.csv",
+    );
+}